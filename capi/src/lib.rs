@@ -0,0 +1,3 @@
+//! The C API of dynwave, built as a shared and a static library, see `include/dynwave.h`.
+
+pub use dynwave::capi::*;
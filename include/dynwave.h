@@ -0,0 +1,34 @@
+/* C API of dynwave, enabled with the `capi` feature. */
+#ifndef DYNWAVE_H
+#define DYNWAVE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A player of interleaved stereo samples on the default output device. */
+typedef struct DynwavePlayer DynwavePlayer;
+
+/* Returns NULL on failure. `buffer_samples` of 0 uses the default buffer of a quarter of a second. */
+DynwavePlayer *dynwave_new(uint32_t sample_rate, size_t buffer_samples);
+void dynwave_free(DynwavePlayer *player);
+
+void dynwave_queue(DynwavePlayer *player, const float *samples, size_t len);
+void dynwave_queue_i16(DynwavePlayer *player, const int16_t *samples, size_t len);
+
+/* Return 0 on success and -1 on failure. */
+int dynwave_play(DynwavePlayer *player);
+int dynwave_pause(DynwavePlayer *player);
+
+void dynwave_clear(DynwavePlayer *player);
+size_t dynwave_buffered_samples(const DynwavePlayer *player);
+uint32_t dynwave_output_sample_rate(const DynwavePlayer *player);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DYNWAVE_H */
@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+pub(crate) type LatencyCallback = Box<dyn FnMut(Duration) + Send + 'static>;
+
+/// Configuration of the adaptive latency of the player,
+/// set with [`AudioPlayerBuilder::adaptive_latency`](crate::AudioPlayerBuilder::adaptive_latency).
+///
+/// The player keeps a latency target, and after an underrun, playback only resumes once the buffer
+/// holds that much audio. The target starts at `initial`, and grows after repeated underruns up to `max`,
+/// giving the lowest latency that works on the machine without tuning [`BufferSize`](crate::BufferSize) by hand.
+///
+/// The buffer grows if needed to hold twice the target, and the application is told about the changes
+/// with [`AudioPlayerBuilder::on_latency_change`](crate::AudioPlayerBuilder::on_latency_change).
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AdaptiveLatency, AudioPlayer};
+/// # use std::time::Duration;
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .adaptive_latency(
+///         AdaptiveLatency::new(Duration::from_millis(20), Duration::from_millis(200)).grow_after(1),
+///     )
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+/// assert_eq!(player.latency_target(), Some(Duration::from_millis(20)));
+///
+/// // 20ms of audio, played for 40ms
+/// player.queue(&[0.5; 960 * 2]);
+/// clock.advance(Duration::from_millis(40));
+///
+/// // the underrun is noticed on the next queue
+/// player.queue(&[0.5; 960 * 2]);
+/// assert_eq!(player.latency_target(), Some(Duration::from_millis(30)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveLatency {
+    pub(crate) initial: Duration,
+    pub(crate) max: Duration,
+    pub(crate) grow_after: u32,
+    pub(crate) grow_within: Option<Duration>,
+    pub(crate) shrink_after: Option<Duration>,
+}
+
+impl AdaptiveLatency {
+    /// Start with a latency target of `initial`, that can grow up to `max`.
+    ///
+    /// By default, the target grows by half after 3 underruns, and never shrinks.
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+            grow_after: 3,
+            grow_within: None,
+            shrink_after: None,
+        }
+    }
+
+    /// Grow the target after `underruns` underruns since the last change.
+    pub fn grow_after(mut self, underruns: u32) -> Self {
+        self.grow_after = underruns.max(1);
+        self
+    }
+
+    /// Only grow the target if the [`grow_after`](Self::grow_after) underruns happen within `window`,
+    /// so occasional underruns don't add latency, or count them all if `None`.
+    pub fn grow_within(mut self, window: Option<Duration>) -> Self {
+        self.grow_within = window;
+        self
+    }
+
+    /// Shrink the target back towards `initial` after `duration` without underruns,
+    /// or never if `None`.
+    pub fn shrink_after(mut self, duration: Option<Duration>) -> Self {
+        self.shrink_after = duration;
+        self
+    }
+}
+
+/// Tracks the underruns and adjusts the latency target
+pub(crate) struct LatencyTuner {
+    config: AdaptiveLatency,
+    target: Duration,
+    seen_underruns: u64,
+    underruns_since_change: u32,
+    // when the underruns counted towards growing started
+    counting_since: Instant,
+    stable_since: Instant,
+    callback: Option<LatencyCallback>,
+}
+
+impl LatencyTuner {
+    pub fn new(config: AdaptiveLatency) -> Self {
+        Self {
+            target: config.initial,
+            config,
+            seen_underruns: 0,
+            underruns_since_change: 0,
+            counting_since: Instant::now(),
+            stable_since: Instant::now(),
+            callback: None,
+        }
+    }
+
+    pub fn set_callback(&mut self, callback: Option<LatencyCallback>) {
+        self.callback = callback;
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    /// Update with the total number of `underruns` so far, returns the new target if it changed
+    pub fn update(&mut self, underruns: u64, now: Instant) -> Option<Duration> {
+        let new_underruns = underruns.saturating_sub(self.seen_underruns);
+        self.seen_underruns = underruns;
+
+        let target = if new_underruns > 0 {
+            self.stable_since = now;
+            let expired = self
+                .config
+                .grow_within
+                .is_some_and(|window| now.saturating_duration_since(self.counting_since) > window);
+            if self.underruns_since_change == 0 || expired {
+                self.underruns_since_change = 0;
+                self.counting_since = now;
+            }
+            self.underruns_since_change = self
+                .underruns_since_change
+                .saturating_add(new_underruns.min(u32::MAX as u64) as u32);
+            if self.underruns_since_change < self.config.grow_after {
+                return None;
+            }
+            (self.target * 3 / 2).min(self.config.max)
+        } else {
+            let stable_for = self.config.shrink_after?;
+            if now.saturating_duration_since(self.stable_since) < stable_for {
+                return None;
+            }
+            (self.target * 2 / 3).max(self.config.initial)
+        };
+
+        self.underruns_since_change = 0;
+        self.stable_since = now;
+        if target == self.target {
+            return None;
+        }
+        self.target = target;
+        if let Some(callback) = &mut self.callback {
+            callback(target);
+        }
+        Some(target)
+    }
+}
@@ -0,0 +1,433 @@
+use std::sync::{Arc, Mutex};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, SupportedBufferSize,
+};
+
+use super::{
+    OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream, StreamErrorReporter,
+};
+use crate::{
+    error::{AudioPlayerError, CallbackError, PlayError},
+    negotiation::{choose_config, Preferred},
+    sample::FloatSample,
+    utils, CallbackSize, ConfigPreference, StreamMetadata,
+};
+
+/// The function filling the output of the device, passed to the [`raw_callback`](DeviceBackend::raw_callback)
+pub type FillFn<'a> = dyn FnMut(&mut Data, &OutputCallbackInfo) + 'a;
+
+/// The callback wrapping the output callback of the device, shared by the streams of the player
+pub(crate) type RawCallback =
+    Arc<Mutex<dyn FnMut(&mut Data, &OutputCallbackInfo, &mut FillFn) + Send>>;
+
+/// The default backend, plays the samples on an audio device using [cpal].
+///
+/// The options here can also be set from [`AudioPlayerBuilder`](crate::AudioPlayerBuilder),
+/// which creates this backend if no other backend is provided.
+#[derive(Default)]
+pub struct DeviceBackend {
+    host: Option<cpal::HostId>,
+    device: Option<cpal::Device>,
+    callback_size: CallbackSize,
+    supported_buffer_size: Option<SupportedBufferSize>,
+    config_preference: ConfigPreference,
+    preferred_sample_rate: Option<u32>,
+    prefer_default_config: bool,
+    host_fallback: bool,
+    native_channels: bool,
+    sample_formats: Vec<SampleFormat>,
+    // the channels of the chosen config, if the device can't play stereo
+    device_channels: Option<u16>,
+    metadata: StreamMetadata,
+    raw_callback: Option<RawCallback>,
+}
+
+impl DeviceBackend {
+    /// Creates a backend for the default output device of the default host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the default output device of the host `host` instead of the default host.
+    pub fn host(mut self, host: cpal::HostId) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// Use `device` as the output device, instead of the default output device of the host.
+    ///
+    /// This overrides [`host`](Self::host).
+    pub fn device(mut self, device: cpal::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Set the number of frames the device will request in each callback. See [`CallbackSize`] for options.
+    pub fn callback_size(mut self, callback_size: CallbackSize) -> Self {
+        self.callback_size = callback_size;
+        self
+    }
+
+    /// Set which configuration of the device is chosen if none matches the player exactly.
+    /// See [`ConfigPreference`] for options.
+    pub fn config_preference(mut self, preference: ConfigPreference) -> Self {
+        self.config_preference = preference;
+        self
+    }
+
+    /// If the device doesn't support the sample rate of the player, prefer a configuration with `sample_rate`,
+    /// for example the rate the device runs at in the system mixer, or one that resamples with a simple ratio.
+    pub fn preferred_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.preferred_sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// If the device doesn't support the sample rate of the player, so the output is resampled anyway,
+    /// prefer the sample rate and format of the default configuration of the device over the others,
+    /// and over the [`preferred_sample_rate`](Self::preferred_sample_rate).
+    ///
+    /// On several hosts, the other configurations go through another resampler of the system,
+    /// which adds latency. The default is `false`.
+    pub fn prefer_default_config(mut self, prefer: bool) -> Self {
+        self.prefer_default_config = prefer;
+        self
+    }
+
+    /// If the default device of the host is missing or can't play stereo, try the default devices
+    /// of the other hosts available on the platform, for example ALSA when PulseAudio is broken.
+    ///
+    /// Only used when no [`device`](Self::device) is set. The default is `false`.
+    pub fn host_fallback(mut self, host_fallback: bool) -> Self {
+        self.host_fallback = host_fallback;
+        self
+    }
+
+    /// If the device has no stereo configuration, use the channels of its default configuration,
+    /// and mix the stereo output of the player to them, instead of failing with
+    /// [`AudioPlayerError::DualChannelNotSupported`].
+    ///
+    /// Mono devices play the two channels mixed, and devices with more channels play the output
+    /// on the first two channels, usually front left and right. The default is `false`.
+    pub fn native_channels(mut self, native_channels: bool) -> Self {
+        self.native_channels = native_channels;
+        self
+    }
+
+    /// Only use configurations with these sample formats, the first ones are preferred,
+    /// for example to avoid a format that is broken in a driver.
+    ///
+    /// The formats are chosen before the sample rate, so a configuration with the first format
+    /// is used even if it needs resampling. If no configuration has one of the formats,
+    /// [`AudioPlayerError::UnsupportedOutputFormat`] is returned.
+    /// By default, all the formats are accepted, see [`ConfigPreference`].
+    pub fn sample_formats(mut self, formats: &[SampleFormat]) -> Self {
+        self.sample_formats = formats.to_vec();
+        self
+    }
+
+    /// Set the names and role of the stream shown by the system mixer, see [`StreamMetadata`].
+    pub fn metadata(mut self, metadata: StreamMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Wrap the output callback of the device with `callback`, for custom processing or measurements
+    /// on the samples in the format of the device, after the negotiation and resampling of the player.
+    ///
+    /// `callback` receives the output buffer, the [`OutputCallbackInfo`] of cpal, and the function of the player
+    /// filling the buffer. It should call it, then process the buffer, or fill the buffer itself to replace
+    /// the output of the player, the queued samples are not consumed then.
+    ///
+    /// This runs in the output callback, so it should not block or panic.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{backend::{DeviceBackend, FillFn}, AudioPlayer};
+    /// # use cpal::{Data, OutputCallbackInfo};
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(DeviceBackend::new().raw_callback(
+    ///         |data: &mut Data, info: &OutputCallbackInfo, fill: &mut FillFn| {
+    ///             fill(data, info);
+    ///             // the samples are in the format of the device
+    ///             if let Some(samples) = data.as_slice_mut::<f32>() {
+    ///                 samples.iter_mut().for_each(|sample| *sample = sample.clamp(-0.5, 0.5));
+    ///             }
+    ///         },
+    ///     ))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn raw_callback(
+        mut self,
+        callback: impl FnMut(&mut Data, &OutputCallbackInfo, &mut FillFn) + Send + 'static,
+    ) -> Self {
+        self.raw_callback = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// Use the `callback` of another backend, see [`raw_callback`](Self::raw_callback)
+    pub(crate) fn shared_raw_callback(mut self, callback: Option<RawCallback>) -> Self {
+        self.raw_callback = callback;
+        self
+    }
+
+    fn output_device(&mut self) -> Result<&cpal::Device, AudioPlayerError> {
+        if self.device.is_none() {
+            let host = match self.host {
+                Some(host_id) => cpal::host_from_id(host_id)?,
+                None => cpal::default_host(),
+            };
+            self.device = Some(
+                host.default_output_device()
+                    .ok_or(AudioPlayerError::NoOutputDevice)?,
+            );
+        }
+        Ok(self.device.as_ref().unwrap())
+    }
+
+    fn err_fn(reporter: StreamErrorReporter) -> impl FnMut(cpal::StreamError) + Send + 'static {
+        move |err| match err {
+            cpal::StreamError::DeviceNotAvailable => reporter.report_stream_lost(),
+            err => reporter.report_error(CallbackError::Stream(err.to_string())),
+        }
+    }
+}
+
+impl<T> OutputBackend<T> for DeviceBackend
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        let options = NegotiateOptions {
+            preference: self.config_preference,
+            preferred_rate: self.preferred_sample_rate,
+            prefer_default_config: self.prefer_default_config,
+            native_channels: self.native_channels,
+            sample_formats: self.sample_formats.clone(),
+        };
+        let fallback = self.host_fallback && self.device.is_none();
+        let result = self
+            .output_device()
+            .and_then(|device| negotiate_device(device, request, &options));
+        let (config, buffer_size) = match result {
+            Err(err) if fallback => {
+                let current = self.host.unwrap_or_else(|| cpal::default_host().id());
+                let (host, device, negotiated) = cpal::available_hosts()
+                    .into_iter()
+                    .filter(|&host| host != current)
+                    .filter_map(|host| {
+                        let device = cpal::host_from_id(host).ok()?.default_output_device()?;
+                        let negotiated = negotiate_device(&device, request, &options).ok()?;
+                        Some((host, device, negotiated))
+                    })
+                    .next()
+                    .ok_or(err)?;
+                self.host = Some(host);
+                self.device = Some(device);
+                negotiated
+            }
+            result => result?,
+        };
+
+        self.supported_buffer_size = Some(buffer_size);
+        // the player keeps working in stereo, and the source mixes it to the device channels
+        self.device_channels = (config.channels != request.channels).then_some(config.channels);
+        Ok(OutputConfig {
+            channels: request.channels,
+            ..config
+        })
+    }
+
+    fn native_sample_rate(&mut self) -> Option<u32> {
+        let config = self.output_device().ok()?.default_output_config().ok()?;
+        Some(config.sample_rate().0)
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let supported_buffer_size = self
+            .supported_buffer_size
+            .unwrap_or(SupportedBufferSize::Unknown);
+        let callback_size = self.callback_size;
+        self.metadata.apply();
+        let device_channels = self.device_channels;
+        let raw_callback = self.raw_callback.clone();
+        let output_device = self.output_device()?;
+
+        let mut stream_config = cpal::StreamConfig {
+            channels: device_channels.unwrap_or(config.channels),
+            sample_rate: cpal::SampleRate(config.sample_rate),
+            buffer_size: callback_size.resolve(&supported_buffer_size)?,
+        };
+
+        let build_stream = |stream_config: &cpal::StreamConfig, mut source: OutputSource<T>| {
+            if let Some(channels) = device_channels {
+                source.set_device_channels(channels);
+            }
+            let error_fn = Self::err_fn(source.error_reporter());
+            let mut output_data_fn = utils::create_output_processor(config.sample_format, source)?;
+            if let Some(raw_callback) = raw_callback.clone() {
+                output_data_fn = Box::new(move |data: &mut Data, info: &OutputCallbackInfo| {
+                    // only locked by the callback of one stream at a time
+                    match raw_callback.try_lock() {
+                        Ok(mut callback) => callback(data, info, &mut output_data_fn),
+                        Err(_) => output_data_fn(data, info),
+                    }
+                });
+            }
+
+            output_device
+                .build_output_stream_raw(
+                    stream_config,
+                    config.sample_format,
+                    output_data_fn,
+                    error_fn,
+                    None,
+                )
+                .map_err(AudioPlayerError::from)
+        };
+
+        let slot = source.slot().clone();
+        let output_stream = match build_stream(&stream_config, source) {
+            Err(
+                AudioPlayerError::StreamConfigNotSupported
+                | AudioPlayerError::StreamConfigInvalidArgument,
+            ) if matches!(
+                callback_size,
+                CallbackSize::Fixed(_) | CallbackSize::Minimum
+            ) =>
+            {
+                // the device didn't like the requested size, fallback to the default,
+                // the source went back to its slot when the failed callback was dropped
+                stream_config.buffer_size = cpal::BufferSize::Default;
+                let source = OutputSource::take(&slot).expect("Source returned to the slot");
+                build_stream(&stream_config, source)?
+            }
+            result => result?,
+        };
+
+        Ok(Box::new(output_stream))
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.device.as_ref()?.name().ok()
+    }
+}
+
+/// The options of [`DeviceBackend`] used to choose the configuration
+struct NegotiateOptions {
+    preference: ConfigPreference,
+    preferred_rate: Option<u32>,
+    prefer_default_config: bool,
+    native_channels: bool,
+    sample_formats: Vec<SampleFormat>,
+}
+
+/// Choose the stereo configuration of `output_device` for `request`,
+/// or the default one with `native_channels` if the device can't play stereo
+fn negotiate_device(
+    output_device: &cpal::Device,
+    request: &OutputRequest,
+    options: &NegotiateOptions,
+) -> Result<(OutputConfig, SupportedBufferSize), AudioPlayerError> {
+    // configurations that can't be played are skipped, so another format of the device is used
+    let conf = output_device
+        .supported_output_configs()?
+        .filter(|c| utils::is_playable(c.sample_format()))
+        .collect::<Vec<_>>();
+
+    let allowed = |format| {
+        utils::is_playable(format)
+            && (options.sample_formats.is_empty() || options.sample_formats.contains(&format))
+    };
+    let default_config = || {
+        let config = output_device.default_output_config()?;
+        if !allowed(config.sample_format()) {
+            return Err(AudioPlayerError::UnsupportedOutputFormat(
+                config.sample_format(),
+            ));
+        }
+        Ok(config)
+    };
+    let preferred = Preferred {
+        rate: options.preferred_rate,
+        default: options
+            .prefer_default_config
+            .then(|| output_device.default_output_config().ok())
+            .flatten()
+            .map(|config| (config.sample_rate(), config.sample_format())),
+    };
+
+    let used_conf = if options.sample_formats.is_empty() {
+        choose_config(
+            &conf,
+            default_config,
+            request.sample_rate,
+            request.sample_format,
+            2,
+            options.preference,
+            preferred,
+        )?
+    } else {
+        // the formats come first, in order, then the sample rate
+        let by_format = options.sample_formats.iter().find_map(|&format| {
+            let configs = conf
+                .iter()
+                .filter(|c| c.sample_format() == format && c.channels() == 2)
+                .cloned()
+                .collect::<Vec<_>>();
+            (!configs.is_empty()).then(|| {
+                choose_config(
+                    &configs,
+                    || unreachable!("the configs have stereo"),
+                    request.sample_rate,
+                    format,
+                    2,
+                    options.preference,
+                    preferred,
+                )
+            })
+        });
+        match by_format {
+            Some(used_conf) => used_conf?,
+            None => default_config()?,
+        }
+    };
+
+    // the supported configurations of the device can be listed with `devices`
+    if used_conf.channels() != 2 && !options.native_channels {
+        return Err(AudioPlayerError::DualChannelNotSupported);
+    }
+
+    let config = OutputConfig {
+        sample_rate: used_conf.sample_rate().0,
+        sample_format: used_conf.sample_format(),
+        channels: used_conf.channels(),
+    };
+    Ok((config, *used_conf.buffer_size()))
+}
+
+impl OutputStream for cpal::Stream {
+    fn play(&self) -> Result<(), PlayError> {
+        StreamTrait::play(self).map_err(|e| e.into())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        StreamTrait::pause(self).map_err(|e| e.into())
+    }
+}
@@ -0,0 +1,182 @@
+//! Output backends for the audio player.
+//!
+//! By default, the [`AudioPlayer`](crate::AudioPlayer) plays the samples on an audio device with [cpal],
+//! using the [`DeviceBackend`]. Other backends can be used with [`AudioPlayerBuilder::backend`](crate::AudioPlayerBuilder::backend),
+//! the queueing and resampling logic is the same for all backends.
+//!
+//! The [`NullBackend`] doesn't play anything, and consumes the samples at the pace of a [`VirtualClock`],
+//! which is useful for testing.
+//!
+//! The [`UdpBackend`] streams the output over the network, with RTP by default.
+//!
+//! With the `wav` feature, the [`WavBackend`] writes the output into a WAV file.
+//!
+//! With the `rodio` feature, the [`RodioBackend`] plays the output as a [`rodio::Source`].
+//!
+//! With the `websocket` feature, the [`WebSocketBackend`] streams the output to WebSocket clients.
+//!
+//! Custom backends can be implemented with the [`OutputBackend`] and [`OutputStream`] traits,
+//! they receive an [`OutputSource`] to pull the samples from.
+pub(crate) mod device;
+mod null;
+#[cfg(feature = "rodio")]
+mod rodio_backend;
+pub(crate) mod source;
+mod udp;
+#[cfg(feature = "wav")]
+mod wav;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use device::{DeviceBackend, FillFn};
+pub use null::{CallbackCapture, NullBackend, VirtualClock};
+#[cfg(feature = "rodio")]
+pub use rodio_backend::{RodioBackend, RodioSource, RodioSourceHandle};
+pub use source::{OutputSource, StreamErrorReporter};
+pub use udp::UdpBackend;
+#[cfg(feature = "wav")]
+pub use wav::WavBackend;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketBackend;
+
+use cpal::SampleFormat;
+
+use crate::error::{AudioPlayerError, PlayError};
+
+/// The format of the samples queued into the player, used by the backend to negotiate the output configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputRequest {
+    /// The sample rate of the queued samples in Hz.
+    pub sample_rate: u32,
+    /// The sample format of the queued samples.
+    pub sample_format: SampleFormat,
+    /// The number of interleaved channels of the queued samples.
+    pub channels: u16,
+}
+
+/// The output configuration negotiated by a backend.
+///
+/// If the `sample_rate` is different from the requested one, the player resamples the samples
+/// before they reach the [`OutputSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputConfig {
+    /// The sample rate of the output in Hz.
+    pub sample_rate: u32,
+    /// The sample format of the output, the samples are converted to it by the backend.
+    pub sample_format: SampleFormat,
+    /// The number of interleaved channels of the output.
+    pub channels: u16,
+}
+
+/// How fast a backend that is not driven by an audio device consumes the samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pacing {
+    #[default]
+    /// Consume the samples at the rate of the output, like an audio device would,
+    /// underruns are filled with silence.
+    RealTime,
+    /// Consume the samples as soon as they are queued, only the queued samples are used,
+    /// no silence is inserted when the buffer is empty.
+    AsFastAsPossible,
+}
+
+/// A target for the samples played by the [`AudioPlayer`](crate::AudioPlayer).
+///
+/// # Example
+/// A backend that pulls the samples from a separate thread:
+/// ```rust,no_run
+/// # use dynwave::{AudioPlayer, backend::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream}};
+/// # use dynwave::error::{AudioPlayerError, PlayError};
+/// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+///
+/// struct ThreadBackend;
+///
+/// struct ThreadStream {
+///     playing: Arc<AtomicBool>,
+/// }
+///
+/// impl OutputStream for ThreadStream {
+///     fn play(&self) -> Result<(), PlayError> {
+///         self.playing.store(true, Ordering::Relaxed);
+///         Ok(())
+///     }
+///     fn pause(&self) -> Result<(), PlayError> {
+///         self.playing.store(false, Ordering::Relaxed);
+///         Ok(())
+///     }
+/// }
+///
+/// impl OutputBackend<f32> for ThreadBackend {
+///     fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+///         // accept whatever we get, no resampling needed
+///         Ok(OutputConfig {
+///             sample_rate: request.sample_rate,
+///             sample_format: request.sample_format,
+///             channels: request.channels,
+///         })
+///     }
+///
+///     fn build_stream(
+///         &mut self,
+///         config: &OutputConfig,
+///         mut source: OutputSource<f32>,
+///     ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+///         let chunk = config.sample_rate as usize / 100 * config.channels as usize;
+///         let playing = Arc::new(AtomicBool::new(false));
+///         let thread_playing = playing.clone();
+///         std::thread::spawn(move || {
+///             let mut buffer = vec![0.0; chunk];
+///             loop {
+///                 if thread_playing.load(Ordering::Relaxed) {
+///                     source.fill(&mut buffer);
+///                     // do something with `buffer`
+///                 }
+///                 std::thread::sleep(std::time::Duration::from_millis(10));
+///             }
+///         });
+///         Ok(Box::new(ThreadStream { playing }))
+///     }
+/// }
+///
+/// let player = AudioPlayer::<f32>::builder(44100)
+///     .backend(ThreadBackend)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait OutputBackend<T> {
+    /// Negotiate the output configuration for the samples described by `request`.
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError>;
+
+    /// Build a stream that plays the samples from `source` with the negotiated `config`.
+    ///
+    /// The stream should start paused, and only pull from `source` while playing.
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError>;
+
+    /// The name of the device the samples are played on, if any, shown by
+    /// [`AudioPlayerBuilder::negotiate`](crate::AudioPlayerBuilder::negotiate).
+    ///
+    /// Returns `None` by default.
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+
+    /// The sample rate the output plays without conversion, for example the rate of the default
+    /// configuration of the device, used by [`AudioPlayerBuilder::auto_sample_rate`](crate::AudioPlayerBuilder::auto_sample_rate).
+    ///
+    /// Returns `None` by default.
+    fn native_sample_rate(&mut self) -> Option<u32> {
+        None
+    }
+}
+
+/// A stream created by an [`OutputBackend`], controls the playback.
+pub trait OutputStream {
+    /// Start or resume the playback
+    fn play(&self) -> Result<(), PlayError>;
+    /// Pause the playback
+    fn pause(&self) -> Result<(), PlayError>;
+}
@@ -0,0 +1,425 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use super::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream};
+use crate::error::{AudioPlayerError, PlayError};
+use crate::sample::FloatSample;
+
+struct NullState<T> {
+    source: Option<OutputSource<T>>,
+    config: Option<OutputConfig>,
+    playing: bool,
+    period_frames: Option<usize>,
+    output_latency: Option<Duration>,
+    // frames that are due, but not enough to fill a period
+    pending_frames: usize,
+    // the fraction of a frame that is due from `advance`, in nanoseconds * sample_rate
+    pending_nanos: u128,
+    frames_played: u64,
+    scratch: Vec<T>,
+    // the sample format negotiated for the device, set by a capture
+    device_format: Option<SampleFormat>,
+    // fills the callbacks converted into the samples of a capture, and records them
+    capture: Option<CaptureFn<T>>,
+}
+
+/// Fills a callback of this number of samples from the source, and records it
+type CaptureFn<T> = Box<dyn FnMut(&mut OutputSource<T>, usize) + Send>;
+
+/// A backend that doesn't play anything, the samples are consumed at the pace of a [`VirtualClock`].
+///
+/// This is useful for testing the audio path of an application without an audio device,
+/// and in a deterministic way, since the samples are only consumed when the clock is advanced.
+///
+/// By default, the output configuration is the same as the queued samples, so no resampling happens,
+/// use [`sample_rate`](Self::sample_rate) to simulate a device with a different sample rate.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{AudioPlayer, PlayerState, backend::NullBackend};
+/// # use std::time::Duration;
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+///
+/// let mut player = AudioPlayer::<f32>::builder(44100)
+///     .backend(backend)
+///     .build()
+///     .unwrap();
+///
+/// // 10ms of stereo samples
+/// player.queue(&[0.5; 441 * 2]);
+/// player.play().unwrap();
+///
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(player.state(), PlayerState::Playing);
+/// assert_eq!(clock.frames_played(), 220);
+///
+/// // consumed more than what was queued
+/// clock.advance(Duration::from_millis(10));
+/// assert_eq!(player.state(), PlayerState::Underrun);
+/// ```
+pub struct NullBackend<T> {
+    sample_rate: Option<u32>,
+    state: Arc<Mutex<NullState<T>>>,
+}
+
+impl<T> Default for NullBackend<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> NullBackend<T> {
+    /// Creates a new null backend, the samples are only consumed when its [`VirtualClock`] is advanced.
+    pub fn new() -> Self {
+        Self {
+            sample_rate: None,
+            state: Arc::new(Mutex::new(NullState {
+                source: None,
+                config: None,
+                playing: false,
+                period_frames: None,
+                output_latency: None,
+                pending_frames: 0,
+                pending_nanos: 0,
+                frames_played: 0,
+                scratch: Vec::new(),
+                device_format: None,
+                capture: None,
+            })),
+        }
+    }
+
+    /// Simulate a device running at `sample_rate` Hz, the player will resample if it's different
+    /// from the rate of the queued samples.
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Simulate a device that consumes the samples in periods of `frames`, like the callbacks of a real device.
+    ///
+    /// Without this, the clock consumes exactly the frames that are due when advanced.
+    pub fn period_frames(self, frames: usize) -> Self {
+        lock(&self.state).period_frames = Some(frames.max(1));
+        self
+    }
+
+    /// Simulate a device that plays the samples `latency` after they are filled, like a Bluetooth device,
+    /// it's reported to the player, see [`AudioPlayer::device_latency`](crate::AudioPlayer::device_latency).
+    pub fn output_latency(self, latency: Duration) -> Self {
+        lock(&self.state).output_latency = Some(latency);
+        self
+    }
+
+    /// Returns the clock controlling this backend.
+    pub fn clock(&self) -> VirtualClock<T> {
+        VirtualClock {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: FloatSample + SizedSample> NullBackend<T> {
+    /// Record the samples of each callback, converted into the sample type `S` as a device playing `S` samples
+    /// would receive them, after the dither and the underrun fill, see [`CallbackCapture`].
+    ///
+    /// The device is negotiated with the format of `S`, so the capture must be set before building the player.
+    /// While capturing, [`VirtualClock::render_frames`] returns no samples, they are in the capture.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new().period_frames(256);
+    /// let clock = backend.clock();
+    /// let capture = backend.capture::<i16>();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.5; 300 * 2]);
+    /// clock.advance_frames(512);
+    ///
+    /// let callbacks = capture.callbacks();
+    /// assert_eq!(callbacks.len(), 2);
+    /// assert!(callbacks[0].iter().all(|&sample| sample == 16384));
+    /// // the buffer ran out in the second callback
+    /// assert_eq!(callbacks[1][..44 * 2], [16384; 44 * 2]);
+    /// assert!(callbacks[1][44 * 2..].iter().all(|&sample| sample == 0));
+    /// ```
+    pub fn capture<S>(&self) -> CallbackCapture<S>
+    where
+        S: SizedSample + FromSample<T> + Send + 'static,
+    {
+        let capture = CallbackCapture {
+            callbacks: Arc::new(Mutex::new(Vec::new())),
+        };
+        let callbacks = capture.callbacks.clone();
+        let mut state = lock(&self.state);
+        state.device_format = Some(S::FORMAT);
+        state.capture = Some(Box::new(move |source, len| {
+            let mut samples = vec![S::EQUILIBRIUM; len];
+            source.fill_converted(&mut samples);
+            lock(&callbacks).push(samples);
+        }));
+        capture
+    }
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> OutputBackend<T> for NullBackend<T> {
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        Ok(OutputConfig {
+            sample_rate: self.sample_rate.unwrap_or(request.sample_rate),
+            sample_format: lock(&self.state)
+                .device_format
+                .unwrap_or(request.sample_format),
+            channels: request.channels,
+        })
+    }
+
+    fn native_sample_rate(&mut self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let mut state = lock(&self.state);
+        state.source = Some(source);
+        state.config = Some(*config);
+        state.playing = false;
+
+        Ok(Box::new(NullStream {
+            state: self.state.clone(),
+        }))
+    }
+}
+
+struct NullStream<T> {
+    state: Arc<Mutex<NullState<T>>>,
+}
+
+impl<T> OutputStream for NullStream<T> {
+    fn play(&self) -> Result<(), PlayError> {
+        lock(&self.state).playing = true;
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        lock(&self.state).playing = false;
+        Ok(())
+    }
+}
+
+impl<T> Drop for NullStream<T> {
+    fn drop(&mut self) {
+        // release the source, as a device would when its stream is dropped
+        let mut state = lock(&self.state);
+        state.playing = false;
+        state.source = None;
+    }
+}
+
+/// The clock driving a [`NullBackend`], samples are consumed from the player only when it's advanced,
+/// and only while the player is playing.
+///
+/// The clock can be cloned and moved to other threads.
+pub struct VirtualClock<T> {
+    state: Arc<Mutex<NullState<T>>>,
+}
+
+impl<T> Clone for VirtualClock<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: FloatSample + SizedSample> VirtualClock<T> {
+    /// Advance the clock by `duration`, consuming the samples that would be played by a device
+    /// running at the negotiated sample rate during that time.
+    ///
+    /// Fractions of frames are accumulated for the next calls.
+    pub fn advance(&self, duration: Duration) {
+        let frames = {
+            let mut state = lock(&self.state);
+            let Some(config) = state.config else {
+                return;
+            };
+            if !state.playing {
+                return;
+            }
+            let total = state.pending_nanos + duration.as_nanos() * config.sample_rate as u128;
+            state.pending_nanos = total % 1_000_000_000;
+            (total / 1_000_000_000) as usize
+        };
+        self.advance_frames(frames);
+    }
+
+    /// Advance the clock by `frames` frames at the negotiated sample rate.
+    pub fn advance_frames(&self, frames: usize) {
+        self.fill_frames(frames, |_| {});
+    }
+
+    /// Same as [`advance_frames`](Self::advance_frames), but returns the interleaved samples
+    /// the player filled, as a device would play them.
+    pub fn render_frames(&self, frames: usize) -> Vec<T> {
+        let mut rendered = Vec::new();
+        self.fill_frames(frames, |samples| rendered.extend_from_slice(samples));
+        rendered
+    }
+
+    /// Fill `output` with the whole frames the player plays, converted into the sample type `S`
+    /// as a device playing `S` samples would, returns the number of samples filled.
+    ///
+    /// The frames are filled in one callback, whatever the [`period_frames`](NullBackend::period_frames).
+    pub fn render_into<S>(&self, output: &mut [S]) -> usize
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        self.render(output, true)
+    }
+
+    /// Same as [`render_into`](Self::render_into), outside of the real-time checks,
+    /// for the renders that don't simulate an audio callback
+    pub(crate) fn render_offline<S>(&self, output: &mut [S]) -> usize
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        self.render(output, false)
+    }
+
+    fn render<S>(&self, output: &mut [S], realtime: bool) -> usize
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let mut state = lock(&self.state);
+        let state = &mut *state;
+        let (Some(config), Some(source)) = (state.config, state.source.as_mut()) else {
+            return 0;
+        };
+        if !state.playing {
+            return 0;
+        }
+
+        let channels = config.channels.max(1) as usize;
+        let len = output.len() / channels * channels;
+        if len == 0 {
+            return 0;
+        }
+        if let Some(latency) = state.output_latency {
+            source.report_latency(latency);
+        }
+        if realtime {
+            source.fill_converted(&mut output[..len]);
+        } else {
+            source.fill_converted_offline(&mut output[..len]);
+        }
+        state.frames_played += (len / channels) as u64;
+        len
+    }
+
+    fn fill_frames(&self, frames: usize, mut on_fill: impl FnMut(&[T])) {
+        let mut state = lock(&self.state);
+        let state = &mut *state;
+        let (Some(config), Some(source)) = (state.config, state.source.as_mut()) else {
+            return;
+        };
+        if !state.playing {
+            return;
+        }
+
+        state.pending_frames += frames;
+        let period = state.period_frames.unwrap_or(state.pending_frames).max(1);
+        while state.pending_frames >= period {
+            state.pending_frames -= period;
+            if let Some(latency) = state.output_latency {
+                source.report_latency(latency);
+            }
+            let len = period * config.channels as usize;
+            if let Some(capture) = &mut state.capture {
+                capture(source, len);
+            } else {
+                state.scratch.resize(len, T::EQUILIBRIUM);
+                source.fill(&mut state.scratch);
+                on_fill(&state.scratch);
+            }
+            state.frames_played += period as u64;
+        }
+    }
+
+    /// Simulate the device being disconnected, the stream stops and reports the loss to the player,
+    /// which handles it with its [`RecoveryPolicy`](crate::RecoveryPolicy).
+    ///
+    /// A stream built again by the player plays normally.
+    pub fn disconnect(&self) {
+        let mut state = lock(&self.state);
+        if let Some(source) = &state.source {
+            source.error_reporter().report_stream_lost();
+        }
+        state.playing = false;
+    }
+
+    /// The number of frames consumed from the player so far.
+    pub fn frames_played(&self) -> u64 {
+        lock(&self.state).frames_played
+    }
+
+    /// The time elapsed on the clock so far, based on the frames consumed.
+    pub fn elapsed(&self) -> Duration {
+        let state = lock(&self.state);
+        match state.config {
+            Some(config) => {
+                Duration::from_secs_f64(state.frames_played as f64 / config.sample_rate as f64)
+            }
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// The samples of the callbacks of a [`NullBackend`], converted into the sample type `S`,
+/// from [`NullBackend::capture`].
+///
+/// Useful in integration tests, to check what a device would have played for the queued samples
+/// and the [`period_frames`](NullBackend::period_frames) of the callbacks. The clones share the same callbacks.
+pub struct CallbackCapture<S> {
+    callbacks: Arc<Mutex<Vec<Vec<S>>>>,
+}
+
+impl<S> Clone for CallbackCapture<S> {
+    fn clone(&self) -> Self {
+        Self {
+            callbacks: self.callbacks.clone(),
+        }
+    }
+}
+
+impl<S: Clone> CallbackCapture<S> {
+    /// The samples of each callback, in the order they were filled.
+    pub fn callbacks(&self) -> Vec<Vec<S>> {
+        lock(&self.callbacks).clone()
+    }
+
+    /// The samples of all the callbacks, one after the other.
+    pub fn samples(&self) -> Vec<S> {
+        lock(&self.callbacks).concat()
+    }
+
+    /// Remove the recorded callbacks, to check only the next ones.
+    pub fn clear(&self) {
+        lock(&self.callbacks).clear();
+    }
+}
+
+fn lock<T>(state: &Mutex<T>) -> MutexGuard<'_, T> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,186 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use cpal::SizedSample;
+
+use super::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream};
+use crate::error::{AudioPlayerError, PlayError};
+use crate::sample::FloatSample;
+
+/// The number of frames pulled from the player at once
+const PERIOD_FRAMES: usize = 256;
+
+type RodioSlot<T> = Arc<Mutex<Option<RodioSource<T>>>>;
+
+/// A backend that plays the player as a [`rodio::Source`], for applications already using [rodio].
+///
+/// After the player is built, take the [`RodioSource`] from the [`RodioSourceHandle`] and play it
+/// with rodio, for example with `rodio::Sink::append`. The source never ends, and plays silence
+/// while the player is paused or has no samples, like a device would.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{AudioPlayer, backend::RodioBackend};
+/// let backend = RodioBackend::new();
+/// let handle = backend.source_handle();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .build()
+///     .unwrap();
+///
+/// let mut source = handle.take().unwrap();
+/// player.play().unwrap();
+/// player.queue(&[0.5; 512]);
+///
+/// // with rodio: `sink.append(source)`
+/// assert_eq!(source.next(), Some(0.5));
+/// ```
+pub struct RodioBackend<T> {
+    sample_rate: Option<u32>,
+    slot: RodioSlot<T>,
+}
+
+impl<T> Default for RodioBackend<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RodioBackend<T> {
+    /// Creates a new rodio backend, using the sample rate of the queued samples.
+    pub fn new() -> Self {
+        Self {
+            sample_rate: None,
+            slot: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Output samples at `sample_rate` Hz, the player will resample if it's different
+    /// from the rate of the queued samples.
+    ///
+    /// rodio converts the rate of the source to the rate of its device anyway,
+    /// so this is only useful if the device rate is known.
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Returns the handle to take the [`RodioSource`] from, after the player is built.
+    pub fn source_handle(&self) -> RodioSourceHandle<T> {
+        RodioSourceHandle {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T: FloatSample + SizedSample + rodio::Sample + Send + 'static> OutputBackend<T>
+    for RodioBackend<T>
+{
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        Ok(OutputConfig {
+            sample_rate: self.sample_rate.unwrap_or(request.sample_rate),
+            sample_format: request.sample_format,
+            channels: request.channels,
+        })
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let playing = Arc::new(AtomicBool::new(false));
+        let period = PERIOD_FRAMES * config.channels as usize;
+        *self
+            .slot
+            .lock()
+            .map_err(|_| AudioPlayerError::DeviceNotAvailable)? = Some(RodioSource {
+            source,
+            config: *config,
+            playing: playing.clone(),
+            buffer: vec![T::EQUILIBRIUM; period],
+            position: period,
+        });
+        Ok(Box::new(RodioStream { playing }))
+    }
+}
+
+/// Gives the [`RodioSource`] of a [`RodioBackend`], see [`RodioBackend::source_handle`].
+#[derive(Clone)]
+pub struct RodioSourceHandle<T> {
+    slot: RodioSlot<T>,
+}
+
+impl<T> RodioSourceHandle<T> {
+    /// Take the source, returns `None` if the player wasn't built yet, or the source was already taken.
+    pub fn take(&self) -> Option<RodioSource<T>> {
+        self.slot.lock().ok()?.take()
+    }
+}
+
+/// The output of a player as an infinite [`rodio::Source`], see [`RodioBackend`].
+pub struct RodioSource<T> {
+    source: OutputSource<T>,
+    config: OutputConfig,
+    playing: Arc<AtomicBool>,
+    buffer: Vec<T>,
+    position: usize,
+}
+
+impl<T: FloatSample + SizedSample> Iterator for RodioSource<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.position == self.buffer.len() {
+            if self.playing.load(Ordering::Acquire) {
+                self.source.fill(&mut self.buffer);
+            } else {
+                self.buffer.fill(T::EQUILIBRIUM);
+            }
+            self.position = 0;
+        }
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<T: FloatSample + SizedSample + rodio::Sample> rodio::Source for RodioSource<T> {
+    fn current_frame_len(&self) -> Option<usize> {
+        // the parameters never change
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.config.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+struct RodioStream {
+    playing: Arc<AtomicBool>,
+}
+
+impl OutputStream for RodioStream {
+    fn play(&self) -> Result<(), PlayError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
@@ -0,0 +1,888 @@
+use std::{
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::{FromSample, SizedSample};
+use ringbuf::{
+    traits::{Consumer, Observer, Producer},
+    HeapCons, HeapProd,
+};
+
+use crate::{
+    convert,
+    crossfade::CrossfadeSource,
+    downmix,
+    drift::{self, DriftEstimator},
+    effects::{AudioEffect, Dither, Ditherer, SoftLimiter},
+    engine::{EngineInput, EngineInputs, SyncGate},
+    error::CallbackError,
+    mixer::TrackSource,
+    rewind::RewindHistory,
+    sample::FloatSample,
+    schedule::StartTime,
+    state::PlayerShared,
+    timestamps::CallbackTimestamp,
+    underrun::{self, UnderrunFill, UnderrunFills},
+    volume::{Ducker, VolumeFade, VolumeRamp},
+};
+
+use super::OutputConfig;
+#[cfg(feature = "debug-rt-checks")]
+use crate::rt_checks::{AllocPermit, RealtimeSection};
+
+/// The duration of the fade when pausing with [`PauseMode::FadeAndRetain`](crate::PauseMode::FadeAndRetain)
+const PAUSE_FADE_SECONDS: f32 = 0.01;
+
+/// The duration of the fades at the ends of the stepped frames, see [`AudioPlayer::set_frame_stepping`](crate::AudioPlayer::set_frame_stepping)
+const STEP_RAMP_SECONDS: f32 = 0.002;
+
+/// The duration of the fades to and from the silence of [`AudioPlayerBuilder::silence_padding`](crate::AudioPlayerBuilder::silence_padding)
+const PADDING_RAMP_SECONDS: f32 = 0.005;
+
+/// The time the callback load is averaged over, see [`AudioPlayer::callback_load`](crate::AudioPlayer::callback_load)
+const LOAD_WINDOW_SECONDS: f32 = 0.5;
+
+/// The largest difference of the measured consumption rate from the nominal rate, as a fraction of it,
+/// larger ones come from broken callback timings, not from the system resampling
+const MAX_RATE_MISMATCH: f64 = 0.5;
+
+/// Commands sent from the player to the output side, processed at the start of each fill
+pub(crate) enum SourceCommand<T> {
+    /// Copy every played sample into this buffer, or stop copying if `None`
+    #[cfg_attr(not(feature = "wav"), allow(dead_code))]
+    SetTap(Option<HeapProd<T>>),
+    /// Mix the samples of this track into the output, until its [`Track`](crate::Track) is dropped and emptied
+    AddTrack(TrackSource<T>),
+    /// Add an effect at the end of the effect chain
+    AddEffect(Box<dyn AudioEffect<T>>),
+    /// Remove all the effects
+    ClearEffects,
+    /// Play silence until the start time, then the queued samples
+    ScheduleStart(StartTime),
+    /// Play the rewind history backwards instead of the queued samples
+    SetRewinding(bool),
+    /// Fade out these samples over the next samples, they were removed from the buffer
+    /// by a clear, or are the old stream of a crossfade
+    Crossfade(CrossfadeSource<T>),
+    /// Play these samples until they end and the producer is dropped, then the queued samples
+    SwapSource(HeapCons<T>),
+    /// Mix the players attached to an [`AudioEngine`](crate::AudioEngine) into the output
+    AttachInputs(EngineInputs<T>),
+    /// Play silence until the players of the [`SyncGroup`](crate::SyncGroup) start together
+    SyncStart(SyncGate),
+    /// Lower the queued samples to the gain while one-shot clips play, then restore them
+    Duck {
+        gain: f32,
+        attack_frames: u64,
+        release_frames: u64,
+    },
+}
+
+/// The consumer of the player's buffer, the player locks it to read or reset the buffer
+pub(crate) type SharedConsumer<T> = Arc<Mutex<Box<dyn crate::ring::RingConsumer<T>>>>;
+
+/// The processing stages of the output, in the order they run
+pub(crate) struct OutputStages<T> {
+    pub effects: Vec<Box<dyn AudioEffect<T>>>,
+    pub limiter: Option<SoftLimiter>,
+    /// Observe the final output without changing it, e.g. meters
+    pub monitors: Vec<Box<dyn AudioEffect<T>>>,
+    /// Applied last, only when converting to integer formats
+    pub dither: Dither,
+    /// Records the queued samples to be played backwards
+    pub rewind: Option<RewindHistory<T>>,
+    pub underrun_fills: UnderrunFills,
+    /// The longest gap concealed when the buffer runs out
+    pub concealment: Duration,
+    /// The time without queueing after which the output pads with silence
+    pub padding: Option<Duration>,
+}
+
+/// The state of the output side of the player, this is moved into the output stream.
+pub(crate) struct SourceInner<T> {
+    buffer_consumer: SharedConsumer<T>,
+    shared: Arc<PlayerShared>,
+    commands: HeapCons<SourceCommand<T>>,
+    errors: HeapProd<CallbackError>,
+    tap: Option<HeapProd<T>>,
+    tracks: Vec<TrackSource<T>>,
+    // the players attached after the last fill, and the ones being mixed
+    pending_inputs: Option<EngineInputs<T>>,
+    inputs: Vec<EngineInput<T>>,
+    effects: Vec<Box<dyn AudioEffect<T>>>,
+    // always the last stage, after the user effects
+    limiter: Option<SoftLimiter>,
+    ditherer: Ditherer,
+    monitors: Vec<Box<dyn AudioEffect<T>>>,
+    rewind: Option<RewindHistory<T>>,
+    rewinding: bool,
+    // the buffer ran out, so we wait for the resume threshold before playing again
+    starved: bool,
+    // the samples fading out, and how many of them were played
+    crossfade: Option<CrossfadeSource<T>>,
+    // the samples of the source before `swap_source`, played before the buffer
+    previous: Option<HeapCons<T>>,
+    config: OutputConfig,
+    drift: DriftEstimator,
+    // the rate the device consumes the samples, measured from the times of the callbacks
+    consumption: DriftEstimator,
+    // the time the samples of the current fill will be played, if reported by the backend
+    playback_time: Option<Instant>,
+    // the frames filled so far
+    output_frames: u64,
+    // when the last fill started, for the callback stats
+    last_fill_time: Option<Instant>,
+    // the rolling time spent filling, relative to the duration of the fills
+    callback_load: f32,
+    // frames of silence to play before the queued samples, from a scheduled start
+    start_delay: u64,
+    // the start of the sync group this player waits for
+    sync_gate: Option<SyncGate>,
+    // the gain of the fade when pausing with a fade, and its change per frame
+    pause_gain: f32,
+    pause_step: f32,
+    volume: VolumeRamp,
+    // the bits of the last volume fade started, see `PlayerShared::volume_fade`
+    volume_fade: u64,
+    ducker: Ducker,
+    // the gain of the left and right channels, moving to zero when muted
+    channel_gains: [f32; 2],
+    // the stereo width, moving to the one set in the player
+    stereo_width: f32,
+    underrun_fills: UnderrunFills,
+    concealment: Duration,
+    padding: Option<Duration>,
+    // the frames played since the player last queued samples, and the queue count seen then
+    idle_frames: u64,
+    seen_queues: u64,
+    // nothing was queued for the `padding` time, so the output faded to silence and holds the buffer,
+    // and fades in again after the next queue
+    padding_silence: bool,
+    padding_fade_in: bool,
+    // the samples of the last fill that were missing from the buffer, filled with silence
+    missing: Range<usize>,
+    // the last played frame, held with `UnderrunFill::HoldLast`
+    last_frame: Vec<T>,
+    // used to convert the samples into other formats
+    scratch: Vec<T>,
+}
+
+impl<T: FloatSample + SizedSample> SourceInner<T> {
+    /// Switch to a new output configuration, when the player moves to another device
+    pub(crate) fn set_config(&mut self, config: OutputConfig) {
+        if config.sample_rate != self.config.sample_rate {
+            self.drift = DriftEstimator::new(config.sample_rate, drift::MAX_DRIFT);
+            self.consumption = DriftEstimator::new(config.sample_rate, MAX_RATE_MISMATCH);
+            self.shared.set_measured_rate(None);
+            self.shared.set_drift(1.0);
+            self.pause_step = 1.0 / (config.sample_rate as f32 * PAUSE_FADE_SECONDS);
+        }
+        self.config = config;
+    }
+
+    fn process_commands(&mut self) {
+        // the commands are rare, adding tracks and effects can grow their lists
+        #[cfg(feature = "debug-rt-checks")]
+        let _permit = AllocPermit::new();
+        while let Some(command) = self.commands.try_pop() {
+            match command {
+                SourceCommand::SetTap(tap) => self.tap = tap,
+                SourceCommand::AddTrack(track) => self.tracks.push(track),
+                SourceCommand::AddEffect(effect) => self.effects.push(effect),
+                SourceCommand::ClearEffects => self.effects.clear(),
+                SourceCommand::SetRewinding(rewinding) => self.rewinding = rewinding,
+                SourceCommand::Crossfade(crossfade) => self.crossfade = Some(crossfade),
+                SourceCommand::SwapSource(previous) => self.previous = Some(previous),
+                SourceCommand::ScheduleStart(start) => {
+                    self.start_delay = self.frames_until(start);
+                }
+                SourceCommand::AttachInputs(inputs) => self.pending_inputs = Some(inputs),
+                SourceCommand::SyncStart(gate) => self.sync_gate = Some(gate),
+                SourceCommand::Duck {
+                    gain,
+                    attack_frames,
+                    release_frames,
+                } => self.ducker.duck(gain, attack_frames, release_frames),
+            }
+        }
+    }
+
+    /// Start the volume fade requested last by the player, if it's a new one
+    fn update_volume(&mut self) {
+        let bits = self.shared.volume_fade();
+        if bits != self.volume_fade {
+            self.volume_fade = bits;
+            let fade = VolumeFade::from_bits(bits);
+            self.volume.fade_to(fade.target, fade.frames, fade.curve);
+        }
+    }
+
+    /// Fade in the new samples in `output`, while fading out the samples removed by the last clear
+    fn mix_crossfade(&mut self, output: &mut [T]) {
+        let Some(crossfade) = &mut self.crossfade else {
+            return;
+        };
+        if crossfade.mix_into(output, self.config.channels.max(1) as usize) {
+            self.crossfade = None;
+        }
+    }
+
+    /// The number of frames from the start of this fill until `start`
+    fn frames_until(&self, start: StartTime) -> u64 {
+        match start {
+            StartTime::AfterFrames(frames) => frames,
+            StartTime::At(instant) => {
+                let now = self.playback_time.unwrap_or_else(Instant::now);
+                let delay = instant.saturating_duration_since(now);
+                (delay.as_secs_f64() * self.config.sample_rate as f64).round() as u64
+            }
+        }
+    }
+
+    /// Fade the output out while pausing with a fade, or in after resuming
+    fn apply_pause_fade(&mut self, output: &mut [T]) {
+        let target = if self.shared.is_fading_out() {
+            0.0
+        } else {
+            1.0
+        };
+        if self.pause_gain == target {
+            return;
+        }
+        let channels = self.config.channels.max(1) as usize;
+        for frame in output.chunks_mut(channels) {
+            self.pause_gain += (target - self.pause_gain).clamp(-self.pause_step, self.pause_step);
+            for sample in frame {
+                *sample *= T::coerce(self.pause_gain);
+            }
+        }
+        self.shared.set_faded_out(self.pause_gain == 0.0);
+    }
+
+    /// Silence the muted channels, the gain moves over the pause fade duration to avoid clicks
+    fn apply_channel_mutes(&mut self, output: &mut [T]) {
+        let channels = self.config.channels.max(1) as usize;
+        for (channel, gain) in self.channel_gains.iter_mut().enumerate().take(channels) {
+            let target = if self.shared.is_channel_muted(channel) {
+                0.0
+            } else {
+                1.0
+            };
+            if *gain == 1.0 && target == 1.0 {
+                continue;
+            }
+            for sample in output.iter_mut().skip(channel).step_by(channels) {
+                *gain += (target - *gain).clamp(-self.pause_step, self.pause_step);
+                *sample *= T::coerce(*gain);
+            }
+        }
+    }
+
+    /// Scale the difference between the left and right channels by the stereo width,
+    /// the width moves at the speed of the pause fade to avoid clicks
+    fn apply_stereo_width(&mut self, output: &mut [T]) {
+        let target = self.shared.stereo_width();
+        let channels = self.config.channels.max(1) as usize;
+        if channels < 2 || (self.stereo_width == 1.0 && target == 1.0) {
+            return;
+        }
+        let half = T::coerce(0.5);
+        for frame in output.chunks_exact_mut(channels) {
+            self.stereo_width +=
+                (target - self.stereo_width).clamp(-self.pause_step, self.pause_step);
+            let mid = (frame[0] + frame[1]) * half;
+            let side = (frame[0] - frame[1]) * half * T::coerce(self.stereo_width);
+            frame[0] = mid + side;
+            frame[1] = mid - side;
+        }
+    }
+
+    /// Replace the silence of the missing samples with the last played samples,
+    /// if set with [`UnderrunFill::HoldLast`]
+    fn hold_missing(&mut self, output: &mut [T]) {
+        let channels = self.config.channels.max(1) as usize;
+        self.last_frame.resize(channels, T::EQUILIBRIUM);
+        if self.underrun_fills.get(self.config.sample_format) == UnderrunFill::HoldLast {
+            for i in self.missing.clone() {
+                // the samples filled by tracks or effects are kept
+                if output[i] == T::EQUILIBRIUM {
+                    output[i] = match i.checked_sub(channels) {
+                        Some(previous) => output[previous],
+                        None => self.last_frame[i],
+                    };
+                }
+            }
+        }
+        if let Some(frame) = output.rchunks_exact(channels).next() {
+            self.last_frame.copy_from_slice(frame);
+        }
+    }
+
+    /// Count the frames of this fill, and record them in the callback stats
+    fn record_stats(&mut self, samples: usize) {
+        let frames = (samples / self.config.channels.max(1) as usize) as u64;
+        self.output_frames += frames;
+        self.shared.add_frames_delivered(frames);
+        self.shared.add_fill();
+        let now = Instant::now();
+        let interval = self.last_fill_time.map(|last| now.duration_since(last));
+        self.last_fill_time = Some(now);
+        if let Some(ratio) = self.consumption.report(now) {
+            self.shared
+                .set_measured_rate(Some(ratio * self.config.sample_rate as f64));
+        }
+        self.consumption.filled(frames as usize);
+        let fill = self
+            .buffer_consumer
+            .try_lock()
+            .ok()
+            .map(|consumer| consumer.occupied_len() as u64);
+        self.shared.stats().record(frames, fill, interval);
+    }
+
+    /// Average the time `elapsed` filling `budget` of output into the callback load
+    fn record_load(&mut self, elapsed: Duration, budget: Duration) {
+        if budget.is_zero() {
+            return;
+        }
+        let load = elapsed.as_secs_f32() / budget.as_secs_f32();
+        let weight = (budget.as_secs_f32() / LOAD_WINDOW_SECONDS).min(1.0);
+        self.callback_load += (load - self.callback_load) * weight;
+        self.shared.set_callback_load(self.callback_load);
+    }
+
+    /// Fill the output with samples from the buffer, missing samples are filled with silence
+    fn fill(&mut self, output: &mut [T]) {
+        self.process_commands();
+        self.update_volume();
+        self.record_stats(output.len());
+
+        self.missing = 0..0;
+
+        if self.sync_gate.as_ref().is_some_and(SyncGate::is_open) {
+            self.sync_gate = None;
+        }
+        // paused with a fade that is done, or without stopping the stream,
+        // the buffer is kept until resumed, same while waiting for the sync group
+        if self.shared.is_holding()
+            || self.sync_gate.is_some()
+            || (self.pause_gain == 0.0 && self.shared.is_fading_out())
+        {
+            output.fill(T::EQUILIBRIUM);
+            self.drift
+                .filled(output.len() / self.config.channels.max(1) as usize);
+            return;
+        }
+
+        // only locked by the player for a short time to read or reset the buffer,
+        // so we play silence for this callback instead of waiting
+        output.fill(T::EQUILIBRIUM);
+
+        // a scheduled start begins in the middle of the output
+        let channels = self.config.channels.max(1) as usize;
+        let delay = self.start_delay.min((output.len() / channels) as u64);
+        self.start_delay -= delay;
+        let mut queued = &mut output[delay as usize * channels..];
+        if self.shared.is_fading_out() {
+            // only the samples faded out are played, the rest is kept for resuming
+            let fade_frames = (self.pause_gain / self.pause_step).ceil() as usize;
+            let len = queued.len().min(fade_frames * channels);
+            queued = &mut queued[..len];
+        }
+
+        // the source before `swap_source` plays until it ends,
+        // then the queued samples continue right after its last sample
+        let mut previous_len = 0;
+        let mut main_end = queued.len();
+        if let Some(previous) = &mut self.previous {
+            previous_len = previous.pop_slice(queued);
+            if previous.is_empty() && !previous.write_is_held() {
+                self.previous = None;
+            } else {
+                main_end = previous_len;
+            }
+        }
+        let main = &mut queued[previous_len..main_end];
+
+        let stepping = self.shared.is_frame_stepping();
+        let queue_count = self.shared.queue_count();
+        if queue_count != self.seen_queues {
+            self.seen_queues = queue_count;
+            self.idle_frames = 0;
+            self.padding_fade_in |= std::mem::take(&mut self.padding_silence);
+        }
+        let padding_ramp = (self.config.sample_rate as f32 * PADDING_RAMP_SECONDS) as usize;
+        let start_padding = !self.padding_silence
+            && !stepping
+            && self.padding.is_some_and(|padding| {
+                self.idle_frames >= (padding.as_secs_f64() * self.config.sample_rate as f64) as u64
+            });
+        let mut popped = 0;
+        if let Ok(mut consumer) = self.buffer_consumer.try_lock() {
+            if self.rewinding {
+                // the samples queued while rewinding are dropped, so we don't build up latency
+                let skipped = consumer.skip(main.len());
+                self.shared.add_frames_consumed((skipped / channels) as u64);
+                if let Some(rewind) = &mut self.rewind {
+                    popped = rewind.rewind_into(main);
+                }
+            } else if !self.padding_silence
+                && (!self.starved
+                    || stepping
+                    || consumer.occupied_len() >= self.shared.resume_threshold())
+            {
+                // only the fade to the silence is played, the rest is kept for the next queue
+                let len = if start_padding {
+                    main.len().min(padding_ramp * channels)
+                } else {
+                    main.len()
+                };
+                popped = consumer.pop_slice(&mut main[..len]);
+                self.shared.add_frames_consumed((popped / channels) as u64);
+                if popped > 0 {
+                    self.shared.notify_space();
+                }
+                if let Some(rewind) = &mut self.rewind {
+                    rewind.record(&main[..popped]);
+                }
+                if stepping {
+                    // the gaps between the steps are silent, so the steps start and stop without a click
+                    let ramp_frames = (self.config.sample_rate as f32 * STEP_RAMP_SECONDS) as usize;
+                    underrun::ramp_step(
+                        &mut main[..popped],
+                        channels,
+                        ramp_frames,
+                        self.starved,
+                        consumer.occupied_len() == 0,
+                    );
+                } else if start_padding || (self.padding_fade_in && popped > 0) {
+                    underrun::ramp_step(
+                        &mut main[..popped],
+                        channels,
+                        padding_ramp,
+                        std::mem::take(&mut self.padding_fade_in),
+                        start_padding,
+                    );
+                } else {
+                    let max_frames =
+                        (self.concealment.as_secs_f64() * self.config.sample_rate as f64) as usize;
+                    underrun::conceal(main, popped, channels, max_frames);
+                }
+                self.starved = popped < main.len();
+                self.padding_silence = start_padding;
+            }
+        } else if !main.is_empty() {
+            // dropped if the player didn't take the previous errors
+            let _ = self.errors.try_push(CallbackError::BufferBusy);
+        }
+        self.idle_frames += (main.len() / channels) as u64;
+        // the silence left after the popped samples, rounded to whole frames
+        let base = delay as usize * channels + previous_len;
+        let missing_start = (base + popped + channels - 1) / channels * channels;
+        self.missing = missing_start.min(base + main.len())..base + main.len();
+
+        let clip_frames = self
+            .tracks
+            .iter()
+            .map(TrackSource::oneshot_remaining)
+            .max()
+            .unwrap_or(0)
+            / channels;
+        self.ducker
+            .apply(queued, channels, clip_frames.saturating_sub(delay as usize));
+        let queued_len = queued.len();
+
+        self.mix_crossfade(output);
+
+        // the output is not silent as long as one of the tracks filled it
+        let mut filled = previous_len + popped == queued_len;
+        for track in &mut self.tracks {
+            filled |= track.mix_into(output) == output.len();
+        }
+        self.tracks.retain(|track| !track.is_finished());
+
+        if let Some(pending) = &self.pending_inputs {
+            // new players can wait for the next fill
+            if let Ok(mut pending) = pending.try_lock() {
+                self.inputs.append(&mut pending);
+            }
+        }
+        self.inputs.retain(|input| !input.is_detached());
+        for input in &mut self.inputs {
+            filled |= input.mix_into(output, &self.shared, self.playback_time);
+        }
+
+        // the silence between the steps, or padding the queue, is not an underrun
+        self.shared
+            .report_fill(!filled && !stepping && !self.padding_silence);
+        self.apply_pause_fade(output);
+        self.volume.apply(output, channels);
+        // widening raises the peaks, which the limiter catches
+        self.apply_stereo_width(output);
+
+        for effect in &mut self.effects {
+            effect.process(output, &self.config);
+        }
+        if let Some(limiter) = &mut self.limiter {
+            limiter.process(output, &self.config);
+        }
+        self.apply_channel_mutes(output);
+        self.hold_missing(output);
+        for monitor in &mut self.monitors {
+            monitor.process(output, &self.config);
+        }
+
+        if let Some(tap) = &mut self.tap {
+            tap.push_slice(output);
+        }
+
+        self.drift
+            .filled(output.len() / self.config.channels.max(1) as usize);
+    }
+}
+
+/// Reports the errors of an output stream to its player, from [`OutputSource::error_reporter`].
+///
+/// It can be cloned and moved into the error callback of the stream.
+#[derive(Clone)]
+pub struct StreamErrorReporter {
+    shared: Arc<PlayerShared>,
+}
+
+impl StreamErrorReporter {
+    /// Report an error of the stream that doesn't stop it,
+    /// to be read with [`AudioPlayer::take_errors`](crate::AudioPlayer::take_errors).
+    pub fn report_error(&self, error: CallbackError) {
+        self.shared.report_error(error);
+    }
+
+    /// Report that the stream stopped and can't play anymore, for example when the device was disconnected.
+    ///
+    /// The player closes the stream and handles it with its [`RecoveryPolicy`](crate::RecoveryPolicy).
+    pub fn report_stream_lost(&self) {
+        self.shared.set_stream_lost();
+    }
+}
+
+/// Where the [`SourceInner`] goes back to when the [`OutputSource`] is dropped,
+/// so that a new stream can be built with the same buffer.
+pub(crate) type SourceSlot<T> = Arc<Mutex<Option<SourceInner<T>>>>;
+
+/// The `OutputSource` is the output side of an [`AudioPlayer`](crate::AudioPlayer),
+/// it provides the queued samples (after resampling) to be played by an [`OutputBackend`](super::OutputBackend).
+///
+/// The samples are interleaved, with the number of channels negotiated by the backend.
+///
+/// When the `OutputSource` is dropped (for example with the stream that owns it),
+/// the samples that weren't played yet are kept in the player, and can be played by a new stream.
+pub struct OutputSource<T> {
+    inner: Option<SourceInner<T>>,
+    slot: SourceSlot<T>,
+    // the channels of the device, if they are not the negotiated ones
+    device_channels: Option<u16>,
+}
+
+impl<T: FloatSample + SizedSample> OutputSource<T> {
+    /// Creates the slot holding the output side of the player, use [`OutputSource::take`] to get the source
+    pub(crate) fn new_slot(
+        buffer_consumer: SharedConsumer<T>,
+        shared: Arc<PlayerShared>,
+        commands: HeapCons<SourceCommand<T>>,
+        errors: HeapProd<CallbackError>,
+        config: OutputConfig,
+        stages: OutputStages<T>,
+    ) -> SourceSlot<T> {
+        Arc::new(Mutex::new(Some(SourceInner {
+            buffer_consumer,
+            shared,
+            commands,
+            errors,
+            tap: None,
+            tracks: Vec::new(),
+            pending_inputs: None,
+            inputs: Vec::new(),
+            effects: stages.effects,
+            limiter: stages.limiter,
+            ditherer: Ditherer::new(stages.dither, config.channels as usize),
+            monitors: stages.monitors,
+            rewind: stages.rewind,
+            rewinding: false,
+            starved: true,
+            crossfade: None,
+            previous: None,
+            config,
+            drift: DriftEstimator::new(config.sample_rate, drift::MAX_DRIFT),
+            consumption: DriftEstimator::new(config.sample_rate, MAX_RATE_MISMATCH),
+            playback_time: None,
+            output_frames: 0,
+            last_fill_time: None,
+            callback_load: 0.0,
+            start_delay: 0,
+            sync_gate: None,
+            pause_gain: 1.0,
+            pause_step: 1.0 / (config.sample_rate as f32 * PAUSE_FADE_SECONDS),
+            volume: VolumeRamp::new(),
+            volume_fade: VolumeFade::set(1.0).to_bits(),
+            ducker: Ducker::new(),
+            channel_gains: [1.0; 2],
+            stereo_width: 1.0,
+            underrun_fills: stages.underrun_fills,
+            concealment: stages.concealment,
+            padding: stages.padding,
+            idle_frames: 0,
+            seen_queues: 0,
+            padding_silence: false,
+            padding_fade_in: false,
+            missing: 0..0,
+            last_frame: vec![T::EQUILIBRIUM; config.channels as usize],
+            scratch: Vec::new(),
+        })))
+    }
+
+    /// Take the source out of the slot, returns `None` if it's already in use by another stream
+    pub(crate) fn take(slot: &SourceSlot<T>) -> Option<Self> {
+        let inner = slot.lock().ok()?.take()?;
+        Some(Self {
+            inner: Some(inner),
+            slot: slot.clone(),
+            device_channels: None,
+        })
+    }
+
+    /// Returns the slot this source goes back to when dropped
+    pub(crate) fn slot(&self) -> &SourceSlot<T> {
+        &self.slot
+    }
+
+    fn inner(&mut self) -> &mut SourceInner<T> {
+        self.inner.as_mut().expect("Source is only taken on drop")
+    }
+
+    /// Returns a reporter for the errors of the stream playing this source, see [`StreamErrorReporter`].
+    pub fn error_reporter(&self) -> StreamErrorReporter {
+        let inner = self.inner.as_ref().expect("Source is only taken on drop");
+        StreamErrorReporter {
+            shared: inner.shared.clone(),
+        }
+    }
+
+    /// Report an error that happened while playing this source, without blocking,
+    /// to be read with [`AudioPlayer::take_errors`](crate::AudioPlayer::take_errors).
+    pub fn report_error(&mut self, error: CallbackError) {
+        // dropped if the player didn't take the previous errors
+        let _ = self.inner().errors.try_push(error);
+    }
+
+    /// The number of samples available to be played.
+    pub fn available(&self) -> usize {
+        #[cfg(feature = "debug-rt-checks")]
+        crate::rt_checks::assert_not_realtime("locking the buffer in `OutputSource::available`");
+        self.inner
+            .as_ref()
+            .and_then(|inner| inner.buffer_consumer.lock().ok())
+            .map_or(0, |consumer| consumer.occupied_len())
+    }
+
+    /// Report that the samples of the next [`fill`](Self::fill) will be played by the device at `time`.
+    ///
+    /// This is used to measure the drift of the device clock from its nominal sample rate,
+    /// see [`AudioPlayerBuilder::drift_compensation`](crate::AudioPlayerBuilder::drift_compensation),
+    /// and for [`StartTime::At`].
+    /// It's also available to the application with [`AudioPlayer::callback_timestamp`](crate::AudioPlayer::callback_timestamp).
+    /// Backends without playback timestamps don't need to call this.
+    pub fn report_playback_time(&mut self, time: Instant) {
+        let inner = self.inner();
+        inner.playback_time = Some(time);
+        inner.shared.set_callback_timestamp(CallbackTimestamp {
+            callback: Instant::now(),
+            playback: time,
+            frame: inner.output_frames,
+        });
+        let latency = time.saturating_duration_since(Instant::now());
+        inner
+            .shared
+            .set_device_latency((latency.as_secs_f64() * inner.config.sample_rate as f64) as u64);
+        if let Some(drift) = inner.drift.report(time) {
+            inner.shared.set_drift(drift);
+        }
+    }
+
+    /// Follow the clock of the engine output mixing this source, so all the players attached to the engine
+    /// share its drift, latency and playback times
+    pub(crate) fn follow_clock(&mut self, engine: &PlayerShared, playback_time: Option<Instant>) {
+        let inner = self.inner();
+        inner.playback_time = playback_time;
+        if let Some(time) = playback_time {
+            inner.shared.set_callback_timestamp(CallbackTimestamp {
+                callback: Instant::now(),
+                playback: time,
+                frame: inner.output_frames,
+            });
+        }
+        inner.shared.set_device_latency(engine.device_latency());
+        inner.shared.set_drift(engine.drift());
+    }
+
+    /// Report the time between filling the output and the device playing it, for backends that know
+    /// their latency without timestamps for each callback, see [`AudioPlayer::device_latency`](crate::AudioPlayer::device_latency).
+    ///
+    /// [`report_playback_time`](Self::report_playback_time) reports the latency too.
+    pub fn report_latency(&mut self, latency: Duration) {
+        let inner = self.inner();
+        inner
+            .shared
+            .set_device_latency((latency.as_secs_f64() * inner.config.sample_rate as f64) as u64);
+    }
+
+    /// Fill `output` with the next samples to be played.
+    ///
+    /// If there are not enough samples, the rest is filled with silence,
+    /// and the player goes into the [`PlayerState::Underrun`](crate::PlayerState::Underrun) state.
+    ///
+    /// If the processing panics, for example in an effect, the panic is caught and silence is played,
+    /// from then on, see [`AudioPlayer::has_panicked`](crate::AudioPlayer::has_panicked).
+    pub fn fill(&mut self, output: &mut [T]) {
+        let budget = self.duration_of(output.len(), false);
+        #[cfg(feature = "debug-rt-checks")]
+        let section = RealtimeSection::enter(budget);
+        self.catch_panic(output, budget, |source, output| source.inner().fill(output));
+        #[cfg(feature = "debug-rt-checks")]
+        section.finish();
+    }
+
+    /// The playback time of `samples` samples, in the channels of the device if `device` is `true`
+    fn duration_of(&mut self, samples: usize, device: bool) -> Duration {
+        let device_channels = self.device_channels.filter(|_| device);
+        let config = self.inner().config;
+        let channels = device_channels.unwrap_or(config.channels).max(1) as usize;
+        Duration::from_secs_f64((samples / channels) as f64 / config.sample_rate.max(1) as f64)
+    }
+
+    /// Run `fill` on `output`, or play silence if it panics now or panicked before,
+    /// a panic must not unwind into the audio thread of the backend.
+    ///
+    /// The time spent is recorded in the callback load, relative to the `budget` of the output.
+    fn catch_panic<S: SizedSample>(
+        &mut self,
+        output: &mut [S],
+        budget: Duration,
+        fill: impl FnOnce(&mut Self, &mut [S]),
+    ) {
+        if self.inner().shared.has_panicked() {
+            output.fill(S::EQUILIBRIUM);
+            return;
+        }
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| fill(self, output)));
+        self.inner().record_load(start.elapsed(), budget);
+        if let Err(payload) = result {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            output.fill(S::EQUILIBRIUM);
+            let inner = self.inner();
+            inner.shared.set_panicked();
+            let _ = inner.errors.try_push(CallbackError::Panic(message));
+            // the panic allocated its message and took its time, it's reported as an error instead
+            #[cfg(feature = "debug-rt-checks")]
+            crate::rt_checks::ignore_panicked();
+        }
+    }
+
+    /// Play on a device with `channels` channels in [`fill_converted`](Self::fill_converted),
+    /// when the device doesn't support the negotiated channels.
+    ///
+    /// The stereo samples of the player are mixed to one channel for mono devices,
+    /// and played on the first two channels otherwise, the other channels are silent.
+    pub fn set_device_channels(&mut self, channels: u16) {
+        self.device_channels = Some(channels);
+    }
+
+    /// Same as [`fill`](Self::fill), but converts the samples into the sample type `S`.
+    ///
+    /// If `S` is an integer format, the [`Dither`] configured in the player is applied before the conversion.
+    pub fn fill_converted<S>(&mut self, output: &mut [S])
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let budget = self.duration_of(output.len(), true);
+        #[cfg(feature = "debug-rt-checks")]
+        let section = RealtimeSection::enter(budget);
+        self.catch_panic(output, budget, Self::convert_into);
+        #[cfg(feature = "debug-rt-checks")]
+        section.finish();
+    }
+
+    /// Same as [`fill_converted`](Self::fill_converted), for the renders that don't run in an audio callback,
+    /// so they are not checked for real-time hazards.
+    pub(crate) fn fill_converted_offline<S>(&mut self, output: &mut [S])
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let budget = self.duration_of(output.len(), true);
+        self.catch_panic(output, budget, Self::convert_into);
+    }
+
+    fn convert_into<S>(&mut self, output: &mut [S])
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let device_channels = self.device_channels;
+        let inner = self.inner();
+        let channels = inner.config.channels.max(1) as usize;
+        let device_channels = device_channels.map_or(channels, |channels| channels.max(1) as usize);
+
+        let mut scratch = std::mem::take(&mut inner.scratch);
+        // only grows when the callback size changes
+        #[cfg(feature = "debug-rt-checks")]
+        let permit = AllocPermit::new();
+        scratch.resize(output.len() / device_channels * channels, T::EQUILIBRIUM);
+        #[cfg(feature = "debug-rt-checks")]
+        drop(permit);
+        inner.fill(&mut scratch);
+        // the missing samples are not dithered, so the silence stays at the midpoint
+        let missing = inner.missing.start.min(scratch.len())..inner.missing.end.min(scratch.len());
+        if !S::FORMAT.is_float() {
+            let bits = S::FORMAT.sample_size() as u32 * 8;
+            inner.ditherer.apply(&mut scratch[..missing.start], bits);
+            inner.ditherer.apply(&mut scratch[missing.end..], bits);
+        }
+        let midpoint = inner.underrun_fills.get(S::FORMAT) == UnderrunFill::Midpoint;
+        let is_silence =
+            |i: usize| midpoint && missing.contains(&i) && scratch[i] == T::EQUILIBRIUM;
+        if device_channels == channels {
+            convert::convert_samples(&scratch, output);
+            for i in missing.clone().filter(|&i| is_silence(i)) {
+                output[i] = S::EQUILIBRIUM;
+            }
+        } else {
+            for (i, (out, frame)) in output
+                .chunks_exact_mut(device_channels)
+                .zip(scratch.chunks_exact(channels))
+                .enumerate()
+            {
+                if (i * channels..(i + 1) * channels).all(is_silence) {
+                    out.fill(S::EQUILIBRIUM);
+                } else {
+                    downmix::remap_frame(frame, out);
+                }
+            }
+        }
+        inner.scratch = scratch;
+    }
+}
+
+impl<T> Drop for OutputSource<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            if let Ok(mut slot) = self.slot.lock() {
+                *slot = Some(inner);
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use super::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream, Pacing};
+use crate::error::{AudioPlayerError, CallbackError, PlayError};
+#[cfg(feature = "opus")]
+use crate::opus::{opus_config, OpusEncoder, OpusSettings};
+use crate::sample::FloatSample;
+
+/// The number of frames sent in each packet, 5ms at 48000Hz,
+/// the stereo payload of 960 bytes fits in the usual MTU
+const PACKET_FRAMES: usize = 240;
+
+/// The size of the RTP header, without CSRC or extensions
+const RTP_HEADER_LEN: usize = 12;
+
+/// The dynamic payload type used when the configuration doesn't have a static one
+const DYNAMIC_PAYLOAD_TYPE: u8 = 96;
+
+/// A backend that streams the output over the network with UDP, instead of playing it.
+///
+/// This allows a headless machine to send its audio to a client, the samples are sent as
+/// 16-bit signed big-endian PCM (`L16`), in packets of 240 frames.
+/// By default, each packet is an RTP packet (RFC 3550), so the stream can be played by
+/// RTP clients, like `ffplay` or GStreamer's `rtpL16depay`, with [`raw`](Self::raw) only the samples are sent.
+///
+/// The payload type is `10` for stereo at 44100Hz as defined in RFC 3551, and `96` (dynamic) otherwise,
+/// so the client needs to be told the sample rate and channels, for example with an SDP file.
+///
+/// With the `opus` feature, the samples can be encoded with Opus instead, see [`opus`](Self::opus),
+/// which needs a fraction of the bandwidth of `L16`.
+///
+/// Sending is done from a separate thread, errors are printed and the packet is dropped.
+///
+/// # Example
+/// ```rust
+/// # use std::net::UdpSocket;
+/// # use dynwave::{AudioPlayer, backend::{Pacing, UdpBackend}};
+/// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+///
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(UdpBackend::new(client.local_addr().unwrap()).pacing(Pacing::AsFastAsPossible))
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+/// player.queue(&[0.5; 240 * 2]);
+///
+/// let mut packet = [0; 1500];
+/// let len = client.recv(&mut packet).unwrap();
+/// // RTP header, then 240 stereo frames of 16-bit samples
+/// assert_eq!(len, 12 + 240 * 2 * 2);
+/// assert_eq!(packet[0], 0x80);
+/// assert_eq!(i16::from_be_bytes([packet[12], packet[13]]), 16384);
+/// ```
+pub struct UdpBackend {
+    address: io::Result<SocketAddr>,
+    sample_rate: Option<u32>,
+    pacing: Pacing,
+    rtp: bool,
+    #[cfg(feature = "opus")]
+    opus: Option<OpusSettings>,
+}
+
+impl UdpBackend {
+    /// Creates a backend sending to `address`, the address is resolved now, and any error is
+    /// returned when the player is built.
+    pub fn new(address: impl ToSocketAddrs) -> Self {
+        let address = address.to_socket_addrs().and_then(|mut addresses| {
+            addresses
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))
+        });
+        Self {
+            address,
+            sample_rate: None,
+            pacing: Pacing::RealTime,
+            rtp: true,
+            #[cfg(feature = "opus")]
+            opus: None,
+        }
+    }
+
+    /// Send the samples with this sample rate, the player will resample if it's different from
+    /// the rate of the queued samples. By default, the rate of the queued samples is used.
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Set how fast the samples are sent, see [`Pacing`].
+    ///
+    /// With [`Pacing::RealTime`], silence is sent when the buffer is empty, so the client
+    /// receives a continuous stream.
+    pub fn pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Send only the samples in each packet, without the RTP header.
+    pub fn raw(mut self) -> Self {
+        self.rtp = false;
+        self
+    }
+
+    /// Encode each packet with Opus, at `bitrate` bits per second, or the default of libopus if `None`,
+    /// about 120kbit/s for stereo, instead of the 1.5Mbit/s of `L16`.
+    ///
+    /// Opus streams are played at 48000Hz, so the player resamples other rates, and setting
+    /// another [`sample_rate`](Self::sample_rate) fails with [`AudioPlayerError::SampleRateNotSupported`].
+    /// Only mono and stereo can be encoded, the player maps its output to stereo.
+    /// The RTP packets follow RFC 7587 with the dynamic payload type `96`,
+    /// and can be played by `UdpReceiver::opus` with the `udp-receiver` feature.
+    ///
+    /// With [`Pacing::AsFastAsPossible`], only whole packets are sent, the samples that don't fill one
+    /// wait for the next ones.
+    ///
+    /// Only available with the `opus` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::net::UdpSocket;
+    /// # use dynwave::{AudioPlayer, backend::{Pacing, UdpBackend}};
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    ///
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(
+    ///         UdpBackend::new(client.local_addr().unwrap())
+    ///             .opus(Some(64000))
+    ///             .pacing(Pacing::AsFastAsPossible),
+    ///     )
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    /// player.queue(&[0.5; 240 * 2]);
+    ///
+    /// let mut packet = [0; 1500];
+    /// let len = client.recv(&mut packet).unwrap();
+    /// // RTP header with the dynamic payload type, then a 5ms Opus packet
+    /// assert_eq!(packet[1], 96);
+    /// assert!(len < 12 + 240 * 2 * 2);
+    /// ```
+    #[cfg(feature = "opus")]
+    pub fn opus(mut self, bitrate: Option<u32>) -> Self {
+        self.opus = Some(OpusSettings { bitrate });
+        self
+    }
+}
+
+impl<T> OutputBackend<T> for UdpBackend
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    i16: FromSample<T>,
+    f32: FromSample<T>,
+{
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        #[cfg(feature = "opus")]
+        if self.opus.is_some() {
+            return opus_config(self.sample_rate, request);
+        }
+        Ok(OutputConfig {
+            sample_rate: self.sample_rate.unwrap_or(request.sample_rate),
+            sample_format: SampleFormat::I16,
+            channels: request.channels,
+        })
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        mut source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let address = match &self.address {
+            Ok(address) => *address,
+            Err(err) => return Err(io::Error::new(err.kind(), err.to_string()).into()),
+        };
+        let local: SocketAddr = if address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let channels = config.channels as usize;
+        let period_samples = PACKET_FRAMES * channels;
+        let period = Duration::from_secs(PACKET_FRAMES as u64) / config.sample_rate;
+        let pacing = self.pacing;
+        let mut payload = Payload::L16;
+        #[cfg(feature = "opus")]
+        if let Some(opus) = self.opus {
+            payload = Payload::Opus(OpusEncoder::new(config.channels, opus.bitrate)?);
+        }
+        let mut packetizer = self.rtp.then(|| RtpPacketizer::new(config, &payload));
+
+        let thread = {
+            let playing = playing.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut buffer = vec![T::EQUILIBRIUM; period_samples];
+                let mut packet = Vec::with_capacity(RTP_HEADER_LEN + period_samples * 2);
+                let mut next_period = Instant::now();
+
+                while !stop.load(Ordering::Acquire) {
+                    if !playing.load(Ordering::Acquire) {
+                        std::thread::sleep(Duration::from_millis(1));
+                        next_period = Instant::now();
+                        continue;
+                    }
+
+                    let len = match pacing {
+                        Pacing::RealTime => {
+                            let now = Instant::now();
+                            if now < next_period {
+                                std::thread::sleep(next_period - now);
+                            }
+                            next_period += period;
+                            period_samples
+                        }
+                        Pacing::AsFastAsPossible => {
+                            // only whole frames that are available, no silence is sent
+                            let available = source.available().min(period_samples);
+                            let available = available - available % channels;
+                            if available == 0
+                                || (payload.whole_periods() && available < period_samples)
+                            {
+                                std::thread::sleep(Duration::from_millis(1));
+                                continue;
+                            }
+                            available
+                        }
+                    };
+
+                    source.fill(&mut buffer[..len]);
+
+                    packet.clear();
+                    if let Some(packetizer) = &mut packetizer {
+                        packetizer.write_header(&mut packet, len / channels);
+                    }
+                    if let Err(err) = payload.write(&buffer[..len], &mut packet) {
+                        source.report_error(CallbackError::Stream(format!(
+                            "an error occurred while encoding audio: {}",
+                            err
+                        )));
+                        continue;
+                    }
+
+                    match socket.send(&packet) {
+                        // nobody is listening yet, the packet is just lost
+                        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {}
+                        Err(err) => source.report_error(CallbackError::Stream(format!(
+                            "an error occurred while sending audio: {}",
+                            err
+                        ))),
+                        Ok(_) => {}
+                    }
+                }
+            })
+        };
+
+        Ok(Box::new(UdpStream {
+            playing,
+            stop,
+            thread: Some(thread),
+        }))
+    }
+}
+
+/// The encoding of the samples in the packets
+enum Payload {
+    /// 16-bit signed big-endian PCM
+    L16,
+    #[cfg(feature = "opus")]
+    Opus(OpusEncoder),
+}
+
+impl Payload {
+    /// Whether only whole packets can be encoded
+    fn whole_periods(&self) -> bool {
+        !matches!(self, Self::L16)
+    }
+
+    /// Append the encoded `samples` to `packet`
+    fn write<T>(&mut self, samples: &[T], packet: &mut Vec<u8>) -> Result<(), String>
+    where
+        T: FloatSample,
+        i16: FromSample<T>,
+        f32: FromSample<T>,
+    {
+        match self {
+            Self::L16 => {
+                for &sample in samples {
+                    packet.extend_from_slice(&i16::from_sample_(sample).to_be_bytes());
+                }
+            }
+            #[cfg(feature = "opus")]
+            Self::Opus(encoder) => {
+                packet.extend_from_slice(encoder.encode(samples).map_err(|err| err.to_string())?)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the RTP headers of the packets of one stream
+struct RtpPacketizer {
+    payload_type: u8,
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+}
+
+impl RtpPacketizer {
+    fn new(config: &OutputConfig, payload: &Payload) -> Self {
+        let payload_type = match (payload, config.sample_rate, config.channels) {
+            (Payload::L16, 44100, 2) => 10,
+            (Payload::L16, 44100, 1) => 11,
+            _ => DYNAMIC_PAYLOAD_TYPE,
+        };
+        // should be random to identify the stream, the time is random enough here
+        let ssrc = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos() ^ time.as_secs() as u32);
+
+        Self {
+            payload_type,
+            sequence: 0,
+            timestamp: 0,
+            ssrc,
+        }
+    }
+
+    /// Write the header of the next packet with `frames` frames into `packet`
+    fn write_header(&mut self, packet: &mut Vec<u8>, frames: usize) {
+        // version 2, no padding, extension or CSRC
+        packet.push(0x80);
+        // no marker
+        packet.push(self.payload_type);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add(frames as u32);
+    }
+}
+
+struct UdpStream {
+    playing: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OutputStream for UdpStream {
+    fn play(&self) -> Result<(), PlayError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for UdpStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use super::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream, Pacing};
+use crate::{
+    error::{AudioPlayerError, CallbackError, PlayError},
+    sample::FloatSample,
+    wav::{wav_format_for, WavFileWriter, WAV_FORMATS},
+};
+
+// the duration of audio written in each iteration, like the callback of a device
+const PERIOD: Duration = Duration::from_millis(10);
+
+/// A backend that writes the output into a WAV file instead of playing it.
+///
+/// The samples written are exactly what would have been sent to a device (after resampling and
+/// format conversion), which is useful for regression-testing the audio output.
+///
+/// Writing is done from a separate thread, the file is finalized when the player is dropped.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{AudioPlayer, backend::{Pacing, WavBackend}};
+/// let mut player = AudioPlayer::<f32>::builder(44100)
+///     .backend(WavBackend::new("output.wav").pacing(Pacing::AsFastAsPossible))
+///     .build()
+///     .unwrap();
+///
+/// player.play().unwrap();
+/// player.queue(&[0.0; 44100 * 2]);
+/// ```
+pub struct WavBackend {
+    path: PathBuf,
+    sample_rate: Option<u32>,
+    sample_format: Option<SampleFormat>,
+    pacing: Pacing,
+}
+
+impl WavBackend {
+    /// Creates a backend writing into the file at `path`, the file is created (or truncated)
+    /// when the player is built.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            sample_rate: None,
+            sample_format: None,
+            pacing: Pacing::RealTime,
+        }
+    }
+
+    /// Write the file with this sample rate, the player will resample if it's different from
+    /// the rate of the queued samples. By default, the rate of the queued samples is used.
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Write the samples in this format, only `I8`, `I16`, `I32`, and `F32` are supported.
+    ///
+    /// By default, the format of the queued samples is used if supported, `F32` for `F64`, and `I16` otherwise.
+    pub fn sample_format(mut self, sample_format: SampleFormat) -> Self {
+        self.sample_format = Some(sample_format);
+        self
+    }
+
+    /// Set how fast the samples are consumed, see [`Pacing`].
+    pub fn pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+}
+
+impl<T> OutputBackend<T> for WavBackend
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    f32: FromSample<T>,
+{
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        let sample_format = match self.sample_format {
+            Some(format) if WAV_FORMATS.contains(&format) => format,
+            Some(_) => return Err(AudioPlayerError::StreamConfigNotSupported),
+            None => wav_format_for(request.sample_format),
+        };
+
+        Ok(OutputConfig {
+            sample_rate: self.sample_rate.unwrap_or(request.sample_rate),
+            sample_format,
+            channels: request.channels,
+        })
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        mut source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let mut writer = WavFileWriter::create(
+            &self.path,
+            config.sample_rate,
+            config.channels,
+            config.sample_format,
+        )?;
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let channels = config.channels as usize;
+        let period_samples =
+            (config.sample_rate as usize * PERIOD.as_millis() as usize / 1000) * channels;
+        let pacing = self.pacing;
+
+        let thread = {
+            let playing = playing.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut buffer = vec![T::EQUILIBRIUM; period_samples];
+                let mut next_period = Instant::now();
+
+                while !stop.load(Ordering::Acquire) {
+                    if !playing.load(Ordering::Acquire) {
+                        std::thread::sleep(Duration::from_millis(1));
+                        next_period = Instant::now();
+                        continue;
+                    }
+
+                    let len = match pacing {
+                        Pacing::RealTime => {
+                            let now = Instant::now();
+                            if now < next_period {
+                                std::thread::sleep(next_period - now);
+                            }
+                            next_period += PERIOD;
+                            period_samples
+                        }
+                        Pacing::AsFastAsPossible => {
+                            // only whole frames that are available, no silence is written
+                            let available = source.available().min(period_samples);
+                            let available = available - available % channels;
+                            if available == 0 {
+                                std::thread::sleep(Duration::from_millis(1));
+                                continue;
+                            }
+                            available
+                        }
+                    };
+
+                    source.fill(&mut buffer[..len]);
+                    if let Err(err) = writer.write(&buffer[..len]) {
+                        source.report_error(CallbackError::Stream(format!(
+                            "an error occurred while writing the WAV file: {}",
+                            err
+                        )));
+                        break;
+                    }
+                }
+
+                if let Err(err) = writer.finalize() {
+                    source.report_error(CallbackError::Stream(format!(
+                        "an error occurred while finalizing the WAV file: {}",
+                        err
+                    )));
+                }
+            })
+        };
+
+        Ok(Box::new(WavStream {
+            playing,
+            stop,
+            thread: Some(thread),
+        }))
+    }
+}
+
+struct WavStream {
+    playing: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OutputStream for WavStream {
+    fn play(&self) -> Result<(), PlayError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for WavStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+use tungstenite::{protocol::WebSocketConfig, Message, WebSocket};
+
+use super::{OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream, Pacing};
+use crate::error::{AudioPlayerError, CallbackError, PlayError};
+#[cfg(feature = "opus")]
+use crate::opus::{opus_config, OpusEncoder, OpusSettings};
+use crate::sample::FloatSample;
+
+// the duration of audio sent in each message, like the callback of a device
+const PERIOD: Duration = Duration::from_millis(10);
+
+// the data waiting to be sent to a client, more than that and the client is too slow and disconnected
+const MAX_CLIENT_BUFFER: usize = 1024 * 1024;
+
+type Client = WebSocket<TcpStream>;
+
+/// A backend that streams the output to WebSocket clients, for remote play in a browser for example.
+///
+/// The backend listens for connections on the address given to [`bind`](Self::bind),
+/// and sends to every client:
+/// - A text message with the configuration of the stream when it connects,
+///   like `{"sample_rate":48000,"channels":2,"format":"f32"}`.
+/// - A binary message for every 10ms of audio, with the interleaved samples in little-endian,
+///   either 32-bit floats (`"f32"`) or 16-bit signed integers (`"i16"`), see [`sample_format`](Self::sample_format).
+///   Or with the `opus` feature, one Opus packet (`"opus"`), see [`opus`](Self::opus).
+///
+/// The messages are sent from a separate thread, at the rate of the output with [`Pacing::RealTime`],
+/// like an audio device, silence is sent when the buffer is empty so clients stay in sync.
+/// Clients that can't keep up are disconnected.
+///
+/// Only available with the `websocket` feature.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{AudioPlayer, backend::{Pacing, WebSocketBackend}};
+/// let backend = WebSocketBackend::bind("127.0.0.1:0")
+///     .unwrap()
+///     .pacing(Pacing::AsFastAsPossible);
+/// let address = backend.local_addr().unwrap();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+///
+/// // a client, like a browser
+/// let (mut client, _) = tungstenite::connect(format!("ws://{}", address)).unwrap();
+/// let config = client.read().unwrap().into_text().unwrap();
+/// assert_eq!(config, r#"{"sample_rate":48000,"channels":2,"format":"f32"}"#);
+///
+/// player.queue(&[0.5; 480 * 2]);
+/// let samples = client.read().unwrap().into_data();
+/// assert_eq!(samples.len(), 480 * 2 * 4);
+/// assert_eq!(samples[..4], 0.5f32.to_le_bytes());
+/// ```
+pub struct WebSocketBackend {
+    listener: Option<TcpListener>,
+    sample_rate: Option<u32>,
+    sample_format: SampleFormat,
+    pacing: Pacing,
+    #[cfg(feature = "opus")]
+    opus: Option<OpusSettings>,
+}
+
+impl WebSocketBackend {
+    /// Creates a backend listening for WebSocket connections on `address`.
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: Some(TcpListener::bind(address)?),
+            sample_rate: None,
+            sample_format: SampleFormat::F32,
+            pacing: Pacing::RealTime,
+            #[cfg(feature = "opus")]
+            opus: None,
+        })
+    }
+
+    /// The address the backend is listening on, useful when binding to port `0`.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.listener {
+            Some(listener) => listener.local_addr(),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the listener is used by the player",
+            )),
+        }
+    }
+
+    /// Send the samples with this sample rate, the player will resample if it's different from
+    /// the rate of the queued samples. By default, the rate of the queued samples is used.
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Send the samples in this format, only `F32` (the default) and `I16` are supported.
+    pub fn sample_format(mut self, sample_format: SampleFormat) -> Self {
+        self.sample_format = sample_format;
+        self
+    }
+
+    /// Set how fast the samples are sent, see [`Pacing`].
+    pub fn pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Encode each message with Opus, at `bitrate` bits per second, or the default of libopus if `None`,
+    /// the format of the configuration is `"opus"`, and each binary message is one 10ms Opus packet,
+    /// that can be decoded with WebCodecs' `AudioDecoder` in a browser.
+    ///
+    /// Opus streams are sent at 48000Hz, so the player resamples other rates, and setting
+    /// another [`sample_rate`](Self::sample_rate) fails with [`AudioPlayerError::SampleRateNotSupported`].
+    /// Only mono and stereo can be encoded, the player maps its output to stereo.
+    /// The [`sample_format`](Self::sample_format) is ignored.
+    ///
+    /// With [`Pacing::AsFastAsPossible`], only whole messages are sent, the samples that don't fill one
+    /// wait for the next ones.
+    ///
+    /// Only available with the `opus` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{AudioPlayer, backend::{Pacing, WebSocketBackend}};
+    /// let backend = WebSocketBackend::bind("127.0.0.1:0")
+    ///     .unwrap()
+    ///     .opus(None)
+    ///     .pacing(Pacing::AsFastAsPossible);
+    /// let address = backend.local_addr().unwrap();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// let (mut client, _) = tungstenite::connect(format!("ws://{}", address)).unwrap();
+    /// let config = client.read().unwrap().into_text().unwrap();
+    /// assert_eq!(config, r#"{"sample_rate":48000,"channels":2,"format":"opus"}"#);
+    ///
+    /// player.queue(&[0.5; 480 * 2]);
+    /// let packet = client.read().unwrap().into_data();
+    /// assert!(packet.len() < 480 * 2 * 2);
+    /// ```
+    #[cfg(feature = "opus")]
+    pub fn opus(mut self, bitrate: Option<u32>) -> Self {
+        self.opus = Some(OpusSettings { bitrate });
+        self
+    }
+}
+
+impl<T> OutputBackend<T> for WebSocketBackend
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    i16: FromSample<T>,
+    f32: FromSample<T>,
+{
+    fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        #[cfg(feature = "opus")]
+        if self.opus.is_some() {
+            return opus_config(self.sample_rate, request);
+        }
+        if !matches!(self.sample_format, SampleFormat::F32 | SampleFormat::I16) {
+            return Err(AudioPlayerError::StreamConfigNotSupported);
+        }
+
+        Ok(OutputConfig {
+            sample_rate: self.sample_rate.unwrap_or(request.sample_rate),
+            sample_format: self.sample_format,
+            channels: request.channels,
+        })
+    }
+
+    fn build_stream(
+        &mut self,
+        config: &OutputConfig,
+        mut source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let listener = self
+            .listener
+            .take()
+            .ok_or(AudioPlayerError::DeviceNotAvailable)?;
+        listener.set_nonblocking(true)?;
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        // clients that finished the handshake, but didn't get the configuration yet
+        let pending = Arc::new(Mutex::new(Vec::new()));
+
+        let acceptor = {
+            let stop = stop.clone();
+            let reporter = source.error_reporter();
+            let pending = pending.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Acquire) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Some(client) = handshake(stream) {
+                                pending.lock().unwrap().push(client);
+                            }
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                            std::thread::sleep(Duration::from_millis(10));
+                        }
+                        Err(err) => reporter.report_error(CallbackError::Stream(format!(
+                            "an error occurred while accepting a client: {}",
+                            err
+                        ))),
+                    }
+                }
+            })
+        };
+
+        let channels = config.channels as usize;
+        let period_samples =
+            (config.sample_rate as usize * PERIOD.as_millis() as usize / 1000) * channels;
+        let pacing = self.pacing;
+        let mut encoding = match config.sample_format {
+            SampleFormat::F32 => Encoding::F32,
+            _ => Encoding::I16,
+        };
+        #[cfg(feature = "opus")]
+        if let Some(opus) = self.opus {
+            encoding = Encoding::Opus(OpusEncoder::new(config.channels, opus.bitrate)?);
+        }
+        let header = format!(
+            r#"{{"sample_rate":{},"channels":{},"format":"{}"}}"#,
+            config.sample_rate,
+            config.channels,
+            encoding.name(),
+        );
+
+        let sender = {
+            let playing = playing.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut clients: Vec<Client> = Vec::new();
+                let mut buffer = vec![T::EQUILIBRIUM; period_samples];
+                let mut next_period = Instant::now();
+
+                while !stop.load(Ordering::Acquire) {
+                    for mut client in pending.lock().unwrap().drain(..) {
+                        if send(&mut client, Message::Text(header.clone())) {
+                            clients.push(client);
+                        }
+                    }
+
+                    if !playing.load(Ordering::Acquire) {
+                        std::thread::sleep(Duration::from_millis(1));
+                        next_period = Instant::now();
+                        continue;
+                    }
+
+                    let len = match pacing {
+                        Pacing::RealTime => {
+                            let now = Instant::now();
+                            if now < next_period {
+                                std::thread::sleep(next_period - now);
+                            }
+                            next_period += PERIOD;
+                            period_samples
+                        }
+                        Pacing::AsFastAsPossible => {
+                            // only whole frames that are available, no silence is sent
+                            let available = source.available().min(period_samples);
+                            let available = available - available % channels;
+                            if available == 0
+                                || (encoding.whole_periods() && available < period_samples)
+                            {
+                                std::thread::sleep(Duration::from_millis(1));
+                                continue;
+                            }
+                            available
+                        }
+                    };
+
+                    source.fill(&mut buffer[..len]);
+
+                    let data = match encoding.encode(&buffer[..len]) {
+                        Ok(data) => data,
+                        Err(err) => {
+                            source.report_error(CallbackError::Stream(format!(
+                                "an error occurred while encoding audio: {}",
+                                err
+                            )));
+                            continue;
+                        }
+                    };
+                    clients.retain_mut(|client| {
+                        receive(client) && send(client, Message::Binary(data.clone()))
+                    });
+                }
+            })
+        };
+
+        Ok(Box::new(WebSocketStream {
+            playing,
+            stop,
+            threads: vec![acceptor, sender],
+        }))
+    }
+}
+
+/// The encoding of the samples in the binary messages
+enum Encoding {
+    F32,
+    I16,
+    #[cfg(feature = "opus")]
+    Opus(OpusEncoder),
+}
+
+impl Encoding {
+    /// The name of the format in the configuration message
+    fn name(&self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::I16 => "i16",
+            #[cfg(feature = "opus")]
+            Self::Opus(_) => "opus",
+        }
+    }
+
+    /// Whether only whole periods can be encoded
+    fn whole_periods(&self) -> bool {
+        !matches!(self, Self::F32 | Self::I16)
+    }
+
+    /// The data of the message with `samples`
+    fn encode<T>(&mut self, samples: &[T]) -> Result<Vec<u8>, String>
+    where
+        T: FloatSample,
+        i16: FromSample<T>,
+        f32: FromSample<T>,
+    {
+        Ok(match self {
+            Self::F32 => samples
+                .iter()
+                .flat_map(|&s| f32::from_sample_(s).to_le_bytes())
+                .collect(),
+            Self::I16 => samples
+                .iter()
+                .flat_map(|&s| i16::from_sample_(s).to_le_bytes())
+                .collect(),
+            #[cfg(feature = "opus")]
+            Self::Opus(encoder) => encoder
+                .encode(samples)
+                .map_err(|err| err.to_string())?
+                .to_vec(),
+        })
+    }
+}
+
+/// Do the WebSocket handshake on a new connection, and make it non-blocking
+fn handshake(stream: TcpStream) -> Option<Client> {
+    // the handshake is blocking, but shouldn't take long
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok()?;
+    let _ = stream.set_nodelay(true);
+
+    let config = WebSocketConfig {
+        max_write_buffer_size: MAX_CLIENT_BUFFER,
+        ..Default::default()
+    };
+    let client = tungstenite::accept_with_config(stream, Some(config)).ok()?;
+    client.get_ref().set_nonblocking(true).ok()?;
+    Some(client)
+}
+
+/// Send `message` to `client`, returns `false` if the client should be disconnected
+fn send(client: &mut Client, message: Message) -> bool {
+    match client.send(message) {
+        Ok(()) => true,
+        // the message is kept in the buffer of the client until it can be sent
+        Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => true,
+        Err(_) => false,
+    }
+}
+
+/// Handle the messages from `client`, like pings and close, returns `false` if the client should be disconnected
+fn receive(client: &mut Client) -> bool {
+    loop {
+        match client.read() {
+            // messages from the client are ignored
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                return true
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
+struct WebSocketStream {
+    playing: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl OutputStream for WebSocketStream {
+    fn play(&self) -> Result<(), PlayError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for WebSocketStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,1437 @@
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cpal::{Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, SupportedBufferSize};
+
+use crate::{
+    adaptive::LatencyCallback,
+    backend::{
+        device::RawCallback, DeviceBackend, FillFn, NullBackend, OutputBackend, OutputConfig,
+        OutputRequest,
+    },
+    effects::{AudioEffect, Dither, LowPass, SoftLimiter},
+    error::AudioPlayerError,
+    negotiation::check_exact_rate,
+    ring::SampleRing,
+    sample::FloatSample,
+    silence::SilenceCallback,
+    stats::OverloadCallback,
+    underrun::UnderrunFills,
+    watchdog::StallCallback,
+    watermark::WatermarkCallback,
+    AdaptiveLatency, AudioPlayer, BufferLevel, BufferSize, ConfigPreference, InputLayout,
+    JitterBuffer, Negotiation, OfflineRenderer, PartialFrames, RecoveryPolicy, ResamplingAlgorithm,
+    SilenceDetection, StreamMetadata, UnderrunFill,
+};
+
+/// The default of [`AudioPlayerBuilder::fade_out_on_drop`]
+const DEFAULT_DROP_FADE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// The `CallbackSize` enum represents the number of frames the audio device requests
+/// in each call of the output callback.
+///
+/// Smaller callbacks reduce the latency of the device itself, but will increase the CPU usage,
+/// and might cause underruns on slower machines.
+///
+/// Not all devices support all sizes, see each variant for how this is handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallbackSize {
+    #[default]
+    /// Use the default of the device backend
+    Default,
+    /// Request a fixed number of frames per callback.
+    ///
+    /// If the device reports a supported range, the value is clamped to it,
+    /// and if the device fails to create the stream with it, we fallback to [`CallbackSize::Default`].
+    Fixed(u32),
+    /// Request exactly this number of frames per callback.
+    ///
+    /// If the device doesn't support it, [`AudioPlayerError::CallbackSizeNotSupported`] is returned.
+    Exact(u32),
+    /// Request the smallest number of frames per callback supported by the device.
+    ///
+    /// If the device doesn't report a supported range, or fails to create the stream with it,
+    /// we fallback to [`CallbackSize::Default`].
+    Minimum,
+}
+
+impl CallbackSize {
+    /// Resolve the requested size against the range supported by the device
+    pub(crate) fn resolve(
+        &self,
+        supported: &SupportedBufferSize,
+    ) -> Result<cpal::BufferSize, AudioPlayerError> {
+        match (*self, supported) {
+            (Self::Default, _) | (Self::Minimum, SupportedBufferSize::Unknown) => {
+                Ok(cpal::BufferSize::Default)
+            }
+            (Self::Minimum, SupportedBufferSize::Range { min, .. }) => {
+                Ok(cpal::BufferSize::Fixed(*min))
+            }
+            (Self::Fixed(frames), SupportedBufferSize::Range { min, max }) => {
+                Ok(cpal::BufferSize::Fixed(frames.clamp(*min, *max)))
+            }
+            (Self::Exact(frames), SupportedBufferSize::Range { min, max })
+                if frames < *min || frames > *max =>
+            {
+                Err(AudioPlayerError::CallbackSizeNotSupported {
+                    requested: frames,
+                    min: *min,
+                    max: *max,
+                })
+            }
+            (Self::Fixed(frames) | Self::Exact(frames), _) => Ok(cpal::BufferSize::Fixed(frames)),
+        }
+    }
+}
+
+/// A bundle of the latency options of [`AudioPlayerBuilder`], set with [`AudioPlayerBuilder::preset`].
+///
+/// Each preset sets the [`buffer_size`](AudioPlayerBuilder::buffer_size),
+/// the [`callback_size`](AudioPlayerBuilder::callback_size), the chunk size of the resampler
+/// with [`low_latency`](AudioPlayerBuilder::low_latency), and how much audio is buffered
+/// before resuming after an underrun with [`adaptive_latency`](AudioPlayerBuilder::adaptive_latency).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// For emulators and games where the audio has to follow the input closely,
+    /// on machines that can keep up with small callbacks.
+    ///
+    /// A quarter second buffer, the smallest callbacks of the device, small resampler chunks,
+    /// and a latency target of `10ms` that can grow up to `60ms` after underruns.
+    LowLatency,
+    /// The defaults of most applications.
+    ///
+    /// A half second buffer, the default callbacks of the device, and a latency target of `40ms`
+    /// that can grow up to `120ms` after underruns.
+    Balanced,
+    /// For music and video players, where latency doesn't matter, but underruns do.
+    ///
+    /// A one second buffer, callbacks of `1024` frames, and a latency target of `100ms`
+    /// that can grow up to `250ms` after underruns.
+    HighQuality,
+}
+
+/// The default [`buffer_size`](AudioPlayerBuilder::buffer_size) and [`callback_size`](AudioPlayerBuilder::callback_size)
+/// of the players on the audio devices of a platform, set with [`AudioPlayerBuilder::platform_profile`].
+///
+/// The audio stacks of the platforms behave differently with the same sizes, so by default,
+/// the profile of the current platform is used, see [`current`](Self::current).
+/// The options set on the builder, directly or with a [`Preset`], override the profile,
+/// and players with a custom [`backend`](AudioPlayerBuilder::backend) don't use it.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{AudioPlayer, BufferSize, CallbackSize, PlatformProfile};
+/// // the sizes of the profile for Android, on all platforms
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .platform_profile(Some(PlatformProfile {
+///         buffer_size: BufferSize::HalfSecond,
+///         callback_size: CallbackSize::Fixed(1024),
+///     }))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PlatformProfile {
+    /// The size of the buffer of the queued samples.
+    pub buffer_size: BufferSize,
+    /// The number of frames requested by the device in each callback.
+    pub callback_size: CallbackSize,
+}
+
+impl PlatformProfile {
+    /// The profile of the platform this is compiled for:
+    /// - Android: a half second buffer and callbacks of `1024` frames, the devices are often Bluetooth,
+    ///   and the callbacks of the small default size come late on many phones.
+    /// - Windows, macOS and iOS: a buffer of `125ms`, WASAPI and CoreAudio keep up with smaller buffers.
+    /// - Others: a quarter second buffer, the same as [`BufferSize::default`].
+    ///
+    /// All of them use the default callback size of the device, except Android.
+    /// With [`low_latency`](AudioPlayerBuilder::low_latency), the callback size of the profile is not used.
+    pub fn current() -> Self {
+        if cfg!(target_os = "android") {
+            Self {
+                buffer_size: BufferSize::HalfSecond,
+                callback_size: CallbackSize::Fixed(1024),
+            }
+        } else if cfg!(any(
+            target_os = "windows",
+            target_os = "macos",
+            target_os = "ios"
+        )) {
+            Self {
+                buffer_size: BufferSize::Custom(|sample_rate, channels| {
+                    sample_rate as usize / 8 * channels as usize
+                }),
+                callback_size: CallbackSize::Default,
+            }
+        } else {
+            Self {
+                buffer_size: BufferSize::default(),
+                callback_size: CallbackSize::default(),
+            }
+        }
+    }
+}
+
+/// Builder for [`AudioPlayer`], allows configuring the player beyond what [`AudioPlayer::new`] provides.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{AudioPlayer, BufferSize, CallbackSize};
+/// let player = AudioPlayer::<f32>::builder(44100)
+///     .buffer_size(BufferSize::QuarterSecond)
+///     .callback_size(CallbackSize::Fixed(256))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct AudioPlayerBuilder<T> {
+    pub(crate) sample_rate: u32,
+    pub(crate) buffer_size: Option<BufferSize>,
+    pub(crate) ring: Option<Box<dyn SampleRing<T>>>,
+    pub(crate) callback_size: Option<CallbackSize>,
+    pub(crate) platform_profile: Option<PlatformProfile>,
+    pub(crate) host: Option<cpal::HostId>,
+    pub(crate) device: Option<cpal::Device>,
+    pub(crate) host_fallback: bool,
+    pub(crate) native_channels: bool,
+    pub(crate) sample_formats: Vec<SampleFormat>,
+    pub(crate) low_latency: bool,
+    pub(crate) drift_compensation: bool,
+    pub(crate) resampler_load_limit: Option<f32>,
+    pub(crate) resampling_algorithm: ResamplingAlgorithm,
+    pub(crate) config_preference: ConfigPreference,
+    pub(crate) preferred_sample_rate: Option<u32>,
+    pub(crate) prefer_default_config: bool,
+    pub(crate) exact_sample_rate: bool,
+    pub(crate) auto_sample_rate: bool,
+    pub(crate) stream_metadata: StreamMetadata,
+    pub(crate) raw_callback: Option<RawCallback>,
+    pub(crate) backend: Option<Box<dyn OutputBackend<T>>>,
+    pub(crate) effects: Vec<Box<dyn AudioEffect<T>>>,
+    pub(crate) limiter: Option<SoftLimiter>,
+    pub(crate) input_limiter: Option<SoftLimiter>,
+    pub(crate) dither: Dither,
+    pub(crate) underrun_fills: UnderrunFills,
+    pub(crate) underrun_concealment: Duration,
+    pub(crate) silence_padding: Option<Duration>,
+    pub(crate) input_lowpass: Option<LowPass<T>>,
+    pub(crate) input_layout: InputLayout,
+    pub(crate) partial_frames: PartialFrames,
+    pub(crate) visualization: Option<Duration>,
+    pub(crate) silence_detection: Option<SilenceDetection>,
+    pub(crate) on_silence: Option<SilenceCallback>,
+    pub(crate) rewind: Option<Duration>,
+    pub(crate) adaptive_latency: Option<AdaptiveLatency>,
+    pub(crate) on_latency_change: Option<LatencyCallback>,
+    pub(crate) high_latency_threshold: Option<Duration>,
+    pub(crate) latency_clamp: Option<(Duration, Duration)>,
+    pub(crate) jitter_buffer: Option<JitterBuffer>,
+    pub(crate) watermarks: Option<(f32, f32)>,
+    pub(crate) on_watermark: Option<WatermarkCallback>,
+    pub(crate) callback_load_threshold: f32,
+    pub(crate) on_callback_overload: Option<OverloadCallback>,
+    pub(crate) recovery_policy: RecoveryPolicy,
+    pub(crate) watchdog: Option<Duration>,
+    pub(crate) on_stall: Option<StallCallback>,
+    pub(crate) sleep_recovery: bool,
+    pub(crate) reset_frames_delivered_on_play: bool,
+    pub(crate) deterministic: bool,
+    pub(crate) drop_fade_timeout: Duration,
+    pub(crate) retries: (u32, Duration),
+    _phantom: PhantomData<T>,
+}
+
+impl<T> AudioPlayerBuilder<T> {
+    /// Creates a new builder for a player that will play samples of `sample_rate` Hz.
+    ///
+    /// Prefer using [`AudioPlayer::builder`].
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            buffer_size: None,
+            ring: None,
+            callback_size: None,
+            platform_profile: Some(PlatformProfile::current()),
+            host: None,
+            device: None,
+            host_fallback: false,
+            native_channels: false,
+            sample_formats: Vec::new(),
+            low_latency: false,
+            drift_compensation: false,
+            resampler_load_limit: None,
+            resampling_algorithm: ResamplingAlgorithm::Fft,
+            config_preference: ConfigPreference::default(),
+            preferred_sample_rate: None,
+            prefer_default_config: false,
+            exact_sample_rate: false,
+            auto_sample_rate: false,
+            stream_metadata: StreamMetadata::new(),
+            raw_callback: None,
+            backend: None,
+            effects: Vec::new(),
+            limiter: None,
+            input_limiter: None,
+            dither: Dither::default(),
+            underrun_fills: UnderrunFills::default(),
+            underrun_concealment: Duration::ZERO,
+            silence_padding: None,
+            input_lowpass: None,
+            input_layout: InputLayout::default(),
+            partial_frames: PartialFrames::default(),
+            visualization: None,
+            silence_detection: None,
+            on_silence: None,
+            rewind: None,
+            adaptive_latency: None,
+            on_latency_change: None,
+            high_latency_threshold: None,
+            latency_clamp: None,
+            jitter_buffer: None,
+            watermarks: None,
+            on_watermark: None,
+            callback_load_threshold: 80.0,
+            on_callback_overload: None,
+            recovery_policy: RecoveryPolicy::default(),
+            watchdog: None,
+            on_stall: None,
+            sleep_recovery: true,
+            reset_frames_delivered_on_play: false,
+            deterministic: false,
+            drop_fade_timeout: DEFAULT_DROP_FADE_TIMEOUT,
+            retries: (0, Duration::ZERO),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the latency options together from a [`Preset`], instead of one by one.
+    ///
+    /// The options set after this override the ones of the preset.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, Preset};
+    /// # use std::time::Duration;
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .preset(Preset::LowLatency)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(player.buffer_capacity(), 48000 / 4 * 2);
+    /// assert_eq!(player.latency_target(), Some(Duration::from_millis(10)));
+    /// ```
+    pub fn preset(self, preset: Preset) -> Self {
+        let (buffer_size, callback_size, low_latency, initial_ms, max_ms) = match preset {
+            Preset::LowLatency => (
+                BufferSize::QuarterSecond,
+                CallbackSize::Minimum,
+                true,
+                10,
+                60,
+            ),
+            Preset::Balanced => (
+                BufferSize::HalfSecond,
+                CallbackSize::Default,
+                false,
+                40,
+                120,
+            ),
+            Preset::HighQuality => (
+                BufferSize::OneSecond,
+                CallbackSize::Fixed(1024),
+                false,
+                100,
+                250,
+            ),
+        };
+        self.buffer_size(buffer_size)
+            .callback_size(callback_size)
+            .low_latency(low_latency)
+            .adaptive_latency(AdaptiveLatency::new(
+                Duration::from_millis(initial_ms),
+                Duration::from_millis(max_ms),
+            ))
+    }
+
+    /// Set the size of the buffer that will store the audio samples. See [`BufferSize`] for options.
+    pub fn buffer_size(mut self, buffer_size: BufferSize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Use `ring` to create the buffer of the samples instead of the default [`HeapRing`](crate::ring::HeapRing),
+    /// see the [`ring`](crate::ring) module for more details.
+    pub fn ring_buffer(mut self, ring: impl SampleRing<T> + 'static) -> Self {
+        self.ring = Some(Box::new(ring));
+        self
+    }
+
+    /// Set the number of frames the device will request in each callback. See [`CallbackSize`] for options.
+    pub fn callback_size(mut self, callback_size: CallbackSize) -> Self {
+        self.callback_size = Some(callback_size);
+        self
+    }
+
+    /// Use the sizes of `profile` when they are not set on the builder, or the generic defaults
+    /// of [`BufferSize`] and [`CallbackSize`] if `None`.
+    ///
+    /// The default is the profile of the current platform, see [`PlatformProfile`].
+    pub fn platform_profile(mut self, profile: Option<PlatformProfile>) -> Self {
+        self.platform_profile = profile;
+        self
+    }
+
+    /// Use the default output device of the host `host` instead of the default host.
+    ///
+    /// The available hosts can be listed with [`cpal::available_hosts`].
+    pub fn host(mut self, host: cpal::HostId) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// Use the ASIO host, for low latency on Windows with pro audio interfaces.
+    ///
+    /// ASIO devices usually only support one sample format (mostly `i32`) at the rate
+    /// configured in the driver, the samples will be converted (and resampled if needed) to match it.
+    #[cfg(all(target_os = "windows", feature = "asio"))]
+    pub fn asio(self) -> Self {
+        self.host(cpal::HostId::Asio)
+    }
+
+    /// Use `device` as the output device, instead of the default output device of the host.
+    ///
+    /// This overrides [`host`](Self::host).
+    pub fn device(mut self, device: cpal::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Require the device to play the samples at the sample rate of the player, without resampling,
+    /// for applications where resampling is not acceptable, like bit-perfect playback.
+    ///
+    /// Building the player fails with [`AudioPlayerError::SampleRateNotSupported`] if the negotiated
+    /// output has another sample rate. The default is `false`, the samples are resampled when needed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, error::AudioPlayerError, AudioPlayer};
+    /// let result = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .exact_sample_rate(true)
+    ///     .build();
+    /// assert!(matches!(result, Err(AudioPlayerError::SampleRateNotSupported(44100))));
+    /// ```
+    pub fn exact_sample_rate(mut self, exact: bool) -> Self {
+        self.exact_sample_rate = exact;
+        self
+    }
+
+    /// Play the samples at the sample rate the output uses natively, instead of the rate given to the builder,
+    /// so they are never resampled, for generators that can produce samples at any rate.
+    ///
+    /// The rate given to the builder is only requested if the backend has no native rate,
+    /// see [`OutputBackend::native_sample_rate`]. The chosen rate is returned by [`AudioPlayer::sample_rate`].
+    /// See also [`AudioPlayer::new_auto`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(96000))
+    ///     .auto_sample_rate(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(player.sample_rate(), 96000);
+    /// assert!(!player.is_resampling());
+    /// ```
+    pub fn auto_sample_rate(mut self, auto: bool) -> Self {
+        self.auto_sample_rate = auto;
+        self
+    }
+
+    /// If the device doesn't support the sample rate of the player, prefer the configurations supporting
+    /// `sample_rate`, and resample to it.
+    ///
+    /// For example the rate used by the system mixer, to avoid resampling twice,
+    /// or a multiple of the rate of the player for a simpler resampling ratio.
+    ///
+    /// Only used by the default device backend.
+    pub fn preferred_output_rate(mut self, sample_rate: u32) -> Self {
+        self.preferred_sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// If the device doesn't support the sample rate of the player, so the output is resampled anyway,
+    /// prefer the default configuration of the device, see
+    /// [`DeviceBackend::prefer_default_config`](crate::backend::DeviceBackend::prefer_default_config).
+    ///
+    /// Many systems resample the other configurations again to the rate of their mixer, adding latency.
+    /// Only used by the default device backend. The default is `false`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::AudioPlayer;
+    /// // an unusual rate, resampled to the rate the device runs at by default
+    /// let player = AudioPlayer::<f32>::builder(32768)
+    ///     .prefer_default_config(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn prefer_default_config(mut self, prefer: bool) -> Self {
+        self.prefer_default_config = prefer;
+        self
+    }
+
+    /// If the default device of the host is missing or can't play stereo, try the default devices
+    /// of the other available hosts before failing, for example ALSA when PulseAudio is broken.
+    ///
+    /// Not used when a [`device`](Self::device) is set. The default is `false`.
+    pub fn host_fallback(mut self, host_fallback: bool) -> Self {
+        self.host_fallback = host_fallback;
+        self
+    }
+
+    /// Set the application name, stream name and media role shown by the system mixer,
+    /// see [`StreamMetadata`], so users can find and route the stream.
+    ///
+    /// Only used by the default device backend, and only on Linux, where it's picked up
+    /// by PulseAudio and PipeWire through their ALSA plugins.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, MediaRole, StreamMetadata};
+    /// let player = AudioPlayer::<f32>::builder(44100)
+    ///     .stream_metadata(
+    ///         StreamMetadata::new()
+    ///             .application_name("MyEmulator")
+    ///             .media_role(MediaRole::Game),
+    ///     )
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn stream_metadata(mut self, metadata: StreamMetadata) -> Self {
+        self.stream_metadata = metadata;
+        self
+    }
+
+    /// Wrap the output callback of the device with `callback`, see [`DeviceBackend::raw_callback`].
+    ///
+    /// The callback is kept when the output moves to another device with [`AudioPlayer::set_device`].
+    pub fn raw_callback(
+        mut self,
+        callback: impl FnMut(&mut Data, &OutputCallbackInfo, &mut FillFn) + Send + 'static,
+    ) -> Self {
+        self.raw_callback = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// Accept devices without a stereo configuration, for example mono USB speakers or multichannel
+    /// interfaces, by using the channels of their default configuration, and mixing the output to them,
+    /// see [`DeviceBackend::native_channels`](crate::backend::DeviceBackend::native_channels).
+    ///
+    /// The player still works in stereo, [`AudioPlayer::output_channels`] is `2`.
+    /// Only used by the default device backend. The default is `false`.
+    pub fn native_channels(mut self, native_channels: bool) -> Self {
+        self.native_channels = native_channels;
+        self
+    }
+
+    /// Only use the output sample formats in `formats`, the first ones are preferred, see
+    /// [`DeviceBackend::sample_formats`](crate::backend::DeviceBackend::sample_formats).
+    ///
+    /// Only used by the default device backend.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::AudioPlayer;
+    /// # use cpal::SampleFormat;
+    /// // prefer F32, then I16, and never anything else
+    /// let player = AudioPlayer::<f32>::builder(44100)
+    ///     .sample_formats(&[SampleFormat::F32, SampleFormat::I16])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn sample_formats(mut self, formats: &[SampleFormat]) -> Self {
+        self.sample_formats = formats.to_vec();
+        self
+    }
+
+    /// Set which configuration of the device is chosen if none matches the player exactly.
+    /// See [`ConfigPreference`] for options.
+    ///
+    /// Only used by the default device backend.
+    pub fn config_preference(mut self, preference: ConfigPreference) -> Self {
+        self.config_preference = preference;
+        self
+    }
+
+    /// Set the channel layout of the queued samples, see [`InputLayout`]. The default is stereo.
+    ///
+    /// Samples in other layouts are converted to stereo when queued, so the sample counts of
+    /// the buffer and of [`AudioPlayer::buffered_samples`] are always in stereo.
+    pub fn input_layout(mut self, layout: InputLayout) -> Self {
+        self.input_layout = layout;
+        self
+    }
+
+    /// Set what is done with the samples at the end of a call to [`AudioPlayer::queue`] that don't make
+    /// a whole frame of the input layout, see [`PartialFrames`]. The default is [`PartialFrames::Carry`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, PartialFrames};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .partial_frames(PartialFrames::Discard)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(player.queue(&[0.1, 0.2, 0.3]), 1);
+    /// assert_eq!(player.snapshot().buffered, [0.1, 0.2]);
+    /// ```
+    pub fn partial_frames(mut self, partial_frames: PartialFrames) -> Self {
+        self.partial_frames = partial_frames;
+        self
+    }
+
+    /// Configure the player for low latency output.
+    ///
+    /// This will:
+    /// - Use [`CallbackSize::Minimum`] if the callback size was not set,
+    ///   on Android, this is the smallest burst size AAudio/OpenSL reports for the device.
+    /// - Use smaller chunks in the resampler (if resampling is needed), since the resampler
+    ///   holds a full chunk before producing any output.
+    ///
+    /// This is useful on Android, where most devices only support `48000Hz`, so resampling is active
+    /// for most emulators.
+    ///
+    /// Note: AAudio's performance mode is not exposed by [cpal], so it can't be set here.
+    pub fn low_latency(mut self, low_latency: bool) -> Self {
+        self.low_latency = low_latency;
+        self
+    }
+
+    /// Compensate the drift of the device clock from its nominal sample rate.
+    ///
+    /// Over long sessions, the small difference between the rate the samples are queued at and
+    /// the rate the device actually plays at makes the buffer creep towards underruns or overflows.
+    /// With this, the drift is measured from the playback timestamps of the device,
+    /// and the samples are resampled to follow it, even if the sample rates match.
+    ///
+    /// The resampling uses [`ResamplingAlgorithm::Polynomial`](crate::ResamplingAlgorithm::Polynomial),
+    /// or the [`resampling_algorithm`](Self::resampling_algorithm) if its ratio can be adjusted,
+    /// and only backends reporting the playback time (like the default one) are measured,
+    /// see [`OutputSource::report_playback_time`](crate::backend::OutputSource::report_playback_time).
+    pub fn drift_compensation(mut self, drift_compensation: bool) -> Self {
+        self.drift_compensation = drift_compensation;
+        self
+    }
+
+    /// Resample with `algorithm` when the device doesn't play the sample rate of the player,
+    /// the default is [`ResamplingAlgorithm::Fft`].
+    ///
+    /// The built-in algorithms, [`ResamplingAlgorithm::BuiltinCubic`] and [`ResamplingAlgorithm::BuiltinLinear`],
+    /// are much cheaper than the ones of rubato, for low-end machines and sources without much high
+    /// frequency content. Without the `resample` feature, they are the only ones available,
+    /// and with the `lite-resample` feature they replace the other algorithms.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, ResamplingAlgorithm};
+    /// let mut player = AudioPlayer::<f32>::builder(32000)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .resampling_algorithm(ResamplingAlgorithm::BuiltinLinear)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     player.resampling_info().unwrap().algorithm,
+    ///     ResamplingAlgorithm::BuiltinLinear
+    /// );
+    ///
+    /// player.queue(&[0.5; 3200 * 2]);
+    /// assert!(player.buffered_samples() > 4000 * 2);
+    /// ```
+    pub fn resampling_algorithm(mut self, algorithm: ResamplingAlgorithm) -> Self {
+        self.resampling_algorithm = algorithm;
+        self
+    }
+
+    /// Switch to a cheaper resampling algorithm when resampling takes more than `limit` of real time,
+    /// for example `0.25` for 250ms per second of audio, so the resampler doesn't cause underruns
+    /// on machines that can't keep up with it.
+    ///
+    /// The time is measured in [`AudioPlayer::queue`] over each second of queued audio, and the resampler
+    /// goes from [`ResamplingAlgorithm::Fft`](crate::ResamplingAlgorithm::Fft) to
+    /// [`Polynomial`](crate::ResamplingAlgorithm::Polynomial), then [`Linear`](crate::ResamplingAlgorithm::Linear).
+    /// The built-in [`BuiltinCubic`](crate::ResamplingAlgorithm::BuiltinCubic) goes to
+    /// [`BuiltinLinear`](crate::ResamplingAlgorithm::BuiltinLinear).
+    /// Each switch is reported with [`Diagnostic::ResamplingDegraded`](crate::Diagnostic::ResamplingDegraded).
+    ///
+    /// Disabled by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, Diagnostic, ResamplingAlgorithm};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .resampler_load_limit(0.0)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // no time is fast enough
+    /// player.queue(&[0.5; 44100 * 2]);
+    /// assert_eq!(
+    ///     player.resampling_info().unwrap().algorithm,
+    ///     ResamplingAlgorithm::Polynomial
+    /// );
+    /// assert!(player.diagnostics().contains(&Diagnostic::ResamplingDegraded {
+    ///     to: ResamplingAlgorithm::Polynomial
+    /// }));
+    /// ```
+    pub fn resampler_load_limit(mut self, limit: f32) -> Self {
+        self.resampler_load_limit = Some(limit.max(0.0));
+        self
+    }
+
+    /// Play the samples using `backend` instead of an audio device.
+    ///
+    /// When a backend is set, the device options ([`callback_size`](Self::callback_size),
+    /// [`host`](Self::host) and [`device`](Self::device)) are ignored, they should be configured
+    /// in the backend itself if it supports them.
+    ///
+    /// See the [`backend`](crate::backend) module for more details.
+    pub fn backend(mut self, backend: impl OutputBackend<T> + 'static) -> Self {
+        self.backend = Some(Box::new(backend));
+        self
+    }
+
+    /// Add `effect` at the end of the effect chain, see [`effects`](crate::effects) for more details.
+    ///
+    /// More effects can be added after the player is created with [`AudioPlayer::add_effect`].
+    pub fn effect(mut self, effect: impl AudioEffect<T> + 'static) -> Self {
+        self.effects.push(Box::new(effect));
+        self
+    }
+
+    /// Enable a [`SoftLimiter`] as the last stage of the output, after all the effects.
+    ///
+    /// This prevents harsh clipping when the samples exceed full scale,
+    /// e.g. after resampling, mixing tracks, or amplifying effects.
+    pub fn limiter(mut self, enabled: bool) -> Self {
+        self.limiter = enabled.then(SoftLimiter::new);
+        self
+    }
+
+    /// Apply a [`SoftLimiter`] to the queued samples, before they are resampled,
+    /// for sources that sometimes go over full scale.
+    ///
+    /// The resampler rings around the samples clipped later at the output,
+    /// which is heard as harsh artifacts, limiting the input avoids them.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .input_limiter(true)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[1.5, -0.5]);
+    /// let buffered = player.snapshot().buffered;
+    /// assert!(buffered[0] < 1.0);
+    /// assert_eq!(buffered[1], -0.5);
+    /// ```
+    pub fn input_limiter(mut self, enabled: bool) -> Self {
+        self.input_limiter = enabled.then(SoftLimiter::new);
+        self
+    }
+
+    /// Set the [`Dither`] applied when the output format is an integer format of 16 bits or less.
+    ///
+    /// This is applied after all the effects and the limiter, right before the conversion.
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Set what is played in place of the missing samples when the buffer runs out,
+    /// for devices using `format`, see [`UnderrunFill`].
+    ///
+    /// The default is [`UnderrunFill::Midpoint`] for all formats.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, UnderrunFill};
+    /// # use cpal::SampleFormat;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .underrun_fill(SampleFormat::F32, UnderrunFill::HoldLast)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// // the buffer runs out in the middle, and the last sample is held
+    /// clock.advance_frames(960);
+    /// assert_eq!(player.levels()[0].rms, 0.5);
+    /// ```
+    pub fn underrun_fill(mut self, format: SampleFormat, fill: UnderrunFill) -> Self {
+        self.underrun_fills.set(format, fill);
+        self
+    }
+
+    /// Conceal the gaps of at most `max_gap` when the buffer runs out, instead of playing silence.
+    ///
+    /// The last played samples are extrapolated and faded out over the gap,
+    /// which turns short underruns from clicks into barely audible artifacts.
+    /// Longer gaps are filled as set with [`underrun_fill`](Self::underrun_fill).
+    ///
+    /// Disabled by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .underrun_concealment(Duration::from_millis(1))
+    ///     .visualization(Duration::from_millis(10))
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 476 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// // 4 frames are missing, and faded out instead of dropping to silence
+    /// clock.advance_frames(480);
+    /// let mut samples = Vec::new();
+    /// player.recent_samples(&mut samples);
+    /// let concealed = &samples[476 * 2..];
+    /// assert!(concealed[0] > 0.3 && concealed[7] > 0.0);
+    /// assert!(concealed.windows(4).all(|w| w[0] > w[2]));
+    /// ```
+    pub fn underrun_concealment(mut self, max_gap: Duration) -> Self {
+        self.underrun_concealment = max_gap;
+        self
+    }
+
+    /// When nothing was queued for `interval`, fade the output to silence and keep the buffered samples,
+    /// instead of playing them until the buffer runs out, then fade them in again on the next
+    /// [`queue`](AudioPlayer::queue).
+    ///
+    /// This smooths over the pauses of producers with an irregular rate, like a frame that took too long
+    /// or the garbage collection of a scripting engine, as the samples continue where they stopped
+    /// without the abrupt cut of an underrun. The silence isn't counted as an underrun.
+    ///
+    /// Disabled by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new().period_frames(480);
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .silence_padding(Duration::from_millis(20))
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 4800 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// // the producer stalls, the samples after the fade are kept
+    /// clock.advance_frames(9600);
+    /// let kept = player.buffered_samples();
+    /// assert!(kept > 3000 * 2);
+    /// assert_eq!(player.metrics().underruns, 0);
+    ///
+    /// // and played after the next queue
+    /// player.queue(&[0.5; 480 * 2]);
+    /// clock.advance_frames(480);
+    /// assert_eq!(player.buffered_samples(), kept);
+    /// ```
+    pub fn silence_padding(mut self, interval: Duration) -> Self {
+        self.silence_padding = Some(interval);
+        self
+    }
+
+    /// Keep the last `duration` of played samples, to be read with [`AudioPlayer::recent_samples`].
+    ///
+    /// This is disabled by default, as it copies every played sample.
+    pub fn visualization(mut self, duration: Duration) -> Self {
+        self.visualization = Some(duration);
+        self
+    }
+
+    /// Detect when the queued samples are silent, see [`SilenceDetection`].
+    pub fn silence_detection(mut self, silence_detection: SilenceDetection) -> Self {
+        self.silence_detection = Some(silence_detection);
+        self
+    }
+
+    /// Call `callback` with `true` when the queued samples become silent, and with `false` when they are not anymore.
+    ///
+    /// The callback is called from [`AudioPlayer::queue`], and only if [`silence_detection`](Self::silence_detection) is set.
+    pub fn on_silence(mut self, callback: impl FnMut(bool) + Send + 'static) -> Self {
+        self.on_silence = Some(Box::new(callback));
+        self
+    }
+
+    /// Keep the last `duration` of played samples, to be played backwards with [`AudioPlayer::set_rewinding`].
+    ///
+    /// Only the queued samples are kept, not the tracks of a [`Mixer`](crate::Mixer) or the one-shot clips.
+    pub fn rewind(mut self, duration: Duration) -> Self {
+        self.rewind = Some(duration);
+        self
+    }
+
+    /// Try to create the output stream again up to `retries` times, waiting `backoff` between the attempts,
+    /// when it fails with an error that can go away, see [`AudioPlayerError::is_transient`].
+    ///
+    /// On some systems the device is busy for a moment right after the system resumes,
+    /// or while another application releases it, see [`AudioPlayerError::DeviceBusy`].
+    /// [`build`](Self::build) blocks during the attempts.
+    /// The default is to not retry.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{
+    /// #     backend::{NullBackend, OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream},
+    /// #     error::AudioPlayerError,
+    /// #     AudioPlayer,
+    /// # };
+    /// # use std::time::Duration;
+    /// // a device that is busy the first time it's used
+    /// struct BusyOnce(bool, NullBackend<f32>);
+    ///
+    /// impl OutputBackend<f32> for BusyOnce {
+    ///     fn negotiate(&mut self, request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+    ///         if !std::mem::replace(&mut self.0, true) {
+    ///             return Err(AudioPlayerError::DeviceNotAvailable);
+    ///         }
+    ///         self.1.negotiate(request)
+    ///     }
+    ///
+    ///     fn build_stream(
+    ///         &mut self,
+    ///         config: &OutputConfig,
+    ///         source: OutputSource<f32>,
+    ///     ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+    ///         self.1.build_stream(config, source)
+    ///     }
+    /// }
+    ///
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(BusyOnce(false, NullBackend::new()))
+    ///     .retries(3, Duration::from_millis(10))
+    ///     .build();
+    /// assert!(player.is_ok());
+    /// ```
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = (retries, backoff);
+        self
+    }
+
+    /// Set what happens when the output stream stops at runtime, see [`RecoveryPolicy`].
+    /// The default is [`RecoveryPolicy::Never`].
+    pub fn recovery_policy(mut self, policy: RecoveryPolicy) -> Self {
+        self.recovery_policy = policy;
+        self
+    }
+
+    /// Treat the output stream as lost when no output callback happens for `timeout` while playing,
+    /// for devices that stop calling back without reporting an error, e.g. a sleeping device or a stuck backend.
+    ///
+    /// The callbacks are checked on every [`AudioPlayer::queue`], and a stall is handled with the
+    /// [`recovery_policy`](Self::recovery_policy), and reported to [`on_stall`](Self::on_stall).
+    /// The timeout should be much longer than the callbacks period, devices may take a while to start.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, RecoveryPolicy};
+    /// # use std::{sync::mpsc, time::Duration};
+    /// let (sender, receiver) = mpsc::channel();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     // the clock of the backend is never advanced, so no callbacks happen
+    ///     .backend(NullBackend::new())
+    ///     .watchdog(Duration::from_millis(5))
+    ///     .on_stall(move || sender.send(()).unwrap())
+    ///     .recovery_policy(RecoveryPolicy::ReopenSameDevice)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.5; 480 * 2]);
+    /// std::thread::sleep(Duration::from_millis(10));
+    /// player.queue(&[0.5; 480 * 2]);
+    /// assert!(receiver.try_recv().is_ok());
+    /// // the stream was built again, and plays
+    /// assert!(player.is_open());
+    /// assert!(player.is_playing());
+    /// ```
+    pub fn watchdog(mut self, timeout: Duration) -> Self {
+        self.watchdog = Some(timeout);
+        self
+    }
+
+    /// Call `callback` when the [`watchdog`](Self::watchdog) notices that the output callbacks stopped.
+    ///
+    /// The callback is called from [`AudioPlayer::queue`], before the stream is recovered.
+    pub fn on_stall(mut self, callback: impl FnMut() + Send + 'static) -> Self {
+        self.on_stall = Some(Box::new(callback));
+        self
+    }
+
+    /// Rebuild the stream on the next [`AudioPlayer::queue`] after the machine slept, enabled by default,
+    /// see [`AudioPlayer::recover_from_sleep`].
+    ///
+    /// The sleep is noticed when the system clock moves ahead of the monotonic clock, which doesn't count
+    /// the time asleep on Linux and macOS. On other platforms, use the [`watchdog`](Self::watchdog)
+    /// or call [`AudioPlayer::recover_from_sleep`] from the resume notification of the platform.
+    pub fn sleep_recovery(mut self, enabled: bool) -> Self {
+        self.sleep_recovery = enabled;
+        self
+    }
+
+    /// Start the [`AudioPlayer::frames_delivered`] counter from zero on every [`AudioPlayer::play`],
+    /// before the stream starts, so it counts the frames since the start of the playback. Disabled by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .reset_frames_delivered_on_play(true)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.play().unwrap();
+    /// clock.advance_frames(960);
+    /// player.pause().unwrap();
+    ///
+    /// player.play().unwrap();
+    /// assert_eq!(player.frames_delivered(), 0);
+    /// clock.advance_frames(480);
+    /// assert_eq!(player.frames_delivered(), 480);
+    /// ```
+    pub fn reset_frames_delivered_on_play(mut self, enabled: bool) -> Self {
+        self.reset_frames_delivered_on_play = enabled;
+        self
+    }
+
+    /// Make the output depend only on the queued samples and the configuration, for reproducible tests.
+    ///
+    /// The features that depend on the time of the calls are disabled: the [`drift_compensation`](Self::drift_compensation),
+    /// the [`resampler_load_limit`](Self::resampler_load_limit), the [`adaptive_latency`](Self::adaptive_latency),
+    /// the [`jitter_buffer`](Self::jitter_buffer), the [`watchdog`](Self::watchdog) and the
+    /// [`sleep_recovery`](Self::sleep_recovery). The resampler already works in chunks of a fixed size,
+    /// and the dither uses a fixed seed, so with a backend consuming the samples at fixed points, like a
+    /// [`NullBackend`], the same samples queued in the same calls are played the same, to the bit.
+    ///
+    /// See [`render`](Self::render) to render samples offline.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Disable the features depending on time, when [`deterministic`](Self::deterministic)
+    pub(crate) fn apply_deterministic(&mut self) {
+        if self.deterministic {
+            self.drift_compensation = false;
+            self.resampler_load_limit = None;
+            self.adaptive_latency = None;
+            self.jitter_buffer = None;
+            self.watchdog = None;
+            self.sleep_recovery = false;
+        }
+    }
+
+    /// When the player is dropped while playing, fade the output out before stopping the stream,
+    /// waiting at most `timeout` for the fade to be played.
+    ///
+    /// Stopping the stream in the middle of the audio causes a click on some backends.
+    /// The fade is the same as [`PauseMode::FadeAndRetain`](crate::PauseMode::FadeAndRetain), so the drop
+    /// blocks for about 10ms, plus the period of the device.
+    /// The default is 50ms, use [`Duration::ZERO`] to stop the stream right away.
+    pub fn fade_out_on_drop(mut self, timeout: Duration) -> Self {
+        self.drop_fade_timeout = timeout;
+        self
+    }
+
+    /// Adjust the latency to the machine, growing it after underruns, see [`AdaptiveLatency`].
+    pub fn adaptive_latency(mut self, adaptive_latency: AdaptiveLatency) -> Self {
+        self.adaptive_latency = Some(adaptive_latency);
+        self
+    }
+
+    /// Buffer at least the latency of the device when it's above `threshold`, typically on Bluetooth outputs,
+    /// which consume the samples in large bursts and underrun with small buffers.
+    ///
+    /// The device latency is checked on every [`AudioPlayer::queue`], once it's above `threshold`,
+    /// the [`latency_target`](AudioPlayer::latency_target) is raised to it, and the buffer grows if needed.
+    /// Only works with backends reporting the latency, see [`AudioPlayer::device_latency`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new().output_latency(Duration::from_millis(200));
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .high_latency_compensation(Duration::from_millis(100))
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.0; 4800 * 2]);
+    /// player.play().unwrap();
+    /// clock.advance_frames(480);
+    /// assert_eq!(player.device_latency(), Duration::from_millis(200));
+    ///
+    /// player.queue(&[0.0; 480 * 2]);
+    /// assert_eq!(player.latency_target(), Some(Duration::from_millis(200)));
+    /// ```
+    pub fn high_latency_compensation(mut self, threshold: Duration) -> Self {
+        self.high_latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Limit the buffered audio to `max`, when a [`AudioPlayer::queue`] fills the buffer above it,
+    /// the oldest samples are dropped to get back to `target`, keeping the audio in sync with the video
+    /// after a hiccup of the application or the device.
+    ///
+    /// The samples playing when the buffer is clamped are crossfaded with the ones after the drop,
+    /// same as [`AudioPlayer::clear`], and the dropped samples are counted in [`Metrics::samples_dropped`](crate::Metrics::samples_dropped).
+    /// `target` is capped at `max`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .latency_clamp(Duration::from_millis(100), Duration::from_millis(40))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.5; 4800 * 2]);
+    /// assert_eq!(player.buffered_samples(), 4800 * 2);
+    /// // above 100ms, back to 40ms
+    /// player.queue(&[0.5; 480 * 2]);
+    /// assert_eq!(player.buffered_samples(), 1920 * 2);
+    /// ```
+    pub fn latency_clamp(mut self, max: Duration, target: Duration) -> Self {
+        self.latency_clamp = Some((max, target.min(max)));
+        self
+    }
+
+    /// Adjust the latency to the jitter of samples arriving over a network, see [`JitterBuffer`].
+    pub fn jitter_buffer(mut self, jitter_buffer: JitterBuffer) -> Self {
+        self.jitter_buffer = Some(jitter_buffer);
+        self
+    }
+
+    /// Call `callback` with the new latency target when the [`adaptive_latency`](Self::adaptive_latency) changes it.
+    ///
+    /// The callback is called from [`AudioPlayer::queue`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AdaptiveLatency, AudioPlayer};
+    /// # use std::{sync::mpsc, time::Duration};
+    /// let (sender, receiver) = mpsc::channel();
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .adaptive_latency(
+    ///         AdaptiveLatency::new(Duration::from_millis(20), Duration::from_millis(200))
+    ///             .grow_after(1)
+    ///             .grow_within(Some(Duration::from_secs(10))),
+    ///     )
+    ///     .on_latency_change(move |target| sender.send(target).unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.5; 960 * 2]);
+    /// clock.advance(Duration::from_millis(40));
+    /// player.queue(&[0.5; 960 * 2]);
+    /// assert_eq!(receiver.try_recv(), Ok(Duration::from_millis(30)));
+    /// ```
+    pub fn on_latency_change(mut self, callback: impl FnMut(Duration) + Send + 'static) -> Self {
+        self.on_latency_change = Some(Box::new(callback));
+        self
+    }
+
+    /// Track the fill level of the buffer against a `low` and `high` watermark,
+    /// both are fractions of the buffer capacity from `0.0` to `1.0`.
+    ///
+    /// The level is checked on every [`AudioPlayer::queue`], see [`BufferLevel`] and [`on_watermark`](Self::on_watermark).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, BufferLevel, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .buffer_size(BufferSize::QuarterSecond)
+    ///     .watermarks(0.25, 0.75)
+    ///     .on_watermark(|level| println!("buffer level: {level:?}"))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.0; 1000]);
+    /// assert_eq!(player.buffer_level(), BufferLevel::Low);
+    /// player.queue(&[0.0; 20000]);
+    /// assert_eq!(player.buffer_level(), BufferLevel::High);
+    /// ```
+    pub fn watermarks(mut self, low: f32, high: f32) -> Self {
+        self.watermarks = Some((low, high));
+        self
+    }
+
+    /// Call `callback` with the new [`BufferLevel`] when the buffer crosses one of the [`watermarks`](Self::watermarks).
+    ///
+    /// The callback is called from [`AudioPlayer::queue`].
+    pub fn on_watermark(mut self, callback: impl FnMut(BufferLevel) + Send + 'static) -> Self {
+        self.on_watermark = Some(Box::new(callback));
+        self
+    }
+
+    /// The [`callback_load`](AudioPlayer::callback_load) from which the output is considered overloaded,
+    /// as a percentage of the time available to the callbacks, `80.0` by default.
+    ///
+    /// See [`on_callback_overload`](Self::on_callback_overload).
+    pub fn callback_load_threshold(mut self, threshold: f32) -> Self {
+        self.callback_load_threshold = threshold.max(0.0);
+        self
+    }
+
+    /// Call `callback` with `true` when the [`callback_load`](AudioPlayer::callback_load) reaches
+    /// the [`callback_load_threshold`](Self::callback_load_threshold), and with `false` when it goes back under it.
+    ///
+    /// The output glitches once a callback takes longer than the audio it fills, so this can warn the users
+    /// that the effects or the resampling are too heavy for their machine, before they hear it.
+    ///
+    /// The callback is called from [`AudioPlayer::queue`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::{sync::mpsc, time::Duration};
+    /// # #[cfg(feature = "debug-rt-checks")]
+    /// # dynwave::set_rt_time_budget(100.0);
+    /// let (sender, receiver) = mpsc::channel();
+    /// let backend = NullBackend::new().period_frames(480);
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     // a heavy effect, taking half of the 10ms of each callback
+    ///     .effect(|_: &mut [f32]| std::thread::sleep(Duration::from_millis(5)))
+    ///     .callback_load_threshold(10.0)
+    ///     .on_callback_overload(move |overloaded| sender.send(overloaded).unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// clock.advance_frames(480 * 20);
+    /// assert!(player.callback_load() > 10.0);
+    /// player.queue(&[0.0; 480 * 2]);
+    /// assert_eq!(receiver.try_recv(), Ok(true));
+    /// ```
+    pub fn on_callback_overload(mut self, callback: impl FnMut(bool) + Send + 'static) -> Self {
+        self.on_callback_overload = Some(Box::new(callback));
+        self
+    }
+}
+
+impl<T: FloatSample + SizedSample> AudioPlayerBuilder<T> {
+    /// Filter the queued samples with a [`LowPass`] with a cutoff of `cutoff` Hz, before they are resampled.
+    ///
+    /// This reduces the aliasing of crude sources, like a 1-bit beeper or an undersampled PSG,
+    /// which is audible after resampling.
+    pub fn input_lowpass(mut self, cutoff: f32) -> Self {
+        self.input_lowpass = Some(LowPass::new(cutoff));
+        self
+    }
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> AudioPlayerBuilder<T>
+where
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// The callback size requested from the device, the smallest one for low latency by default
+    pub(crate) fn device_callback_size(&self) -> CallbackSize {
+        match self.callback_size.unwrap_or_default() {
+            CallbackSize::Default if self.low_latency => CallbackSize::Minimum,
+            callback_size => callback_size,
+        }
+    }
+
+    /// Negotiate the output configuration like [`build`](Self::build), without opening a stream,
+    /// and return what would be used, see [`Negotiation`].
+    ///
+    /// Useful to show or check the audio path in a settings screen. Building the player after this
+    /// uses the same device.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut builder = AudioPlayer::<f32>::builder(44100).backend(NullBackend::new().sample_rate(48000));
+    /// let negotiation = builder.negotiate().unwrap();
+    /// assert_eq!(negotiation.output.sample_rate, 48000);
+    /// assert!(negotiation.resampling);
+    /// assert!(!negotiation.format_conversion);
+    ///
+    /// let player = builder.build().unwrap();
+    /// assert_eq!(player.output_config(), negotiation.output);
+    /// ```
+    pub fn negotiate(&mut self) -> Result<Negotiation, AudioPlayerError> {
+        let mut backend = self.take_backend();
+        let sample_rate = self.requested_sample_rate(backend.as_mut());
+        let result = backend.negotiate(&OutputRequest {
+            sample_rate,
+            sample_format: T::FORMAT,
+            channels: 2,
+        });
+        let device = backend.device_name();
+        self.backend = Some(backend);
+        let output = result?;
+        let sample_rate = self.input_sample_rate(&output);
+        check_exact_rate(
+            self.exact_sample_rate,
+            sample_rate,
+            &output,
+            self.resampling_algorithm,
+        )?;
+        Ok(Negotiation {
+            device,
+            output,
+            resampling: output.sample_rate != sample_rate,
+            format_conversion: output.sample_format != T::FORMAT,
+        })
+    }
+
+    /// The sample rate to request from `backend`, its native rate with `auto_sample_rate`
+    pub(crate) fn requested_sample_rate(&self, backend: &mut dyn OutputBackend<T>) -> u32 {
+        if self.auto_sample_rate {
+            backend.native_sample_rate().unwrap_or(self.sample_rate)
+        } else {
+            self.sample_rate
+        }
+    }
+
+    /// The sample rate of the queued samples, once the backend chose the `output` configuration
+    pub(crate) fn input_sample_rate(&self, output: &OutputConfig) -> u32 {
+        if self.auto_sample_rate {
+            output.sample_rate
+        } else {
+            self.sample_rate
+        }
+    }
+
+    /// The backend set with [`backend`](Self::backend), or the device backend with the configured options
+    fn take_backend(&mut self) -> Box<dyn OutputBackend<T>> {
+        match self.backend.take() {
+            Some(backend) => backend,
+            None => {
+                // only the players on the devices of the platform use its profile
+                if let Some(profile) = self.platform_profile {
+                    self.buffer_size.get_or_insert(profile.buffer_size);
+                    // low latency asks for the smallest callbacks instead
+                    if !self.low_latency {
+                        self.callback_size.get_or_insert(profile.callback_size);
+                    }
+                }
+                let mut backend = DeviceBackend::new()
+                    .callback_size(self.device_callback_size())
+                    .config_preference(self.config_preference)
+                    .prefer_default_config(self.prefer_default_config)
+                    .host_fallback(self.host_fallback)
+                    .native_channels(self.native_channels)
+                    .sample_formats(&self.sample_formats)
+                    .metadata(self.stream_metadata.clone())
+                    .shared_raw_callback(self.raw_callback.clone());
+                if let Some(sample_rate) = self.preferred_sample_rate {
+                    backend = backend.preferred_sample_rate(sample_rate);
+                }
+                if let Some(host) = self.host {
+                    backend = backend.host(host);
+                }
+                if let Some(device) = self.device.take() {
+                    backend = backend.device(device);
+                }
+                Box::new(backend)
+            }
+        }
+    }
+
+    /// Creates the [`AudioPlayer`] with the configured options.
+    ///
+    /// Check [`AudioPlayer::new`] for the possible errors.
+    pub fn build(mut self) -> Result<AudioPlayer<T>, AudioPlayerError> {
+        let backend = self.take_backend();
+        AudioPlayer::from_builder(self, backend)
+    }
+
+    /// Creates a [`deterministic`](Self::deterministic) player rendering its output offline,
+    /// as a device running at `output_rate` would play it, see [`OfflineRenderer`].
+    ///
+    /// The backend set on the builder is replaced.
+    pub fn build_offline(self, output_rate: u32) -> Result<OfflineRenderer<T>, AudioPlayerError> {
+        let backend = NullBackend::new().sample_rate(output_rate);
+        let clock = backend.clock();
+        let player = self
+            .deterministic(true)
+            .fade_out_on_drop(Duration::ZERO)
+            .backend(backend)
+            .build()?;
+        player.play()?;
+        Ok(OfflineRenderer::new(player, clock))
+    }
+
+    /// Render `data` offline through a [`deterministic`](Self::deterministic) player, as a device
+    /// running at `output_rate` would play it, and return the interleaved stereo output.
+    ///
+    /// The samples go through the whole path of the player, the input layout, resampling, effects,
+    /// volume and dither, the backend set on the builder is replaced. The samples are queued in chunks
+    /// of a fixed size, and the samples kept by the resampler are flushed at the end,
+    /// so the same input and configuration always render the same output, for golden-file tests.
+    /// See [`build_offline`](Self::build_offline) to render in several steps or into other sample types.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::AudioPlayer;
+    /// let input = (0..44100)
+    ///     .flat_map(|i| [(i as f32 / 100.0).sin() * 0.5; 2])
+    ///     .collect::<Vec<_>>();
+    /// let render = || AudioPlayer::<f32>::builder(44100).render(48000, &input).unwrap();
+    ///
+    /// let output = render();
+    /// assert!(output.len() >= 48000 * 2);
+    /// assert_eq!(output, render());
+    /// ```
+    pub fn render(self, output_rate: u32, data: &[T]) -> Result<Vec<T>, AudioPlayerError> {
+        let mut renderer = self.build_offline(output_rate)?;
+        let mut output = Vec::new();
+        renderer.render(data, &mut output);
+        renderer.finish(&mut output);
+        Ok(output)
+    }
+}
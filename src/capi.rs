@@ -0,0 +1,173 @@
+//! C API, enabled with the `capi` feature.
+//!
+//! Plays interleaved stereo `f32` or `i16` samples on the default output device.
+//! The header is in `include/dynwave.h`, and the shared and static libraries are built by the
+//! `dynwave-capi` crate in the `capi` directory, with `cargo build --release -p dynwave-capi`.
+//!
+//! Functions returning `int` return `0` on success and `-1` on failure.
+//! Panics don't unwind into C, the functions fail instead.
+
+use std::{
+    ffi::c_int,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{AudioPlayer, BufferSize};
+
+/// An [`AudioPlayer`] of `f32` samples, opaque to C.
+pub struct DynwavePlayer {
+    player: AudioPlayer<f32>,
+    // converted `i16` samples
+    scratch: Vec<f32>,
+}
+
+/// Run `body`, returning `failed` if it panics, as unwinding into C is undefined behavior
+fn catch_panic<R>(failed: R, body: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(failed)
+}
+
+fn result_code<E>(result: Result<(), E>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Creates a player for the default output device, with samples of `sample_rate` Hz.
+///
+/// `buffer_samples` is the number of samples the buffer can hold, see [`BufferSize::Samples`],
+/// or `0` for the default of a quarter of a second.
+///
+/// Returns `NULL` on failure. The player must be freed with [`dynwave_free`].
+#[no_mangle]
+pub extern "C" fn dynwave_new(sample_rate: u32, buffer_samples: usize) -> *mut DynwavePlayer {
+    catch_panic(ptr::null_mut(), || {
+        let buffer_size = match buffer_samples {
+            0 => BufferSize::default(),
+            samples => BufferSize::Samples(samples),
+        };
+        match AudioPlayer::new(sample_rate, buffer_size) {
+            Ok(player) => Box::into_raw(Box::new(DynwavePlayer {
+                player,
+                scratch: Vec::new(),
+            })),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Stops and frees the player.
+///
+/// # Safety
+/// `player` must be `NULL` or returned by [`dynwave_new`], and not used after this.
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_free(player: *mut DynwavePlayer) {
+    if !player.is_null() {
+        catch_panic((), || drop(Box::from_raw(player)));
+    }
+}
+
+/// Queues `len` interleaved stereo samples from `samples`, see [`AudioPlayer::queue`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`], and `samples` must point to `len` valid samples.
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_queue(
+    player: *mut DynwavePlayer,
+    samples: *const f32,
+    len: usize,
+) {
+    let Some(player) = player.as_mut() else {
+        return;
+    };
+    if samples.is_null() || len == 0 {
+        return;
+    }
+    catch_panic((), || {
+        player.player.queue(slice::from_raw_parts(samples, len));
+    });
+}
+
+/// Same as [`dynwave_queue`], but for `i16` samples.
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`], and `samples` must point to `len` valid samples.
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_queue_i16(
+    player: *mut DynwavePlayer,
+    samples: *const i16,
+    len: usize,
+) {
+    let Some(player) = player.as_mut() else {
+        return;
+    };
+    if samples.is_null() || len == 0 {
+        return;
+    }
+    let samples = slice::from_raw_parts(samples, len);
+    catch_panic((), || {
+        player.scratch.clear();
+        player
+            .scratch
+            .extend(samples.iter().map(|&sample| sample as f32 / 32768.0));
+        player.player.queue(&player.scratch);
+    });
+}
+
+/// Starts the player, see [`AudioPlayer::play`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`].
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_play(player: *mut DynwavePlayer) -> c_int {
+    match player.as_ref() {
+        Some(player) => catch_panic(-1, || result_code(player.player.play())),
+        None => -1,
+    }
+}
+
+/// Pauses the player, see [`AudioPlayer::pause`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`].
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_pause(player: *mut DynwavePlayer) -> c_int {
+    match player.as_ref() {
+        Some(player) => catch_panic(-1, || result_code(player.player.pause())),
+        None => -1,
+    }
+}
+
+/// Removes the samples waiting to be played, see [`AudioPlayer::clear`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`].
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_clear(player: *mut DynwavePlayer) {
+    if let Some(player) = player.as_mut() {
+        catch_panic((), || player.player.clear());
+    }
+}
+
+/// The number of samples waiting to be played, see [`AudioPlayer::buffered_samples`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`].
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_buffered_samples(player: *const DynwavePlayer) -> usize {
+    player.as_ref().map_or(0, |player| {
+        catch_panic(0, || player.player.buffered_samples())
+    })
+}
+
+/// The sample rate used by the device, see [`AudioPlayer::output_sample_rate`].
+///
+/// # Safety
+/// `player` must be returned by [`dynwave_new`].
+#[no_mangle]
+pub unsafe extern "C" fn dynwave_output_sample_rate(player: *const DynwavePlayer) -> u32 {
+    player.as_ref().map_or(0, |player| {
+        catch_panic(0, || player.player.output_sample_rate())
+    })
+}
@@ -0,0 +1,73 @@
+use std::any::TypeId;
+
+use cpal::{FromSample, SizedSample};
+
+/// The scale of `f32` samples in `i16`, same as [`FromSample`]
+const I16_SCALE: f32 = 32768.0;
+
+/// Convert `input` into `output`, with the same result as converting each sample with [`FromSample`].
+///
+/// `f32` to `i16`, the most common conversion for devices without float formats, has its own path,
+/// vectorized with NEON on `aarch64` with the `neon` feature.
+pub(crate) fn convert_samples<T, S>(input: &[T], output: &mut [S])
+where
+    T: SizedSample + 'static,
+    S: SizedSample + FromSample<T> + 'static,
+{
+    if TypeId::of::<T>() == TypeId::of::<f32>() && TypeId::of::<S>() == TypeId::of::<i16>() {
+        // SAFETY: `T` is `f32` and `S` is `i16`, the slices keep their lengths
+        let (input, output) = unsafe {
+            (
+                std::slice::from_raw_parts(input.as_ptr().cast::<f32>(), input.len()),
+                std::slice::from_raw_parts_mut(output.as_mut_ptr().cast::<i16>(), output.len()),
+            )
+        };
+        f32_to_i16(input, output);
+        return;
+    }
+    for (out, &sample) in output.iter_mut().zip(input) {
+        *out = S::from_sample(sample);
+    }
+}
+
+fn f32_to_i16(input: &[f32], output: &mut [i16]) {
+    let len = input.len().min(output.len());
+    let (input, output) = (&input[..len], &mut output[..len]);
+    #[cfg(all(feature = "neon", target_arch = "aarch64"))]
+    let (input, output) = neon::f32_to_i16(input, output);
+    for (out, &sample) in output.iter_mut().zip(input) {
+        // `as` saturates, so the clamp is done by the conversion itself
+        *out = (sample * I16_SCALE) as i16;
+    }
+}
+
+#[cfg(all(feature = "neon", target_arch = "aarch64"))]
+mod neon {
+    use std::arch::aarch64::{
+        vcombine_s16, vcvtq_s32_f32, vld1q_f32, vmulq_n_f32, vqmovn_s32, vst1q_s16,
+    };
+
+    use super::I16_SCALE;
+
+    /// Convert the samples 8 at a time, returns the remaining ones.
+    ///
+    /// Like `as`, the conversion truncates towards zero and saturates, first to `i32` then to `i16`.
+    pub fn f32_to_i16<'a, 'b>(
+        input: &'a [f32],
+        output: &'b mut [i16],
+    ) -> (&'a [f32], &'b mut [i16]) {
+        let converted = input.len() / 8 * 8;
+        for (input, output) in input.chunks_exact(8).zip(output.chunks_exact_mut(8)) {
+            // SAFETY: NEON is always available on `aarch64`, and the chunks have 8 samples
+            unsafe {
+                let low = vcvtq_s32_f32(vmulq_n_f32(vld1q_f32(input.as_ptr()), I16_SCALE));
+                let high = vcvtq_s32_f32(vmulq_n_f32(vld1q_f32(input.as_ptr().add(4)), I16_SCALE));
+                vst1q_s16(
+                    output.as_mut_ptr(),
+                    vcombine_s16(vqmovn_s32(low), vqmovn_s32(high)),
+                );
+            }
+        }
+        (&input[converted..], &mut output[converted..])
+    }
+}
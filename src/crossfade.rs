@@ -0,0 +1,80 @@
+use cpal::SizedSample;
+use ringbuf::{
+    traits::{Consumer, Observer, Producer},
+    HeapCons, HeapProd,
+};
+
+use crate::resampler::AudioResampler;
+use crate::sample::FloatSample;
+
+/// The old stream of [`AudioPlayer::crossfade`](crate::AudioPlayer::crossfade)
+/// and [`AudioPlayer::swap_source`](crate::AudioPlayer::swap_source).
+///
+/// It starts with the samples that were queued in the player, and more samples of the old stream
+/// can be queued into it while it's played, the player itself moves to the new stream.
+///
+/// The samples are interleaved stereo, same as [`AudioPlayer::queue`](crate::AudioPlayer::queue),
+/// with the sample rate of the player.
+pub struct OutgoingStream<T: FloatSample> {
+    producer: HeapProd<T>,
+    resampler: Option<AudioResampler<T>>,
+}
+
+impl<T: FloatSample + SizedSample> OutgoingStream<T> {
+    pub(crate) fn new(producer: HeapProd<T>, resampler: Option<AudioResampler<T>>) -> Self {
+        Self {
+            producer,
+            resampler,
+        }
+    }
+
+    /// Queue more samples of the old stream.
+    ///
+    /// Samples that don't fit in the buffer, or queued after the old stream ended, are dropped.
+    pub fn queue(&mut self, data: &[T]) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.resample_into_producer(data, &mut self.producer);
+        } else {
+            self.producer.push_slice(data);
+        }
+    }
+
+    /// Returns `true` once the crossfade ended, and the old stream is not played anymore.
+    pub fn is_finished(&self) -> bool {
+        !self.producer.read_is_held()
+    }
+}
+
+/// The output side of a crossfade, fades the old samples out while the new ones are faded in
+pub(crate) struct CrossfadeSource<T> {
+    old: HeapCons<T>,
+    frames: usize,
+    played: usize,
+}
+
+impl<T: FloatSample + SizedSample> CrossfadeSource<T> {
+    pub fn new(old: HeapCons<T>, frames: usize) -> Self {
+        Self {
+            old,
+            frames: frames.max(1),
+            played: 0,
+        }
+    }
+
+    /// Mix the old samples into the new ones in `output`, returns `true` when the fade is done
+    pub fn mix_into(&mut self, output: &mut [T], channels: usize) -> bool {
+        for frame in output.chunks_mut(channels) {
+            if self.played >= self.frames {
+                break;
+            }
+            let fade_in = T::coerce(self.played as f32 / self.frames as f32);
+            for new in frame {
+                // the old stream is silent if it ran out of samples
+                let old = self.old.try_pop().unwrap_or(T::EQUILIBRIUM);
+                *new = *new * fade_in + old * (T::coerce(1.0) - fade_in);
+            }
+            self.played += 1;
+        }
+        self.played >= self.frames
+    }
+}
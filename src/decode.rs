@@ -0,0 +1,45 @@
+//! Decoding audio files to queue into the player, used by [`AudioPlayer::queue_reader`](crate::AudioPlayer::queue_reader)
+use std::io::Read;
+
+use cpal::{FromSample, SizedSample};
+
+use crate::sample::FloatSample;
+use crate::{downmix::InputLayout, error::AudioPlayerError, resampler::AudioResampler};
+
+/// Decode the WAV audio in `reader` into stereo samples at `sample_rate`
+pub(crate) fn decode_wav<T, R>(reader: R, sample_rate: u32) -> Result<Vec<T>, AudioPlayerError>
+where
+    T: FloatSample + SizedSample + FromSample<f32>,
+    R: Read,
+{
+    let reader = hound::WavReader::new(reader)?;
+    let spec = reader.spec();
+    let layout = match spec.channels {
+        1 => InputLayout::Mono,
+        2 => InputLayout::Stereo,
+        6 => InputLayout::Surround51,
+        channels => return Err(AudioPlayerError::UnsupportedInputChannels(channels)),
+    };
+
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|s| s.map(T::from_sample_))
+            .collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| T::from_sample_(s as f32 * scale)))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let mut stereo = Vec::with_capacity(samples.len() / layout.channels() * 2);
+    layout.convert_to_stereo(&samples, &mut stereo);
+
+    match AudioResampler::for_rates(spec.sample_rate, sample_rate, 2, false)? {
+        Some(mut resampler) => Ok(resampler.resample_all(&stereo)),
+        None => Ok(stereo),
+    }
+}
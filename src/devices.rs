@@ -0,0 +1,213 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    SampleFormat,
+};
+
+use crate::{error::AudioPlayerError, utils};
+
+/// The sample rates checked in the ranges of the configurations by [`OutputDevice::supported_passthrough_rates`]
+const COMMON_SAMPLE_RATES: [u32; 11] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// A range of output configurations supported by a device, see [`OutputDevice::configs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceConfig {
+    /// The number of channels.
+    pub channels: u16,
+    /// The minimum supported sample rate in Hz.
+    pub min_sample_rate: u32,
+    /// The maximum supported sample rate in Hz.
+    pub max_sample_rate: u32,
+    /// The sample format.
+    pub sample_format: SampleFormat,
+}
+
+/// An output device and a summary of its capabilities, returned by [`devices`].
+#[derive(Clone)]
+pub struct OutputDevice {
+    name: String,
+    is_default: bool,
+    configs: Vec<DeviceConfig>,
+    device: cpal::Device,
+}
+
+impl OutputDevice {
+    /// The name of the device, as reported by the host.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this is the default output device of the host.
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+
+    /// The configurations supported by the device.
+    ///
+    /// Empty if the device failed to report them, e.g. if it's used exclusively by another application.
+    pub fn configs(&self) -> &[DeviceConfig] {
+        &self.configs
+    }
+
+    /// The numbers of channels supported by the device, sorted and without duplicates.
+    pub fn channel_counts(&self) -> Vec<u16> {
+        let mut channels = self.configs.iter().map(|c| c.channels).collect::<Vec<_>>();
+        channels.sort_unstable();
+        channels.dedup();
+        channels
+    }
+
+    /// The lowest and highest sample rates supported by the device, or `None` if there are no configurations.
+    pub fn sample_rate_range(&self) -> Option<(u32, u32)> {
+        let min = self.configs.iter().map(|c| c.min_sample_rate).min()?;
+        let max = self.configs.iter().map(|c| c.max_sample_rate).max()?;
+        Some((min, max))
+    }
+
+    /// The sample formats supported by the device, without duplicates.
+    pub fn sample_formats(&self) -> Vec<SampleFormat> {
+        let mut formats = Vec::new();
+        for config in &self.configs {
+            if !formats.contains(&config.sample_format) {
+                formats.push(config.sample_format);
+            }
+        }
+        formats
+    }
+
+    /// Whether the device supports `sample_rate` with stereo output, which avoids resampling.
+    pub fn supports_sample_rate(&self, sample_rate: u32) -> bool {
+        self.configs.iter().any(|c| {
+            c.channels == 2 && c.min_sample_rate <= sample_rate && c.max_sample_rate >= sample_rate
+        })
+    }
+
+    /// The input sample rates that can be played on this device without resampling, sorted and without duplicates.
+    ///
+    /// These are the common sample rates in the ranges of the stereo configurations, and the rates of the
+    /// configurations with a single rate. Applications that can choose the rate they produce, like emulators
+    /// mixing their audio, can pick one of them to save the cost of resampling.
+    pub fn supported_passthrough_rates(&self) -> Vec<u32> {
+        let mut rates = Vec::new();
+        for c in self
+            .configs
+            .iter()
+            .filter(|c| c.channels == 2 && utils::is_playable(c.sample_format))
+        {
+            if c.min_sample_rate == c.max_sample_rate {
+                rates.push(c.min_sample_rate);
+            }
+            rates.extend(
+                COMMON_SAMPLE_RATES
+                    .iter()
+                    .filter(|&&rate| c.min_sample_rate <= rate && c.max_sample_rate >= rate),
+            );
+        }
+        rates.sort_unstable();
+        rates.dedup();
+        rates
+    }
+
+    /// The [cpal] device, to be used with [`AudioPlayerBuilder::device`](crate::AudioPlayerBuilder::device).
+    pub fn device(&self) -> &cpal::Device {
+        &self.device
+    }
+
+    /// Returns the [cpal] device, to be used with [`AudioPlayerBuilder::device`](crate::AudioPlayerBuilder::device).
+    pub fn into_device(self) -> cpal::Device {
+        self.device
+    }
+}
+
+impl std::fmt::Debug for OutputDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputDevice")
+            .field("name", &self.name)
+            .field("is_default", &self.is_default)
+            .field("configs", &self.configs)
+            .finish()
+    }
+}
+
+/// List the output devices of the default host, with their supported configurations.
+///
+/// Devices that fail to report their name are skipped.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{devices, AudioPlayer};
+/// let devices = devices().unwrap();
+/// for device in &devices {
+///     println!("{} {:?} {:?}", device.name(), device.sample_rate_range(), device.sample_formats());
+/// }
+///
+/// let selected = devices.into_iter().find(|d| d.supports_sample_rate(44100)).unwrap();
+/// let player = AudioPlayer::<f32>::builder(44100)
+///     .device(selected.into_device())
+///     .build()
+///     .unwrap();
+/// ```
+pub fn devices() -> Result<Vec<OutputDevice>, AudioPlayerError> {
+    host_devices(&cpal::default_host())
+}
+
+/// Same as [`devices`], but for the host `host`, see [`cpal::available_hosts`].
+pub fn devices_of_host(host: cpal::HostId) -> Result<Vec<OutputDevice>, AudioPlayerError> {
+    host_devices(&cpal::host_from_id(host)?)
+}
+
+/// The input sample rates that can be played without resampling on the default output device of the default host,
+/// see [`OutputDevice::supported_passthrough_rates`].
+///
+/// Returns [`AudioPlayerError::NoOutputDevice`] if there is no default output device.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{supported_passthrough_rates, AudioPlayer};
+/// let rates = supported_passthrough_rates().unwrap();
+/// // mix the emulator audio at 48000 Hz if the device plays it, otherwise resample
+/// let sample_rate = if rates.contains(&48000) { 48000 } else { 44100 };
+/// let player = AudioPlayer::<f32>::builder(sample_rate).build().unwrap();
+/// ```
+pub fn supported_passthrough_rates() -> Result<Vec<u32>, AudioPlayerError> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or(AudioPlayerError::NoOutputDevice)?;
+    let name = device.name().unwrap_or_default();
+    Ok(output_device(device, name, true).supported_passthrough_rates())
+}
+
+fn host_devices(host: &cpal::Host) -> Result<Vec<OutputDevice>, AudioPlayerError> {
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+
+    let mut devices = Vec::new();
+    for device in host.output_devices()? {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let is_default = default_name.as_deref() == Some(name.as_str());
+        devices.push(output_device(device, name, is_default));
+    }
+    Ok(devices)
+}
+
+fn output_device(device: cpal::Device, name: String, is_default: bool) -> OutputDevice {
+    let configs = match device.supported_output_configs() {
+        Ok(configs) => configs
+            .map(|c| DeviceConfig {
+                channels: c.channels(),
+                min_sample_rate: c.min_sample_rate().0,
+                max_sample_rate: c.max_sample_rate().0,
+                sample_format: c.sample_format(),
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    OutputDevice {
+        name,
+        is_default,
+        configs,
+        device,
+    }
+}
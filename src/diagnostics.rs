@@ -0,0 +1,133 @@
+use std::fmt;
+
+use cpal::SampleFormat;
+
+use crate::{backend::OutputConfig, ResamplingAlgorithm};
+
+/// The maximum number of diagnostics kept by the player, newer ones are dropped
+pub(crate) const DIAGNOSTICS_CAPACITY: usize = 64;
+
+/// A decision or a notable event of the player, collected to be shown to the users,
+/// for example in the bug reports of an application, see [`AudioPlayer::diagnostics`](crate::AudioPlayer::diagnostics).
+///
+/// These are not errors, the player works as expected, but they explain why the audio might be
+/// different from the queued samples, or the latency higher.
+/// The problems of the output are reported with [`CallbackError`](crate::error::CallbackError) instead.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, Diagnostic};
+/// let player = AudioPlayer::<f32>::builder(44100)
+///     .backend(NullBackend::new().sample_rate(48000))
+///     .build()
+///     .unwrap();
+/// assert!(player
+///     .diagnostics()
+///     .contains(&Diagnostic::Resampling { from: 44100, to: 48000 }));
+///
+/// for diagnostic in player.diagnostics() {
+///     println!("{diagnostic}");
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// The output plays on the device with this name.
+    Device(String),
+    /// The output doesn't support the sample rate of the queued samples, so they are resampled.
+    Resampling {
+        /// The sample rate of the queued samples.
+        from: u32,
+        /// The sample rate of the output.
+        to: u32,
+    },
+    /// The output doesn't support the sample format of the queued samples, so they are converted.
+    FormatConversion {
+        /// The sample format of the queued samples.
+        from: SampleFormat,
+        /// The sample format of the output.
+        to: SampleFormat,
+    },
+    /// The output isn't stereo, the queued stereo samples are mapped to its channels.
+    ChannelMapping {
+        /// The number of channels of the output.
+        channels: u16,
+    },
+    /// Resampling took longer than the limit, so the resampler switched to a cheaper algorithm,
+    /// see [`AudioPlayerBuilder::resampler_load_limit`](crate::AudioPlayerBuilder::resampler_load_limit).
+    ResamplingDegraded {
+        /// The algorithm used from now on.
+        to: ResamplingAlgorithm,
+    },
+    /// The output stream stopped and was built again, see [`RecoveryPolicy`](crate::RecoveryPolicy).
+    StreamRecovered,
+    /// The output stream was built again after the machine slept,
+    /// see [`AudioPlayer::recover_from_sleep`](crate::AudioPlayer::recover_from_sleep).
+    SleepRecovered,
+    /// The output stream stopped, and the output moved to the standby device,
+    /// see [`AudioPlayer::set_standby_device`](crate::AudioPlayer::set_standby_device).
+    StandbyActivated,
+}
+
+impl Diagnostic {
+    /// The decisions of the negotiation of `output`, for queued samples of `sample_rate` and `sample_format`
+    pub(crate) fn for_output(
+        device: Option<String>,
+        sample_rate: u32,
+        sample_format: SampleFormat,
+        output: &OutputConfig,
+    ) -> Vec<Self> {
+        let mut diagnostics = Vec::new();
+        diagnostics.extend(device.map(Self::Device));
+        if output.sample_rate != sample_rate {
+            diagnostics.push(Self::Resampling {
+                from: sample_rate,
+                to: output.sample_rate,
+            });
+        }
+        if output.sample_format != sample_format {
+            diagnostics.push(Self::FormatConversion {
+                from: sample_format,
+                to: output.sample_format,
+            });
+        }
+        if output.channels != 2 {
+            diagnostics.push(Self::ChannelMapping {
+                channels: output.channels,
+            });
+        }
+        diagnostics
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Device(name) => write!(f, "Playing on the device `{}`", name),
+            Self::Resampling { from, to } => write!(f, "Resampling {}Hz to {}Hz", from, to),
+            Self::FormatConversion { from, to } => {
+                write!(f, "Converting the samples from {} to {}", from, to)
+            }
+            Self::ChannelMapping { channels } => {
+                write!(
+                    f,
+                    "Mapping stereo to the {} channels of the output",
+                    channels
+                )
+            }
+            Self::ResamplingDegraded { to } => write!(
+                f,
+                "Resampling was too slow, switched to the {:?} algorithm",
+                to
+            ),
+            Self::StreamRecovered => write!(f, "The output stream stopped and was built again"),
+            Self::SleepRecovered => write!(f, "The output stream was built again after a sleep"),
+            Self::StandbyActivated => {
+                write!(
+                    f,
+                    "The output stream stopped and moved to the standby device"
+                )
+            }
+        }
+    }
+}
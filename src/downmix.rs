@@ -0,0 +1,129 @@
+use cpal::{FromSample, SizedSample};
+
+use crate::sample::FloatSample;
+
+/// `-3dB`, the gain of the center and surround channels in the downmix
+const MINUS_3DB: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// The channel layout of the samples queued into the player,
+/// set with [`AudioPlayerBuilder::input_layout`](crate::AudioPlayerBuilder::input_layout).
+///
+/// The player always outputs stereo, other layouts are converted when queued.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, InputLayout};
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .input_layout(InputLayout::Surround51)
+///     .build()
+///     .unwrap();
+///
+/// // one frame of 5.1 audio, front left, front right, center, LFE, surround left, surround right
+/// player.queue(&[1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+/// assert_eq!(player.buffered_samples(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputLayout {
+    /// One channel, played on both output channels.
+    Mono,
+    #[default]
+    /// Two interleaved channels, left and right.
+    Stereo,
+    /// Six interleaved channels in the usual WAVE order: front left, front right, center,
+    /// low frequency (LFE), surround left and surround right.
+    ///
+    /// Downmixed to stereo with the ITU-R BS.775 coefficients, the center and surround channels
+    /// are mixed at `-3dB`, and the LFE channel is dropped.
+    /// The result is scaled down so that full scale input can't clip.
+    Surround51,
+}
+
+impl InputLayout {
+    /// The number of interleaved channels in this layout.
+    pub fn channels(&self) -> usize {
+        match self {
+            Self::Mono => 1,
+            Self::Stereo => 2,
+            Self::Surround51 => 6,
+        }
+    }
+
+    /// Convert `data` in this layout into stereo samples in `output`
+    pub(crate) fn convert_to_stereo<T: FloatSample>(&self, data: &[T], output: &mut Vec<T>) {
+        output.clear();
+        match self {
+            Self::Mono => {
+                for &sample in data {
+                    output.push(sample);
+                    output.push(sample);
+                }
+            }
+            Self::Stereo => output.extend_from_slice(data),
+            Self::Surround51 => {
+                let side = T::coerce(MINUS_3DB);
+                let scale = T::coerce(1.0 / (1.0 + 2.0 * MINUS_3DB));
+                for frame in data.chunks_exact(6) {
+                    let [fl, fr, c, _lfe, sl, sr] =
+                        [frame[0], frame[1], frame[2], frame[3], frame[4], frame[5]];
+                    output.push((fl + c * side + sl * side) * scale);
+                    output.push((fr + c * side + sr * side) * scale);
+                }
+            }
+        }
+    }
+}
+
+/// What [`AudioPlayer::queue`](crate::AudioPlayer::queue) does with samples that don't make a whole frame
+/// of the [`InputLayout`], at the end of a call, set with
+/// [`AudioPlayerBuilder::partial_frames`](crate::AudioPlayerBuilder::partial_frames).
+///
+/// Queueing them as they are would swap the channels of all the samples after them.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer};
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .build()
+///     .unwrap();
+///
+/// // the right sample of the second frame comes with the next call
+/// player.queue(&[0.1, 0.2, 0.3]);
+/// assert_eq!(player.buffered_samples(), 2);
+/// player.queue(&[0.4]);
+/// assert_eq!(player.snapshot().buffered, [0.1, 0.2, 0.3, 0.4]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PartialFrames {
+    #[default]
+    /// Keep the samples, and queue them before the samples of the next call.
+    ///
+    /// This is the default.
+    Carry,
+    /// Drop the samples, they are counted in the samples dropped returned by `queue`,
+    /// so applications can notice they produce broken frames.
+    Discard,
+}
+
+/// Convert a frame of the player into a frame of a device with other channels,
+/// mixed to one channel for mono devices, and on the first channels of the device otherwise
+pub(crate) fn remap_frame<T, S>(frame: &[T], output: &mut [S])
+where
+    T: FloatSample,
+    S: SizedSample + FromSample<T>,
+{
+    if output.len() == 1 {
+        let sum = frame
+            .iter()
+            .fold(T::coerce(0.0), |sum, &sample| sum + sample);
+        output[0] = S::from_sample(sum / T::coerce(frame.len()));
+        return;
+    }
+    for (i, out) in output.iter_mut().enumerate() {
+        *out = match frame.get(i) {
+            Some(&sample) => S::from_sample(sample),
+            None => S::EQUILIBRIUM,
+        };
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+/// Minimum time measured before the drift is reported, shorter windows are dominated by the jitter of the timestamps
+const MIN_WINDOW: Duration = Duration::from_secs(5);
+/// The measurement restarts if the timestamps differ from the played frames by more than this,
+/// i.e. the stream was paused or the device skipped
+const MAX_JITTER: f64 = 0.1;
+/// Drifts further than this from the nominal rate are treated as broken timestamps
+pub(crate) const MAX_DRIFT: f64 = 0.01;
+
+/// Estimates how fast the device plays compared to its nominal sample rate,
+/// from the playback timestamps of the output callbacks
+pub(crate) struct DriftEstimator {
+    sample_rate: f64,
+    max_drift: f64,
+    // frames filled since the start of the measurement
+    frames: u64,
+    // the timestamp at the start of the measurement
+    start: Option<Instant>,
+    // the last timestamp, and the frames filled up to it
+    last: Option<(Instant, u64)>,
+}
+
+impl DriftEstimator {
+    /// Creates an estimator reporting drifts up to `max_drift` from the nominal rate, as a fraction of it
+    pub fn new(sample_rate: u32, max_drift: f64) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            max_drift,
+            frames: 0,
+            start: None,
+            last: None,
+        }
+    }
+
+    /// Count `frames` filled by the callback
+    pub fn filled(&mut self, frames: usize) {
+        self.frames += frames as u64;
+    }
+
+    /// The frames filled next will be played at `time`, returns the drift when enough time was measured
+    pub fn report(&mut self, time: Instant) -> Option<f64> {
+        if let Some((last_time, last_frames)) = self.last {
+            let expected = (self.frames - last_frames) as f64 / self.sample_rate;
+            let jitter = time
+                .checked_duration_since(last_time)
+                .map_or(f64::INFINITY, |elapsed| elapsed.as_secs_f64() - expected);
+            if jitter.abs() > MAX_JITTER {
+                self.start = None;
+            }
+        }
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(time);
+                self.frames = 0;
+                time
+            }
+        };
+        self.last = Some((time, self.frames));
+
+        let elapsed = time.saturating_duration_since(start);
+        if elapsed < MIN_WINDOW {
+            return None;
+        }
+        let drift = self.frames as f64 / elapsed.as_secs_f64() / self.sample_rate;
+        ((drift - 1.0).abs() <= self.max_drift).then_some(drift)
+    }
+}
@@ -0,0 +1,1362 @@
+//! Processing stages applied to the output of the player.
+//!
+//! Effects run in the output callback on the interleaved samples, after resampling and mixing,
+//! right before they are converted to the output sample format.
+//! They are added with [`AudioPlayerBuilder::effect`](crate::AudioPlayerBuilder::effect)
+//! or [`AudioPlayer::add_effect`](crate::AudioPlayer::add_effect), and run in the order they were added.
+//!
+//! Any `FnMut(&mut [T]) + Send` closure can be used as an effect.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cpal::{FromSample, SizedSample};
+
+use crate::backend::OutputConfig;
+use crate::sample::FloatSample;
+
+/// A processing stage in the output of the player, see the [module docs](self).
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::{NullBackend, OutputConfig}, effects::AudioEffect, AudioPlayer};
+/// /// Swap the left and right channels
+/// struct SwapChannels;
+///
+/// impl AudioEffect<f32> for SwapChannels {
+///     fn process(&mut self, samples: &mut [f32], config: &OutputConfig) {
+///         for frame in samples.chunks_exact_mut(config.channels as usize) {
+///             frame.swap(0, 1);
+///         }
+///     }
+/// }
+///
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(SwapChannels)
+///     .build()
+///     .unwrap();
+///
+/// // closures can be used as well
+/// player.add_effect(|samples: &mut [f32]| samples.iter_mut().for_each(|s| *s *= 0.5));
+/// ```
+pub trait AudioEffect<T>: Send {
+    /// Process the interleaved `samples` in place.
+    ///
+    /// This is called from the output callback, so it should not block.
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig);
+}
+
+impl<T, F> AudioEffect<T> for F
+where
+    F: FnMut(&mut [T]) + Send,
+{
+    fn process(&mut self, samples: &mut [T], _config: &OutputConfig) {
+        self(samples)
+    }
+}
+
+/// A soft clipper that keeps the samples within full scale (`-1.0..=1.0`).
+///
+/// Samples below the threshold are not changed, and above it they are gradually compressed
+/// towards full scale, instead of the harsh clipping that happens when converting to integer formats.
+///
+/// Enabled at the end of the output with [`AudioPlayerBuilder::limiter`](crate::AudioPlayerBuilder::limiter),
+/// or can be added to the effect chain directly.
+#[derive(Debug, Clone, Copy)]
+pub struct SoftLimiter {
+    threshold: f32,
+}
+
+impl Default for SoftLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SoftLimiter {
+    /// Creates a limiter with a threshold of `0.8`.
+    pub fn new() -> Self {
+        Self { threshold: 0.8 }
+    }
+
+    /// Set the level where the limiter starts to compress the samples, clamped to `0.0..=0.99`.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.clamp(0.0, 0.99);
+        self
+    }
+
+    fn limit<T: FloatSample + SizedSample>(&self, sample: T) -> T {
+        let threshold = T::coerce(self.threshold);
+        let (magnitude, negative) = if sample < T::EQUILIBRIUM {
+            (-sample, true)
+        } else {
+            (sample, false)
+        };
+        if magnitude <= threshold {
+            return sample;
+        }
+        // `u / (1 + u)` has a slope of 1 at the knee, and approaches 1 without reaching it
+        let headroom = T::coerce(1.0) - threshold;
+        let over = (magnitude - threshold) / headroom;
+        let limited = threshold + headroom * over / (T::coerce(1.0) + over);
+        if negative {
+            -limited
+        } else {
+            limited
+        }
+    }
+}
+
+impl<T: FloatSample + SizedSample> AudioEffect<T> for SoftLimiter {
+    fn process(&mut self, samples: &mut [T], _config: &OutputConfig) {
+        for sample in samples.iter_mut() {
+            *sample = self.limit(*sample);
+        }
+    }
+}
+
+/// Dithering applied when the output format is an integer format with 16 bits or less.
+///
+/// Without dithering, the samples are truncated to the integer format, which causes distortion
+/// that can be heard in quiet sounds, dithering replaces it with a low level noise.
+///
+/// Set with [`AudioPlayerBuilder::dither`](crate::AudioPlayerBuilder::dither).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dither {
+    #[default]
+    /// No dithering, the samples are converted as is.
+    None,
+    /// Triangular probability density function dither, white noise of up to 1 LSB.
+    Tpdf,
+    /// Like [`Dither::Tpdf`], but the noise is shaped towards the high frequencies,
+    /// where it's less audible.
+    NoiseShaped,
+}
+
+/// Applies [`Dither`] to the samples before they are converted
+pub(crate) struct Ditherer {
+    mode: Dither,
+    // xorshift state
+    rng: u32,
+    // the previous random value for each channel, for noise shaping
+    previous: Vec<f32>,
+}
+
+impl Ditherer {
+    pub fn new(mode: Dither, channels: usize) -> Self {
+        Self {
+            mode,
+            rng: 0x9E37_79B9,
+            previous: vec![0.0; channels.max(1)],
+        }
+    }
+
+    /// Uniform random value in `-0.5..0.5`
+    fn next_random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f32 / u32::MAX as f32) - 0.5
+    }
+
+    /// Add dither noise to `samples` which will be converted to an integer format of `bits` bits
+    pub fn apply<T: FloatSample>(&mut self, samples: &mut [T], bits: u32) {
+        if self.mode == Dither::None || bits > 16 {
+            return;
+        }
+        // the step between two values of the integer format, in the range of `-1.0..=1.0`
+        let lsb = 2.0 / (1u32 << bits) as f32;
+        let channels = self.previous.len();
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let noise = match self.mode {
+                Dither::None => unreachable!(),
+                Dither::Tpdf => self.next_random() + self.next_random(),
+                Dither::NoiseShaped => {
+                    // the difference of two consecutive values is TPDF with a high-pass slope
+                    let random = self.next_random();
+                    let previous = std::mem::replace(&mut self.previous[i % channels], random);
+                    random - previous
+                }
+            };
+            *sample += T::coerce(noise * lsb);
+        }
+    }
+}
+
+/// Removes the DC offset from the samples, with a one-pole high-pass filter.
+///
+/// Emulated sound chips often output a signal that is not centered around zero,
+/// which causes pops when the player starts or stops, and reduces the headroom before clipping.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::DcBlocker, AudioPlayer};
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(DcBlocker::new())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DcBlocker<T> {
+    cutoff: f32,
+    // the coefficient for `sample_rate`, computed on the first process
+    coefficient: Option<(u32, T)>,
+    // previous input and output for each channel
+    state: Vec<(T, T)>,
+}
+
+impl<T: FloatSample + SizedSample> Default for DcBlocker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: FloatSample + SizedSample> DcBlocker<T> {
+    /// Creates a DC blocker with a cutoff of `20Hz`.
+    pub fn new() -> Self {
+        Self {
+            cutoff: 20.0,
+            coefficient: None,
+            state: Vec::new(),
+        }
+    }
+
+    /// Set the cutoff frequency in Hz, frequencies below it are attenuated.
+    pub fn cutoff(mut self, cutoff: f32) -> Self {
+        self.cutoff = cutoff.max(0.0);
+        self.coefficient = None;
+        self
+    }
+
+    fn coefficient(&mut self, sample_rate: u32) -> T {
+        match self.coefficient {
+            Some((rate, coefficient)) if rate == sample_rate => coefficient,
+            _ => {
+                let coefficient = T::coerce(
+                    (1.0 - 2.0 * std::f64::consts::PI * self.cutoff as f64 / sample_rate as f64)
+                        .max(0.0),
+                );
+                self.coefficient = Some((sample_rate, coefficient));
+                coefficient
+            }
+        }
+    }
+}
+
+impl<T: FloatSample + SizedSample> AudioEffect<T> for DcBlocker<T> {
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let coefficient = self.coefficient(config.sample_rate);
+        let channels = config.channels.max(1) as usize;
+        self.state
+            .resize(channels, (T::EQUILIBRIUM, T::EQUILIBRIUM));
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let (previous_input, previous_output) = &mut self.state[i % channels];
+            let output = *sample - *previous_input + coefficient * *previous_output;
+            *previous_input = *sample;
+            *previous_output = output;
+            *sample = output;
+        }
+    }
+}
+
+/// The response of a [`Biquad`] filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BiquadKind {
+    LowPass,
+    /// Boost or cut below the frequency, by the gain in dB
+    LowShelf(f32),
+    /// Boost or cut around the frequency, by the gain in dB
+    Peaking(f32),
+    /// Boost or cut above the frequency, by the gain in dB
+    HighShelf(f32),
+}
+
+/// A second order IIR filter, with the coefficients from the "Audio EQ Cookbook" by Robert Bristow-Johnson
+#[derive(Debug, Clone)]
+struct Biquad<T> {
+    kind: BiquadKind,
+    frequency: f32,
+    q: f32,
+    // `b0, b1, b2, a1, a2` normalized by `a0`, for `sample_rate`
+    coefficients: Option<(u32, [T; 5])>,
+    // `x[n-1], x[n-2], y[n-1], y[n-2]` for each channel
+    state: Vec<[T; 4]>,
+}
+
+impl<T: FloatSample + SizedSample> Biquad<T> {
+    fn new(kind: BiquadKind, frequency: f32, q: f32) -> Self {
+        Self {
+            kind,
+            frequency,
+            q,
+            coefficients: None,
+            state: Vec::new(),
+        }
+    }
+
+    fn coefficients(&mut self, sample_rate: u32) -> [T; 5] {
+        if let Some((rate, coefficients)) = self.coefficients {
+            if rate == sample_rate {
+                return coefficients;
+            }
+        }
+        // keep the frequency below nyquist, so the filter stays stable
+        let frequency = (self.frequency as f64).clamp(1.0, sample_rate as f64 * 0.49);
+        let w0 = 2.0 * std::f64::consts::PI * frequency / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * self.q.max(0.01) as f64);
+        let cos_w0 = w0.cos();
+
+        let amplitude = |gain: f32| 10f64.powf(gain as f64 / 40.0);
+
+        let [b0, b1, b2, a0, a1, a2] = match self.kind {
+            BiquadKind::LowPass => [
+                (1.0 - cos_w0) / 2.0,
+                1.0 - cos_w0,
+                (1.0 - cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ],
+            BiquadKind::LowShelf(gain) => {
+                let a = amplitude(gain);
+                let sqrt_alpha = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_alpha),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_alpha),
+                    (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_alpha,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_alpha,
+                ]
+            }
+            BiquadKind::Peaking(gain) => {
+                let a = amplitude(gain);
+                [
+                    1.0 + alpha * a,
+                    -2.0 * cos_w0,
+                    1.0 - alpha * a,
+                    1.0 + alpha / a,
+                    -2.0 * cos_w0,
+                    1.0 - alpha / a,
+                ]
+            }
+            BiquadKind::HighShelf(gain) => {
+                let a = amplitude(gain);
+                let sqrt_alpha = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_alpha),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_alpha),
+                    (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_alpha,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_alpha,
+                ]
+            }
+        };
+        let coefficients = [b0, b1, b2, a1, a2].map(|c| T::coerce(c / a0));
+        self.coefficients = Some((sample_rate, coefficients));
+        coefficients
+    }
+
+    /// Change the kind of the filter, the state is kept, so it can be changed while playing
+    fn set_kind(&mut self, kind: BiquadKind) {
+        if self.kind != kind {
+            self.kind = kind;
+            self.coefficients = None;
+        }
+    }
+
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let [b0, b1, b2, a1, a2] = self.coefficients(config.sample_rate);
+        let channels = config.channels.max(1) as usize;
+        self.state.resize(channels, [T::EQUILIBRIUM; 4]);
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let [x1, x2, y1, y2] = &mut self.state[i % channels];
+            let x = *sample;
+            let y = b0 * x + b1 * *x1 + b2 * *x2 - a1 * *y1 - a2 * *y2;
+            *x2 = *x1;
+            *x1 = x;
+            *y2 = *y1;
+            *y1 = y;
+            *sample = y;
+        }
+    }
+}
+
+/// A low-pass filter, attenuates the frequencies above the cutoff.
+///
+/// This is mostly useful on the input samples before resampling, for crude sources like a 1-bit beeper,
+/// where the aliasing after resampling can be heard,
+/// see [`AudioPlayerBuilder::input_lowpass`](crate::AudioPlayerBuilder::input_lowpass).
+#[derive(Debug, Clone)]
+pub struct LowPass<T> {
+    filter: Biquad<T>,
+}
+
+impl<T: FloatSample + SizedSample> LowPass<T> {
+    /// Creates a low-pass filter with a cutoff of `cutoff` Hz.
+    pub fn new(cutoff: f32) -> Self {
+        Self {
+            filter: Biquad::new(BiquadKind::LowPass, cutoff, std::f32::consts::FRAC_1_SQRT_2),
+        }
+    }
+
+    /// Set the resonance of the filter at the cutoff, the default `0.707` has no resonance.
+    pub fn q(mut self, q: f32) -> Self {
+        self.filter.q = q;
+        self.filter.coefficients = None;
+        self
+    }
+}
+
+impl<T: FloatSample + SizedSample> AudioEffect<T> for LowPass<T> {
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        self.filter.process(samples, config);
+    }
+}
+
+/// The largest boost or cut of the [`Equalizer`] bands in dB
+const EQUALIZER_MAX_GAIN: f32 = 24.0;
+
+/// The gains of the [`Equalizer`] bands, from [`Equalizer::controls`].
+///
+/// The gains can be changed while playing, from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct EqualizerControls {
+    // `f32` bits of the gains in dB
+    gains: Arc<[AtomicU32; 3]>,
+}
+
+impl EqualizerControls {
+    fn set(&self, band: usize, gain: f32) {
+        let gain = gain.clamp(-EQUALIZER_MAX_GAIN, EQUALIZER_MAX_GAIN);
+        self.gains[band].store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self, band: usize) -> f32 {
+        f32::from_bits(self.gains[band].load(Ordering::Relaxed))
+    }
+
+    /// Set the gain of the bass band in dB, clamped to `-24.0..=24.0`.
+    pub fn set_bass(&self, gain: f32) {
+        self.set(0, gain);
+    }
+
+    /// Set the gain of the mid band in dB, clamped to `-24.0..=24.0`.
+    pub fn set_mid(&self, gain: f32) {
+        self.set(1, gain);
+    }
+
+    /// Set the gain of the treble band in dB, clamped to `-24.0..=24.0`.
+    pub fn set_treble(&self, gain: f32) {
+        self.set(2, gain);
+    }
+
+    /// The gain of the bass band in dB.
+    pub fn bass(&self) -> f32 {
+        self.get(0)
+    }
+
+    /// The gain of the mid band in dB.
+    pub fn mid(&self) -> f32 {
+        self.get(1)
+    }
+
+    /// The gain of the treble band in dB.
+    pub fn treble(&self) -> f32 {
+        self.get(2)
+    }
+}
+
+/// A 3-band tone equalizer: a bass shelf at `200Hz`, a mid peak at `1kHz`, and a treble shelf at `4kHz`.
+///
+/// All the bands start at `0dB`, so the samples are not changed until the gains are set with the [`EqualizerControls`].
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::Equalizer, AudioPlayer};
+/// let equalizer = Equalizer::new();
+/// let controls = equalizer.controls();
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(equalizer)
+///     .build()
+///     .unwrap();
+///
+/// // can be changed at any time after
+/// controls.set_bass(6.0);
+/// controls.set_treble(-3.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Equalizer<T> {
+    controls: EqualizerControls,
+    bands: [Biquad<T>; 3],
+}
+
+impl<T: FloatSample + SizedSample> Default for Equalizer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: FloatSample + SizedSample> Equalizer<T> {
+    /// Creates an equalizer with all the bands at `0dB`.
+    pub fn new() -> Self {
+        let q = std::f32::consts::FRAC_1_SQRT_2;
+        Self {
+            controls: EqualizerControls::default(),
+            bands: [
+                Biquad::new(BiquadKind::LowShelf(0.0), 200.0, q),
+                Biquad::new(BiquadKind::Peaking(0.0), 1000.0, q),
+                Biquad::new(BiquadKind::HighShelf(0.0), 4000.0, q),
+            ],
+        }
+    }
+
+    /// The controls to change the gains of the bands.
+    pub fn controls(&self) -> EqualizerControls {
+        self.controls.clone()
+    }
+}
+
+impl<T: FloatSample + SizedSample> AudioEffect<T> for Equalizer<T> {
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let [bass, mid, treble] = &mut self.bands;
+        bass.set_kind(BiquadKind::LowShelf(self.controls.bass()));
+        mid.set_kind(BiquadKind::Peaking(self.controls.mid()));
+        treble.set_kind(BiquadKind::HighShelf(self.controls.treble()));
+
+        for band in &mut self.bands {
+            band.process(samples, config);
+        }
+    }
+}
+
+/// Automatic gain that brings the loudness of the output to a target level.
+///
+/// The loudness is the RMS level of the output over the last few seconds, this is a simple measure,
+/// not a full EBU R128 implementation, but it's enough to avoid big volume jumps when switching
+/// between games with very different master volumes.
+///
+/// The gain changes slowly, so the dynamics of the sound are kept, and silence is not amplified.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::{LoudnessNormalizer, SoftLimiter}, AudioPlayer};
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(LoudnessNormalizer::new().target(-18.0))
+///     // the normalizer can amplify, the limiter makes sure it doesn't clip
+///     .limiter(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoudnessNormalizer {
+    target: f64,
+    max_gain: f64,
+    // the average of the squared samples
+    mean_square: Option<f64>,
+    gain: f64,
+}
+
+impl Default for LoudnessNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoudnessNormalizer {
+    /// Duration the loudness is averaged over, in seconds
+    const WINDOW: f64 = 3.0;
+    /// Time for the gain to get most of the way to a new target, in seconds
+    const GAIN_SMOOTHING: f64 = 1.0;
+    /// Blocks quieter than this mean square (-60dBFS) are not measured, so silence is not amplified
+    const GATE: f64 = 1e-6;
+
+    /// Creates a normalizer with a target of `-20dBFS` RMS, and a maximum gain of `12dB`.
+    pub fn new() -> Self {
+        Self {
+            target: db_to_power(-20.0),
+            max_gain: db_to_amplitude(12.0),
+            mean_square: None,
+            gain: 1.0,
+        }
+    }
+
+    /// Set the target loudness, as the RMS level in dBFS.
+    pub fn target(mut self, target: f32) -> Self {
+        self.target = db_to_power(target.min(0.0) as f64);
+        self
+    }
+
+    /// Set the maximum gain in dB, the output is never amplified more than this,
+    /// but it can be attenuated without limit.
+    pub fn max_gain(mut self, max_gain: f32) -> Self {
+        self.max_gain = db_to_amplitude(max_gain.max(0.0) as f64);
+        self
+    }
+
+    /// The current gain applied to the output, in dB.
+    pub fn current_gain(&self) -> f32 {
+        (20.0 * self.gain.log10()) as f32
+    }
+}
+
+fn db_to_amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+fn db_to_power(db: f64) -> f64 {
+    10f64.powf(db / 10.0)
+}
+
+impl<T> AudioEffect<T> for LoudnessNormalizer
+where
+    T: FloatSample + SizedSample,
+    f64: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        if samples.is_empty() {
+            return;
+        }
+        let block_mean_square = samples
+            .iter()
+            .map(|&sample| {
+                let sample = f64::from_sample_(sample);
+                sample * sample
+            })
+            .sum::<f64>()
+            / samples.len() as f64;
+
+        let frames = (samples.len() / config.channels.max(1) as usize) as f64;
+        let block_seconds = frames / config.sample_rate as f64;
+
+        if block_mean_square > Self::GATE {
+            let weight = (block_seconds / Self::WINDOW).min(1.0);
+            let mean_square = self.mean_square.get_or_insert(block_mean_square);
+            *mean_square += (block_mean_square - *mean_square) * weight;
+        }
+
+        let target_gain = match self.mean_square {
+            Some(mean_square) => (self.target / mean_square).sqrt().min(self.max_gain),
+            None => 1.0,
+        };
+        let previous_gain = self.gain;
+        self.gain += (target_gain - self.gain) * (block_seconds / Self::GAIN_SMOOTHING).min(1.0);
+
+        // move the gain gradually over the block to avoid steps
+        let step = (self.gain - previous_gain) / samples.len() as f64;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample *= T::coerce(previous_gain + step * (i + 1) as f64);
+        }
+    }
+}
+
+/// A noise gate, silences the output while it's quieter than a threshold,
+/// like the constant low-level hiss some emulated sound chips produce when they should be silent.
+///
+/// The gate opens over the attack time when a frame of any channel reaches the threshold,
+/// and closes over the release time after the output stayed below it for the hold time.
+///
+/// # Example
+/// ```rust
+/// # use std::time::Duration;
+/// # use dynwave::{backend::NullBackend, effects::NoiseGate, AudioPlayer};
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(NoiseGate::new().threshold(-50.0).release(Duration::from_millis(200)))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct NoiseGate {
+    // in amplitude
+    threshold: f64,
+    attack: Duration,
+    hold: Duration,
+    release: Duration,
+    gain: f64,
+    // the frames since the output was last above the threshold
+    quiet_frames: u64,
+}
+
+impl Default for NoiseGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseGate {
+    /// Creates a gate with a threshold of `-60dBFS`, an attack of `1ms`, a hold of `20ms`, and a release of `100ms`.
+    pub fn new() -> Self {
+        Self {
+            threshold: db_to_amplitude(-60.0),
+            attack: Duration::from_millis(1),
+            hold: Duration::from_millis(20),
+            release: Duration::from_millis(100),
+            gain: 1.0,
+            quiet_frames: 0,
+        }
+    }
+
+    /// Set the level in dBFS below which the output is silenced.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = db_to_amplitude(threshold.min(0.0) as f64);
+        self
+    }
+
+    /// Set the time for the gate to open fully.
+    pub fn attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Set the time the output must stay below the threshold before the gate closes,
+    /// so it doesn't close between the periods of low notes.
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Set the time for the gate to close fully.
+    pub fn release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+}
+
+impl<T> AudioEffect<T> for NoiseGate
+where
+    T: FloatSample + SizedSample,
+    f64: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let rate = config.sample_rate as f64;
+        let attack_step = 1.0 / (self.attack.as_secs_f64() * rate).max(1.0);
+        let release_step = 1.0 / (self.release.as_secs_f64() * rate).max(1.0);
+        let hold_frames = (self.hold.as_secs_f64() * rate) as u64;
+
+        for frame in samples.chunks_mut(config.channels.max(1) as usize) {
+            let peak = frame
+                .iter()
+                .map(|&sample| f64::from_sample_(sample).abs())
+                .fold(0.0, f64::max);
+            if peak >= self.threshold {
+                self.quiet_frames = 0;
+            } else {
+                self.quiet_frames = self.quiet_frames.saturating_add(1);
+            }
+            if self.quiet_frames <= hold_frames {
+                self.gain = (self.gain + attack_step).min(1.0);
+            } else {
+                self.gain = (self.gain - release_step).max(0.0);
+            }
+            if self.gain < 1.0 {
+                for sample in frame {
+                    *sample *= T::coerce(self.gain);
+                }
+            }
+        }
+    }
+}
+
+/// The largest ratio of the [`Compressor`], above it compresses like a limiter
+const COMPRESSOR_MAX_RATIO: f32 = 100.0;
+
+/// The settings of a [`Compressor`], from [`Compressor::controls`].
+///
+/// The settings can be changed while playing, from any thread.
+#[derive(Debug, Clone)]
+pub struct CompressorControls {
+    // `f32` bits of the threshold and makeup gain in dB, the ratio, the attack and release in seconds,
+    // and the current gain reduction in dB
+    params: Arc<[AtomicU32; 6]>,
+}
+
+impl CompressorControls {
+    const THRESHOLD: usize = 0;
+    const RATIO: usize = 1;
+    const ATTACK: usize = 2;
+    const RELEASE: usize = 3;
+    const MAKEUP_GAIN: usize = 4;
+    const GAIN_REDUCTION: usize = 5;
+
+    fn new() -> Self {
+        let controls = Self {
+            params: Default::default(),
+        };
+        controls.set_threshold(-18.0);
+        controls.set_ratio(4.0);
+        controls.set_attack(Duration::from_millis(10));
+        controls.set_release(Duration::from_millis(100));
+        controls
+    }
+
+    fn set(&self, param: usize, value: f32) {
+        self.params[param].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self, param: usize) -> f32 {
+        f32::from_bits(self.params[param].load(Ordering::Relaxed))
+    }
+
+    /// Set the level in dBFS above which the output is compressed, clamped to `-60.0..=0.0`.
+    pub fn set_threshold(&self, threshold: f32) {
+        self.set(Self::THRESHOLD, threshold.clamp(-60.0, 0.0));
+    }
+
+    /// Set the compression ratio, the level above the threshold is divided by it, clamped to `1.0..=100.0`.
+    pub fn set_ratio(&self, ratio: f32) {
+        self.set(Self::RATIO, ratio.clamp(1.0, COMPRESSOR_MAX_RATIO));
+    }
+
+    /// Set the time for the compression to react to a louder output.
+    pub fn set_attack(&self, attack: Duration) {
+        self.set(Self::ATTACK, attack.as_secs_f32());
+    }
+
+    /// Set the time for the compression to recover after the output got quieter.
+    pub fn set_release(&self, release: Duration) {
+        self.set(Self::RELEASE, release.as_secs_f32());
+    }
+
+    /// Set the gain in dB applied after the compression, to bring the level back up, clamped to `0.0..=24.0`.
+    pub fn set_makeup_gain(&self, gain: f32) {
+        self.set(Self::MAKEUP_GAIN, gain.clamp(0.0, 24.0));
+    }
+
+    /// The threshold in dBFS.
+    pub fn threshold(&self) -> f32 {
+        self.get(Self::THRESHOLD)
+    }
+
+    /// The compression ratio.
+    pub fn ratio(&self) -> f32 {
+        self.get(Self::RATIO)
+    }
+
+    /// The attack time.
+    pub fn attack(&self) -> Duration {
+        Duration::from_secs_f32(self.get(Self::ATTACK))
+    }
+
+    /// The release time.
+    pub fn release(&self) -> Duration {
+        Duration::from_secs_f32(self.get(Self::RELEASE))
+    }
+
+    /// The makeup gain in dB.
+    pub fn makeup_gain(&self) -> f32 {
+        self.get(Self::MAKEUP_GAIN)
+    }
+
+    /// The gain reduction applied at the end of the last output callback in dB, `0.0` or lower,
+    /// for gain reduction meters.
+    pub fn gain_reduction(&self) -> f32 {
+        self.get(Self::GAIN_REDUCTION)
+    }
+}
+
+/// A dynamic range compressor, lowers the level of the output above a threshold by a ratio,
+/// for example to hear the quiet parts of a soundtrack on small speakers without the loud parts being too loud.
+///
+/// The level is the peak of each frame over all the channels, so the channels are compressed together.
+/// The settings start at a threshold of `-18dBFS`, a ratio of `4`, an attack of `10ms`, a release of `100ms`,
+/// and no makeup gain, and can be changed while playing with the [`CompressorControls`].
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::Compressor, AudioPlayer};
+/// let compressor = Compressor::new();
+/// let controls = compressor.controls();
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(compressor)
+///     // the makeup gain can amplify, the limiter makes sure it doesn't clip
+///     .limiter(true)
+///     .build()
+///     .unwrap();
+///
+/// // can be changed at any time after
+/// controls.set_ratio(8.0);
+/// controls.set_makeup_gain(6.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    controls: CompressorControls,
+    // the smoothed gain reduction in dB
+    reduction: f64,
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compressor {
+    /// Creates a compressor with the default settings, see [`Compressor`].
+    pub fn new() -> Self {
+        Self {
+            controls: CompressorControls::new(),
+            reduction: 0.0,
+        }
+    }
+
+    /// The controls to change the settings of the compressor.
+    pub fn controls(&self) -> CompressorControls {
+        self.controls.clone()
+    }
+}
+
+/// The coefficient of a one-pole smoothing over `time` seconds at `sample_rate`
+fn smoothing_coefficient(time: f32, sample_rate: u32) -> f64 {
+    let frames = time as f64 * sample_rate as f64;
+    if frames < 1.0 {
+        0.0
+    } else {
+        (-1.0 / frames).exp()
+    }
+}
+
+impl<T> AudioEffect<T> for Compressor
+where
+    T: FloatSample + SizedSample,
+    f64: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let controls = &self.controls;
+        let threshold = controls.threshold() as f64;
+        let ratio = controls.ratio() as f64;
+        let makeup_gain = controls.makeup_gain() as f64;
+        let attack =
+            smoothing_coefficient(controls.get(CompressorControls::ATTACK), config.sample_rate);
+        let release = smoothing_coefficient(
+            controls.get(CompressorControls::RELEASE),
+            config.sample_rate,
+        );
+
+        for frame in samples.chunks_mut(config.channels.max(1) as usize) {
+            let peak = frame
+                .iter()
+                .map(|&sample| f64::from_sample_(sample).abs())
+                .fold(0.0, f64::max);
+            let level = 20.0 * peak.max(1e-9).log10();
+            let target = if level > threshold {
+                (threshold - level) * (1.0 - 1.0 / ratio)
+            } else {
+                0.0
+            };
+            // more reduction follows the attack, less reduction the release
+            let coefficient = if target < self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = target + (self.reduction - target) * coefficient;
+
+            let gain = db_to_amplitude(self.reduction + makeup_gain);
+            for sample in frame {
+                *sample *= T::coerce(gain);
+            }
+        }
+        controls.set(CompressorControls::GAIN_REDUCTION, self.reduction as f32);
+    }
+}
+
+/// The lengths of the delay lines of the [`Reverb`] in milliseconds, without common factors so the echoes don't line up
+const REVERB_DELAYS_MS: [f64; 4] = [29.7, 37.1, 41.1, 43.7];
+
+/// The settings of a [`Reverb`], from [`Reverb::controls`].
+///
+/// The settings can be changed while playing, from any thread.
+#[derive(Debug, Clone)]
+pub struct ReverbControls {
+    // `f32` bits of the send level, room size and damping
+    params: Arc<[AtomicU32; 3]>,
+}
+
+impl ReverbControls {
+    const SEND: usize = 0;
+    const ROOM_SIZE: usize = 1;
+    const DAMPING: usize = 2;
+
+    fn new() -> Self {
+        let controls = Self {
+            params: Default::default(),
+        };
+        controls.set_send(0.3);
+        controls.set_room_size(0.5);
+        controls.set_damping(0.5);
+        controls
+    }
+
+    fn set(&self, param: usize, value: f32) {
+        self.params[param].store(value.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self, param: usize) -> f32 {
+        f32::from_bits(self.params[param].load(Ordering::Relaxed))
+    }
+
+    /// Set how much of the output is sent into the reverb, clamped to `0.0..=1.0`, `0.0` turns the reverb off.
+    pub fn set_send(&self, send: f32) {
+        self.set(Self::SEND, send);
+    }
+
+    /// Set the size of the room, longer reverb tails for larger rooms, clamped to `0.0..=1.0`.
+    pub fn set_room_size(&self, room_size: f32) {
+        self.set(Self::ROOM_SIZE, room_size);
+    }
+
+    /// Set how fast the high frequencies fade in the reverb tail, clamped to `0.0..=1.0`.
+    pub fn set_damping(&self, damping: f32) {
+        self.set(Self::DAMPING, damping);
+    }
+
+    /// The send level.
+    pub fn send(&self) -> f32 {
+        self.get(Self::SEND)
+    }
+
+    /// The room size.
+    pub fn room_size(&self) -> f32 {
+        self.get(Self::ROOM_SIZE)
+    }
+
+    /// The damping.
+    pub fn damping(&self) -> f32 {
+        self.get(Self::DAMPING)
+    }
+}
+
+/// A delay line of the [`Reverb`], with a low pass filter for the damping
+#[derive(Debug, Clone)]
+struct DelayLine {
+    buffer: Vec<f64>,
+    position: usize,
+    filtered: f64,
+}
+
+impl DelayLine {
+    fn new(frames: usize) -> Self {
+        Self {
+            buffer: vec![0.0; frames.max(1)],
+            position: 0,
+            filtered: 0.0,
+        }
+    }
+
+    /// The oldest sample of the line, filtered by the `damping`
+    fn read(&mut self, damping: f64) -> f64 {
+        self.filtered = self.buffer[self.position] * (1.0 - damping) + self.filtered * damping;
+        self.filtered
+    }
+
+    fn write(&mut self, sample: f64) {
+        self.buffer[self.position] = sample;
+        self.position = (self.position + 1) % self.buffer.len();
+    }
+}
+
+/// A light reverb, adds the ambience of a room to dry sounds like the music of old sound chips.
+///
+/// The output is mixed to mono and sent into a feedback delay network of four delay lines,
+/// the reverb is added to the output, spread over the left and right channels.
+/// The send level, room size and damping can be changed while playing with the [`ReverbControls`].
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::Reverb, AudioPlayer};
+/// let reverb = Reverb::new();
+/// let controls = reverb.controls();
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .effect(reverb)
+///     .build()
+///     .unwrap();
+///
+/// // a large hall
+/// controls.set_room_size(0.9);
+/// controls.set_send(0.5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    controls: ReverbControls,
+    // the delay lines for `sample_rate`, created on the first process
+    lines: Option<(u32, [DelayLine; 4])>,
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reverb {
+    /// Creates a reverb with a send level of `0.3`, and a medium room size and damping.
+    pub fn new() -> Self {
+        Self {
+            controls: ReverbControls::new(),
+            lines: None,
+        }
+    }
+
+    /// The controls to change the settings of the reverb.
+    pub fn controls(&self) -> ReverbControls {
+        self.controls.clone()
+    }
+
+    fn lines(&mut self, sample_rate: u32) -> &mut [DelayLine; 4] {
+        if !matches!(self.lines, Some((rate, _)) if rate == sample_rate) {
+            let lines = REVERB_DELAYS_MS
+                .map(|ms| DelayLine::new((ms * sample_rate as f64 / 1000.0) as usize));
+            self.lines = Some((sample_rate, lines));
+        }
+        &mut self.lines.as_mut().expect("Lines created above").1
+    }
+}
+
+impl<T> AudioEffect<T> for Reverb
+where
+    T: FloatSample + SizedSample,
+    f64: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let send = self.controls.send() as f64;
+        let feedback = 0.7 + 0.28 * self.controls.room_size() as f64;
+        let damping = self.controls.damping() as f64 * 0.9;
+        let channels = config.channels.max(1) as usize;
+        let lines = self.lines(config.sample_rate);
+
+        for frame in samples.chunks_exact_mut(channels) {
+            let input = frame.iter().map(|&s| f64::from_sample_(s)).sum::<f64>() / channels as f64;
+            let [a, b, c, d] = [0, 1, 2, 3].map(|i| lines[i].read(damping));
+            // the Hadamard matrix mixes the lines without changing the energy
+            let mixed = [a + b + c + d, a - b + c - d, a + b - c - d, a - b - c + d];
+            for (line, mixed) in lines.iter_mut().zip(mixed) {
+                line.write(input * send + mixed * 0.5 * feedback);
+            }
+
+            let (left, right) = ((a + c) * 0.5, (b + d) * 0.5);
+            if channels >= 2 {
+                frame[0] += T::coerce(left);
+                frame[1] += T::coerce(right);
+            } else {
+                frame[0] += T::coerce((left + right) * 0.5);
+            }
+        }
+    }
+}
+
+/// The length of the grains of the [`PitchShift`] in milliseconds, long enough for low notes,
+/// and short enough that the grains don't sound like echoes
+const PITCH_SHIFT_GRAIN_MS: f64 = 40.0;
+
+/// The pitch of a [`PitchShift`], from [`PitchShift::controls`].
+///
+/// The pitch can be changed while playing, from any thread.
+#[derive(Debug, Clone)]
+pub struct PitchShiftControls {
+    // `f32` bits of the pitch ratio
+    ratio: Arc<AtomicU32>,
+}
+
+impl PitchShiftControls {
+    fn new() -> Self {
+        Self {
+            ratio: Arc::new(AtomicU32::new(1f32.to_bits())),
+        }
+    }
+
+    /// Set the ratio of the pitch to the original one, clamped to `0.5..=2.0`,
+    /// `2.0` is an octave up, and `1.0` leaves the samples as they are.
+    pub fn set_ratio(&self, ratio: f32) {
+        self.ratio
+            .store(ratio.clamp(0.5, 2.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Set the shift of the pitch in semitones, clamped to `-12.0..=12.0`, same as a ratio of `2^(semitones / 12)`.
+    pub fn set_semitones(&self, semitones: f32) {
+        self.set_ratio(2f32.powf(semitones.clamp(-12.0, 12.0) / 12.0));
+    }
+
+    /// The pitch ratio.
+    pub fn ratio(&self) -> f32 {
+        f32::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    /// The pitch shift in semitones.
+    pub fn semitones(&self) -> f32 {
+        12.0 * self.ratio().log2()
+    }
+}
+
+/// The last frames of the output, read back by the [`PitchShift`] at a moving delay
+#[derive(Debug, Clone)]
+struct GrainBuffer {
+    sample_rate: u32,
+    channels: usize,
+    // the longest delay in frames, the length of a grain
+    grain: usize,
+    // interleaved frames, with room for the interpolation after the longest delay
+    samples: Vec<f64>,
+    // the frame written last
+    position: usize,
+    // how far the first grain is, from `0.0` to `1.0`, the second one is half a grain after
+    phase: f64,
+}
+
+impl GrainBuffer {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let grain = (PITCH_SHIFT_GRAIN_MS * sample_rate as f64 / 1000.0) as usize;
+        Self {
+            sample_rate,
+            channels,
+            grain: grain.max(1),
+            samples: vec![0.0; (grain.max(1) + 2) * channels],
+            position: 0,
+            phase: 0.0,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    fn write(&mut self, frame: &[f64]) {
+        self.position = (self.position + 1) % self.frames();
+        let start = self.position * self.channels;
+        self.samples[start..start + self.channels].copy_from_slice(frame);
+    }
+
+    /// The sample of `channel` played `delay` frames ago, interpolated linearly
+    fn read(&self, delay: f64, channel: usize) -> f64 {
+        let frames = self.frames();
+        let whole = delay as usize;
+        let newer = (self.position + frames - whole) % frames;
+        let older = (newer + frames - 1) % frames;
+        let t = delay - whole as f64;
+        let (newer, older) = (
+            self.samples[newer * self.channels + channel],
+            self.samples[older * self.channels + channel],
+        );
+        newer + (older - newer) * t
+    }
+}
+
+/// Shift the pitch of the output without changing its speed, for example to play PAL games sped up
+/// from 50 to 60 frames per second at their original pitch.
+///
+/// The output is read back in two overlapping grains, at a delay moving faster or slower than the samples,
+/// crossfaded so the jumps at the end of each grain are not heard. It's a light algorithm,
+/// fine for small corrections, larger shifts sound rougher. The pitch can be changed while playing
+/// with the [`PitchShiftControls`], a ratio of `1.0` plays the samples as they are.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, effects::PitchShift, AudioPlayer};
+/// let pitch_shift = PitchShift::new();
+/// let controls = pitch_shift.controls();
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .effect(pitch_shift)
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+///
+/// // a game running at 60 fps instead of 50, back to its original pitch
+/// controls.set_ratio(50.0 / 60.0);
+/// let tone = (0..9600)
+///     .flat_map(|i| [(i as f32 * 1200.0 / 48000.0 * std::f32::consts::TAU).sin() * 0.5; 2])
+///     .collect::<Vec<_>>();
+/// player.queue(&tone);
+///
+/// // 1000Hz after the shift, 200 zero crossings in 100ms
+/// let output = clock.render_frames(9600);
+/// let left = output.iter().step_by(2).skip(4800).collect::<Vec<_>>();
+/// let crossings = left.windows(2).filter(|w| (w[0] < &0.0) != (w[1] < &0.0)).count();
+/// assert!((190..=210).contains(&crossings));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PitchShift {
+    controls: PitchShiftControls,
+    // created on the first process, and when the output configuration changes
+    buffer: Option<GrainBuffer>,
+    frame: Vec<f64>,
+}
+
+impl Default for PitchShift {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PitchShift {
+    /// Creates a pitch shift with a ratio of `1.0`, the samples are played as they are until it's changed.
+    pub fn new() -> Self {
+        Self {
+            controls: PitchShiftControls::new(),
+            buffer: None,
+            frame: Vec::new(),
+        }
+    }
+
+    /// The controls to change the pitch.
+    pub fn controls(&self) -> PitchShiftControls {
+        self.controls.clone()
+    }
+}
+
+impl<T> AudioEffect<T> for PitchShift
+where
+    T: FloatSample + SizedSample,
+    f64: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], config: &OutputConfig) {
+        let ratio = self.controls.ratio() as f64;
+        let channels = config.channels.max(1) as usize;
+        if !matches!(&self.buffer, Some(buffer) if buffer.sample_rate == config.sample_rate && buffer.channels == channels)
+        {
+            self.buffer = Some(GrainBuffer::new(config.sample_rate, channels));
+            self.frame = vec![0.0; channels];
+        }
+        let buffer = self.buffer.as_mut().expect("Buffer created above");
+        let grain = buffer.grain as f64;
+
+        for frame in samples.chunks_exact_mut(channels) {
+            for (input, &sample) in self.frame.iter_mut().zip(frame.iter()) {
+                *input = f64::from_sample_(sample);
+            }
+            buffer.write(&self.frame);
+            // the history is kept, so changing the ratio doesn't start from silence
+            if ratio == 1.0 {
+                continue;
+            }
+
+            let phase = buffer.phase;
+            let second = (phase + 0.5) % 1.0;
+            // the gains of the two grains add up to 1, and are 0 when a grain jumps
+            let gain = (std::f64::consts::PI * phase).sin().powi(2);
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let first = buffer.read(phase * grain, channel);
+                let second = buffer.read(second * grain, channel);
+                *sample = T::coerce(first * gain + second * (1.0 - gain));
+            }
+            // the delay shrinks when reading faster than the samples are written
+            buffer.phase = (phase + (1.0 - ratio) / grain).rem_euclid(1.0);
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use cpal::{FromSample, SizedSample};
+use ringbuf::{
+    traits::{Observer, Producer, Split},
+    HeapProd, HeapRb,
+};
+
+use crate::{
+    backend::{
+        source::SourceCommand, OutputBackend, OutputConfig, OutputRequest, OutputSource,
+        OutputStream,
+    },
+    error::{AudioPlayerError, PlayError},
+    mixer::{TrackControls, TrackSource},
+    resampler::AudioResampler,
+    sample::FloatSample,
+    state::PlayerShared,
+    AudioPlayer, AudioPlayerBuilder,
+};
+
+/// The default maximum number of voices playing at the same time, see [`AudioEngine::set_max_voices`]
+const DEFAULT_MAX_VOICES: usize = 32;
+
+/// The players attached to an engine, waiting to be picked up by its output
+pub(crate) type EngineInputs<T> = Arc<Mutex<Vec<EngineInput<T>>>>;
+
+/// The `AudioEngine` plays multiple [`AudioPlayer`]s with one output stream.
+///
+/// Opening several players opens several streams on the device, which some hosts don't handle well.
+/// Instead, players created with [`attach`](Self::attach) are attached to the engine,
+/// and their output is mixed into its stream.
+///
+/// The attached players are full players, each with its own sample rate, buffer, effects and state,
+/// and they play and pause independently. The output stream of the engine is always playing,
+/// it's owned by the engine's own player, see [`player_mut`](Self::player_mut),
+/// the effects of that player are applied to the mix of all the attached players.
+///
+/// The attached players are silent after the engine is dropped.
+///
+/// The attached players share the clock of the engine output, so they can't drift apart,
+/// see [`stream_offset`](Self::stream_offset), and start together with a [`SyncGroup`].
+///
+/// For short sounds, like the effects of a game, the engine also plays voices with
+/// [`play_voice`](Self::play_voice), limited to a maximum number playing at the same time,
+/// when the limit is reached, the voice with the lowest priority is stopped to make room.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioEngine, AudioPlayer, PlayerState};
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let engine = AudioEngine::new(AudioPlayer::<f32>::builder(48000).backend(backend)).unwrap();
+///
+/// let mut emulator = engine.attach(44100).build().unwrap();
+/// let mut music = engine.attach(48000).build().unwrap();
+/// emulator.play().unwrap();
+/// music.play().unwrap();
+///
+/// emulator.queue(&[0.25; 441 * 2]);
+/// music.queue(&[0.25; 480 * 2]);
+///
+/// clock.advance_frames(240);
+/// assert_eq!(music.state(), PlayerState::Playing);
+/// assert_eq!(music.buffered_samples(), 240 * 2);
+/// ```
+pub struct AudioEngine<T: FloatSample> {
+    player: AudioPlayer<T>,
+    inputs: EngineInputs<T>,
+    voices: Vec<Voice<T>>,
+    max_voices: usize,
+    next_voice: u64,
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> AudioEngine<T>
+where
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// Creates an engine with the output stream of the player built from `builder`, and starts playing it.
+    ///
+    /// The sample rate of `builder` is the rate preferred for the output, the attached players
+    /// are resampled to the rate chosen for the device.
+    pub fn new(builder: AudioPlayerBuilder<T>) -> Result<Self, AudioPlayerError> {
+        let mut player = builder.build()?;
+        let inputs = EngineInputs::default();
+        player.send_command(SourceCommand::AttachInputs(inputs.clone()));
+        player.play()?;
+        Ok(Self {
+            player,
+            inputs,
+            voices: Vec::new(),
+            max_voices: DEFAULT_MAX_VOICES,
+            next_voice: 0,
+        })
+    }
+
+    /// Returns a builder for a player of `sample_rate` Hz attached to this engine.
+    ///
+    /// The builder can be configured like any other, but not with another
+    /// [`backend`](AudioPlayerBuilder::backend).
+    pub fn attach(&self, sample_rate: u32) -> AudioPlayerBuilder<T> {
+        AudioPlayer::builder(sample_rate).backend(EngineBackend {
+            config: self.player.output_config,
+            inputs: self.inputs.clone(),
+        })
+    }
+
+    /// Returns an empty [`SyncGroup`], to start attached players together.
+    pub fn sync_group<'a>(&self) -> SyncGroup<'a, T> {
+        SyncGroup {
+            engine: self.player.shared.clone(),
+            players: Vec::new(),
+        }
+    }
+
+    /// The number of output frames `player` played ahead of `other`, negative if it's behind.
+    ///
+    /// The attached players share the clock of the engine output: they are filled in the same callbacks,
+    /// and follow its drift with [`AudioPlayerBuilder::drift_compensation`], so this offset only changes
+    /// when one of them doesn't play its queued samples, because it's paused, underruns,
+    /// or started later, see [`SyncGroup`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioEngine, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let engine = AudioEngine::new(AudioPlayer::<f32>::builder(48000).backend(backend)).unwrap();
+    ///
+    /// let mut top = engine.attach(48000).build().unwrap();
+    /// let mut bottom = engine.attach(48000).build().unwrap();
+    /// top.queue(&[0.25; 4800 * 2]);
+    /// bottom.queue(&[0.25; 4800 * 2]);
+    ///
+    /// top.play().unwrap();
+    /// clock.advance_frames(480);
+    /// bottom.play().unwrap();
+    /// clock.advance_frames(480);
+    /// assert_eq!(engine.stream_offset(&top, &bottom), 480);
+    /// assert_eq!(engine.stream_offset(&bottom, &top), -480);
+    /// ```
+    pub fn stream_offset(&self, player: &AudioPlayer<T>, other: &AudioPlayer<T>) -> i64 {
+        player
+            .shared
+            .frames_consumed()
+            .wrapping_sub(other.shared.frames_consumed()) as i64
+    }
+
+    /// The configuration of the output stream, shared by all the attached players.
+    pub fn output_config(&self) -> OutputConfig {
+        self.player.output_config
+    }
+
+    /// Play `clip` of `sample_rate` Hz once with `priority`, mixed on top of the attached players.
+    ///
+    /// If [`max_voices`](Self::max_voices) are already playing, the voice with the lowest priority
+    /// (the oldest one if several have the same) is faded out and replaced, if its priority is
+    /// not higher than `priority`. Otherwise, the clip is not played and `None` is returned.
+    ///
+    /// The samples are interleaved stereo, and resampled to the output rate if needed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioEngine, AudioPlayer};
+    /// let engine = AudioPlayer::<f32>::builder(48000).backend(NullBackend::new());
+    /// let mut engine = AudioEngine::new(engine).unwrap();
+    /// engine.set_max_voices(2);
+    ///
+    /// let footstep = engine.play_voice(&[0.1; 4800], 48000, 0).unwrap();
+    /// let coin = engine.play_voice(&[0.2; 4800], 48000, 1).unwrap();
+    /// assert!(footstep.is_some() && coin.is_some());
+    ///
+    /// // the footstep is stolen by the explosion
+    /// let explosion = engine.play_voice(&[0.5; 4800], 48000, 5).unwrap();
+    /// assert!(explosion.is_some());
+    /// assert!(!engine.is_voice_playing(footstep.unwrap()));
+    ///
+    /// // no voice with a lower priority left
+    /// assert_eq!(engine.play_voice(&[0.1; 4800], 48000, 0).unwrap(), None);
+    /// ```
+    pub fn play_voice(
+        &mut self,
+        clip: &[T],
+        sample_rate: u32,
+        priority: u32,
+    ) -> Result<Option<VoiceId>, AudioPlayerError> {
+        self.voices.retain(|voice| voice.is_playing());
+        if self.voices.len() >= self.max_voices {
+            let Some(lowest) = self.lowest_voice() else {
+                // no voices allowed at all
+                return Ok(None);
+            };
+            if self.voices[lowest].priority > priority {
+                return Ok(None);
+            }
+            self.voices.remove(lowest).controls.stop();
+        }
+
+        let output_config = self.player.output_config;
+        let resampled = match AudioResampler::for_rates(
+            sample_rate,
+            output_config.sample_rate,
+            output_config.channels,
+            self.player.low_latency,
+        )? {
+            Some(mut resampler) => resampler.resample_all(clip),
+            None => clip.to_vec(),
+        };
+
+        let (mut producer, consumer) = HeapRb::new(resampled.len().max(1)).split();
+        producer.push_slice(&resampled);
+        let controls = TrackControls::new();
+        self.player
+            .send_command(SourceCommand::AddTrack(TrackSource::new(
+                consumer,
+                controls.clone(),
+                output_config.sample_rate,
+            )));
+
+        let id = VoiceId(self.next_voice);
+        self.next_voice += 1;
+        self.voices.push(Voice {
+            id,
+            priority,
+            producer,
+            controls,
+        });
+        Ok(Some(id))
+    }
+
+    /// Set the maximum number of voices playing at the same time, the default is 32.
+    ///
+    /// If more voices are playing, the ones with the lowest priority are stopped.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+        self.voices.retain(|voice| voice.is_playing());
+        while self.voices.len() > max_voices {
+            let lowest = self.lowest_voice().expect("More voices than the maximum");
+            self.voices.remove(lowest).controls.stop();
+        }
+    }
+
+    /// The maximum number of voices playing at the same time, see [`set_max_voices`](Self::set_max_voices).
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    /// The number of voices still playing.
+    pub fn active_voices(&self) -> usize {
+        self.voices
+            .iter()
+            .filter(|voice| voice.is_playing())
+            .count()
+    }
+
+    /// Returns `true` if the voice is still playing, it wasn't stopped, stolen, or finished.
+    pub fn is_voice_playing(&self, id: VoiceId) -> bool {
+        self.voice(id).is_some()
+    }
+
+    /// The volume, mute and pan controls of the voice, or `None` if it's not playing anymore.
+    pub fn voice_controls(&self, id: VoiceId) -> Option<&TrackControls> {
+        self.voice(id).map(|voice| &voice.controls)
+    }
+
+    /// Fade out the voice and stop it, does nothing if it's not playing anymore.
+    pub fn stop_voice(&mut self, id: VoiceId) {
+        if let Some(i) = self.voices.iter().position(|voice| voice.id == id) {
+            self.voices.remove(i).controls.stop();
+        }
+    }
+
+    /// The index of the voice to steal, the lowest priority, then the oldest
+    fn lowest_voice(&self) -> Option<usize> {
+        self.voices
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, voice)| (voice.priority, voice.id.0))
+            .map(|(i, _)| i)
+    }
+
+    fn voice(&self, id: VoiceId) -> Option<&Voice<T>> {
+        self.voices
+            .iter()
+            .find(|voice| voice.id == id && voice.is_playing())
+    }
+
+    /// The player owning the output stream.
+    pub fn player(&self) -> &AudioPlayer<T> {
+        &self.player
+    }
+
+    /// The player owning the output stream, samples queued into it are mixed with the attached players,
+    /// and its effects are applied to the mix.
+    pub fn player_mut(&mut self) -> &mut AudioPlayer<T> {
+        &mut self.player
+    }
+}
+
+/// Players attached to the same [`AudioEngine`] that start playing in the same output callback,
+/// from [`AudioEngine::sync_group`].
+///
+/// The first queued sample of each player is played on the same frame of the output,
+/// and since the engine mixes them in the same callbacks, they stay sample aligned afterwards,
+/// as long as none of them underruns or is paused alone.
+///
+/// Players that are not attached to the engine start in the same callback of the engine,
+/// but are not aligned with it.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioEngine, AudioPlayer};
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let engine = AudioEngine::new(AudioPlayer::<f32>::builder(48000).backend(backend)).unwrap();
+///
+/// let mut game = engine.attach(48000).build().unwrap();
+/// let mut commentary = engine.attach(44100).build().unwrap();
+/// game.queue(&[0.25; 4800 * 2]);
+/// commentary.queue(&[0.25; 4410 * 2]);
+///
+/// engine.sync_group().with(&mut game).with(&mut commentary).play().unwrap();
+/// clock.advance_frames(2400);
+/// assert_eq!(game.buffered_samples(), 2400 * 2);
+/// assert_eq!(commentary.buffered_samples(), 2400 * 2);
+/// ```
+pub struct SyncGroup<'a, T: FloatSample> {
+    engine: Arc<PlayerShared>,
+    players: Vec<&'a mut AudioPlayer<T>>,
+}
+
+impl<'a, T: FloatSample + SizedSample + Send + 'static> SyncGroup<'a, T>
+where
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// Add `player` to the group.
+    pub fn with(mut self, player: &'a mut AudioPlayer<T>) -> Self {
+        self.players.push(player);
+        self
+    }
+
+    /// Play all the players of the group, they start together in the next output callback of the engine.
+    ///
+    /// Players that were already playing hold their queued samples until then.
+    /// If one of them fails to play, the others still start.
+    pub fn play(&mut self) -> Result<(), PlayError> {
+        let start = Arc::new(AtomicU64::new(u64::MAX));
+        let result = self.players.iter_mut().try_for_each(|player| {
+            player.send_command(SourceCommand::SyncStart(SyncGate {
+                engine: self.engine.clone(),
+                start: start.clone(),
+            }));
+            player.play()
+        });
+        // the callback running now may have filled some of the players already,
+        // so they start in the one after it
+        start.store(self.engine.fill_count(), Ordering::Release);
+        result
+    }
+}
+
+/// Holds a player of a [`SyncGroup`] until the group plays
+pub(crate) struct SyncGate {
+    engine: Arc<PlayerShared>,
+    // the fill of the engine after which the players start, `u64::MAX` until the group plays
+    start: Arc<AtomicU64>,
+}
+
+impl SyncGate {
+    pub fn is_open(&self) -> bool {
+        self.engine.fill_count() > self.start.load(Ordering::Acquire)
+    }
+}
+
+/// Identifies a voice played with [`AudioEngine::play_voice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId(u64);
+
+/// A voice played by the engine
+struct Voice<T> {
+    id: VoiceId,
+    priority: u32,
+    // kept to know when all the samples are played
+    producer: HeapProd<T>,
+    controls: TrackControls,
+}
+
+impl<T> Voice<T> {
+    fn is_playing(&self) -> bool {
+        !self.producer.is_empty() && !self.controls.is_stopped()
+    }
+}
+
+/// Attaches the output of a player to an engine
+struct EngineBackend<T> {
+    config: OutputConfig,
+    inputs: EngineInputs<T>,
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> OutputBackend<T> for EngineBackend<T> {
+    fn negotiate(&mut self, _request: &OutputRequest) -> Result<OutputConfig, AudioPlayerError> {
+        Ok(self.config)
+    }
+
+    fn build_stream(
+        &mut self,
+        _config: &OutputConfig,
+        source: OutputSource<T>,
+    ) -> Result<Box<dyn OutputStream>, AudioPlayerError> {
+        let playing = Arc::new(AtomicBool::new(false));
+        let attached = Arc::new(AtomicBool::new(true));
+        self.inputs
+            .lock()
+            .map_err(|_| AudioPlayerError::DeviceNotAvailable)?
+            .push(EngineInput {
+                source,
+                playing: playing.clone(),
+                attached: attached.clone(),
+                scratch: Vec::new(),
+            });
+        Ok(Box::new(EngineStream { playing, attached }))
+    }
+}
+
+/// The output of an attached player, mixed in the output of the engine
+pub(crate) struct EngineInput<T> {
+    source: OutputSource<T>,
+    playing: Arc<AtomicBool>,
+    // cleared when the player is dropped
+    attached: Arc<AtomicBool>,
+    scratch: Vec<T>,
+}
+
+impl<T: FloatSample + SizedSample> EngineInput<T> {
+    /// Add the output of the player to `output`, with the clock of the `engine` output,
+    /// returns `true` if it's playing
+    pub fn mix_into(
+        &mut self,
+        output: &mut [T],
+        engine: &PlayerShared,
+        playback_time: Option<Instant>,
+    ) -> bool {
+        if !self.playing.load(Ordering::Acquire) {
+            return false;
+        }
+        self.source.follow_clock(engine, playback_time);
+        // only grows when the callback size changes
+        #[cfg(feature = "debug-rt-checks")]
+        let permit = crate::rt_checks::AllocPermit::new();
+        self.scratch.resize(output.len(), T::EQUILIBRIUM);
+        #[cfg(feature = "debug-rt-checks")]
+        drop(permit);
+        self.source.fill(&mut self.scratch);
+        for (out, &sample) in output.iter_mut().zip(&self.scratch) {
+            *out += sample;
+        }
+        true
+    }
+
+    /// The player was dropped
+    pub fn is_detached(&self) -> bool {
+        !self.attached.load(Ordering::Acquire)
+    }
+}
+
+struct EngineStream {
+    playing: Arc<AtomicBool>,
+    attached: Arc<AtomicBool>,
+}
+
+impl OutputStream for EngineStream {
+    fn play(&self) -> Result<(), PlayError> {
+        self.playing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PlayError> {
+        self.playing.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for EngineStream {
+    fn drop(&mut self) {
+        self.attached.store(false, Ordering::Release);
+    }
+}
@@ -1,28 +1,57 @@
 //! Error types for the audio player.
 //!
-//! The error types are defined in this module. The error types are used in the [`AudioPlayer`](crate::AudioPlayer).
+//! The error types are defined in this module. The error types are used in the [`AudioPlayer`](crate::AudioPlayer)
+//! and [`AudioRecorder`](crate::AudioRecorder).
 
 use std::{error::Error, fmt};
 
 use cpal::{
-    BackendSpecificError, BuildStreamError, DefaultStreamConfigError, PauseStreamError,
-    PlayStreamError, SupportedStreamConfigsError,
+    BackendSpecificError, BuildStreamError, DefaultStreamConfigError, DevicesError,
+    HostUnavailable, PauseStreamError, PlayStreamError, SampleFormat, SupportedStreamConfigsError,
 };
+#[cfg(feature = "resample")]
 use rubato::ResamplerConstructionError;
 
-/// The `AudioPlayerError` enum represents the possible errors that can occur when constructing [`AudioPlayer`](crate::AudioPlayer).
+/// The `AudioPlayerError` enum represents the possible errors that can occur when constructing [`AudioPlayer`](crate::AudioPlayer)
+/// or [`AudioRecorder`](crate::AudioRecorder).
 ///
 /// Most are related to issue with the device or the API when trying to use the device.
 #[derive(Debug)]
 pub enum AudioPlayerError {
     /// From [cpal]: No output device was found.
     NoOutputDevice,
+    /// From [cpal]: No input device was found, when creating an [`AudioRecorder`](crate::AudioRecorder).
+    NoInputDevice,
+    /// From [cpal]: The requested host is not available on this platform.
+    HostUnavailable,
     /// The device doesn't support dual channel which is what's supported for now here.
     DualChannelNotSupported,
     /// From [cpal]: The device associated with the stream is no longer available.
     DeviceNotAvailable,
     /// From [cpal]: See the [`BackendSpecificError`] docs for more information about this error variant.
     DeviceBackendSpecificError(BackendSpecificError),
+    /// From [cpal]: The device is used exclusively by another application, for example another emulator
+    /// or a music player in exclusive mode. It can be retried with [`AudioPlayerBuilder::retries`](crate::AudioPlayerBuilder::retries)
+    /// until the other application releases it.
+    ///
+    /// Detected from the message of the backend error: `EBUSY` on ALSA, `AUDCLNT_E_DEVICE_IN_USE` on WASAPI,
+    /// and hog mode on CoreAudio.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::error::AudioPlayerError;
+    /// # use cpal::{BackendSpecificError, BuildStreamError};
+    /// let err = BuildStreamError::BackendSpecific {
+    ///     err: BackendSpecificError {
+    ///         description: "ALSA function 'snd_pcm_open' failed with error 'EBUSY: Device or resource busy'"
+    ///             .to_string(),
+    ///     },
+    /// };
+    /// let err = AudioPlayerError::from(err);
+    /// assert!(matches!(err, AudioPlayerError::DeviceBusy(_)));
+    /// assert!(err.is_transient());
+    /// ```
+    DeviceBusy(BackendSpecificError),
     /// From [cpal]: Returned if e.g. the default input format was requested on an output-only audio device
     StreamTypeNotSupported,
     /// From [cpal]: We called something the C-Layer API did not understand
@@ -32,27 +61,168 @@ pub enum AudioPlayerError {
     /// From [cpal]: The specified stream configuration is not supported by the device (this should not happen as we will perform resampling if needed)
     StreamConfigNotSupported,
     /// From [rubato]: Error while constructing the resampler.
+    #[cfg(feature = "resample")]
     ResamplerConstructionError(ResamplerConstructionError),
+    /// The device doesn't support the [`CallbackSize::Exact`](crate::CallbackSize::Exact) requested,
+    /// the supported range is `min..=max` frames.
+    CallbackSizeNotSupported {
+        /// The requested number of frames
+        requested: u32,
+        /// The minimum number of frames supported by the device
+        min: u32,
+        /// The maximum number of frames supported by the device
+        max: u32,
+    },
+    /// An I/O error, from backends writing to files for example.
+    Io(std::io::Error),
+    /// From [`measure_latency`](crate::measure_latency): The played chirp was not found in the recording.
+    LatencyNotDetected,
+    /// Failed to start or pause a stream, see [`PlayError`].
+    Play(PlayError),
+    /// The [`AudioSnapshot`](crate::AudioSnapshot) was taken from a player with a different sample rate or output configuration.
+    SnapshotMismatch,
+    /// The device only supports a sample format that can't be played, see [`SampleFormat`].
+    UnsupportedOutputFormat(SampleFormat),
+    /// The sample format of the input can't be converted, see [`PcmWriter`](crate::PcmWriter)
+    /// and [`AudioRecorder`](crate::AudioRecorder).
+    UnsupportedInputFormat(SampleFormat),
+    /// The decoded audio has a number of channels that can't be converted to stereo,
+    /// or the frames given to [`AudioPlayer::queue_frames`](crate::AudioPlayer::queue_frames)
+    /// don't have the channels of the input layout.
+    UnsupportedInputChannels(u16),
+    /// The device can't play this sample rate, and resampling was not allowed with
+    /// [`AudioPlayerBuilder::exact_sample_rate`](crate::AudioPlayerBuilder::exact_sample_rate).
+    SampleRateNotSupported(u32),
+    /// The samples need resampling from the rate `from` to `to`, but the crate was built
+    /// without the `resample` feature, and neither the `lite-resample` feature nor a built-in
+    /// [`ResamplingAlgorithm`](crate::ResamplingAlgorithm) is used.
+    ResamplingUnavailable {
+        /// The sample rate of the samples.
+        from: u32,
+        /// The sample rate they need to be played at.
+        to: u32,
+    },
+    /// The platform can't capture the audio of its output, see
+    /// [`AudioRecorderBuilder::loopback`](crate::AudioRecorderBuilder::loopback).
+    #[cfg(feature = "loopback")]
+    LoopbackNotSupported,
+    /// From libopus: the encoder or decoder of an Opus stream couldn't be created,
+    /// see [`UdpBackend::opus`](crate::backend::UdpBackend::opus).
+    #[cfg(feature = "opus")]
+    Opus(String),
 }
 
+impl AudioPlayerError {
+    /// Returns `true` if the error can go away by trying again, for example if the device
+    /// is busy right after the system resumed, or while another application releases it.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::NoOutputDevice
+                | Self::DeviceNotAvailable
+                | Self::DeviceBackendSpecificError(_)
+                | Self::DeviceBusy(_)
+        )
+    }
+
+    /// A backend error, [`DeviceBusy`](Self::DeviceBusy) if its message says that another application
+    /// uses the device exclusively
+    fn from_backend(err: BackendSpecificError) -> Self {
+        let description = err.description.to_ascii_lowercase();
+        if BUSY_MESSAGES
+            .iter()
+            .any(|message| description.contains(message))
+        {
+            Self::DeviceBusy(err)
+        } else {
+            Self::DeviceBackendSpecificError(err)
+        }
+    }
+}
+
+/// Parts of the messages of the hosts when the device is used exclusively by another application,
+/// in lowercase: `EBUSY` from ALSA, `AUDCLNT_E_DEVICE_IN_USE` from WASAPI, and hog mode from CoreAudio
+const BUSY_MESSAGES: [&str; 6] = [
+    "ebusy",
+    "device or resource busy",
+    "audclnt_e_device_in_use",
+    "0x8889000a",
+    "!hog",
+    "hog mode",
+];
+
 impl Error for AudioPlayerError {}
 
 impl fmt::Display for AudioPlayerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::NoOutputDevice => write!(f, "No output device found"),
+            Self::NoInputDevice => write!(f, "No input device found"),
+            Self::HostUnavailable => write!(f, "Host unavailable"),
             Self::DualChannelNotSupported => write!(f, "Dual channel not supported"),
             Self::DeviceNotAvailable => write!(f, "Device not available"),
             Self::DeviceBackendSpecificError(err) => {
                 write!(f, "Device backend specific error: {}", err)
             }
+            Self::DeviceBusy(err) => {
+                write!(f, "Device used exclusively by another application: {}", err)
+            }
             Self::StreamTypeNotSupported => write!(f, "Stream type not supported"),
             Self::StreamConfigInvalidArgument => write!(f, "Stream config invalid argument"),
             Self::StreamIdOverflow => write!(f, "Stream id overflow"),
             Self::StreamConfigNotSupported => write!(f, "Stream config not supported"),
+            #[cfg(feature = "resample")]
             Self::ResamplerConstructionError(err) => {
                 write!(f, "Resampler construction error: {}", err)
             }
+            Self::CallbackSizeNotSupported {
+                requested,
+                min,
+                max,
+            } => write!(
+                f,
+                "Callback size of {} frames not supported, supported range is {}..={}",
+                requested, min, max
+            ),
+            Self::Io(err) => write!(f, "I/O error: {}", err),
+            Self::LatencyNotDetected => write!(f, "Latency chirp not detected in the recording"),
+            Self::Play(err) => write!(f, "Play error: {}", err),
+            Self::SnapshotMismatch => write!(f, "Snapshot doesn't match the player configuration"),
+            Self::UnsupportedOutputFormat(format) => {
+                write!(f, "Output sample format {} is not supported", format)
+            }
+            Self::UnsupportedInputFormat(format) => {
+                write!(f, "Input sample format {} is not supported", format)
+            }
+            Self::UnsupportedInputChannels(channels) => {
+                write!(f, "Input with {} channels is not supported", channels)
+            }
+            Self::SampleRateNotSupported(sample_rate) => {
+                write!(f, "Sample rate of {}Hz is not supported", sample_rate)
+            }
+            Self::ResamplingUnavailable { from, to } => write!(
+                f,
+                "Resampling {}Hz to {}Hz needs the `resample` or `lite-resample` feature",
+                from, to
+            ),
+            #[cfg(feature = "loopback")]
+            Self::LoopbackNotSupported => write!(f, "Loopback capture not supported"),
+            #[cfg(feature = "opus")]
+            Self::Opus(err) => write!(f, "Opus error: {}", err),
+        }
+    }
+}
+
+impl From<HostUnavailable> for AudioPlayerError {
+    fn from(_: HostUnavailable) -> Self {
+        Self::HostUnavailable
+    }
+}
+
+impl From<DevicesError> for AudioPlayerError {
+    fn from(e: DevicesError) -> Self {
+        match e {
+            DevicesError::BackendSpecific { err } => Self::from_backend(err),
         }
     }
 }
@@ -62,9 +232,7 @@ impl From<SupportedStreamConfigsError> for AudioPlayerError {
         match e {
             SupportedStreamConfigsError::DeviceNotAvailable => Self::DeviceNotAvailable,
             SupportedStreamConfigsError::InvalidArgument => Self::StreamConfigInvalidArgument,
-            SupportedStreamConfigsError::BackendSpecific { err } => {
-                Self::DeviceBackendSpecificError(err)
-            }
+            SupportedStreamConfigsError::BackendSpecific { err } => Self::from_backend(err),
         }
     }
 }
@@ -74,9 +242,7 @@ impl From<DefaultStreamConfigError> for AudioPlayerError {
         match e {
             DefaultStreamConfigError::DeviceNotAvailable => Self::DeviceNotAvailable,
             DefaultStreamConfigError::StreamTypeNotSupported => Self::StreamTypeNotSupported,
-            DefaultStreamConfigError::BackendSpecific { err } => {
-                Self::DeviceBackendSpecificError(err)
-            }
+            DefaultStreamConfigError::BackendSpecific { err } => Self::from_backend(err),
         }
     }
 }
@@ -88,11 +254,34 @@ impl From<BuildStreamError> for AudioPlayerError {
             BuildStreamError::StreamConfigNotSupported => Self::StreamConfigNotSupported,
             BuildStreamError::InvalidArgument => Self::StreamConfigInvalidArgument,
             BuildStreamError::StreamIdOverflow => Self::StreamIdOverflow,
-            BuildStreamError::BackendSpecific { err } => Self::DeviceBackendSpecificError(err),
+            BuildStreamError::BackendSpecific { err } => Self::from_backend(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for AudioPlayerError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(any(feature = "wav", feature = "decode"))]
+impl From<hound::Error> for AudioPlayerError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(err) => Self::Io(err),
+            err => Self::Io(std::io::Error::new(std::io::ErrorKind::Other, err)),
         }
     }
 }
 
+impl From<PlayError> for AudioPlayerError {
+    fn from(e: PlayError) -> Self {
+        Self::Play(e)
+    }
+}
+
+#[cfg(feature = "resample")]
 impl From<ResamplerConstructionError> for AudioPlayerError {
     fn from(e: ResamplerConstructionError) -> Self {
         Self::ResamplerConstructionError(e)
@@ -110,6 +299,8 @@ pub enum PlayError {
     DeviceNotAvailable,
     /// From [cpal]: See the [`BackendSpecificError`] docs for more information about this error variant.
     DeviceBackendSpecificError(BackendSpecificError),
+    /// The output stream was closed with [`AudioPlayer::close`](crate::AudioPlayer::close).
+    Closed,
 }
 
 impl Error for PlayError {}
@@ -121,6 +312,7 @@ impl fmt::Display for PlayError {
             Self::DeviceBackendSpecificError(err) => {
                 write!(f, "Device backend specific error: {}", err)
             }
+            Self::Closed => write!(f, "The output stream is closed"),
         }
     }
 }
@@ -142,3 +334,56 @@ impl From<PauseStreamError> for PlayError {
         }
     }
 }
+
+/// The `CallbackError` enum represents the problems that happen on the output side of the player,
+/// while playing, collected to be read with [`AudioPlayer::take_errors`](crate::AudioPlayer::take_errors).
+///
+/// The output keeps playing after these, but the device might have played silence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackError {
+    /// The device asked for samples in another format than the negotiated one,
+    /// silence was played for this callback.
+    FormatMismatch(SampleFormat),
+    /// The buffer was locked by the player, silence was played for this callback.
+    BufferBusy,
+    /// An error reported by the backend of the stream, with its description.
+    Stream(String),
+    /// The output panicked, with the panic message, see [`AudioPlayer::has_panicked`](crate::AudioPlayer::has_panicked).
+    Panic(String),
+}
+
+impl Error for CallbackError {}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FormatMismatch(format) => {
+                write!(f, "The device requested samples in format {}", format)
+            }
+            Self::BufferBusy => write!(f, "The buffer was busy, silence was played"),
+            Self::Stream(err) => write!(f, "Stream error: {}", err),
+            Self::Panic(message) => write!(f, "The output panicked: {}", message),
+        }
+    }
+}
+
+/// The error of [`AudioPlayer::try_queue`](crate::AudioPlayer::try_queue) when the samples don't all fit in the buffer.
+///
+/// The first `accepted` samples were queued, the rest should be queued again later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFullError {
+    /// The number of samples that were queued.
+    pub accepted: usize,
+}
+
+impl Error for QueueFullError {}
+
+impl fmt::Display for QueueFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The buffer is full, only {} samples were queued",
+            self.accepted
+        )
+    }
+}
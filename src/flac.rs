@@ -0,0 +1,499 @@
+//! A simple FLAC encoder, to write recordings losslessly compressed
+//!
+//! Each block is encoded with the best fixed predictor and partitioned Rice coding,
+//! stereo blocks also try the left/side, right/side and mid/side decorrelation.
+//! This doesn't compress as well as LPC, but is fast enough to run while playing.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use cpal::{FromSample, SampleFormat};
+
+use crate::sample::FloatSample;
+
+/// The number of frames in each FLAC block
+const BLOCK_SIZE: usize = 4096;
+/// The highest fixed predictor order in FLAC
+const MAX_FIXED_ORDER: usize = 4;
+/// The highest partition order we try for the residual
+const MAX_PARTITION_ORDER: u32 = 8;
+/// The highest Rice parameter with the 4 bits parameters, 15 is the escape code
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// Pick the number of bits per sample to use for samples in `format`
+pub(crate) fn flac_bits_for(format: SampleFormat) -> u32 {
+    match format {
+        SampleFormat::I8 | SampleFormat::U8 => 8,
+        SampleFormat::I16 | SampleFormat::U16 => 16,
+        _ => 24,
+    }
+}
+
+pub(crate) struct FlacFileWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    channels: usize,
+    bits: u32,
+    // interleaved samples waiting for a full block
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+    // reused between blocks
+    channel_buffers: Vec<Vec<i64>>,
+    bits_writer: BitWriter,
+}
+
+impl FlacFileWriter {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16, bits: u32) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC supports 1 to 8 channels",
+            ));
+        }
+
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate,
+            channels: channels as usize,
+            bits,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_frames: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+            channel_buffers: vec![Vec::with_capacity(BLOCK_SIZE); channels as usize],
+            bits_writer: BitWriter::default(),
+        };
+        writer.file.write_all(b"fLaC")?;
+        // rewritten on `finalize` with the number of samples
+        writer.write_stream_info()?;
+        Ok(writer)
+    }
+
+    /// Write interleaved samples, converting them to the bits per sample of the file
+    pub fn write<T>(&mut self, samples: &[T]) -> io::Result<()>
+    where
+        T: FloatSample,
+        i8: FromSample<T>,
+        i16: FromSample<T>,
+        i32: FromSample<T>,
+    {
+        for &sample in samples {
+            let sample = match self.bits {
+                8 => i8::from_sample_(sample) as i32,
+                16 => i16::from_sample_(sample) as i32,
+                _ => i32::from_sample_(sample) >> 8,
+            };
+            self.pending.push(sample);
+            if self.pending.len() == BLOCK_SIZE * self.channels {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> io::Result<()> {
+        // drop partial frames, they can't be encoded
+        let whole = self.pending.len() - self.pending.len() % self.channels;
+        self.pending.truncate(whole);
+        if !self.pending.is_empty() {
+            self.write_block()?;
+        }
+        self.file.seek(SeekFrom::Start(4))?;
+        self.write_stream_info()?;
+        self.file.flush()
+    }
+
+    fn write_stream_info(&mut self) -> io::Result<()> {
+        let mut bits = BitWriter::default();
+        // last metadata block, STREAMINFO, 34 bytes
+        bits.write(1, 1);
+        bits.write(0, 7);
+        bits.write(34, 24);
+
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        // 0 when unknown
+        let min_frame_size = if self.max_frame_size == 0 {
+            0
+        } else {
+            self.min_frame_size
+        };
+        bits.write(min_frame_size as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(self.bits as u64 - 1, 5);
+        bits.write(self.total_frames >> 32, 4);
+        bits.write(self.total_frames & 0xFFFF_FFFF, 32);
+        // MD5 of the samples, all zeros when not computed
+        for _ in 0..4 {
+            bits.write(0, 32);
+        }
+        self.file.write_all(&bits.finish())
+    }
+
+    /// Encode the pending samples as one FLAC frame
+    fn write_block(&mut self) -> io::Result<()> {
+        let block_size = self.pending.len() / self.channels;
+        for (channel, buffer) in self.channel_buffers.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.extend(
+                self.pending
+                    .iter()
+                    .skip(channel)
+                    .step_by(self.channels)
+                    .map(|&s| s as i64),
+            );
+        }
+        self.pending.clear();
+
+        let assignment = if self.channels == 2 {
+            stereo_assignment(&mut self.channel_buffers)
+        } else {
+            ChannelAssignment::Independent
+        };
+
+        let bits = &mut self.bits_writer;
+        // sync code and fixed block size
+        bits.write(0b1111_1111_1111_1000, 16);
+        let block_size_code = if block_size == BLOCK_SIZE {
+            // 256 * 2^(12 - 8)
+            12
+        } else {
+            // 16 bits block size - 1 at the end of the header
+            7
+        };
+        bits.write(block_size_code, 4);
+        // sample rate from STREAMINFO
+        bits.write(0, 4);
+        bits.write(assignment.code(self.channels), 4);
+        let size_code = match self.bits {
+            8 => 0b001,
+            16 => 0b100,
+            _ => 0b110,
+        };
+        bits.write(size_code, 3);
+        bits.write(0, 1);
+        write_utf8_number(bits, self.frame_number);
+        if block_size_code == 7 {
+            bits.write(block_size as u64 - 1, 16);
+        }
+        let crc = crc8(bits.bytes());
+        bits.write(crc as u64, 8);
+
+        for (channel, samples) in self.channel_buffers.iter().enumerate() {
+            let bits_per_sample = self.bits + assignment.extra_bits(channel) as u32;
+            write_subframe(bits, samples, bits_per_sample);
+        }
+
+        bits.align();
+        let crc = crc16(bits.bytes());
+        bits.write(crc as u64, 16);
+
+        let frame = bits.finish();
+        self.min_frame_size = self.min_frame_size.min(frame.len() as u32);
+        self.max_frame_size = self.max_frame_size.max(frame.len() as u32);
+        self.frame_number += 1;
+        self.total_frames += block_size as u64;
+        self.file.write_all(&frame)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ChannelAssignment {
+    Independent,
+    LeftSide,
+    RightSide,
+    MidSide,
+}
+
+impl ChannelAssignment {
+    fn code(&self, channels: usize) -> u64 {
+        match self {
+            Self::Independent => channels as u64 - 1,
+            Self::LeftSide => 8,
+            Self::RightSide => 9,
+            Self::MidSide => 10,
+        }
+    }
+
+    /// The side channel needs one more bit
+    fn extra_bits(&self, channel: usize) -> u8 {
+        match (self, channel) {
+            (Self::LeftSide, 1) | (Self::RightSide, 0) | (Self::MidSide, 1) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Pick the stereo decorrelation with the smallest residual, and convert `channels` to it
+fn stereo_assignment(channels: &mut [Vec<i64>]) -> ChannelAssignment {
+    let (left, right) = (&channels[0], &channels[1]);
+    let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+    let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+
+    let left_cost = best_fixed_order(left).1;
+    let right_cost = best_fixed_order(right).1;
+    let side_cost = best_fixed_order(&side).1;
+    let mid_cost = best_fixed_order(&mid).1;
+
+    let costs = [
+        (left_cost + right_cost, ChannelAssignment::Independent),
+        (left_cost + side_cost, ChannelAssignment::LeftSide),
+        (side_cost + right_cost, ChannelAssignment::RightSide),
+        (mid_cost + side_cost, ChannelAssignment::MidSide),
+    ];
+    let assignment = costs.iter().min_by_key(|(cost, _)| *cost).unwrap().1;
+
+    match assignment {
+        ChannelAssignment::Independent => {}
+        ChannelAssignment::LeftSide => channels[1] = side,
+        ChannelAssignment::RightSide => channels[0] = side,
+        ChannelAssignment::MidSide => {
+            channels[0] = mid;
+            channels[1] = side;
+        }
+    }
+    assignment
+}
+
+/// Returns the fixed predictor order with the smallest sum of absolute residuals, and that sum
+fn best_fixed_order(samples: &[i64]) -> (usize, u64) {
+    let max_order = MAX_FIXED_ORDER.min(samples.len().saturating_sub(1));
+    (0..=max_order)
+        .map(|order| {
+            let sum = fixed_residuals(samples, order)
+                .map(|r| r.unsigned_abs())
+                .sum::<u64>();
+            (order, sum)
+        })
+        .min_by_key(|&(_, sum)| sum)
+        .unwrap_or((0, 0))
+}
+
+/// The residuals of the fixed predictor of `order`, for the samples after the warmup
+fn fixed_residuals(samples: &[i64], order: usize) -> impl Iterator<Item = i64> + '_ {
+    (order..samples.len()).map(move |i| {
+        let s = |back: usize| samples[i - back];
+        match order {
+            0 => s(0),
+            1 => s(0) - s(1),
+            2 => s(0) - 2 * s(1) + s(2),
+            3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+            _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+        }
+    })
+}
+
+fn write_subframe(bits: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    let verbatim_size = samples.len() as u64 * bits_per_sample as u64;
+
+    if samples.iter().all(|&s| s == samples[0]) {
+        // CONSTANT
+        write_subframe_header(bits, 0b000000);
+        bits.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    let (order, _) = best_fixed_order(samples);
+    let residuals: Vec<u64> = fixed_residuals(samples, order).map(zigzag).collect();
+    let (partition_order, parameters, residual_size) = rice_partitions(&residuals, order);
+
+    if order as u64 * bits_per_sample as u64 + residual_size >= verbatim_size {
+        // VERBATIM
+        write_subframe_header(bits, 0b000001);
+        for &sample in samples {
+            bits.write_signed(sample, bits_per_sample);
+        }
+        return;
+    }
+
+    // FIXED with `order`
+    write_subframe_header(bits, 0b001000 | order as u64);
+    for &sample in &samples[..order] {
+        bits.write_signed(sample, bits_per_sample);
+    }
+    // partitioned Rice coding with 4 bits parameters
+    bits.write(0b00, 2);
+    bits.write(partition_order as u64, 4);
+    let partition_len = samples.len() >> partition_order;
+    let mut residuals = residuals.iter();
+    for (partition, &parameter) in parameters.iter().enumerate() {
+        let len = if partition == 0 {
+            partition_len - order
+        } else {
+            partition_len
+        };
+        bits.write(parameter as u64, 4);
+        for &residual in residuals.by_ref().take(len) {
+            bits.write_rice(residual, parameter);
+        }
+    }
+}
+
+fn write_subframe_header(bits: &mut BitWriter, subframe_type: u64) {
+    // zero padding, the type, and no wasted bits
+    bits.write(0, 1);
+    bits.write(subframe_type, 6);
+    bits.write(0, 1);
+}
+
+/// Pick the partition order and the Rice parameters of the partitions for `residuals`,
+/// returns them with the size of the encoded residual in bits
+fn rice_partitions(residuals: &[u64], order: usize) -> (u32, Vec<u32>, u64) {
+    let block_size = residuals.len() + order;
+    let mut best = (0, Vec::new(), u64::MAX);
+
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partition_len = block_size >> partition_order;
+        if block_size % (1 << partition_order) != 0 || partition_len <= order {
+            break;
+        }
+
+        let mut parameters = Vec::with_capacity(1 << partition_order);
+        let mut size = 6u64;
+        let mut start = 0;
+        for partition in 0..1 << partition_order {
+            let len = if partition == 0 {
+                partition_len - order
+            } else {
+                partition_len
+            };
+            let partition = &residuals[start..start + len];
+            start += len;
+
+            let (parameter, bits) = (0..=MAX_RICE_PARAMETER)
+                .map(|k| {
+                    let bits: u64 = partition.iter().map(|&r| (r >> k) + 1 + k as u64).sum();
+                    (k, bits)
+                })
+                .min_by_key(|&(_, bits)| bits)
+                .unwrap();
+            parameters.push(parameter);
+            size += 4 + bits;
+        }
+
+        if size < best.2 {
+            best = (partition_order, parameters, size);
+        }
+    }
+    best
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Write the frame number in the UTF-8 like coding of FLAC
+fn write_utf8_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+    // the number of continuation bytes, each holding 6 bits
+    let continuation = match value {
+        0..=0x7FF => 1,
+        0x800..=0xFFFF => 2,
+        0x1_0000..=0x1F_FFFF => 3,
+        0x20_0000..=0x3FF_FFFF => 4,
+        0x400_0000..=0x7FFF_FFFF => 5,
+        _ => 6,
+    };
+    // `continuation + 1` ones, then a zero
+    let prefix = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+    bits.write(prefix | (value >> (6 * continuation)), 8);
+    for i in (0..continuation).rev() {
+        bits.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Writes values MSB first, as FLAC expects
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    // the number of bits in `accumulator`
+    len: u32,
+}
+
+impl BitWriter {
+    /// Write the `count` low bits of `value`, `count` is at most 32
+    fn write(&mut self, value: u64, count: u32) {
+        debug_assert!(count <= 32);
+        let mask = (1u64 << count) - 1;
+        self.accumulator = (self.accumulator << count) | (value & mask);
+        self.len += count;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.accumulator >> self.len) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, count: u32) {
+        self.write(value as u64, count);
+    }
+
+    fn write_rice(&mut self, value: u64, parameter: u32) {
+        let mut quotient = value >> parameter;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        // `quotient` zeros, then a one
+        self.write(1, quotient as u32 + 1);
+        self.write(value, parameter);
+    }
+
+    /// Pad with zeros to the next byte
+    fn align(&mut self) {
+        if self.len > 0 {
+            self.write(0, 8 - self.len);
+        }
+    }
+
+    /// The complete bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the written bytes, padded to a byte, and reset the writer
+    fn finish(&mut self) -> Vec<u8> {
+        self.align();
+        self.accumulator = 0;
+        std::mem::take(&mut self.bytes)
+    }
+}
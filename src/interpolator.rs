@@ -0,0 +1,115 @@
+use crate::{resampler::ChunkResampler, sample::FloatSample};
+
+/// The number of input frames kept from the previous chunk, for the points before and after the interpolated one
+const HISTORY: usize = 3;
+
+/// The built-in resampler, interpolating between the input frames of each channel.
+///
+/// Much cheaper than the resamplers of rubato, without any anti-aliasing filter,
+/// which is fine for sources with little content near the Nyquist frequency.
+pub(crate) struct Interpolator<T> {
+    cubic: bool,
+    ratio: f64,
+    // the input frames advanced for each output frame
+    step: f64,
+    chunk_size: usize,
+    // the position of the next output frame in the window, the window starts with the history
+    position: f64,
+    // for each channel, the last frames of the previous chunk followed by the current chunk
+    windows: Vec<Vec<T>>,
+}
+
+impl<T: FloatSample> Interpolator<T> {
+    pub fn new(ratio: f64, chunk_size: usize, channels: usize, cubic: bool) -> Self {
+        let mut interpolator = Self {
+            cubic,
+            ratio,
+            step: 1.0 / ratio,
+            chunk_size: chunk_size.max(1),
+            position: 0.0,
+            windows: (0..channels)
+                .map(|_| Vec::with_capacity(HISTORY + chunk_size.max(1)))
+                .collect(),
+        };
+        interpolator.reset();
+        interpolator
+    }
+
+    /// The number of output frames produced by the next chunk, starting at `position`
+    fn frames_for(&self, step: f64) -> usize {
+        // the points around the interpolated one must be in the window
+        let end = (HISTORY + self.chunk_size - 2) as f64;
+        ((end - self.position) / step).ceil().max(0.0) as usize
+    }
+
+    fn interpolate(&self, window: &[T], position: f64) -> T {
+        let index = position as usize;
+        let t = T::coerce(position - index as f64);
+        let (y1, y2) = (window[index], window[index + 1]);
+        if !self.cubic {
+            return y1 + (y2 - y1) * t;
+        }
+        // Catmull-Rom spline through the 4 frames around the position
+        let (y0, y3) = (window[index - 1], window[index + 2]);
+        let half = T::coerce(0.5);
+        let a = half * (y3 - y0) + T::coerce(1.5) * (y1 - y2);
+        let b = y0 - T::coerce(2.5) * y1 + y2 + y2 - half * y3;
+        let c = half * (y2 - y0);
+        ((a * t + b) * t + c) * t + y1
+    }
+}
+
+impl<T: FloatSample> ChunkResampler<T> for Interpolator<T> {
+    fn process(&mut self, input: &[Vec<T>], output: &mut [Vec<T>]) -> usize {
+        let frames = self.frames_for(self.step);
+        let mut windows = std::mem::take(&mut self.windows);
+        for ((window, input), output) in windows.iter_mut().zip(input).zip(output.iter_mut()) {
+            window.extend_from_slice(&input[..self.chunk_size]);
+            for (frame, sample) in output[..frames].iter_mut().enumerate() {
+                *sample = self.interpolate(window, self.position + frame as f64 * self.step);
+            }
+            // keep the last frames for the next chunk
+            let len = window.len();
+            window.copy_within(len - HISTORY.., 0);
+            window.truncate(HISTORY);
+        }
+        self.windows = windows;
+        self.position += frames as f64 * self.step - self.chunk_size as f64;
+        frames
+    }
+
+    fn input_frames_max(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn input_frames_next(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn output_frames_max(&self) -> usize {
+        // the fastest ratio, with the maximum drift, from the earliest position
+        let step = 1.0 / (self.ratio * crate::resampler::MAX_DRIFT);
+        ((HISTORY + self.chunk_size) as f64 / step).ceil() as usize + 1
+    }
+
+    fn output_frames_next(&self) -> usize {
+        self.frames_for(self.step)
+    }
+
+    fn output_delay(&self) -> usize {
+        // the first input frame is after the history, one frame after the first output
+        (2.0 * self.ratio).round() as usize
+    }
+
+    fn reset(&mut self) {
+        self.position = 1.0;
+        for window in &mut self.windows {
+            window.clear();
+            window.resize(HISTORY, T::coerce(0.0));
+        }
+    }
+
+    fn set_ratio_relative(&mut self, ratio: f64) {
+        self.step = 1.0 / (self.ratio * ratio);
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+/// Configuration of the jitter buffer of the player, for samples arriving over a network,
+/// set with [`AudioPlayerBuilder::jitter_buffer`](crate::AudioPlayerBuilder::jitter_buffer).
+///
+/// Samples generated locally arrive at a steady pace, but chunks received from the network,
+/// like netplay audio or a remote emulator core, arrive early or late by varying amounts.
+/// The jitter buffer measures the variation of the arrival times, and sets the latency target
+/// to absorb it, from `min` up to `max`. After an underrun, playback resumes once the target is buffered.
+///
+/// The gaps left by late chunks are concealed, see [`AudioPlayerBuilder::underrun_concealment`](crate::AudioPlayerBuilder::underrun_concealment),
+/// and when the buffer goes above `max`, for example with the burst of chunks received after a network gap,
+/// the oldest samples are dropped to get back to the target, same as [`AudioPlayerBuilder::latency_clamp`](crate::AudioPlayerBuilder::latency_clamp).
+///
+/// The jitter buffer replaces the [`AdaptiveLatency`](crate::AdaptiveLatency) of the player.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, JitterBuffer};
+/// # use std::{thread, time::Duration};
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .jitter_buffer(JitterBuffer::new(Duration::from_millis(20), Duration::from_millis(200)))
+///     .build()
+///     .unwrap();
+/// assert_eq!(player.latency_target(), Some(Duration::from_millis(20)));
+///
+/// // 10ms chunks, the second one arrives late
+/// player.queue(&[0.5; 480 * 2]);
+/// thread::sleep(Duration::from_millis(30));
+/// player.queue(&[0.5; 480 * 2]);
+/// assert!(player.arrival_jitter().unwrap() > Duration::ZERO);
+///
+/// // a burst above the maximum is dropped down to the target
+/// player.queue(&[0.5; 48000 * 2]);
+/// assert!(player.buffered_samples() <= 9600 * 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JitterBuffer {
+    pub(crate) min: Duration,
+    pub(crate) max: Duration,
+    pub(crate) headroom: f32,
+    pub(crate) concealment: Duration,
+    pub(crate) resync_after: Duration,
+}
+
+impl JitterBuffer {
+    /// Keep a latency target between `min` and `max`.
+    ///
+    /// By default, the target is `min` plus 4 times the measured jitter, gaps of up to `20ms`
+    /// are concealed, and the jitter is not measured across pauses of the sender longer than `500ms`.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            headroom: 4.0,
+            concealment: Duration::from_millis(20),
+            resync_after: Duration::from_millis(500),
+        }
+    }
+
+    /// Set the latency target to `min` plus `headroom` times the measured jitter.
+    pub fn headroom(mut self, headroom: f32) -> Self {
+        self.headroom = headroom.max(0.0);
+        self
+    }
+
+    /// Conceal the gaps of at most `max_gap` left by late chunks, see
+    /// [`AudioPlayerBuilder::underrun_concealment`](crate::AudioPlayerBuilder::underrun_concealment).
+    pub fn concealment(mut self, max_gap: Duration) -> Self {
+        self.concealment = max_gap;
+        self
+    }
+
+    /// Treat a time of `gap` without chunks as the sender pausing, not as a late chunk,
+    /// the arrivals are measured again after it.
+    pub fn resync_after(mut self, gap: Duration) -> Self {
+        self.resync_after = gap;
+        self
+    }
+}
+
+/// Measures the jitter of the arrival times of the queued chunks, and adjusts the latency target
+pub(crate) struct JitterTracker {
+    config: JitterBuffer,
+    // the smoothed deviation of the arrival intervals from the chunk durations, in seconds
+    jitter: f64,
+    // the arrival time and the duration of the last chunk
+    last: Option<(Instant, Duration)>,
+    target: Duration,
+}
+
+impl JitterTracker {
+    pub fn new(config: JitterBuffer) -> Self {
+        Self {
+            target: config.min,
+            config,
+            jitter: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    pub fn max(&self) -> Duration {
+        self.config.max
+    }
+
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+
+    /// Record a chunk of `duration` arriving at `now`, returns the new target if it changed
+    pub fn update(&mut self, duration: Duration, now: Instant) -> Option<Duration> {
+        if let Some((last_arrival, last_duration)) = self.last {
+            let interval = now.saturating_duration_since(last_arrival);
+            if interval <= self.config.resync_after {
+                // same smoothing as the interarrival jitter of RTP
+                let deviation = (interval.as_secs_f64() - last_duration.as_secs_f64()).abs();
+                self.jitter += (deviation - self.jitter) / 16.0;
+            }
+        }
+        self.last = Some((now, duration));
+
+        let headroom = Duration::from_secs_f64(self.jitter * self.config.headroom as f64);
+        let target = (self.config.min + headroom).min(self.config.max);
+        // small changes don't resize the buffer
+        let change = target.max(self.target) - target.min(self.target);
+        if change < self.target / 10 {
+            return None;
+        }
+        self.target = target;
+        Some(target)
+    }
+}
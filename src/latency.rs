@@ -0,0 +1,149 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{error::AudioPlayerError, AudioPlayerBuilder, AudioRecorder, BufferSize};
+
+/// Duration of the chirp played during the measurement
+const CHIRP_DURATION: Duration = Duration::from_millis(100);
+/// Time given to both streams to start before playing the chirp
+const SETTLE_DURATION: Duration = Duration::from_millis(300);
+/// Maximum round-trip latency we wait for
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+/// Minimum normalized correlation for the chirp to be considered detected
+const DETECTION_THRESHOLD: f32 = 0.3;
+
+/// The result of [`measure_latency`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyMeasurement {
+    /// The time between queueing the chirp into the player and recording it back.
+    ///
+    /// This includes the output and input latency of the devices, and the time the sound took
+    /// to travel between the speakers and the microphone (~3ms per meter).
+    pub round_trip: Duration,
+    /// How similar the recorded sound was to the played chirp, between `0.0` and `1.0`.
+    ///
+    /// Low values mean the chirp was recorded with a lot of noise, so the result is less reliable.
+    pub correlation: f32,
+}
+
+/// Measure the round-trip latency of the audio devices.
+///
+/// Plays a short chirp with an [`AudioPlayer`](crate::AudioPlayer) created from `player`,
+/// records it back with an [`AudioRecorder`] from the default input device at the same sample rate,
+/// and finds where the chirp is in the recording.
+///
+/// The chirp is queued into an empty buffer, so the result is the minimum latency of the
+/// configuration, any samples kept queued in the player (see [`BufferSize`]) are added on top of it.
+///
+/// The output must be audible by the input device, i.e. speakers and a microphone, or a loopback cable.
+/// The measurement takes around 1.5 seconds.
+///
+/// Returns [`AudioPlayerError::LatencyNotDetected`] if the chirp wasn't found in the recording.
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{measure_latency, AudioPlayer, CallbackSize};
+/// let measurement =
+///     measure_latency(AudioPlayer::builder(48000).callback_size(CallbackSize::Minimum)).unwrap();
+/// println!("round-trip latency: {:?}", measurement.round_trip);
+/// ```
+pub fn measure_latency(
+    player: AudioPlayerBuilder<f32>,
+) -> Result<LatencyMeasurement, AudioPlayerError> {
+    let sample_rate = player.sample_rate;
+    let chirp = chirp(sample_rate);
+
+    // recorded mono samples, and the time the first of them was captured
+    let recorded = Arc::new(Mutex::new((None::<Instant>, Vec::<f32>::new())));
+    let recorder = AudioRecorder::<f32>::builder(sample_rate)
+        .on_data({
+            let recorded = recorded.clone();
+            move |samples| {
+                let mut recorded = recorded.lock().unwrap();
+                let (start, data) = &mut *recorded;
+                if start.is_none() {
+                    let frames = (samples.len() / 2) as f64;
+                    *start =
+                        Some(Instant::now() - Duration::from_secs_f64(frames / sample_rate as f64));
+                }
+                data.extend(samples.chunks_exact(2).map(|f| (f[0] + f[1]) / 2.0));
+            }
+        })
+        .build()?;
+    let mut player = player.buffer_size(BufferSize::OneSecond).build()?;
+
+    recorder.record()?;
+    player.play()?;
+    thread::sleep(SETTLE_DURATION);
+
+    let chirp_start = Instant::now();
+    player.queue(&chirp.iter().flat_map(|&s| [s, s]).collect::<Vec<_>>());
+    thread::sleep(CHIRP_DURATION + MAX_LATENCY);
+
+    recorder.pause()?;
+    player.pause()?;
+    drop(recorder);
+
+    let (record_start, data) = std::mem::take(&mut *recorded.lock().unwrap());
+    let record_start = record_start.ok_or(AudioPlayerError::LatencyNotDetected)?;
+
+    // only search after the chirp was queued
+    let skip = chirp_start
+        .checked_duration_since(record_start)
+        .map_or(0, |d| (d.as_secs_f64() * sample_rate as f64) as usize);
+    let (offset, correlation) = find_chirp(data.get(skip..).unwrap_or_default(), &chirp)
+        .ok_or(AudioPlayerError::LatencyNotDetected)?;
+    if correlation < DETECTION_THRESHOLD {
+        return Err(AudioPlayerError::LatencyNotDetected);
+    }
+
+    let recorded_at =
+        record_start + Duration::from_secs_f64((skip + offset) as f64 / sample_rate as f64);
+    Ok(LatencyMeasurement {
+        round_trip: recorded_at.saturating_duration_since(chirp_start),
+        correlation,
+    })
+}
+
+/// A linear sine sweep from 500Hz to 5000Hz, with a hann window to avoid clicks
+fn chirp(sample_rate: u32) -> Vec<f32> {
+    let len = (CHIRP_DURATION.as_secs_f32() * sample_rate as f32) as usize;
+    let duration = CHIRP_DURATION.as_secs_f32();
+    let (f0, f1) = (500.0, 5000.0);
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let phase = 2.0 * PI * (f0 * t + (f1 - f0) * t * t / (2.0 * duration));
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos();
+            0.5 * window * phase.sin()
+        })
+        .collect()
+}
+
+/// Returns the offset of the best match of `chirp` in `data`, and its normalized correlation
+fn find_chirp(data: &[f32], chirp: &[f32]) -> Option<(usize, f32)> {
+    if data.len() < chirp.len() {
+        return None;
+    }
+    let chirp_energy = chirp.iter().map(|s| s * s).sum::<f32>();
+
+    let (offset, _) = data
+        .windows(chirp.len())
+        .map(|window| window.iter().zip(chirp).map(|(a, b)| a * b).sum::<f32>())
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    let window = &data[offset..offset + chirp.len()];
+    let window_energy = window.iter().map(|s| s * s).sum::<f32>();
+    let dot = window.iter().zip(chirp).map(|(a, b)| a * b).sum::<f32>();
+    let correlation = if window_energy > 0.0 {
+        dot / (chirp_energy * window_energy).sqrt()
+    } else {
+        0.0
+    };
+    Some((offset, correlation))
+}
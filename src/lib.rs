@@ -7,10 +7,16 @@
 //! And will resample the audio if the generated sample rate is not supported by the audio device,
 //!
 //! # Supported sample types
-//! For now, we rely on [`rubato`] crate for resampling, it has the trait [`Sample`] that is implemented for:
+//! The players process float samples implementing [`FloatSample`], it's implemented for:
 //! - [`f32`]
 //! - [`f64`]
 //!
+//! Samples of the other [`Sample`] types, [`i16`], [`i32`] and [`u8`], are promoted to the float type
+//! of the player when queued with [`AudioPlayer::queue_samples`].
+//!
+//! Without the default `resample` feature, rubato is not used, and the player only plays at the sample rate
+//! of the queued samples, see [`AudioPlayerError::ResamplingUnavailable`].
+//!
 //! # Example
 //!
 //! Here's an example of how to use the `AudioPlayer`:
@@ -34,121 +40,182 @@
 //! #     vec![0.0; 1]
 //! # }
 //! ```
+mod adaptive;
+pub mod backend;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod convert;
+mod crossfade;
+#[cfg(feature = "decode")]
+mod decode;
+mod devices;
+mod diagnostics;
+mod downmix;
+mod drift;
+pub mod effects;
+mod engine;
 pub mod error;
+#[cfg(feature = "flac")]
+mod flac;
+mod interpolator;
+mod jitter;
+mod latency;
+pub mod libretro;
+#[cfg(feature = "loopback")]
+mod loopback;
+mod metadata;
+mod meter;
+mod mixer;
+mod negotiation;
+mod offline;
+#[cfg(feature = "opus")]
+mod opus;
+mod pcm;
+mod playback;
+#[cfg(feature = "rt-priority")]
+mod priority;
+mod rate_hint;
+#[cfg(feature = "udp-receiver")]
+mod receiver;
+mod recorder;
+#[cfg(feature = "wav")]
+mod recording;
+mod recovery;
+mod resampler;
+mod rewind;
+pub mod ring;
+#[cfg(feature = "debug-rt-checks")]
+mod rt_checks;
+mod sample;
+mod schedule;
+#[cfg(feature = "shared-memory")]
+mod shm;
+mod silence;
+mod sleep;
+mod snapshot;
+mod standby;
+mod state;
+mod stats;
+mod timestamps;
+mod underrun;
 mod utils;
+mod visualization;
+mod volume;
+mod watchdog;
+mod watermark;
+#[cfg(feature = "wav")]
+mod wav;
 
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    FromSample, SizedSample,
+pub use adaptive::AdaptiveLatency;
+pub use builder::{AudioPlayerBuilder, CallbackSize, PlatformProfile, Preset};
+pub use crossfade::OutgoingStream;
+pub use devices::{
+    devices, devices_of_host, supported_passthrough_rates, DeviceConfig, OutputDevice,
 };
-use error::{AudioPlayerError, PlayError};
-use ringbuf::{
-    traits::{Producer, Split},
-    HeapProd, HeapRb,
+pub use diagnostics::Diagnostic;
+pub use downmix::{InputLayout, PartialFrames};
+pub use engine::{AudioEngine, SyncGroup, VoiceId};
+pub use jitter::JitterBuffer;
+pub use latency::{measure_latency, LatencyMeasurement};
+pub use metadata::{MediaRole, StreamMetadata};
+pub use meter::ChannelLevel;
+pub use mixer::{LoopingClip, Mixer, Track, TrackControls};
+pub use negotiation::{ConfigPreference, Negotiation};
+pub use offline::OfflineRenderer;
+pub use pcm::PcmWriter;
+pub use playback::PlaybackToken;
+#[cfg(feature = "rt-priority")]
+pub use priority::promote_current_thread;
+#[cfg(feature = "udp-receiver")]
+pub use receiver::UdpReceiver;
+pub use recorder::{AudioRecorder, AudioRecorderBuilder};
+pub use recovery::RecoveryPolicy;
+pub use resampler::{resample, ResamplingAlgorithm, ResamplingInfo};
+#[cfg(feature = "debug-rt-checks")]
+pub use rt_checks::{
+    assert_not_realtime, is_realtime, permit_alloc, set_rt_time_budget, RtCheckAllocator,
 };
-use rubato::{FftFixedInOut, Resampler, Sample};
-
-struct AudioResampler<T: Sample> {
-    resampler: FftFixedInOut<T>,
-    pre_resampled_buffer: Vec<T>,
-    pre_resampled_split_buffers: [Vec<T>; 2],
-    resample_process_buffers: [Vec<T>; 2],
-    resampled_buffer: Vec<T>,
-}
+pub use sample::{FloatSample, Sample};
+pub use schedule::StartTime;
+#[cfg(feature = "shared-memory")]
+pub use shm::{SharedMemoryConsumer, SharedMemoryProducer};
+pub use silence::SilenceDetection;
+pub use snapshot::AudioSnapshot;
+pub use state::{Channel, PauseMode, PlayerState};
+pub use stats::{CallbackStats, Metrics};
+pub use timestamps::CallbackTimestamp;
+pub use underrun::UnderrunFill;
+pub use volume::FadeCurve;
+pub use watermark::BufferLevel;
 
-impl<T: Sample + SizedSample> AudioResampler<T> {
-    fn new(input_rate: usize, output_rate: usize) -> Result<Self, AudioPlayerError> {
-        let resampler = FftFixedInOut::<T>::new(
-            input_rate,
-            output_rate,
-            // the number of samples for one video frame in 60 FPS
-            input_rate / 60,
-            2,
-        )?;
-
-        Ok(Self {
-            resampler,
-            pre_resampled_buffer: Vec::new(),
-            pre_resampled_split_buffers: [Vec::new(), Vec::new()],
-            resample_process_buffers: [Vec::new(), Vec::new()],
-            resampled_buffer: Vec::new(),
-        })
-    }
+use adaptive::LatencyTuner;
+use backend::{
+    device::RawCallback,
+    source::{OutputStages, SharedConsumer, SourceCommand, SourceSlot},
+    DeviceBackend, OutputBackend, OutputConfig, OutputRequest, OutputSource, OutputStream,
+};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use crossfade::CrossfadeSource;
+use diagnostics::DIAGNOSTICS_CAPACITY;
+use effects::{AudioEffect, LowPass, SoftLimiter};
+use error::{AudioPlayerError, CallbackError, PlayError, QueueFullError};
+use jitter::JitterTracker;
+use meter::{LevelMeter, Levels};
+use rate_hint::RateHint;
+use recovery::{Recovery, RecoveryAction};
+use resampler::{AudioResampler, LoadMonitor};
+use rewind::RewindHistory;
+use ring::{HeapRing, SampleRing};
+use ringbuf::{
+    traits::{Consumer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+use silence::{SilenceCallback, SilenceDetector};
+use sleep::SleepDetector;
+use standby::{OutputSwitch, RateChange, Standby};
+use state::PlayerShared;
+use stats::LoadWarning;
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use timestamps::Timestamps;
+#[cfg(feature = "spectrum")]
+use visualization::SpectrumAnalyzer;
+use visualization::{History, VisualizationTap};
+use volume::VolumeFade;
+use watchdog::Watchdog;
+use watermark::Watermarks;
 
-    fn resample_into_producer(&mut self, data: &[T], producer: &mut HeapProd<T>) {
-        // helper method to split channels into separate vectors
-        fn read_frames<T: Copy>(inbuffer: &[T], n_frames: usize, outputs: &mut [Vec<T>]) {
-            for output in outputs.iter_mut() {
-                output.clear();
-                output.reserve(n_frames);
-            }
-            let mut value: T;
-            let mut inbuffer_iter = inbuffer.iter();
-            for _ in 0..n_frames {
-                for output in outputs.iter_mut() {
-                    value = *inbuffer_iter.next().unwrap();
-                    output.push(value);
-                }
-            }
-        }
+/// The sample rate requested by [`AudioPlayer::new_auto`] if the backend has no native rate
+const AUTO_SAMPLE_RATE_FALLBACK: u32 = 48000;
 
-        /// Helper to merge channels into a single vector
-        /// the number of channels is the size of `waves` slice
-        fn write_frames<T: Copy>(waves: &[Vec<T>], outbuffer: &mut Vec<T>) {
-            let nbr = waves[0].len();
-            for frame in 0..nbr {
-                for wave in waves.iter() {
-                    outbuffer.push(wave[frame]);
-                }
-            }
-        }
+/// Duration of the crossfade between the cleared samples and the new ones in [`AudioPlayer::clear`]
+const CLEAR_CROSSFADE: Duration = Duration::from_millis(10);
 
-        self.pre_resampled_buffer.extend_from_slice(data);
-        // finish all the frames, as sometimes after appending many data
-        // we might get 2 loops worth of unprocessed audio
-        loop {
-            let frames = self.resampler.input_frames_next();
+/// The number of commands to the output that can be pending, they are processed on every callback
+/// of the output, the ones sent while it's full, for example while paused, wait in the player.
+const COMMANDS_CAPACITY: usize = 64;
 
-            if self.pre_resampled_buffer.len() < frames * 2 {
-                return;
-            }
+/// The maximum number of errors of the output kept until [`AudioPlayer::take_errors`], newer ones are dropped
+const ERRORS_CAPACITY: usize = 64;
 
-            // only read the needed frames
-            read_frames(
-                &self.pre_resampled_buffer,
-                frames,
-                &mut self.pre_resampled_split_buffers,
-            );
+/// Time to move to a new volume set with `set_volume`, to avoid clicks
+const VOLUME_RAMP: Duration = Duration::from_millis(10);
 
-            self.resample_process_buffers[0].clear();
-            self.resample_process_buffers[0].clear();
-
-            let output_frames = self.resampler.output_frames_next();
-            self.resample_process_buffers[0].resize(output_frames, T::EQUILIBRIUM);
-            self.resample_process_buffers[1].resize(output_frames, T::EQUILIBRIUM);
-
-            self.resampler
-                .process_into_buffer(
-                    &self.pre_resampled_split_buffers,
-                    &mut self.resample_process_buffers,
-                    None,
-                )
-                .unwrap();
-
-            // resample
-            if self.resampled_buffer.len() < output_frames * 2 {
-                self.resampled_buffer
-                    .reserve(output_frames * 2 - self.resampled_buffer.len());
-            }
-            self.resampled_buffer.clear();
-            write_frames(&self.resample_process_buffers, &mut self.resampled_buffer);
+/// The widest stereo width of [`AudioPlayer::set_stereo_width`]
+const MAX_STEREO_WIDTH: f32 = 4.0;
 
-            producer.push_slice(&self.resampled_buffer);
+/// How often the drop checks if the output faded out, see [`AudioPlayerBuilder::fade_out_on_drop`]
+const DROP_FADE_POLL: Duration = Duration::from_millis(1);
 
-            self.pre_resampled_buffer = self.pre_resampled_buffer.split_off(frames * 2);
-        }
-    }
-}
+/// The time the machine must sleep between two queues to rebuild the stream,
+/// see [`AudioPlayerBuilder::sleep_recovery`]
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(2);
 
 /// The `BufferSize` enum represents the amount of audio samples that can be stored in the buffer.
 /// Limiting the number of samples in the buffer is crucial for minimizing audio delay in audio playing.
@@ -175,18 +242,39 @@ pub enum BufferSize {
     /// Number of samples to store
     /// Be careful, here you have to calculate based on the sample rate manually
     Samples(usize),
+    /// Number of samples to store, computed from the sample rate and channels of the output,
+    /// after they are negotiated with the device.
+    ///
+    /// Unlike [`Samples`](Self::Samples), this stays right when the output is resampled to another rate,
+    /// or has more channels than requested.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, BufferSize};
+    /// // 100ms of audio
+    /// let player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .buffer_size(BufferSize::Custom(|sample_rate, channels| {
+    ///         sample_rate as usize / 10 * channels as usize
+    ///     }))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(player.buffer_capacity(), 4800 * 2);
+    /// ```
+    Custom(fn(sample_rate: u32, channels: u16) -> usize),
 }
 
 impl BufferSize {
     /// Returns the number of samples in the buffer
     #[inline]
     #[must_use]
-    fn store_for_samples(&self, sample_rate: usize, channels: usize) -> usize {
+    pub(crate) fn store_for_samples(&self, sample_rate: usize, channels: usize) -> usize {
         match self {
             Self::QuarterSecond => sample_rate / 4 * channels,
             Self::HalfSecond => sample_rate / 2 * channels,
             Self::OneSecond => sample_rate * channels,
             Self::Samples(alternative_samples) => *alternative_samples,
+            Self::Custom(size) => size(sample_rate as u32, channels as u16),
         }
     }
 }
@@ -221,13 +309,109 @@ impl BufferSize {
 /// #     vec![0.0; 1]
 /// # }
 /// ```
-pub struct AudioPlayer<T: Sample> {
-    buffer_producer: HeapProd<T>,
+pub struct AudioPlayer<T: FloatSample> {
+    buffer_producer: Box<dyn ring::RingProducer<T>>,
+    buffer_consumer: SharedConsumer<T>,
+    ring: Box<dyn SampleRing<T>>,
     resampler: Option<AudioResampler<T>>,
-    output_stream: cpal::Stream,
+    // the resamplers of the other input rates used with `queue_with_rate`
+    rate_resamplers: Vec<AudioResampler<T>>,
+    // `None` after `close`
+    output_stream: Option<Box<dyn OutputStream>>,
+    // kept to build the stream again after `close`
+    backend: Box<dyn OutputBackend<T>>,
+    source_slot: SourceSlot<T>,
+    // used to build the backend of another device in `set_device`
+    callback_size: CallbackSize,
+    config_preference: ConfigPreference,
+    preferred_sample_rate: Option<u32>,
+    prefer_default_config: bool,
+    exact_sample_rate: bool,
+    native_channels: bool,
+    sample_formats: Vec<SampleFormat>,
+    stream_metadata: StreamMetadata,
+    raw_callback: Option<RawCallback>,
+    recovery: Recovery,
+    watchdog: Option<Watchdog>,
+    sleep: Option<SleepDetector>,
+    drop_fade_timeout: Duration,
+    diagnostics: Vec<Diagnostic>,
+    shared: Arc<PlayerShared>,
+    commands: HeapProd<SourceCommand<T>>,
+    // the commands that didn't fit in `commands`, sent in order once the output processed the others
+    overflow_commands: VecDeque<SourceCommand<T>>,
+    errors: HeapCons<CallbackError>,
+    #[cfg(feature = "wav")]
+    recording: Option<recording::Recording>,
+    input_sample_rate: u32,
+    output_config: OutputConfig,
+    low_latency: bool,
+    drift_compensation: bool,
+    resampling_algorithm: ResamplingAlgorithm,
+    // switches to a cheaper resampler when resampling is too slow
+    resampler_load: Option<LoadMonitor>,
+    input_lowpass: Option<LowPass<T>>,
+    input_limiter: Option<SoftLimiter>,
+    levels: Arc<Levels>,
+    history: Option<Arc<Mutex<History<T>>>>,
+    #[cfg(feature = "spectrum")]
+    spectrum_analyzer: SpectrumAnalyzer,
+    silence: Option<SilenceDetector>,
+    on_silence: Option<SilenceCallback>,
+    // the output stream is paused because of silence
+    suspended: Cell<bool>,
+    // interrupted by the system, and if the player was playing before
+    interrupted: Option<bool>,
+    reset_frames_delivered_on_play: bool,
+    // the backend used when the output stream is lost
+    standby: Option<Standby<T>>,
+    rewinding: bool,
+    // the buffered samples were reversed by `play_reversed`
+    reversed: bool,
+    // the target of the last volume change
+    volume: f32,
+    watermarks: Option<Watermarks>,
+    input_layout: InputLayout,
+    partial_frames: PartialFrames,
+    // the samples of the last incomplete input frame, queued with the next samples
+    partial_frame: Vec<T>,
+    // holds the input samples converted to stereo
+    downmix_scratch: Vec<T>,
+    // holds the filtered samples before resampling
+    input_scratch: Vec<T>,
+    // the samples written by `with_write_chunks` when they can't be written in place
+    write_scratch: Vec<T>,
+    latency_tuner: Option<LatencyTuner>,
+    jitter: Option<JitterTracker>,
+    load_warning: LoadWarning,
+    // the output frames produced per second by the application
+    rate_hint: RateHint,
+    high_latency_threshold: Option<Duration>,
+    // the maximum and target buffered latency of `AudioPlayerBuilder::latency_clamp`
+    latency_clamp: Option<(Duration, Duration)>,
+    // the lowest latency target, raised to the device latency by the high latency compensation
+    latency_floor: Duration,
+    // output frames pushed into the buffer, in the same count as `PlayerShared::frames_consumed`
+    written_frames: u64,
+    timestamps: Timestamps,
+    // counters of `metrics`, in output samples
+    samples_queued: u64,
+    samples_dropped: u64,
+    resampler_time: Duration,
+    // input frames passed to the resampler
+    resampled_frames: u64,
 }
 
-impl<T: Sample + SizedSample> AudioPlayer<T>
+/// The samples pushed since [`AudioPlayer::begin_queue`], the input may be queued in several pieces
+struct QueueProgress {
+    buffered_before: usize,
+    input_frames: usize,
+    pushed: usize,
+    produced: usize,
+    elapsed: Duration,
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> AudioPlayer<T>
 where
     // sadly, cpal uses macro to generate those, and there is no auto way
     // to use the type system to, even though it seems that it makes sense
@@ -269,104 +453,395 @@ where
     ///
     /// This example creates a new `AudioPlayer` with a sample rate of 44100 Hz and a buffer size of half a second.
     pub fn new(sample_rate: u32, buffer_size: BufferSize) -> Result<Self, AudioPlayerError> {
-        let host = cpal::default_host();
-        let output_device = host
-            .default_output_device()
-            .ok_or(AudioPlayerError::NoOutputDevice)?;
+        Self::builder(sample_rate).buffer_size(buffer_size).build()
+    }
 
-        let sample_rate = cpal::SampleRate(sample_rate);
+    /// Creates a new `AudioPlayer` playing samples at the native sample rate of the default device,
+    /// so they are never resampled, see [`AudioPlayerBuilder::auto_sample_rate`].
+    ///
+    /// The chosen rate is returned by [`sample_rate`](Self::sample_rate), the samples should be
+    /// generated at this rate.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let player = AudioPlayer::<f32>::new_auto(BufferSize::HalfSecond).unwrap();
+    /// println!("generate samples at {}Hz", player.sample_rate());
+    /// assert!(!player.is_resampling());
+    /// ```
+    pub fn new_auto(buffer_size: BufferSize) -> Result<Self, AudioPlayerError> {
+        Self::builder(AUTO_SAMPLE_RATE_FALLBACK)
+            .auto_sample_rate(true)
+            .buffer_size(buffer_size)
+            .build()
+    }
 
-        let conf = output_device
-            .supported_output_configs()?
-            .collect::<Vec<_>>();
+    /// Creates a builder for an `AudioPlayer` that will play samples of `sample_rate` Hz.
+    ///
+    /// The builder allows more configuration options than [`AudioPlayer::new`],
+    /// see [`AudioPlayerBuilder`] for the available options.
+    pub fn builder(sample_rate: u32) -> AudioPlayerBuilder<T> {
+        AudioPlayerBuilder::new(sample_rate)
+    }
 
-        let mut found_conf = false;
-
-        for c in &conf {
-            // must have 2 channels and <T> format
-            // (almost all? devices will have at least one configuration with these)
-            if c.channels() == 2
-                && c.sample_format() == T::FORMAT
-                && c.min_sample_rate() <= sample_rate
-                && c.max_sample_rate() >= sample_rate
-            {
-                found_conf = true;
-                break;
-            }
-        }
+    /// Negotiate the output of the default device for samples of `sample_rate` Hz,
+    /// without opening a stream, see [`AudioPlayerBuilder::negotiate`] for the other options.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::AudioPlayer;
+    /// let negotiation = AudioPlayer::<f32>::negotiate(44100).unwrap();
+    /// println!(
+    ///     "{}: {}Hz {}, resampling: {}",
+    ///     negotiation.device.as_deref().unwrap_or("unknown"),
+    ///     negotiation.output.sample_rate,
+    ///     negotiation.output.sample_format,
+    ///     negotiation.resampling,
+    /// );
+    /// ```
+    pub fn negotiate(sample_rate: u32) -> Result<Negotiation, AudioPlayerError> {
+        Self::builder(sample_rate).negotiate()
+    }
 
-        let (output_sample_rate, output_format, resampler) = if found_conf {
-            (sample_rate, T::FORMAT, None)
-        } else {
-            // second time, try to find something that is 2 channels, but format and sample range can
-            // be different, match with highest value
-            let mut max_match = 0;
-            let mut matched_conf = None;
-            for c in &conf {
-                let mut curr_match = 0;
-                if c.channels() == 2 {
-                    curr_match += 1;
-                    if c.sample_format() == T::FORMAT {
-                        curr_match += 3;
-                    }
-                    if c.min_sample_rate() <= sample_rate && c.max_sample_rate() >= sample_rate {
-                        curr_match += 2;
-                    }
-                }
-                if curr_match > max_match {
-                    max_match = curr_match;
-                    matched_conf = Some(c);
+    pub(crate) fn from_builder(
+        mut builder: AudioPlayerBuilder<T>,
+        mut backend: Box<dyn OutputBackend<T>>,
+    ) -> Result<Self, AudioPlayerError> {
+        builder.apply_deterministic();
+        let (retries, backoff) = builder.retries;
+        let requested_rate = builder.requested_sample_rate(backend.as_mut());
+        let output_config = utils::with_retries(retries, backoff, || {
+            backend.negotiate(&OutputRequest {
+                sample_rate: requested_rate,
+                sample_format: T::FORMAT,
+                channels: 2,
+            })
+        })?;
+        let sample_rate = builder.input_sample_rate(&output_config);
+        let resampling_algorithm = match builder.resampling_algorithm {
+            // the ratio can't be adjusted to follow the drift
+            algorithm if builder.drift_compensation && !algorithm.is_adjustable() => {
+                if cfg!(feature = "resample") {
+                    ResamplingAlgorithm::Polynomial
+                } else {
+                    ResamplingAlgorithm::BuiltinCubic
                 }
             }
-
-            let used_conf = match matched_conf {
-                Some(conf) => conf
-                    .try_with_sample_rate(sample_rate)
-                    .unwrap_or_else(|| conf.with_max_sample_rate()),
-                None => output_device.default_output_config()?,
-            };
-
-            if used_conf.channels() != 2 {
-                eprintln!("No supported configuration found for audio device, please open an issue in github `Amjad50/dynwave`\n\
-                      list of supported configurations: {:#?}", conf);
-                return Err(AudioPlayerError::DualChannelNotSupported);
-            }
-
-            (
-                used_conf.sample_rate(),
-                used_conf.sample_format(),
-                Some(AudioResampler::new(
-                    sample_rate.0 as usize,
-                    used_conf.sample_rate().0 as usize,
-                )?),
-            )
+            algorithm => algorithm,
         };
+        negotiation::check_exact_rate(
+            builder.exact_sample_rate,
+            sample_rate,
+            &output_config,
+            resampling_algorithm,
+        )?;
+        let diagnostics = Diagnostic::for_output(
+            backend.device_name(),
+            sample_rate,
+            T::FORMAT,
+            &output_config,
+        );
 
-        let config = cpal::StreamConfig {
-            channels: 2,
-            sample_rate: output_sample_rate,
-            buffer_size: cpal::BufferSize::Default,
+        // if only the format is different, we just convert the samples without resampling
+        let resampler = if builder.drift_compensation || sample_rate != output_config.sample_rate {
+            Some(AudioResampler::with_algorithm(
+                resampling_algorithm,
+                sample_rate,
+                output_config.sample_rate,
+                output_config.channels,
+                builder.low_latency,
+            )?)
+        } else {
+            None
         };
 
-        let ring_buffer_len = buffer_size.store_for_samples(output_sample_rate.0 as usize, 2);
-        let buffer = HeapRb::new(ring_buffer_len);
-        let (buffer_producer, buffer_consumer) = buffer.split();
+        let ring_buffer_len = builder.buffer_size.unwrap_or_default().store_for_samples(
+            output_config.sample_rate as usize,
+            output_config.channels as usize,
+        );
+        let ring = builder.ring.take().unwrap_or_else(|| Box::new(HeapRing));
+        let (buffer_producer, buffer_consumer) = ring.split(ring_buffer_len);
+        let buffer_consumer = Arc::new(Mutex::new(buffer_consumer));
 
-        let output_data_fn = utils::create_output_processor(output_format, buffer_consumer);
+        let (commands, commands_consumer) = HeapRb::new(COMMANDS_CAPACITY).split();
+        let (errors_producer, errors) = HeapRb::new(ERRORS_CAPACITY).split();
 
-        let output_stream = output_device.build_output_stream_raw(
-            &config,
-            output_format,
-            output_data_fn,
-            Self::err_fn,
-            None,
-        )?;
+        let callback_size = builder.device_callback_size();
+        let shared = Arc::new(PlayerShared::new());
+        let levels = Arc::new(Levels::new(output_config.channels as usize));
+        let mut monitors: Vec<Box<dyn AudioEffect<T>>> =
+            vec![Box::new(LevelMeter::new(levels.clone()))];
+
+        let history = builder.visualization.map(|duration| {
+            let frames = (duration.as_secs_f64() * output_config.sample_rate as f64) as usize;
+            let history = Arc::new(Mutex::new(History::new(
+                frames * output_config.channels as usize,
+                T::EQUILIBRIUM,
+            )));
+            monitors.push(Box::new(VisualizationTap::new(history.clone())));
+            history
+        });
+
+        let source_slot = OutputSource::new_slot(
+            buffer_consumer.clone(),
+            shared.clone(),
+            commands_consumer,
+            errors_producer,
+            output_config,
+            OutputStages {
+                effects: builder.effects,
+                limiter: builder.limiter,
+                monitors,
+                dither: builder.dither,
+                underrun_fills: builder.underrun_fills,
+                concealment: builder
+                    .jitter_buffer
+                    .map_or(builder.underrun_concealment, |jitter| {
+                        jitter.concealment.max(builder.underrun_concealment)
+                    }),
+                padding: builder.silence_padding,
+                rewind: builder.rewind.map(|duration| {
+                    RewindHistory::new(
+                        (duration.as_secs_f64() * output_config.sample_rate as f64) as usize,
+                        output_config.channels as usize,
+                        T::EQUILIBRIUM,
+                    )
+                }),
+            },
+        );
+        let output_stream = utils::with_retries(retries, backoff, || {
+            // the source goes back to its slot when a failed attempt drops it
+            let source = OutputSource::take(&source_slot).expect("Source in the slot");
+            backend.build_stream(&output_config, source)
+        })?;
 
-        Ok(Self {
+        let mut player = Self {
             buffer_producer,
-            output_stream,
+            buffer_consumer,
+            ring,
+            output_stream: Some(output_stream),
+            backend,
+            source_slot,
+            callback_size,
+            config_preference: builder.config_preference,
+            preferred_sample_rate: builder.preferred_sample_rate,
+            prefer_default_config: builder.prefer_default_config,
+            exact_sample_rate: builder.exact_sample_rate,
+            native_channels: builder.native_channels,
+            sample_formats: builder.sample_formats.clone(),
+            stream_metadata: builder.stream_metadata.clone(),
+            raw_callback: builder.raw_callback.clone(),
+            recovery: Recovery::new(builder.recovery_policy),
+            watchdog: builder
+                .watchdog
+                .map(|timeout| Watchdog::new(timeout, builder.on_stall)),
+            sleep: builder
+                .sleep_recovery
+                .then(|| SleepDetector::new(SLEEP_THRESHOLD)),
+            drop_fade_timeout: builder.drop_fade_timeout,
+            diagnostics,
+            shared,
+            commands,
+            overflow_commands: VecDeque::new(),
+            errors,
+            #[cfg(feature = "wav")]
+            recording: None,
             resampler,
-        })
+            rate_resamplers: Vec::new(),
+            input_sample_rate: sample_rate,
+            output_config,
+            low_latency: builder.low_latency,
+            drift_compensation: builder.drift_compensation,
+            resampling_algorithm,
+            resampler_load: builder.resampler_load_limit.map(LoadMonitor::new),
+            input_lowpass: builder.input_lowpass,
+            input_limiter: builder.input_limiter,
+            levels,
+            history,
+            #[cfg(feature = "spectrum")]
+            spectrum_analyzer: SpectrumAnalyzer::default(),
+            silence: builder.silence_detection.map(SilenceDetector::new),
+            on_silence: builder.on_silence,
+            suspended: Cell::new(false),
+            interrupted: None,
+            reset_frames_delivered_on_play: builder.reset_frames_delivered_on_play,
+            standby: None,
+            rewinding: false,
+            reversed: false,
+            volume: 1.0,
+            watermarks: builder.watermarks.map(|(low, high)| {
+                let mut watermarks = Watermarks::new(low, high);
+                watermarks.set_callback(builder.on_watermark);
+                watermarks
+            }),
+            input_layout: builder.input_layout,
+            partial_frames: builder.partial_frames,
+            partial_frame: Vec::new(),
+            downmix_scratch: Vec::new(),
+            input_scratch: Vec::new(),
+            write_scratch: Vec::new(),
+            latency_tuner: None,
+            jitter: None,
+            rate_hint: RateHint::new(),
+            load_warning: LoadWarning::new(
+                builder.callback_load_threshold,
+                builder.on_callback_overload,
+            ),
+            high_latency_threshold: builder.high_latency_threshold,
+            latency_clamp: builder.latency_clamp,
+            latency_floor: Duration::ZERO,
+            written_frames: 0,
+            timestamps: Timestamps::new(output_config.sample_rate),
+            samples_queued: 0,
+            samples_dropped: 0,
+            resampler_time: Duration::ZERO,
+            resampled_frames: 0,
+        };
+        if let Some(jitter_buffer) = builder.jitter_buffer {
+            let tracker = JitterTracker::new(jitter_buffer);
+            player.set_latency_target(tracker.target());
+            player.jitter = Some(tracker);
+        } else if let Some(adaptive_latency) = builder.adaptive_latency {
+            let mut tuner = LatencyTuner::new(adaptive_latency);
+            tuner.set_callback(builder.on_latency_change);
+            player.set_latency_target(tuner.target());
+            player.latency_tuner = Some(tuner);
+        }
+        Ok(player)
+    }
+
+    /// The sample rate of the input samples in Hz, i.e. the rate the player was created with,
+    /// or the last rate passed to [`queue_with_rate`](Self::queue_with_rate).
+    pub fn sample_rate(&self) -> u32 {
+        self.input_sample_rate
+    }
+
+    /// The sample rate in Hz used by the output device.
+    ///
+    /// This is the same as [`sample_rate`](Self::sample_rate) unless resampling is active.
+    pub fn output_sample_rate(&self) -> u32 {
+        self.output_config.sample_rate
+    }
+
+    /// The sample format used by the output device.
+    ///
+    /// If it is different from `T`, the samples are converted in the output callback.
+    pub fn output_sample_format(&self) -> SampleFormat {
+        self.output_config.sample_format
+    }
+
+    /// The number of channels of the output device stream.
+    pub fn output_channels(&self) -> u16 {
+        self.output_config.channels
+    }
+
+    /// The output configuration negotiated by the backend, see [`OutputConfig`].
+    pub fn output_config(&self) -> OutputConfig {
+        self.output_config
+    }
+
+    /// Returns `true` if the input samples are resampled before being played,
+    /// i.e. the device didn't support the input sample rate, or the clock drift is compensated
+    /// with [`AudioPlayerBuilder::drift_compensation`], which resamples even when the rates are the same.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{AudioPlayer, backend::NullBackend};
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .drift_compensation(true)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(player.output_sample_rate(), 48000);
+    /// assert!(player.is_resampling());
+    /// ```
+    pub fn is_resampling(&self) -> bool {
+        self.resampler.is_some()
+    }
+
+    /// How fast the device plays compared to its nominal sample rate, as measured from its playback timestamps.
+    ///
+    /// `1.0` until enough time was measured, or if the backend doesn't report the playback time.
+    /// The drift is compensated with [`AudioPlayerBuilder::drift_compensation`].
+    pub fn clock_drift(&self) -> f64 {
+        self.shared.drift()
+    }
+
+    /// The sample rate the output actually consumes the samples at, measured from the times of the callbacks,
+    /// or `None` until a few seconds were measured.
+    ///
+    /// Some systems accept any sample rate, and resample it to the rate of the device without telling,
+    /// or the device runs at another rate than it reports. Then this differs from the [`output_sample_rate`](Self::output_sample_rate)
+    /// by more than the usual [`clock_drift`](Self::clock_drift), which explains a stream drifting even though
+    /// its rate matches the output.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let player = AudioPlayer::<f32>::new(44100, BufferSize::HalfSecond).unwrap();
+    /// // after playing for a few seconds
+    /// if let Some(measured) = player.measured_sample_rate() {
+    ///     let nominal = player.output_sample_rate() as f64;
+    ///     if (measured / nominal - 1.0).abs() > 0.01 {
+    ///         eprintln!("the output plays at {measured:.0}Hz instead of {nominal}Hz");
+    ///     }
+    /// }
+    /// ```
+    pub fn measured_sample_rate(&self) -> Option<f64> {
+        self.shared.measured_rate()
+    }
+
+    /// The input sample rate the application actually produces, measured from the samples it queues
+    /// and the rate the output consumes them at, or `None` until enough was queued, about ten seconds.
+    ///
+    /// An emulator produces the samples of its nominal rate only if it runs at exactly the speed of the emulated
+    /// system, which it rarely does as it follows the display, so the buffer fills or empties slowly.
+    /// Feeding this rate back into its sound timing, or into [`queue_with_rate`](Self::queue_with_rate),
+    /// keeps the buffer level in the long term.
+    ///
+    /// The estimate follows slow changes of the production, it restarts when the application stops queueing
+    /// for a moment, and is forgotten when the input or output rate changes.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::new(44100, BufferSize::HalfSecond).unwrap();
+    /// # let samples = [0.0; 1470];
+    /// // every emulated frame
+    /// player.queue(&samples);
+    /// if let Some(rate) = player.input_rate_hint() {
+    ///     // e.g. "the core effectively produces 44056Hz"
+    ///     println!("the core effectively produces {rate:.0}Hz");
+    /// }
+    /// ```
+    pub fn input_rate_hint(&self) -> Option<f64> {
+        let produced = self.rate_hint.estimate()?;
+        let consumed = self
+            .shared
+            .measured_rate()
+            .unwrap_or_else(|| self.output_config.sample_rate as f64 * self.shared.drift());
+        Some(self.input_sample_rate as f64 * produced / consumed)
+    }
+
+    /// The parameters of the resampling, or `None` if the input samples are played without resampling,
+    /// see [`is_resampling`](Self::is_resampling). With [`AudioPlayerBuilder::drift_compensation`],
+    /// the input and output rates can be the same.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{AudioPlayer, ResamplingAlgorithm, backend::NullBackend};
+    /// let player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let info = player.resampling_info().unwrap();
+    /// assert_eq!((info.input_rate, info.output_rate), (44100, 48000));
+    /// assert_eq!(info.algorithm, ResamplingAlgorithm::Fft);
+    /// ```
+    pub fn resampling_info(&self) -> Option<ResamplingInfo> {
+        self.resampler.as_ref().map(|resampler| resampler.info())
     }
 
     /// Start the player
@@ -379,7 +854,44 @@ where
     ///
     /// Check [`PlayError`] for more information about the possible errors.
     pub fn play(&self) -> Result<(), PlayError> {
-        self.output_stream.play().map_err(|e| e.into())
+        let stream = self.output_stream()?;
+        if self.reset_frames_delivered_on_play {
+            self.shared.reset_frames_delivered();
+        }
+        stream.play()?;
+        self.shared.set_fading_out(false);
+        self.shared.set_holding(false);
+        self.suspended.set(false);
+        self.shared.set_state(PlayerState::Playing);
+        Ok(())
+    }
+
+    /// Start the player, but only start playing the queued samples at `start`, see [`StartTime`].
+    ///
+    /// Silence is played until then, with sample accuracy, so the samples can be queued beforehand,
+    /// for example to start the audio with a video frame flip.
+    /// If the player is already playing, the queued samples are held until `start`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, StartTime};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.5; 960 * 2]);
+    /// player.play_at(StartTime::AfterFrames(480)).unwrap();
+    ///
+    /// // only the frames after the first 480 are played
+    /// clock.advance_frames(960);
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// ```
+    pub fn play_at(&mut self, start: StartTime) -> Result<(), PlayError> {
+        self.send_command(SourceCommand::ScheduleStart(start));
+        self.play()
     }
 
     /// Pause the player
@@ -390,41 +902,2943 @@ where
     ///
     /// Check [`PlayError`] for more information about the possible errors.
     pub fn pause(&self) -> Result<(), PlayError> {
-        self.output_stream.pause().map_err(|e| e.into())
+        self.output_stream()?.pause()?;
+        self.suspended.set(false);
+        self.shared.set_state(PlayerState::Paused);
+        Ok(())
     }
 
-    /// Queues audio samples to be played.
+    /// Pause the player, with `mode` deciding what happens to the queued samples, see [`PauseMode`].
     ///
-    /// The `queue` function takes a slice of audio samples and adds them to the buffer. If a `resampler` is present,
-    /// it resamples the audio data before adding it to the buffer.
+    /// [`pause`](Self::pause) is the same as [`PauseMode::RetainBuffer`].
     ///
-    /// If the buffer is full, the function will drop the audio samples that don't fit in the buffer and won't block.
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, PauseMode, PlayerState};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 4800 * 2]);
+    /// player.play().unwrap();
     ///
-    /// If the player is playing, the audio samples will be played immediately, and if the buffer is emptied, popping sound might be heard.
+    /// // the audio fades out in the next 10ms, the rest is kept
+    /// player.pause_with(PauseMode::FadeAndRetain).unwrap();
+    /// assert_eq!(player.state(), PlayerState::Paused);
+    /// clock.advance_frames(960);
+    /// assert_eq!(player.buffered_samples(), (4800 - 480) * 2);
     ///
-    /// # Parameters
-    /// * `data`: A slice of audio samples to be played.
+    /// // the stream plays silence without stopping, the samples are kept
+    /// player.play().unwrap();
+    /// player.pause_with(PauseMode::KeepAlive).unwrap();
+    /// clock.advance_frames(960);
+    /// assert_eq!(player.buffered_samples(), (4800 - 480) * 2);
+    ///
+    /// // switching content, nothing is played when resumed
+    /// player.pause_with(PauseMode::FlushBuffer).unwrap();
+    /// assert_eq!(player.buffered_samples(), 0);
+    /// ```
+    pub fn pause_with(&mut self, mode: PauseMode) -> Result<(), PlayError> {
+        match mode {
+            PauseMode::RetainBuffer => self.pause(),
+            PauseMode::FlushBuffer => {
+                self.pause()?;
+                self.flush_buffer(0);
+                Ok(())
+            }
+            PauseMode::FadeAndRetain => {
+                // the stream keeps running to play the fade, then holds the buffer
+                self.output_stream()?.play()?;
+                self.shared.set_fading_out(true);
+                self.suspended.set(false);
+                self.shared.set_state(PlayerState::Paused);
+                Ok(())
+            }
+            PauseMode::KeepAlive => {
+                // the stream keeps running, so the device is not stopped and started again
+                self.output_stream()?.play()?;
+                self.shared.set_holding(true);
+                self.suspended.set(false);
+                self.shared.set_state(PlayerState::Paused);
+                Ok(())
+            }
+        }
+    }
+
+    /// Close the output stream, to release the audio device, while keeping the player and its queued samples.
+    ///
+    /// Pausing doesn't release the device on some hosts, which is needed to let other applications
+    /// use it in exclusive mode for example. Use [`reopen`](Self::reopen) to play again.
+    ///
+    /// The player is paused, and [`play`](Self::play) and [`pause`](Self::pause) return
+    /// [`PlayError::Closed`] until it's reopened. Samples can still be queued.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, error::PlayError, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    ///
+    /// player.close();
+    /// assert!(!player.is_open());
+    /// assert!(matches!(player.play(), Err(PlayError::Closed)));
+    ///
+    /// player.reopen().unwrap();
+    /// player.play().unwrap();
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// ```
+    pub fn close(&mut self) {
+        self.recovery.cancel();
+        self.output_stream = None;
+        self.shared.set_fading_out(false);
+        self.shared.set_holding(false);
+        self.suspended.set(false);
+        self.shared.set_state(PlayerState::Paused);
+    }
+
+    /// Build the output stream again after [`close`](Self::close), with the same backend and configuration.
+    ///
+    /// The player is paused after reopening, and plays the samples kept in the buffer when played.
+    /// Does nothing if the stream is open.
+    ///
+    /// Returns [`AudioPlayerError::DeviceNotAvailable`] if the old stream didn't release
+    /// its [`OutputSource`] yet, and the errors of the backend when building the stream.
+    pub fn reopen(&mut self) -> Result<(), AudioPlayerError> {
+        if self.output_stream.is_some() {
+            return Ok(());
+        }
+        let source =
+            OutputSource::take(&self.source_slot).ok_or(AudioPlayerError::DeviceNotAvailable)?;
+        self.output_stream = Some(self.backend.build_stream(&self.output_config, source)?);
+        Ok(())
+    }
+
+    /// Tell the player that the system interrupted its audio, for example by a phone call or a voice assistant
+    /// on mobile platforms, the stream is [closed](Self::close) and the queued samples are kept.
+    ///
+    /// This should be called from the interruption handler of the platform, e.g. the begin of an
+    /// `AVAudioSession` interruption on iOS, or the loss of the audio focus on Android.
+    /// The stream of the player usually doesn't survive the interruption, so it's built again
+    /// with [`end_interruption`](Self::end_interruption).
+    ///
+    /// The player doesn't talk to the audio session of the platform: the application configures it,
+    /// for example the `AVAudioSession` category (`playback`) and the `mixWithOthers` option on iOS,
+    /// or requests the audio focus on Android, observes the interruptions to call these methods,
+    /// and activates the session again before [`end_interruption`](Self::end_interruption).
+    ///
+    /// Does nothing if the player is already interrupted.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// player.begin_interruption();
+    /// assert!(player.is_interrupted());
+    /// assert!(!player.is_open());
+    ///
+    /// // the stream is active again, and plays as before the interruption
+    /// player.end_interruption().unwrap();
+    /// assert!(player.is_playing());
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// ```
+    pub fn begin_interruption(&mut self) {
+        if self.interrupted.is_some() {
+            return;
+        }
+        self.interrupted = Some(self.is_open() && self.is_playing());
+        self.close();
+    }
+
+    /// Build the stream again after [`begin_interruption`](Self::begin_interruption), and play
+    /// if the player was playing when it was interrupted.
+    ///
+    /// This should be called when the platform ends the interruption, or gives back the audio focus,
+    /// after the application activated its audio session again, otherwise building the stream can fail.
+    /// Does nothing if the player is not interrupted, on errors the player stays interrupted,
+    /// so this can be called again.
+    pub fn end_interruption(&mut self) -> Result<(), AudioPlayerError> {
+        let Some(was_playing) = self.interrupted else {
+            return Ok(());
+        };
+        self.reopen()?;
+        self.interrupted = None;
+        if was_playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` between [`begin_interruption`](Self::begin_interruption)
+    /// and [`end_interruption`](Self::end_interruption).
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.is_some()
+    }
+
+    /// Build the stream and reset the resampler after the machine woke up from sleep,
+    /// keeping the queued samples, and play again if the player was playing.
+    ///
+    /// Some devices stay silent after a sleep without reporting an error, so the stream can't be trusted anymore.
+    /// This is done automatically on the next [`queue`](Self::queue) after a sleep, see
+    /// [`AudioPlayerBuilder::sleep_recovery`], it can also be called from the resume notification of the platform.
+    /// Does nothing to the stream if the player is [closed](Self::close).
+    ///
+    /// If building the stream fails, the player is closed, and recovers with the
+    /// [`recovery_policy`](AudioPlayerBuilder::recovery_policy).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// player.recover_from_sleep().unwrap();
+    /// assert!(player.is_open());
+    /// assert!(player.is_playing());
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// ```
+    pub fn recover_from_sleep(&mut self) -> Result<(), AudioPlayerError> {
+        // the filter state is from before the sleep, the input samples waiting for a chunk are kept
+        if let Some(resampler) = &mut self.resampler {
+            let pending = resampler.pending().to_vec();
+            resampler.restore(&pending);
+        }
+        if !self.is_open() {
+            return Ok(());
+        }
+        let playing = self.is_playing();
+        self.close();
+        if let Err(err) = self.reopen() {
+            self.recovery.start(playing);
+            return Err(err);
+        }
+        self.add_diagnostics([Diagnostic::SleepRecovered]);
+        if playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// Move the output to `device`, without recreating the player, for the device settings of an application.
+    ///
+    /// The stream is rebuilt on the new device with the same options the player was built with,
+    /// the queued samples are kept, and the player keeps playing if it was.
+    /// If the new device uses another sample rate, the queued samples are resampled to it,
+    /// and the buffer keeps the same duration.
+    ///
+    /// If building the new stream fails, the player stays [closed](Self::close),
+    /// [`reopen`](Self::reopen) tries the new device again.
     ///
     /// # Example
     /// ```rust,no_run
     /// # use dynwave::{AudioPlayer, BufferSize};
-    /// let sample_rate = 44100;
-    /// let buffer_size = BufferSize::HalfSecond;
-    /// let mut player = AudioPlayer::new(sample_rate, buffer_size).unwrap();
-    /// let samples = vec![0.5, 0.7, 0.9, 1.0, 0.9, 0.7, 0.5, 0.3, 0.1];
-    /// player.queue(&samples);
+    /// let mut player = AudioPlayer::<f32>::new(44100, BufferSize::HalfSecond).unwrap();
+    /// player.play().unwrap();
+    ///
+    /// // the user picked another device in the settings
+    /// let device = dynwave::devices().unwrap().pop().unwrap();
+    /// player.set_device(device.into_device()).unwrap();
     /// ```
-    /// This example creates a new `AudioPlayer` with a sample rate of 44100 Hz and a buffer size of half a second, queues some audio samples, and then starts playing the audio.
-    pub fn queue(&mut self, data: &[T]) {
-        if let Some(resampler) = &mut self.resampler {
-            resampler.resample_into_producer(data, &mut self.buffer_producer);
-        } else {
-            // no resampling
-            self.buffer_producer.push_slice(data);
+    pub fn set_device(&mut self, device: cpal::Device) -> Result<(), AudioPlayerError> {
+        self.switch_backend(DeviceBackend::new().device(device))
+    }
+
+    /// Move the output to the device of `backend`, with the options the player was built with
+    fn switch_backend(&mut self, backend: DeviceBackend) -> Result<(), AudioPlayerError> {
+        let mut backend = self.configure_backend(backend);
+        let config = self.negotiate_backend(&mut backend)?;
+        let switch = self.prepare_output_config(config)?;
+        let playing = self.is_playing();
+        self.use_backend(Box::new(backend), switch, playing)
+    }
+
+    /// Apply the options the player was built with to `backend`
+    fn configure_backend(&self, backend: DeviceBackend) -> DeviceBackend {
+        let mut backend = backend
+            .callback_size(self.callback_size)
+            .config_preference(self.config_preference)
+            .prefer_default_config(self.prefer_default_config)
+            .native_channels(self.native_channels)
+            .sample_formats(&self.sample_formats)
+            .metadata(self.stream_metadata.clone())
+            .shared_raw_callback(self.raw_callback.clone());
+        if let Some(sample_rate) = self.preferred_sample_rate {
+            backend = backend.preferred_sample_rate(sample_rate);
+        }
+        backend
+    }
+
+    /// Negotiate the output of `backend` for the queued samples, and check the player can play on it
+    fn negotiate_backend(
+        &self,
+        backend: &mut dyn OutputBackend<T>,
+    ) -> Result<OutputConfig, AudioPlayerError> {
+        let config = backend.negotiate(&OutputRequest {
+            sample_rate: self.input_sample_rate,
+            sample_format: T::FORMAT,
+            channels: self.output_config.channels,
+        })?;
+        negotiation::check_exact_rate(
+            self.exact_sample_rate,
+            self.input_sample_rate,
+            &config,
+            self.resampling_algorithm,
+        )?;
+        Ok(config)
+    }
+
+    /// Move the output to the negotiated `backend`, with the config of `switch`,
+    /// and play if `playing`
+    fn use_backend(
+        &mut self,
+        backend: Box<dyn OutputBackend<T>>,
+        switch: OutputSwitch<T>,
+        playing: bool,
+    ) -> Result<(), AudioPlayerError> {
+        let config = switch.config;
+        self.close();
+        self.apply_output_config(switch)?;
+        self.add_diagnostics(Diagnostic::for_output(
+            backend.device_name(),
+            self.input_sample_rate,
+            T::FORMAT,
+            &config,
+        ));
+        self.backend = backend;
+        self.reopen()?;
+        if playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// Same as [`set_device`](Self::set_device), with the output device named `name`,
+    /// see [`devices`] for the names.
+    ///
+    /// Returns [`AudioPlayerError::NoOutputDevice`] if there is no device with this name.
+    pub fn set_device_by_name(&mut self, name: &str) -> Result<(), AudioPlayerError> {
+        let device = devices()?
+            .into_iter()
+            .find(|device| device.name() == name)
+            .ok_or(AudioPlayerError::NoOutputDevice)?;
+        self.set_device(device.into_device())
+    }
+
+    /// Keep `device` ready to take over the output when the stream of the current device is lost,
+    /// for deployments where the audio must never stop silently.
+    ///
+    /// The device is negotiated now, with the options the player was built with, and the resamplers
+    /// to its sample rate are created, so the switch only builds the stream, on the next
+    /// [`queue`](Self::queue) after the loss, before the [`recovery_policy`](AudioPlayerBuilder::recovery_policy).
+    /// The queued samples are kept, and the player keeps playing if it was.
+    ///
+    /// The standby device is used once, set it again to keep a standby after a switch.
+    /// If building its stream fails, the recovery policy takes over.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::new(44100, BufferSize::HalfSecond).unwrap();
+    ///
+    /// // the speakers of the cabinet take over if the headphones of the default device fail
+    /// let speakers = dynwave::devices().unwrap().pop().unwrap();
+    /// player.set_standby_device(speakers.into_device()).unwrap();
+    /// player.play().unwrap();
+    /// ```
+    pub fn set_standby_device(&mut self, device: cpal::Device) -> Result<(), AudioPlayerError> {
+        self.set_standby_backend(self.configure_backend(DeviceBackend::new().device(device)))
+    }
+
+    /// Same as [`set_standby_device`](Self::set_standby_device), with any backend,
+    /// used as it is like [`AudioPlayerBuilder::backend`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let primary = NullBackend::new();
+    /// let primary_clock = primary.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(primary)
+    ///     .build()
+    ///     .unwrap();
+    /// let standby = NullBackend::new().sample_rate(96000);
+    /// let standby_clock = standby.clock();
+    /// player.set_standby_backend(standby).unwrap();
+    /// player.play().unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    ///
+    /// primary_clock.disconnect();
+    /// // the standby takes over when queueing, with the buffered samples resampled to its rate
+    /// player.queue(&[0.5; 480 * 2]);
+    /// assert!(!player.has_standby());
+    /// assert!(player.is_playing());
+    /// assert_eq!(player.output_sample_rate(), 96000);
+    ///
+    /// standby_clock.advance_frames(1920);
+    /// assert_eq!(player.buffered_samples(), 0);
+    /// ```
+    pub fn set_standby_backend(
+        &mut self,
+        backend: impl OutputBackend<T> + 'static,
+    ) -> Result<(), AudioPlayerError> {
+        let mut backend: Box<dyn OutputBackend<T>> = Box::new(backend);
+        let config = self.negotiate_backend(backend.as_mut())?;
+        let switch = self.prepare_output_config(config)?;
+        self.standby = Some(Standby { backend, switch });
+        Ok(())
+    }
+
+    /// Drop the standby backend set with [`set_standby_device`](Self::set_standby_device).
+    pub fn clear_standby(&mut self) {
+        self.standby = None;
+    }
+
+    /// Returns `true` if a standby backend is ready to take over the output,
+    /// see [`set_standby_device`](Self::set_standby_device).
+    pub fn has_standby(&self) -> bool {
+        self.standby.is_some()
+    }
+
+    /// Move the lost output to the standby backend, returns `true` if it plays there
+    fn switch_to_standby(&mut self, playing: bool) -> bool {
+        let Some(Standby { backend, switch }) = self.standby.take() else {
+            return false;
+        };
+        if self.use_backend(backend, switch, playing).is_err() {
+            return false;
+        }
+        self.add_diagnostics([Diagnostic::StandbyActivated]);
+        true
+    }
+
+    /// Handle the loss of the output stream reported by the backend, see [`RecoveryPolicy`]
+    fn recover_stream(&mut self) {
+        if self
+            .sleep
+            .as_mut()
+            .is_some_and(|sleep| sleep.check(Instant::now(), SystemTime::now()))
+        {
+            // on errors, the recovery policy takes over below
+            let _ = self.recover_from_sleep();
+        }
+        let active = self.is_open() && self.is_playing() && !self.is_suspended();
+        let callbacks = self.shared.stats().stats().callbacks;
+        if let Some(watchdog) = &mut self.watchdog {
+            if watchdog.check(callbacks, active, Instant::now()) {
+                // handled like a stream stopped by the backend
+                self.shared.set_stream_lost();
+            }
+        }
+        if self.shared.take_stream_lost() {
+            let playing = self.is_playing();
+            self.close();
+            if self.switch_to_standby(playing) {
+                return;
+            }
+            self.recovery.start(playing);
+        }
+        if !self.recovery.is_pending() {
+            return;
         }
+        let result = match self.recovery.next_action(Instant::now()) {
+            Some(RecoveryAction::Reopen) => self.reopen(),
+            Some(RecoveryAction::DefaultDevice) => self.switch_backend(DeviceBackend::new()),
+            None => return,
+        };
+        // on errors, the next attempt is done on a later queue, if any
+        if result.is_ok() {
+            self.add_diagnostics([Diagnostic::StreamRecovered]);
+            if self.recovery.finish() {
+                let _ = self.play();
+            }
+        }
+    }
+
+    /// Creates the resamplers to move the output from the current config to `config`
+    fn prepare_output_config(
+        &self,
+        config: OutputConfig,
+    ) -> Result<OutputSwitch<T>, AudioPlayerError> {
+        let from = self.output_config;
+        let resamplers = if config.sample_rate != from.sample_rate {
+            let converter = AudioResampler::for_rates(
+                from.sample_rate,
+                config.sample_rate,
+                from.channels,
+                self.low_latency,
+            )?;
+            Some(RateChange {
+                converter,
+                resampler: self.new_resampler(self.input_sample_rate, config)?,
+            })
+        } else {
+            None
+        };
+        Ok(OutputSwitch {
+            from,
+            config,
+            resamplers,
+        })
     }
 
-    fn err_fn(err: cpal::StreamError) {
-        eprintln!("an error occurred on audio stream: {}", err);
+    /// Use the config of `switch` for the output, the stream must be closed
+    fn apply_output_config(&mut self, switch: OutputSwitch<T>) -> Result<(), AudioPlayerError> {
+        // the output changed since the resamplers were created
+        let switch = if switch.from == self.output_config {
+            switch
+        } else {
+            self.prepare_output_config(switch.config)?
+        };
+        let OutputSwitch {
+            from: old_config,
+            config,
+            resamplers,
+        } = switch;
+        if let Some(RateChange {
+            converter,
+            mut resampler,
+        }) = resamplers
+        {
+            // the input samples waiting for a chunk are still valid for the new resampler
+            if let (Some(old), Some(new)) = (&self.resampler, &mut resampler) {
+                new.restore(old.pending());
+            }
+
+            // keep the same duration in the buffer
+            let capacity = (self.buffer_capacity() as u64 * config.sample_rate as u64
+                / old_config.sample_rate as u64) as usize;
+            let (mut producer, new_consumer) = self.ring.split(capacity.max(1));
+            let Ok(mut consumer) = self.buffer_consumer.lock() else {
+                return Err(AudioPlayerError::DeviceNotAvailable);
+            };
+            let (first, second) = consumer.as_slices();
+            let buffered = [first, second].concat();
+            let buffered = match converter {
+                Some(mut converter) => converter.resample_all(&buffered),
+                None => buffered,
+            };
+            producer.push_slice(&buffered);
+            *consumer = new_consumer;
+            drop(consumer);
+
+            self.buffer_producer = producer;
+            self.resampler = resampler;
+            self.rate_resamplers.clear();
+            self.timestamps = Timestamps::new(config.sample_rate);
+            let threshold = self.shared.resume_threshold() as u64 * config.sample_rate as u64
+                / old_config.sample_rate as u64;
+            self.shared.set_resume_threshold(threshold as usize);
+        }
+
+        if config.sample_rate != self.output_config.sample_rate {
+            self.rate_hint.reset();
+        }
+        self.output_config = config;
+        self.written_frames = self.written_frames_with(self.buffer_producer.occupied_len());
+        if let Some(inner) = self
+            .source_slot
+            .lock()
+            .ok()
+            .as_mut()
+            .and_then(|slot| slot.as_mut())
+        {
+            inner.set_config(config);
+        }
+        Ok(())
+    }
+
+    /// Creates a resampler from `input_rate` to the output `config`, like the current one,
+    /// `None` if the rates are the same and the drift is not compensated
+    fn new_resampler(
+        &self,
+        input_rate: u32,
+        config: OutputConfig,
+    ) -> Result<Option<AudioResampler<T>>, AudioPlayerError> {
+        if input_rate == config.sample_rate && !self.drift_compensation {
+            return Ok(None);
+        }
+        // keeps the algorithm the resampler was degraded to
+        let algorithm = self
+            .resampler
+            .as_ref()
+            .map_or(self.resampling_algorithm, |resampler| {
+                resampler.info().algorithm
+            });
+        AudioResampler::with_algorithm(
+            algorithm,
+            input_rate,
+            config.sample_rate,
+            config.channels,
+            self.low_latency,
+        )
+        .map(Some)
+    }
+
+    /// Push the queued samples kept in the resampler into the buffer, padded with silence,
+    /// so the end of the audio is played when nothing is queued after it, returns the number of samples dropped.
+    ///
+    /// The resampler works on chunks of samples, and keeps the last samples queued until a chunk is full,
+    /// which never happens when the application stops queueing, at the end of a game or before pausing.
+    /// Does nothing if the samples are not resampled.
+    ///
+    /// The resampler starts again from silence after the flush, so it should only be called
+    /// at the end of the audio, flushing between continuous samples adds a gap.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    /// // the last 10ms of the audio
+    /// player.queue(&[0.5; 441 * 2]);
+    /// assert_eq!(player.buffered_samples(), 0);
+    ///
+    /// player.flush_resampler();
+    /// assert!(player.buffered_samples() >= 480 * 2);
+    /// ```
+    pub fn flush_resampler(&mut self) -> usize {
+        self.drain_resampler()
+    }
+
+    /// Replace the resampler with `resampler`, after passing the samples kept in the current one
+    /// to the buffer, returns the previous resampler and the number of samples dropped
+    fn swap_resampler(
+        &mut self,
+        resampler: Option<AudioResampler<T>>,
+    ) -> (Option<AudioResampler<T>>, usize) {
+        let dropped = self.drain_resampler();
+        (std::mem::replace(&mut self.resampler, resampler), dropped)
+    }
+
+    /// Push the samples kept in the resampler into the buffer, it starts again from silence,
+    /// returns the number of samples dropped
+    fn drain_resampler(&mut self) -> usize {
+        let Some(resampler) = &mut self.resampler else {
+            return 0;
+        };
+        let producer = &mut self.buffer_producer;
+        let (mut pushed, mut produced) = (0, 0);
+        resampler.drain(|resampled| {
+            produced += resampled.len();
+            pushed += producer.push_slice(resampled);
+        });
+        let dropped = produced - pushed;
+        self.samples_queued += produced as u64;
+        self.samples_dropped += dropped as u64;
+        self.written_frames += (pushed / self.output_config.channels.max(1) as usize) as u64;
+        dropped
+    }
+
+    /// Switch to a cheaper resampling algorithm, when the resampling takes longer than the load limit
+    fn degrade_resampler(&mut self) {
+        let Some(info) = self.resampling_info() else {
+            return;
+        };
+        let Some(algorithm) = info.algorithm.cheaper() else {
+            return;
+        };
+        let Ok(resampler) = AudioResampler::with_algorithm(
+            algorithm,
+            info.input_rate,
+            info.output_rate,
+            self.output_config.channels,
+            self.low_latency,
+        ) else {
+            return;
+        };
+        self.swap_resampler(Some(resampler));
+        // the cached resamplers use the previous algorithm
+        self.rate_resamplers.clear();
+        self.add_diagnostics([Diagnostic::ResamplingDegraded { to: algorithm }]);
+    }
+
+    /// Returns `false` if the output stream was closed with [`close`](Self::close).
+    pub fn is_open(&self) -> bool {
+        self.output_stream.is_some()
+    }
+
+    fn output_stream(&self) -> Result<&dyn OutputStream, PlayError> {
+        self.output_stream.as_deref().ok_or(PlayError::Closed)
+    }
+
+    /// Returns the current playback state of the player.
+    ///
+    /// See [`PlayerState`] for more information about the states.
+    pub fn state(&self) -> PlayerState {
+        self.shared.state()
+    }
+
+    /// Returns `true` if the player is playing, this includes the [`PlayerState::Underrun`] state,
+    /// where the player is playing, but is waiting for more samples.
+    pub fn is_playing(&self) -> bool {
+        self.state() != PlayerState::Paused
+    }
+
+    /// The level of each channel of the output, see [`ChannelLevel`].
+    ///
+    /// The levels are measured on the samples sent to the output, after resampling, mixing and effects.
+    /// The peaks are reset on each call, so calling this at a fixed interval (e.g. every frame)
+    /// gives the peaks in that interval.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000).backend(backend).build().unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.5, -0.25].repeat(480));
+    /// clock.advance_frames(480);
+    ///
+    /// let levels = player.levels();
+    /// assert_eq!(levels[0].peak, 0.5);
+    /// assert_eq!(levels[1].rms, 0.25);
+    /// ```
+    pub fn levels(&self) -> Vec<ChannelLevel> {
+        self.levels.read()
+    }
+
+    /// Copy the last played samples into `output`, from the oldest to the newest.
+    ///
+    /// The samples are interleaved, with the output channels and sample rate, exactly as sent to the output,
+    /// useful to draw an oscilloscope or a waveform view.
+    ///
+    /// The number of samples kept is set with [`AudioPlayerBuilder::visualization`],
+    /// if it's not set, `output` is left empty.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(10))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.5; 2000]);
+    /// clock.advance_frames(1000);
+    ///
+    /// let mut samples = Vec::new();
+    /// player.recent_samples(&mut samples);
+    /// // 10ms of stereo samples at 48000Hz
+    /// assert_eq!(samples.len(), 960);
+    /// ```
+    pub fn recent_samples(&self, output: &mut Vec<T>) {
+        output.clear();
+        if let Some(history) = &self.history {
+            if let Ok(history) = history.lock() {
+                history.read_into(output);
+            }
+        }
+    }
+
+    /// Compute the magnitude spectrum of the last played samples into `output`, for spectrum analyzers.
+    ///
+    /// The channels are mixed into one, and the spectrum is computed on the largest power of two
+    /// number of frames kept by [`AudioPlayerBuilder::visualization`], so if it's not set, `output` is left empty.
+    ///
+    /// Bin `i` is the magnitude at `i * output_sample_rate / (2 * output.len())` Hz,
+    /// a full scale sine wave has a magnitude of around `1.0`.
+    ///
+    /// Requires the `spectrum` feature, enabled by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(50))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// // 1500Hz sine wave
+    /// let sine = (0..4800)
+    ///     .flat_map(|i| [(i as f32 * 1500.0 * std::f32::consts::TAU / 48000.0).sin(); 2])
+    ///     .collect::<Vec<_>>();
+    /// player.queue(&sine);
+    /// clock.advance_frames(4800);
+    ///
+    /// let mut spectrum = Vec::new();
+    /// player.spectrum(&mut spectrum);
+    /// let peak = (0..spectrum.len()).max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b])).unwrap();
+    /// let frequency = peak as f32 * 48000.0 / (2.0 * spectrum.len() as f32);
+    /// assert!((frequency - 1500.0).abs() < 48000.0 / (2.0 * spectrum.len() as f32));
+    /// ```
+    #[cfg(feature = "spectrum")]
+    pub fn spectrum(&mut self, output: &mut Vec<f32>) {
+        output.clear();
+        let mut samples = Vec::new();
+        self.recent_samples(&mut samples);
+
+        let channels = self.output_config.channels.max(1) as usize;
+        let mono = samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().map(|&s| f32::from_sample_(s)).sum::<f32>() / channels as f32)
+            .collect::<Vec<_>>();
+        self.spectrum_analyzer.compute(&mono, output);
+    }
+
+    /// Returns `true` if the player is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state() == PlayerState::Paused
+    }
+
+    /// Queues audio samples to be played.
+    ///
+    /// The `queue` function takes a slice of audio samples and adds them to the buffer. If a `resampler` is present,
+    /// it resamples the audio data before adding it to the buffer.
+    ///
+    /// If the buffer is full, the function will drop the audio samples that don't fit in the buffer and won't block.
+    /// Returns the number of samples dropped by this call, counted like the buffer: after the conversion to the
+    /// output channels and resampling. The total is in [`Metrics::samples_dropped`].
+    ///
+    /// If the player is playing, the audio samples will be played immediately, and if the buffer is emptied, popping sound might be heard.
+    ///
+    /// The samples at the end of `data` that don't make a whole frame of the input layout are kept
+    /// for the next call by default, see [`AudioPlayerBuilder::partial_frames`].
+    ///
+    /// # Parameters
+    /// * `data`: A slice of audio samples to be played.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let sample_rate = 44100;
+    /// let buffer_size = BufferSize::HalfSecond;
+    /// let mut player = AudioPlayer::new(sample_rate, buffer_size).unwrap();
+    /// let samples = vec![0.5, 0.7, 0.9, 1.0, 0.9, 0.7, 0.5, 0.3, 0.1];
+    /// player.queue(&samples);
+    /// ```
+    /// This example creates a new `AudioPlayer` with a sample rate of 44100 Hz and a buffer size of half a second, queues some audio samples, and then starts playing the audio.
+    ///
+    /// The dropped samples can be checked to notice when the application produces too fast:
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// let capacity = player.buffer_capacity();
+    /// assert_eq!(player.queue(&vec![0.0; capacity - 100]), 0);
+    /// assert_eq!(player.queue(&[0.0; 300]), 200);
+    /// assert_eq!(player.metrics().samples_dropped, 200);
+    /// ```
+    pub fn queue(&mut self, data: &[T]) -> usize {
+        let mut progress = self.begin_queue();
+        self.push_input(&mut progress, data);
+        self.end_queue(progress)
+    }
+
+    /// Same as [`queue`](Self::queue), for samples kept in several buffers, like chunks rendered per scanline,
+    /// queued one after the other without concatenating them first.
+    ///
+    /// The frames of the input layout may be split across the slices, the incomplete frame at the end
+    /// of the last one is handled like [`queue`](Self::queue) does, see [`PartialFrames`].
+    /// Returns the number of samples dropped because the buffer was full.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let first = [0.25; 4];
+    /// let second = [0.5; 2];
+    /// assert_eq!(player.queue_vectored(&[&first, &second]), 0);
+    /// assert_eq!(player.snapshot().buffered, [0.25, 0.25, 0.25, 0.25, 0.5, 0.5]);
+    ///
+    /// // a frame split across the slices
+    /// player.queue_vectored(&[&[0.1], &[0.2, 0.3], &[0.4]]);
+    /// assert_eq!(player.snapshot().buffered[6..], [0.1, 0.2, 0.3, 0.4]);
+    /// ```
+    pub fn queue_vectored(&mut self, slices: &[&[T]]) -> usize {
+        let mut progress = self.begin_queue();
+        for data in slices {
+            self.push_input(&mut progress, data);
+        }
+        self.end_queue(progress)
+    }
+
+    /// Same as [`queue`](Self::queue), for samples with the input rate `sample_rate`,
+    /// for applications that produce audio at different rates, like frontends running multiple emulator cores.
+    ///
+    /// The rate becomes the input rate of the player, used by the next [`queue`](Self::queue) calls
+    /// and returned by [`sample_rate`](Self::sample_rate). When it changes, the samples of the previous rate
+    /// waiting in the resampler are queued first, and its resampler is kept to be used again if the rate comes back.
+    ///
+    /// Returns [`AudioPlayerError::SampleRateNotSupported`] if the player was built with
+    /// [`exact_sample_rate`](AudioPlayerBuilder::exact_sample_rate) and the output doesn't play at `sample_rate`,
+    /// and the errors of creating the resampler.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue_with_rate(&[0.5; 480 * 2], 48000).unwrap();
+    /// assert!(!player.is_resampling());
+    ///
+    /// player.queue_with_rate(&[0.5; 441 * 2], 44100).unwrap();
+    /// assert_eq!(player.sample_rate(), 44100);
+    /// assert!(player.is_resampling());
+    /// ```
+    pub fn queue_with_rate(
+        &mut self,
+        data: &[T],
+        sample_rate: u32,
+    ) -> Result<usize, AudioPlayerError> {
+        let mut dropped = 0;
+        if sample_rate != self.input_sample_rate {
+            dropped += self.set_input_rate(sample_rate)?;
+        }
+        Ok(dropped + self.queue(data))
+    }
+
+    /// Switch the input rate to `sample_rate`, the samples of the previous rate waiting in the resampler
+    /// are pushed into the buffer, returns the number of them dropped
+    fn set_input_rate(&mut self, sample_rate: u32) -> Result<usize, AudioPlayerError> {
+        negotiation::check_exact_rate(
+            self.exact_sample_rate,
+            sample_rate,
+            &self.output_config,
+            self.resampling_algorithm,
+        )?;
+        let cached = self
+            .rate_resamplers
+            .iter()
+            .position(|resampler| resampler.info().input_rate == sample_rate);
+        let resampler = match cached {
+            Some(index) => Some(self.rate_resamplers.swap_remove(index)),
+            None => self.new_resampler(sample_rate, self.output_config)?,
+        };
+
+        let (previous, dropped) = self.swap_resampler(resampler);
+        self.rate_resamplers.extend(previous);
+        if sample_rate != self.input_sample_rate {
+            self.rate_hint.reset();
+        }
+        self.input_sample_rate = sample_rate;
+        Ok(dropped)
+    }
+
+    /// Same as [`queue`](Self::queue), but only queues the samples that fit in the buffer without dropping any,
+    /// for applications that handle a full buffer themselves, for example by waiting or producing less.
+    ///
+    /// Returns [`QueueFullError`] with the number of samples queued from the start of `data`, if they don't all fit.
+    /// They make whole frames of the input layout, with the samples of an incomplete frame carried from
+    /// the previous call, see [`PartialFrames::Carry`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, error::QueueFullError, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// let capacity = player.buffer_capacity();
+    /// assert_eq!(player.try_queue(&vec![0.0; capacity - 100]), Ok(()));
+    /// assert_eq!(player.try_queue(&[0.0; 300]), Err(QueueFullError { accepted: 100 }));
+    /// assert_eq!(player.metrics().samples_dropped, 0);
+    /// ```
+    ///
+    /// The sample of an incomplete frame carried from the previous call takes space too:
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, error::QueueFullError, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// let capacity = player.buffer_capacity();
+    /// assert_eq!(player.try_queue(&vec![0.0; capacity - 101]), Ok(()));
+    /// assert_eq!(player.try_queue(&[0.0; 300]), Err(QueueFullError { accepted: 101 }));
+    /// assert_eq!(player.buffered_samples(), capacity);
+    /// assert_eq!(player.metrics().samples_dropped, 0);
+    /// ```
+    pub fn try_queue(&mut self, data: &[T]) -> Result<(), QueueFullError> {
+        let channels = self.input_layout.channels();
+        // the carried samples are queued first, with the start of `data`
+        let fitting =
+            (self.input_frames_fitting() * channels).saturating_sub(self.partial_frame.len());
+        if data.len() <= fitting {
+            self.queue(data);
+            return Ok(());
+        }
+        self.queue(&data[..fitting]);
+        Err(QueueFullError { accepted: fitting })
+    }
+
+    /// The number of input frames that can be queued without dropping samples
+    fn input_frames_fitting(&self) -> usize {
+        let channels = self.output_config.channels.max(1) as usize;
+        let vacant = self.buffer_producer.vacant_len() / channels;
+        match &self.resampler {
+            None => vacant,
+            Some(resampler) => {
+                // the frames waiting for a chunk are resampled with the new ones, and the
+                // device drift can make the resampler produce a bit more
+                let pending = resampler.pending().len() / channels;
+                let ratio = self.output_config.sample_rate as f64 / self.input_sample_rate as f64
+                    * self.shared.drift().max(1.0);
+                ((vacant as f64 / ratio) as usize).saturating_sub(pending)
+            }
+        }
+    }
+
+    /// Block the calling thread until the buffer has space for at least `samples` samples,
+    /// or until `timeout` passes, returns `true` if the space is available.
+    ///
+    /// The thread is woken by the output when it plays samples, so producer threads can pace
+    /// themselves without polling [`buffered_samples`](Self::buffered_samples).
+    /// `samples` is counted like the buffer, and is capped to its capacity.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000).backend(backend).build().unwrap();
+    /// player.queue(&vec![0.0; player.buffer_capacity()]);
+    /// player.play().unwrap();
+    /// assert!(!player.wait_for_space(960, Duration::from_millis(1)));
+    ///
+    /// let device = std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_millis(10));
+    ///     clock.advance_frames(480);
+    /// });
+    /// assert!(player.wait_for_space(960, Duration::from_secs(10)));
+    /// device.join().unwrap();
+    /// ```
+    pub fn wait_for_space(&self, samples: usize, timeout: Duration) -> bool {
+        let samples = samples.min(self.buffer_capacity());
+        self.shared
+            .wait_for_space(|| self.buffer_producer.vacant_len() >= samples, timeout)
+    }
+
+    /// Same as [`queue`](Self::queue), with the samples grouped in frames of `CH` channels,
+    /// for applications with a fixed channel layout, which produce their audio as frames.
+    ///
+    /// The frames are queued without copying or allocating.
+    /// Returns the number of samples dropped because the buffer was full, or
+    /// [`AudioPlayerError::UnsupportedInputChannels`] without queueing anything if `CH` is not
+    /// the number of channels of the [`InputLayout`] of the player.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, InputLayout};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue_frames(&[[0.5, -0.5]; 480]).unwrap();
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// assert!(player.queue_frames(&[[0.5]; 480]).is_err());
+    ///
+    /// let mut mono = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .input_layout(InputLayout::Mono)
+    ///     .build()
+    ///     .unwrap();
+    /// mono.queue_frames(&[[0.5]; 480]).unwrap();
+    /// assert_eq!(mono.buffered_samples(), 480 * 2);
+    /// ```
+    pub fn queue_frames<const CH: usize>(
+        &mut self,
+        frames: &[[T; CH]],
+    ) -> Result<usize, AudioPlayerError> {
+        if CH != self.input_layout.channels() {
+            return Err(AudioPlayerError::UnsupportedInputChannels(
+                u16::try_from(CH).unwrap_or(u16::MAX),
+            ));
+        }
+        Ok(self.queue(utils::flatten_frames(frames)))
+    }
+
+    /// Queue interleaved samples of any [`Sample`] type, promoted to the float type of the player,
+    /// then [`queue`](Self::queue)d as usual.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue_samples(&[16384i16, -16384]);
+    /// player.queue_samples(&[192u8, 64]);
+    /// assert_eq!(player.snapshot().buffered, [0.5, -0.5, 0.5, -0.5]);
+    /// ```
+    pub fn queue_samples<S: Sample>(&mut self, data: &[S]) -> usize
+    where
+        T: FromSample<S::Promoted>,
+    {
+        let mut progress = self.begin_queue();
+        let mut converted = [T::EQUILIBRIUM; 256];
+        for chunk in data.chunks(converted.len()) {
+            let converted = &mut converted[..chunk.len()];
+            for (converted, &sample) in converted.iter_mut().zip(chunk) {
+                *converted = T::from_sample_(sample.to_float());
+            }
+            self.push_input(&mut progress, converted);
+        }
+        self.end_queue(progress)
+    }
+
+    /// Write up to `samples` interleaved samples directly into the buffer of the player, without the copy of
+    /// [`queue`](Self::queue), for mixers rendering straight into the output.
+    ///
+    /// `write` is given the space to write into, split in two slices when the buffer wraps around,
+    /// filled with silence, and returns the number of samples it wrote, from the start of the first slice
+    /// and continuing into the second. They are then queued as with [`queue`](Self::queue),
+    /// returns the number of samples queued.
+    ///
+    /// The samples are written in place only when they are played as they are: stereo samples
+    /// without resampling, [`input_lowpass`](AudioPlayerBuilder::input_lowpass) or
+    /// [`input_limiter`](AudioPlayerBuilder::input_limiter), with a ring buffer
+    /// supporting it, see [`RingProducer::write_in_place`](ring::RingProducer::write_in_place),
+    /// and no incomplete frame is carried from the previous call, see [`PartialFrames::Carry`].
+    /// Otherwise, `write` is given a temporary buffer of `samples`, and the second slice is empty.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let queued = player.with_write_chunks(480 * 2, |first, second| {
+    ///     for sample in first.iter_mut().chain(second.iter_mut()) {
+    ///         *sample = 0.25;
+    ///     }
+    ///     first.len() + second.len()
+    /// });
+    /// assert_eq!(queued, 480 * 2);
+    /// assert_eq!(player.buffered_samples(), 480 * 2);
+    /// ```
+    ///
+    /// The written samples are queued after the incomplete frame carried from the previous call:
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.1]);
+    /// player.with_write_chunks(4, |first, _| {
+    ///     first.copy_from_slice(&[0.2, 0.3, 0.2, 0.3]);
+    ///     4
+    /// });
+    /// player.queue(&[0.11, 0.2, 0.3]);
+    /// assert_eq!(
+    ///     player.snapshot().buffered,
+    ///     [0.1, 0.2, 0.3, 0.2, 0.3, 0.11, 0.2, 0.3]
+    /// );
+    /// ```
+    pub fn with_write_chunks(
+        &mut self,
+        samples: usize,
+        write: impl FnOnce(&mut [T], &mut [T]) -> usize,
+    ) -> usize {
+        let in_place = self.resampler.is_none()
+            && self.input_lowpass.is_none()
+            && self.input_limiter.is_none()
+            && self.input_layout == InputLayout::Stereo
+            && self.partial_frame.is_empty();
+        let mut write = Some(write);
+        if in_place {
+            self.recover_stream();
+            let buffered_before = self.buffered_samples();
+            let (silence, input_sample_rate) = (&mut self.silence, self.input_sample_rate);
+            let mut silent = None;
+            let written =
+                self.buffer_producer
+                    .write_in_place(T::EQUILIBRIUM, &mut |first, second| {
+                        let Some(write) = write.take() else {
+                            return 0;
+                        };
+                        let len = samples.min(first.len() + second.len());
+                        let first_len = len.min(first.len());
+                        let (first, second) =
+                            (&mut first[..first_len], &mut second[..len - first_len]);
+                        let written = write(first, second).min(len) / 2 * 2;
+                        if let Some(detector) = silence {
+                            let (written_first, written_second) = (
+                                &first[..written.min(first_len)],
+                                &second[..written.saturating_sub(first_len)],
+                            );
+                            for data in [written_first, written_second] {
+                                silent = detector.process(data, input_sample_rate).or(silent);
+                            }
+                        }
+                        written
+                    });
+            if let Some(written) = written {
+                if let Some(silent) = silent {
+                    self.silence_changed(silent);
+                }
+                self.finish_queue(buffered_before, written / 2, written, written, false);
+                return written;
+            }
+        }
+
+        let Some(write) = write else {
+            return 0;
+        };
+        let mut scratch = std::mem::take(&mut self.write_scratch);
+        scratch.clear();
+        scratch.resize(samples, T::EQUILIBRIUM);
+        let written = write(&mut scratch, &mut []).min(samples);
+        let dropped = self.queue(&scratch[..written]);
+        self.write_scratch = scratch;
+        written - dropped
+    }
+
+    /// Queue raw interleaved PCM bytes of any [`SampleFormat`], converted to the sample type of the player,
+    /// then [`queue`](Self::queue)d as usual.
+    ///
+    /// The samples are little-endian, and `data` should hold whole samples, the bytes of an incomplete
+    /// sample at the end are ignored. To queue bytes that are not aligned to samples,
+    /// for example from a network stream, use [`PcmWriter`].
+    ///
+    /// Returns [`AudioPlayerError::UnsupportedInputFormat`] if `format` can't be converted.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use cpal::SampleFormat;
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // 0.5 and -0.5 as unsigned 8 bits samples
+    /// player.queue_bytes(&[0xC0, 0x40], SampleFormat::U8).unwrap();
+    /// assert_eq!(player.snapshot().buffered, [0.5, -0.5]);
+    /// ```
+    pub fn queue_bytes(&mut self, data: &[u8], format: SampleFormat) -> Result<(), AudioPlayerError>
+    where
+        T: FromSample<i8>
+            + FromSample<i16>
+            + FromSample<i32>
+            + FromSample<i64>
+            + FromSample<u8>
+            + FromSample<u16>
+            + FromSample<u32>
+            + FromSample<u64>
+            + FromSample<f32>
+            + FromSample<f64>,
+    {
+        let mut samples = Vec::with_capacity(data.len() / format.sample_size());
+        pcm::decode_bytes(data, format, &mut samples)?;
+        self.queue(&samples);
+        Ok(())
+    }
+
+    /// Start queueing samples pushed in several pieces, finished by [`end_queue`](Self::end_queue)
+    fn begin_queue(&mut self) -> QueueProgress {
+        self.recover_stream();
+        QueueProgress {
+            buffered_before: self.buffered_samples(),
+            input_frames: 0,
+            pushed: 0,
+            produced: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Push samples of the input layout, continuing the incomplete frame left by the previous piece
+    fn push_input(&mut self, progress: &mut QueueProgress, mut data: &[T]) {
+        let channels = self.input_layout.channels();
+        if !self.partial_frame.is_empty() {
+            let missing = (channels - self.partial_frame.len()).min(data.len());
+            self.partial_frame.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.partial_frame.len() < channels {
+                return;
+            }
+            let frame = std::mem::take(&mut self.partial_frame);
+            self.push_input_frames(progress, &frame);
+            self.partial_frame = frame;
+            self.partial_frame.clear();
+        }
+        let whole = data.len() - data.len() % channels;
+        self.push_input_frames(progress, &data[..whole]);
+        self.partial_frame.extend_from_slice(&data[whole..]);
+    }
+
+    /// Push whole frames of the input layout, converted to stereo
+    fn push_input_frames(&mut self, progress: &mut QueueProgress, data: &[T]) {
+        if data.is_empty() {
+            return;
+        }
+        let mut downmixed = std::mem::take(&mut self.downmix_scratch);
+        let data = match self.input_layout {
+            InputLayout::Stereo => data,
+            layout => {
+                layout.convert_to_stereo(data, &mut downmixed);
+                &downmixed
+            }
+        };
+        self.push_queued_stereo(progress, data);
+        self.downmix_scratch = downmixed;
+    }
+
+    /// Push stereo samples into the buffer, counted in `progress`
+    fn push_queued_stereo(&mut self, progress: &mut QueueProgress, data: &[T]) {
+        self.detect_silence(data);
+        let mut scratch = std::mem::take(&mut self.input_scratch);
+        let (pushed, produced, elapsed) = self.push_stereo(data, &mut scratch);
+        self.input_scratch = scratch;
+        progress.input_frames += data.len() / 2;
+        progress.pushed += pushed;
+        progress.produced += produced;
+        progress.elapsed += elapsed;
+    }
+
+    /// Finish queueing the pushed pieces, the incomplete frame left is kept for the next call
+    /// or discarded, see [`PartialFrames`], returns the dropped samples
+    fn end_queue(&mut self, progress: QueueProgress) -> usize {
+        let QueueProgress {
+            buffered_before,
+            input_frames,
+            mut pushed,
+            mut produced,
+            elapsed,
+        } = progress;
+        let mut discarded = 0;
+        if self.partial_frames == PartialFrames::Discard {
+            discarded = self.partial_frame.len();
+            self.samples_dropped += discarded as u64;
+            self.partial_frame.clear();
+        }
+        if self.shared.is_frame_stepping() {
+            // the stepped frame is played whole, instead of waiting in the resampler for the next one
+            if let Some(resampler) = &mut self.resampler {
+                let producer = &mut self.buffer_producer;
+                resampler.drain(|resampled| {
+                    produced += resampled.len();
+                    pushed += producer.push_slice(resampled);
+                });
+            }
+        }
+
+        let mut overloaded = false;
+        if self.resampler.is_some() {
+            self.resampler_time += elapsed;
+            self.resampled_frames +=
+                (input_frames * 2 / self.output_config.channels.max(1) as usize) as u64;
+            overloaded = self
+                .resampler_load
+                .as_mut()
+                .is_some_and(|load| load.record(elapsed, input_frames, self.input_sample_rate));
+        }
+        discarded + self.finish_queue(buffered_before, input_frames, pushed, produced, overloaded)
+    }
+
+    /// Filter and resample `data` into the buffer, returns the number of samples pushed and produced,
+    /// and the time spent resampling
+    fn push_stereo(&mut self, data: &[T], scratch: &mut Vec<T>) -> (usize, usize, Duration) {
+        let data = if self.input_lowpass.is_some() || self.input_limiter.is_some() {
+            scratch.clear();
+            scratch.extend_from_slice(data);
+            let input_config = OutputConfig {
+                sample_rate: self.input_sample_rate,
+                sample_format: T::FORMAT,
+                channels: 2,
+            };
+            if let Some(lowpass) = &mut self.input_lowpass {
+                lowpass.process(scratch, &input_config);
+            }
+            if let Some(limiter) = &mut self.input_limiter {
+                limiter.process(scratch, &input_config);
+            }
+            &scratch[..]
+        } else {
+            data
+        };
+
+        let Some(resampler) = &mut self.resampler else {
+            // no resampling
+            return (
+                self.buffer_producer.push_slice(data),
+                data.len(),
+                Duration::ZERO,
+            );
+        };
+        resampler.set_drift(self.shared.drift());
+        let start = Instant::now();
+        let producer = &mut self.buffer_producer;
+        let (mut pushed, mut produced) = (0, 0);
+        resampler.resample(data, |resampled| {
+            produced += resampled.len();
+            pushed += producer.push_slice(resampled);
+        });
+        (pushed, produced, start.elapsed())
+    }
+
+    /// Update the state of the player after `produced` samples of `input_frames` input frames were queued,
+    /// of which `pushed` fit in the buffer, returns the number of samples dropped
+    fn finish_queue(
+        &mut self,
+        buffered_before: usize,
+        input_frames: usize,
+        pushed: usize,
+        produced: usize,
+        overloaded: bool,
+    ) -> usize {
+        let dropped = produced - pushed;
+        self.samples_queued += produced as u64;
+        self.samples_dropped += dropped as u64;
+        let channels = self.output_config.channels.max(1) as usize;
+        self.written_frames += (pushed / channels) as u64;
+        self.shared.add_queue();
+        self.flush_commands();
+        self.rate_hint.queued(produced / channels, Instant::now());
+        if overloaded {
+            self.degrade_resampler();
+        }
+
+        if let Some(jitter) = &mut self.jitter {
+            let duration =
+                Duration::from_secs_f64(input_frames as f64 / self.input_sample_rate as f64);
+            if let Some(target) = jitter.update(duration, Instant::now()) {
+                self.set_latency_target(target);
+            }
+        }
+        self.clamp_latency();
+
+        if let Some(tuner) = &mut self.latency_tuner {
+            if let Some(target) = tuner.update(self.shared.underruns(), Instant::now()) {
+                self.set_latency_target(target);
+            }
+        }
+        self.compensate_device_latency();
+
+        self.load_warning.update(self.callback_load());
+
+        if let Some(watermarks) = &mut self.watermarks {
+            let capacity = self.buffer_producer.capacity();
+            watermarks.update(
+                buffered_before,
+                self.buffer_producer.occupied_len(),
+                capacity,
+            );
+        }
+        dropped
+    }
+
+    /// Same as [`queue`](Self::queue), but tags the samples with a presentation `timestamp`,
+    /// to know when they are played with [`playing_timestamp`](Self::playing_timestamp).
+    ///
+    /// The timestamp can be anything increasing with the samples, like the time of the video frame
+    /// the samples go with.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// // two video frames of 20ms each
+    /// player.queue_with_timestamp(&[0.5; 960 * 2], Duration::from_millis(1000));
+    /// player.queue_with_timestamp(&[0.5; 960 * 2], Duration::from_millis(1020));
+    ///
+    /// clock.advance_frames(1200);
+    /// assert_eq!(player.playing_timestamp(), Some(Duration::from_millis(1025)));
+    /// ```
+    pub fn queue_with_timestamp(&mut self, data: &[T], timestamp: Duration) {
+        // the samples kept in the resampler are pushed before `data`
+        let pending_frames = self.resampler.as_ref().map_or(0, |resampler| {
+            (resampler.pending().len() / 2) as u64 * self.output_config.sample_rate as u64
+                / self.input_sample_rate as u64
+        });
+        self.timestamps.prune(
+            self.shared
+                .frames_consumed()
+                .saturating_sub(self.shared.device_latency()),
+        );
+        self.timestamps
+            .push(self.written_frames + pending_frames, timestamp);
+        self.queue(data);
+    }
+
+    /// Same as [`queue`](Self::queue), but returns a [`PlaybackToken`] completed once the device played
+    /// the last frame of `data`, to know precisely when a sound finished.
+    ///
+    /// The samples dropped because the buffer is full are not waited for.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.queue(&[0.0; 480 * 2]);
+    /// let effect = player.queue_tracked(&[0.5; 960 * 2]);
+    ///
+    /// clock.advance_frames(1000);
+    /// assert!(!effect.is_played());
+    /// clock.advance_frames(440);
+    /// assert!(effect.is_played());
+    /// ```
+    pub fn queue_tracked(&mut self, data: &[T]) -> PlaybackToken {
+        self.queue(data);
+        // the end of `data` comes out of the resampler after the samples it keeps, and its delay
+        let resampled_frames = self.resampler.as_ref().map_or(0, |resampler| {
+            ((resampler.pending().len() / 2 + resampler.delay()) as u64)
+                * self.output_config.sample_rate as u64
+                / self.input_sample_rate as u64
+        });
+        PlaybackToken::new(
+            self.shared.clone(),
+            self.written_frames + resampled_frames,
+            self.output_config.sample_rate,
+        )
+    }
+
+    /// The statistics of the output callbacks since the player was created,
+    /// or since [`reset_callback_stats`](Self::reset_callback_stats), see [`CallbackStats`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new().period_frames(480);
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 4800 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// clock.advance_frames(4800);
+    /// let stats = player.callback_stats();
+    /// assert_eq!(stats.callbacks, 10);
+    /// assert_eq!((stats.min_frames, stats.max_frames), (480, 480));
+    /// // the last callback found only its own frames in the buffer
+    /// assert_eq!(stats.min_fill, 480 * 2);
+    /// ```
+    pub fn callback_stats(&self) -> CallbackStats {
+        self.shared.stats().stats()
+    }
+
+    /// A snapshot of the player counters, see [`Metrics`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new().period_frames(480);
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// let capacity = player.metrics().buffer_capacity;
+    /// // queue more than the buffer holds
+    /// player.queue(&vec![0.5; capacity + 960]);
+    /// player.play().unwrap();
+    ///
+    /// clock.advance_frames(480);
+    /// let metrics = player.metrics();
+    /// assert_eq!(metrics.samples_queued, capacity as u64 + 960);
+    /// assert_eq!(metrics.samples_dropped, 960);
+    /// assert_eq!(metrics.samples_played, 960);
+    /// assert_eq!(metrics.buffered, capacity - 960);
+    /// assert_eq!(metrics.underruns, 0);
+    /// ```
+    pub fn metrics(&self) -> Metrics {
+        let channels = self.output_config.channels.max(1) as u64;
+        Metrics {
+            samples_queued: self.samples_queued,
+            samples_played: self.shared.frames_consumed() * channels,
+            samples_dropped: self.samples_dropped,
+            underruns: self.shared.underruns(),
+            buffered: self.buffered_samples(),
+            buffer_capacity: self.buffer_producer.capacity(),
+            resampler_time: self.resampler_time,
+            resampled_duration: Duration::from_secs_f64(
+                self.resampled_frames as f64 / self.input_sample_rate as f64,
+            ),
+        }
+    }
+
+    /// Take the errors that happened on the output side since the last call, see [`CallbackError`].
+    ///
+    /// The output can't return errors while playing, so they are collected without blocking it,
+    /// up to 64 errors, the newer ones are dropped until they are taken.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000).backend(backend).build().unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// clock.advance_frames(480);
+    /// assert!(player.take_errors().is_empty());
+    /// ```
+    pub fn take_errors(&mut self) -> Vec<CallbackError> {
+        let mut errors = self.shared.take_errors();
+        errors.extend(self.errors.pop_iter());
+        errors
+    }
+
+    /// Returns `true` if the output panicked, for example in an effect.
+    ///
+    /// The panic is caught so it doesn't take down the audio thread of the backend,
+    /// and the output only plays silence from then on, the panic message is in [`take_errors`](Self::take_errors).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::{NullBackend, OutputConfig}, effects::AudioEffect, error::CallbackError, AudioPlayer};
+    /// struct Broken;
+    /// impl AudioEffect<f32> for Broken {
+    ///     fn process(&mut self, _: &mut [f32], _: &OutputConfig) {
+    ///         panic!("broken effect");
+    ///     }
+    /// }
+    ///
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .effect(Broken)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// clock.advance_frames(480);
+    /// assert!(player.has_panicked());
+    /// assert_eq!(player.take_errors(), [CallbackError::Panic("broken effect".to_string())]);
+    /// ```
+    pub fn has_panicked(&self) -> bool {
+        self.shared.has_panicked()
+    }
+
+    /// The time spent in the output callbacks, as a percentage of the duration of the audio they fill,
+    /// averaged over the last half second.
+    ///
+    /// The callbacks must fill the audio faster than it plays, the output glitches when this goes over `100.0`,
+    /// and the scheduling of the system makes it glitch before. This includes the effects, the mixed tracks
+    /// and the conversion to the device format, not the resampling, which runs in [`queue`](Self::queue),
+    /// see [`Metrics::resampler_load`].
+    ///
+    /// See [`AudioPlayerBuilder::on_callback_overload`] to be warned when it gets high.
+    pub fn callback_load(&self) -> f32 {
+        self.shared.callback_load() * 100.0
+    }
+
+    /// Start the [`callback_stats`](Self::callback_stats) again from zero.
+    pub fn reset_callback_stats(&self) {
+        self.shared.stats().reset();
+    }
+
+    /// The number of frames delivered to the output since the player was created,
+    /// or since [`reset_frames_delivered`](Self::reset_frames_delivered), updated by each output callback.
+    ///
+    /// This counts every frame filled by the output, including the silence played on underruns
+    /// or while paused without stopping the stream, so it follows the clock of the device,
+    /// for applications that implement their own synchronization.
+    ///
+    /// The counter is 64 bits and wraps around instead of overflowing, use [`u64::wrapping_sub`]
+    /// to get the frames between two readings.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 480 * 2]);
+    ///
+    /// // count from the start of playback
+    /// player.reset_frames_delivered();
+    /// player.play().unwrap();
+    /// clock.advance_frames(960);
+    /// assert_eq!(player.frames_delivered(), 960);
+    /// ```
+    pub fn frames_delivered(&self) -> u64 {
+        self.shared.frames_delivered()
+    }
+
+    /// Start the [`frames_delivered`](Self::frames_delivered) counter again from zero,
+    /// for example right before [`play`](Self::play), or on every `play` with
+    /// [`AudioPlayerBuilder::reset_frames_delivered_on_play`].
+    ///
+    /// The output keeps counting while the counter is reset, the frames it delivers at the same time
+    /// are counted either before or after the reset.
+    pub fn reset_frames_delivered(&self) {
+        self.shared.reset_frames_delivered();
+    }
+
+    /// The timing of the last output callback reported by the device, see [`CallbackTimestamp`],
+    /// for applications doing their own synchronization with the device clock.
+    ///
+    /// Returns `None` before the first callback, or if the backend doesn't report the playback time,
+    /// see [`OutputSource::report_playback_time`](backend::OutputSource::report_playback_time).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// # use std::time::Instant;
+    /// let player = AudioPlayer::<f32>::new(48000, BufferSize::QuarterSecond).unwrap();
+    /// player.play().unwrap();
+    /// # std::thread::sleep(std::time::Duration::from_millis(100));
+    ///
+    /// if let Some(timestamp) = player.callback_timestamp() {
+    ///     // the output frame heard right now
+    ///     let elapsed = Instant::now().saturating_duration_since(timestamp.playback);
+    ///     let frames = (elapsed.as_secs_f64() * player.output_sample_rate() as f64) as u64;
+    ///     println!("playing frame {}", timestamp.frame + frames);
+    /// }
+    /// ```
+    pub fn callback_timestamp(&self) -> Option<CallbackTimestamp> {
+        self.shared.callback_timestamp()
+    }
+
+    /// The timestamp of the samples being played by the device, from the timestamps given to
+    /// [`queue_with_timestamp`](Self::queue_with_timestamp), advanced by the time played since.
+    ///
+    /// The latency of the device is accounted for if the backend reports the playback time,
+    /// see [`OutputSource::report_playback_time`](backend::OutputSource::report_playback_time).
+    ///
+    /// Returns `None` if no timestamped samples were played yet.
+    pub fn playing_timestamp(&self) -> Option<Duration> {
+        let position = self
+            .shared
+            .frames_consumed()
+            .saturating_sub(self.shared.device_latency());
+        self.timestamps.at(position)
+    }
+
+    /// The written frames after the player changed the buffer to hold `occupied` samples,
+    /// must be called while holding the consumer lock, so the output doesn't consume anything meanwhile
+    fn written_frames_with(&self, occupied: usize) -> u64 {
+        let channels = self.output_config.channels.max(1) as usize;
+        self.shared.frames_consumed() + (occupied / channels) as u64
+    }
+
+    /// The number of samples in the buffer waiting to be played, after resampling.
+    ///
+    /// The samples are interleaved, with the output channels, at the output sample rate.
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer_producer.occupied_len()
+    }
+
+    /// The playback time of the samples waiting to be played.
+    ///
+    /// This includes the samples in the buffer, and the input samples kept in the resampler
+    /// until they fill a chunk.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use dynwave::{AudioPlayer, backend::NullBackend};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // 100ms of stereo samples
+    /// player.queue(&[0.0; 4410 * 2]);
+    /// assert_eq!(player.buffered_duration(), Duration::from_millis(100));
+    /// ```
+    pub fn buffered_duration(&self) -> Duration {
+        let output = &self.output_config;
+        let output_frames = self.buffered_samples() / output.channels as usize;
+        let mut duration =
+            Duration::from_secs_f64(output_frames as f64 / output.sample_rate as f64);
+        if let Some(resampler) = &self.resampler {
+            // the resampler always works on stereo input
+            let pending_frames = resampler.pending().len() / 2;
+            duration +=
+                Duration::from_secs_f64(pending_frames as f64 / self.input_sample_rate as f64);
+        }
+        duration
+    }
+
+    /// The delay added by the resampler, in input frames, `0` if the samples are not resampled.
+    ///
+    /// The resampler outputs its first samples late by this delay,
+    /// it's included in [`latency`](Self::latency).
+    pub fn resampler_delay(&self) -> usize {
+        self.resampler
+            .as_ref()
+            .map_or(0, |resampler| resampler.delay())
+    }
+
+    /// The time until the samples queued now are heard.
+    ///
+    /// The sum of the [`buffered_duration`](Self::buffered_duration), the
+    /// [`resampler_delay`](Self::resampler_delay), and the [`device_latency`](Self::device_latency).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use dynwave::{AudioPlayer, backend::NullBackend};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.0; 4410 * 2]);
+    ///
+    /// let delay = player.resampler_delay();
+    /// assert!(delay > 0);
+    /// let resampler_delay = Duration::from_secs_f64(delay as f64 / 44100.0);
+    /// assert_eq!(player.latency(), player.buffered_duration() + resampler_delay);
+    /// ```
+    pub fn latency(&self) -> Duration {
+        let resampler_delay =
+            Duration::from_secs_f64(self.resampler_delay() as f64 / self.input_sample_rate as f64);
+        self.buffered_duration() + resampler_delay + self.device_latency()
+    }
+
+    /// The time between filling the output and the device playing it, if the backend reports it,
+    /// see [`OutputSource::report_playback_time`](backend::OutputSource::report_playback_time).
+    ///
+    /// This is large for wireless outputs, Bluetooth devices often report 100 to 300ms,
+    /// see [`AudioPlayerBuilder::high_latency_compensation`].
+    pub fn device_latency(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.shared.device_latency() as f64 / self.output_config.sample_rate as f64,
+        )
+    }
+
+    /// The maximum number of samples the buffer can hold, see [`BufferSize`].
+    pub fn buffer_capacity(&self) -> usize {
+        self.buffer_producer.capacity()
+    }
+
+    /// Resize the buffer to hold `buffer_size`, see [`BufferSize`].
+    ///
+    /// The samples waiting to be played are moved to the new buffer. If they don't fit,
+    /// the newest ones are dropped, so the playback continues without a gap,
+    /// and they are counted in [`Metrics::samples_dropped`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{AudioPlayer, BufferSize, backend::NullBackend};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .buffer_size(BufferSize::QuarterSecond)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.5; 4800]);
+    /// player.set_buffer_size(BufferSize::OneSecond);
+    /// assert_eq!(player.buffer_capacity(), 48000 * 2);
+    /// assert_eq!(player.buffered_samples(), 4800);
+    ///
+    /// player.set_buffer_size(BufferSize::Samples(1000));
+    /// assert_eq!(player.buffered_samples(), 1000);
+    /// assert_eq!(player.metrics().samples_dropped, 3800);
+    /// ```
+    pub fn set_buffer_size(&mut self, buffer_size: BufferSize) {
+        let ring_buffer_len = buffer_size.store_for_samples(
+            self.output_config.sample_rate as usize,
+            self.output_config.channels as usize,
+        );
+        let (mut producer, new_consumer) = self.ring.split(ring_buffer_len);
+
+        // the callback plays silence while we hold the lock, so keep it short
+        let Ok(mut consumer) = self.buffer_consumer.lock() else {
+            return;
+        };
+        let (first, second) = consumer.as_slices();
+        let pushed = producer.push_slice(first);
+        if pushed == first.len() {
+            producer.push_slice(second);
+        }
+        let dropped = first.len() + second.len() - producer.occupied_len();
+        *consumer = new_consumer;
+        let written_frames = self.written_frames_with(producer.occupied_len());
+        drop(consumer);
+
+        self.buffer_producer = producer;
+        self.written_frames = written_frames;
+        self.samples_dropped += dropped as u64;
+    }
+
+    /// The current latency target of the [`AudioPlayerBuilder::adaptive_latency`],
+    /// [`AudioPlayerBuilder::jitter_buffer`] and [`AudioPlayerBuilder::high_latency_compensation`],
+    /// or `None` if none of them changed it.
+    ///
+    /// After an underrun, playback resumes once this much audio is buffered.
+    pub fn latency_target(&self) -> Option<Duration> {
+        let floor = (!self.latency_floor.is_zero()).then_some(self.latency_floor);
+        let target = self
+            .latency_tuner
+            .as_ref()
+            .map(|tuner| tuner.target())
+            .or_else(|| self.jitter.as_ref().map(|jitter| jitter.target()));
+        match target {
+            Some(target) => Some(target.max(self.latency_floor)),
+            None => floor,
+        }
+    }
+
+    /// The jitter of the arrival times of the queued chunks, measured by the [`AudioPlayerBuilder::jitter_buffer`],
+    /// or `None` if it's not enabled.
+    pub fn arrival_jitter(&self) -> Option<Duration> {
+        self.jitter.as_ref().map(|jitter| jitter.jitter())
+    }
+
+    /// Raise the latency target to the device latency, once it's above the high latency threshold
+    fn compensate_device_latency(&mut self) {
+        let Some(threshold) = self.high_latency_threshold else {
+            return;
+        };
+        let device_latency = self.device_latency();
+        // small changes of the reported latency don't resize the buffer
+        if device_latency > threshold && device_latency > self.latency_floor * 11 / 10 {
+            self.latency_floor = device_latency;
+            let target = self.latency_target().unwrap_or(device_latency);
+            self.set_latency_target(target);
+        }
+    }
+
+    /// Drop the oldest samples down to the target of the latency clamp, or of the jitter buffer,
+    /// once the buffer is above its maximum
+    fn clamp_latency(&mut self) {
+        let jitter_clamp = self
+            .jitter
+            .as_ref()
+            .map(|jitter| (jitter.max(), jitter.target()));
+        let Some((max, target)) = self.latency_clamp.or(jitter_clamp) else {
+            return;
+        };
+        let channels = self.output_config.channels.max(1) as usize;
+        let sample_rate = self.output_config.sample_rate as f64;
+        let max_samples = (max.as_secs_f64() * sample_rate) as usize * channels;
+        if self.buffer_producer.occupied_len() <= max_samples {
+            return;
+        }
+        let target_samples = (target.as_secs_f64() * sample_rate) as usize * channels;
+        let fade_samples = (CLEAR_CROSSFADE.as_secs_f64() * sample_rate) as usize * channels;
+
+        let Ok(mut consumer) = self.buffer_consumer.lock() else {
+            return;
+        };
+        let excess = consumer.occupied_len().saturating_sub(target_samples);
+        let mut tail = vec![T::EQUILIBRIUM; fade_samples.min(excess)];
+        let taken = consumer.pop_slice(&mut tail);
+        let skipped = consumer.skip(excess - taken);
+        let position = self.shared.frames_consumed();
+        let written_frames = self.written_frames_with(consumer.occupied_len());
+        drop(consumer);
+
+        self.written_frames = written_frames;
+        self.samples_dropped += (taken + skipped) as u64;
+        self.timestamps
+            .skip(position, ((taken + skipped) / channels) as u64);
+
+        // `excess` is in whole frames, so the channels are not swapped
+        tail.truncate(taken);
+        if !tail.is_empty() {
+            let frames = tail.len() / channels;
+            let (mut producer, consumer) = HeapRb::new(tail.len()).split();
+            producer.push_slice(&tail);
+            self.send_command(SourceCommand::Crossfade(CrossfadeSource::new(
+                consumer, frames,
+            )));
+        }
+    }
+
+    fn set_latency_target(&mut self, target: Duration) {
+        let target = target.max(self.latency_floor);
+        let channels = self.output_config.channels as usize;
+        let samples =
+            (target.as_secs_f64() * self.output_config.sample_rate as f64) as usize * channels;
+        // leave room for the samples queued while waiting for the target
+        if self.buffer_capacity() < samples * 2 {
+            self.set_buffer_size(BufferSize::Samples(samples * 2));
+        }
+        self.shared.set_resume_threshold(samples);
+    }
+
+    /// The level of the buffer relative to the watermarks, see [`BufferLevel`].
+    ///
+    /// Updated on every [`queue`](Self::queue), always [`BufferLevel::Normal`] if the watermarks
+    /// are not set with [`AudioPlayerBuilder::watermarks`].
+    pub fn buffer_level(&self) -> BufferLevel {
+        self.watermarks
+            .as_ref()
+            .map_or(BufferLevel::Normal, |watermarks| watermarks.level())
+    }
+
+    fn detect_silence(&mut self, data: &[T]) {
+        let Some(detector) = &mut self.silence else {
+            return;
+        };
+        if let Some(silent) = detector.process(data, self.input_sample_rate) {
+            self.silence_changed(silent);
+        }
+    }
+
+    /// Suspend or resume the stream, and notify the callback, when the queued samples become `silent` or not
+    fn silence_changed(&mut self, silent: bool) {
+        let suspend = self
+            .silence
+            .as_ref()
+            .is_some_and(|detector| detector.config.suspend);
+        if suspend && self.is_playing() {
+            // on errors the stream stays as is, and we try again on the next change
+            let result = self.output_stream().and_then(|stream| {
+                if silent {
+                    stream.pause()
+                } else {
+                    stream.play()
+                }
+            });
+            if result.is_ok() {
+                self.suspended.set(silent);
+            }
+        }
+        if let Some(callback) = &mut self.on_silence {
+            callback(silent);
+        }
+    }
+
+    /// Returns `true` if the queued samples are silent, see [`SilenceDetection`].
+    ///
+    /// Always `false` if silence detection is not enabled with [`AudioPlayerBuilder::silence_detection`].
+    pub fn is_silent(&self) -> bool {
+        self.silence
+            .as_ref()
+            .is_some_and(|detector| detector.is_silent())
+    }
+
+    /// Returns `true` if the output stream is paused because the queued samples are silent,
+    /// see [`SilenceDetection::suspend`].
+    ///
+    /// The [`state`](Self::state) stays [`PlayerState::Playing`] while suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.get()
+    }
+
+    /// Start or stop playing the played samples backwards, for the rewind feature of emulators.
+    ///
+    /// The samples are kept for the duration set with [`AudioPlayerBuilder::rewind`],
+    /// while rewinding they are played from the newest to the oldest, each sample is played backwards only once,
+    /// and when the history runs out, silence is played.
+    ///
+    /// The samples queued while rewinding are dropped, and when the rewinding stops,
+    /// the samples queued after that are played normally.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .rewind(Duration::from_secs(5))
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// // a ramp, left and right
+    /// let ramp = (0..480).flat_map(|i| [i as f32 / 480.0, 0.0]).collect::<Vec<_>>();
+    /// player.queue(&ramp);
+    /// clock.advance_frames(480);
+    ///
+    /// player.set_rewinding(true);
+    /// clock.advance_frames(48);
+    ///
+    /// let mut played = Vec::new();
+    /// player.recent_samples(&mut played);
+    /// // the last 48 frames of the ramp, backwards
+    /// assert_eq!(played[0], 479.0 / 480.0);
+    /// assert_eq!(played[94], 432.0 / 480.0);
+    /// ```
+    pub fn set_rewinding(&mut self, rewinding: bool) {
+        if self.rewinding != rewinding {
+            self.rewinding = rewinding;
+            self.send_command(SourceCommand::SetRewinding(rewinding));
+        }
+    }
+
+    /// Returns `true` if the player is rewinding, see [`set_rewinding`](Self::set_rewinding).
+    pub fn is_rewinding(&self) -> bool {
+        self.rewinding
+    }
+
+    /// Play the samples waiting in the buffer backwards, from the newest to the oldest, or forwards again,
+    /// for the rewind effects of emulators and scrubbing in editors.
+    ///
+    /// The buffered samples are reversed in place, the direction changes with the same short crossfade
+    /// as [`clear`](Self::clear) to avoid a click. When it's switched off, the samples that were not played yet
+    /// are played forwards again. The samples queued while playing reversed are played after the buffered ones,
+    /// forwards. Unlike [`set_rewinding`](Self::set_rewinding), this doesn't need the history of
+    /// [`AudioPlayerBuilder::rewind`], only the samples not played yet are reversed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+    /// player.play_reversed(true);
+    /// assert_eq!(player.snapshot().buffered, [0.3, -0.3, 0.2, -0.2, 0.1, -0.1]);
+    /// ```
+    pub fn play_reversed(&mut self, reversed: bool) {
+        if self.reversed == reversed {
+            return;
+        }
+        self.reversed = reversed;
+
+        let channels = self.output_config.channels.max(1) as usize;
+        let fade_samples = (CLEAR_CROSSFADE.as_secs_f64() * self.output_config.sample_rate as f64)
+            as usize
+            * channels;
+        let Ok(mut consumer) = self.buffer_consumer.lock() else {
+            return;
+        };
+        let (first, second) = consumer.as_slices();
+        let mut samples = [first, second].concat();
+        samples.truncate(samples.len() - samples.len() % channels);
+        let tail = samples[..fade_samples.min(samples.len())].to_vec();
+        // reversing the samples reverses the channels of each frame too, so they are reversed back
+        samples.reverse();
+        for frame in samples.chunks_exact_mut(channels) {
+            frame.reverse();
+        }
+        consumer.clear();
+        let pushed = self.buffer_producer.push_slice(&samples);
+        let written_frames = self.written_frames_with(pushed);
+        drop(consumer);
+        self.written_frames = written_frames;
+        self.timestamps.clear();
+        self.fade_out_removed(tail);
+    }
+
+    /// Returns `true` if the buffered samples are played backwards, see [`play_reversed`](Self::play_reversed).
+    pub fn is_playing_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Mute or unmute one channel of the output, for example to check the stereo routing
+    /// of an emulated sound chip, or to listen with a single ear.
+    ///
+    /// The channel is muted at the end of the output, after the effects, so nothing leaks into it.
+    /// The gain moves over a few milliseconds to avoid clicks.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, Channel};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.mute_channel(Channel::Right, true);
+    /// assert!(player.is_channel_muted(Channel::Right));
+    /// player.queue(&[0.5; 4800 * 2]);
+    /// clock.advance_frames(960);
+    ///
+    /// let mut played = Vec::new();
+    /// player.recent_samples(&mut played);
+    /// assert!(played.chunks(2).all(|frame| frame == [0.5, 0.0]));
+    /// ```
+    pub fn mute_channel(&self, channel: Channel, muted: bool) {
+        self.shared.set_channel_muted(channel.index(), muted);
+    }
+
+    /// Returns `true` if `channel` is muted, see [`mute_channel`](Self::mute_channel).
+    pub fn is_channel_muted(&self, channel: Channel) -> bool {
+        self.shared.is_channel_muted(channel.index())
+    }
+
+    /// Set the stereo width of the output, `1.0` plays the channels as they are, `0.0` plays both
+    /// channels in mono, and values above `1.0` widen the stereo image, up to `4.0`.
+    ///
+    /// The width scales the difference between the left and right channels (the side signal),
+    /// keeping their sum. Setting it to `0.0` is useful as an accessibility option for listeners
+    /// with hearing in one ear only. It's applied before the effects and the limiter, and moves over a few milliseconds to avoid clicks.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// player.set_stereo_width(0.0);
+    /// player.queue(&[0.5, 0.0].repeat(4800));
+    /// clock.advance_frames(960);
+    ///
+    /// let mut played = Vec::new();
+    /// player.recent_samples(&mut played);
+    /// assert!(played.chunks(2).all(|frame| frame == [0.25, 0.25]));
+    /// ```
+    pub fn set_stereo_width(&self, width: f32) {
+        self.shared
+            .set_stereo_width(width.clamp(0.0, MAX_STEREO_WIDTH));
+    }
+
+    /// The stereo width, see [`set_stereo_width`](Self::set_stereo_width).
+    pub fn stereo_width(&self) -> f32 {
+        self.shared.stereo_width()
+    }
+
+    /// Set the volume of the output, `1.0` plays the samples as is, and `0.0` is silent.
+    ///
+    /// Values above `1.0` amplify the samples, negative values are treated as `0.0`.
+    /// The volume moves over a few milliseconds to avoid clicks, use [`fade_volume_to`](Self::fade_volume_to)
+    /// for longer fades.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.fade_volume_to(volume, VOLUME_RAMP);
+    }
+
+    /// The volume of the output, see [`set_volume`](Self::set_volume).
+    ///
+    /// During a fade, this is the volume at the end of the fade.
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Set the volume of the output in decibels, `0.0` plays the samples as is,
+    /// `-6.0` is about half the amplitude, and positive values amplify the samples.
+    ///
+    /// `-96dB` and below, including [`f32::NEG_INFINITY`], mute the output.
+    /// Like [`set_volume`](Self::set_volume), the volume moves over a few milliseconds.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.set_volume_db(-20.0);
+    /// assert!((player.volume() - 0.1).abs() < 1e-6);
+    /// assert!((player.volume_db() + 20.0).abs() < 1e-4);
+    ///
+    /// player.set_volume_db(-120.0);
+    /// assert_eq!(player.volume(), 0.0);
+    /// assert_eq!(player.volume_db(), f32::NEG_INFINITY);
+    /// ```
+    pub fn set_volume_db(&mut self, db: f32) {
+        self.set_volume(volume::db_to_gain(db));
+    }
+
+    /// The volume of the output in decibels, [`f32::NEG_INFINITY`] if muted, see [`set_volume_db`](Self::set_volume_db).
+    pub fn volume_db(&self) -> f32 {
+        volume::gain_to_db(self.volume)
+    }
+
+    /// Move the volume of the output linearly to `target` over `duration`,
+    /// for example to lower the music when entering a menu.
+    ///
+    /// The fade is applied in the output, starting with the next samples played,
+    /// a new fade starts from the current volume, even in the middle of another fade.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[1.0; 48000 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// player.fade_volume_to(0.2, Duration::from_millis(100));
+    /// assert_eq!(player.volume(), 0.2);
+    ///
+    /// // half way
+    /// clock.advance_frames(2400);
+    /// let mut played = Vec::new();
+    /// player.recent_samples(&mut played);
+    /// assert!((played.last().unwrap() - 0.6).abs() < 0.01);
+    ///
+    /// clock.advance_frames(4800);
+    /// player.recent_samples(&mut played);
+    /// assert!(played.iter().all(|&sample| (sample - 0.2).abs() < 1e-6));
+    /// ```
+    pub fn fade_volume_to(&mut self, target: f32, duration: Duration) {
+        self.fade_volume_to_with(target, duration, FadeCurve::Linear);
+    }
+
+    /// Same as [`fade_volume_to`](Self::fade_volume_to), with the shape of the fade, see [`FadeCurve`].
+    pub fn fade_volume_to_with(&mut self, target: f32, duration: Duration, curve: FadeCurve) {
+        self.volume = target.max(0.0);
+        let frames = (duration.as_secs_f64() * self.output_config.sample_rate as f64) as u64;
+        self.shared.set_volume_fade(VolumeFade {
+            target: self.volume,
+            frames,
+            curve,
+        });
+    }
+
+    /// Lower the queued samples by `amount` while the clips of [`play_oneshot`](Self::play_oneshot) play,
+    /// for example under a voice-over or a notification, then bring them back.
+    ///
+    /// `amount` is the part of the volume removed, `0.5` halves the samples and `1.0` silences them.
+    /// The gain goes down over `attack`, stays down until the one-shot clips finished,
+    /// then goes back up over `release`, so call this right before playing the clip.
+    /// If no clip is playing, the gain goes back up as soon as it reaches the bottom.
+    ///
+    /// Only the queued samples are ducked, not the clips, the [`Mixer`] tracks or the [`volume`](Self::volume).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.5; 48000 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// let ms = Duration::from_millis(10);
+    /// player.duck(0.75, ms, ms);
+    /// // 100ms of a silent voice-over
+    /// player.play_oneshot(&[0.0; 4800 * 2]).unwrap();
+    ///
+    /// let mut played = Vec::new();
+    /// clock.advance_frames(2400);
+    /// player.recent_samples(&mut played);
+    /// assert!(played.iter().all(|&sample| (sample - 0.125).abs() < 1e-6));
+    ///
+    /// // the clip ended, and the release is done
+    /// clock.advance_frames(4800);
+    /// player.recent_samples(&mut played);
+    /// assert!(played.iter().all(|&sample| (sample - 0.5).abs() < 1e-6));
+    /// ```
+    pub fn duck(&mut self, amount: f32, attack: Duration, release: Duration) {
+        let rate = self.output_config.sample_rate as f64;
+        self.send_command(SourceCommand::Duck {
+            gain: 1.0 - amount.clamp(0.0, 1.0),
+            attack_frames: (attack.as_secs_f64() * rate) as u64,
+            release_frames: (release.as_secs_f64() * rate) as u64,
+        });
+    }
+
+    /// Take a snapshot of the samples waiting to be played, see [`AudioSnapshot`].
+    ///
+    /// This includes the samples in the buffer, and the ones waiting in the resampler,
+    /// but not the tracks of a [`Mixer`] or the one-shot clips.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.25; 1000]);
+    /// let snapshot = player.snapshot();
+    ///
+    /// // continue emulating, then load the state
+    /// player.queue(&[0.5; 5000]);
+    /// player.restore(&snapshot).unwrap();
+    /// assert_eq!(player.snapshot(), snapshot);
+    /// ```
+    pub fn snapshot(&self) -> AudioSnapshot<T> {
+        let buffered = match self.buffer_consumer.lock() {
+            Ok(consumer) => {
+                let (first, second) = consumer.as_slices();
+                [first, second].concat()
+            }
+            Err(_) => Vec::new(),
+        };
+        AudioSnapshot {
+            sample_rate: self.input_sample_rate,
+            output_sample_rate: self.output_config.sample_rate,
+            channels: self.output_config.channels,
+            pending: self
+                .resampler
+                .as_ref()
+                .map_or_else(Vec::new, |resampler| resampler.pending().to_vec()),
+            buffered,
+        }
+    }
+
+    /// Replace the samples waiting to be played with the ones in `snapshot`, see [`AudioSnapshot`].
+    ///
+    /// The internal state of the resampler filter is reset, which might cause a tiny discontinuity,
+    /// but all the samples are restored.
+    ///
+    /// Returns [`AudioPlayerError::SnapshotMismatch`] if the snapshot was taken with a different
+    /// sample rate or output configuration.
+    pub fn restore(&mut self, snapshot: &AudioSnapshot<T>) -> Result<(), AudioPlayerError> {
+        if snapshot.sample_rate != self.input_sample_rate
+            || snapshot.output_sample_rate != self.output_config.sample_rate
+            || snapshot.channels != self.output_config.channels
+        {
+            return Err(AudioPlayerError::SnapshotMismatch);
+        }
+
+        if let Some(resampler) = &mut self.resampler {
+            resampler.restore(&snapshot.pending);
+        }
+        if let Ok(mut consumer) = self.buffer_consumer.lock() {
+            consumer.clear();
+            let pushed = self.buffer_producer.push_slice(&snapshot.buffered);
+            self.written_frames = self.written_frames_with(pushed);
+        }
+        self.timestamps.clear();
+        Ok(())
+    }
+
+    /// Fill the buffer with `duration` of silence, typically before [`play`](Self::play),
+    /// to start with a latency cushion absorbing the jitter of the first queued samples.
+    ///
+    /// The silence is written at the output rate, after the samples already buffered, so the cushion is
+    /// exact whatever the negotiated configuration. Returns the duration of silence written,
+    /// which is shorter than `duration` if the buffer is full.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.prime(Duration::from_millis(20));
+    /// assert_eq!(player.buffered_samples(), 960 * 2);
+    /// assert_eq!(player.buffered_duration(), Duration::from_millis(20));
+    /// ```
+    pub fn prime(&mut self, duration: Duration) -> Duration {
+        let channels = self.output_config.channels.max(1) as usize;
+        let frames =
+            (duration.as_secs_f64() * self.output_config.sample_rate as f64).round() as usize;
+        // whole frames, at least one even with more channels than the usual chunk
+        let silence = vec![T::EQUILIBRIUM; (256 / channels).max(1) * channels];
+        let mut remaining = frames * channels;
+        let mut pushed = 0;
+        while remaining > 0 {
+            let len = remaining.min(silence.len());
+            let chunk_pushed = self.buffer_producer.push_slice(&silence[..len]);
+            pushed += chunk_pushed;
+            remaining -= len;
+            if chunk_pushed < len {
+                break;
+            }
+        }
+        let pushed_frames = pushed / channels;
+        self.written_frames += pushed_frames as u64;
+        Duration::from_secs_f64(pushed_frames as f64 / self.output_config.sample_rate as f64)
+    }
+
+    /// Play the samples of each stepped frame, for debugging an emulator frame by frame.
+    ///
+    /// When stepping, the output plays the queued samples as soon as they are queued, then silence
+    /// until the next step, with a short fade at both ends of each step instead of the crackle of an underrun.
+    /// The samples passed to each [`queue`](Self::queue) are played whole, including the ones that
+    /// would wait in the resampler for the next call. The silence between the steps is not counted as underruns.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, PlayerState};
+    /// let backend = NullBackend::new().sample_rate(48000);
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    /// player.set_frame_stepping(true);
+    ///
+    /// // one frame of a 60 fps emulator, all of it is resampled into the buffer,
+    /// // after the delay of the resampler
+    /// player.queue(&[0.5; 735 * 2]);
+    /// assert!(player.buffered_samples() > 800 * 2);
+    ///
+    /// clock.advance_frames(4800);
+    /// assert_eq!(player.buffered_samples(), 0);
+    /// assert_eq!(player.state(), PlayerState::Playing);
+    /// ```
+    pub fn set_frame_stepping(&self, stepping: bool) {
+        self.shared.set_frame_stepping(stepping);
+    }
+
+    /// Whether the samples are played frame by frame, see [`set_frame_stepping`](Self::set_frame_stepping).
+    pub fn is_frame_stepping(&self) -> bool {
+        self.shared.is_frame_stepping()
+    }
+
+    /// Remove all the samples waiting to be played, for example when seeking or resetting the emulator.
+    ///
+    /// Instead of cutting the audio, which causes a click, the next few milliseconds of the removed samples
+    /// are faded out while the samples queued after this are faded in.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// player.queue(&[0.5; 4800]);
+    /// player.clear();
+    /// assert!(player.snapshot().buffered.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.partial_frame.clear();
+        let channels = self.output_config.channels as usize;
+        let fade_samples = (CLEAR_CROSSFADE.as_secs_f64() * self.output_config.sample_rate as f64)
+            as usize
+            * channels;
+        let tail = self.flush_buffer(fade_samples);
+        self.fade_out_removed(tail);
+    }
+
+    /// Fade out `tail`, the first samples removed from the buffer, while the next samples are faded in
+    fn fade_out_removed(&mut self, mut tail: Vec<T>) {
+        let channels = self.output_config.channels.max(1) as usize;
+        // keep whole frames, so the channels are not swapped
+        tail.truncate(tail.len() - tail.len() % channels);
+        if !tail.is_empty() {
+            let frames = tail.len() / channels;
+            let (mut producer, consumer) = HeapRb::new(tail.len()).split();
+            producer.push_slice(&tail);
+            self.send_command(SourceCommand::Crossfade(CrossfadeSource::new(
+                consumer, frames,
+            )));
+        }
+    }
+
+    /// Crossfade from the stream being played to a new one over `duration`, without a gap,
+    /// for example when switching the background music, or the emulator core.
+    ///
+    /// The player itself moves to the new stream, the samples queued after this call are faded in.
+    /// The samples that were queued before are moved to the returned [`OutgoingStream`] and faded out,
+    /// if they are shorter than the fade, more samples of the old stream can be queued into it
+    /// until the fade ends, it's silent otherwise.
+    ///
+    /// A new crossfade replaces the one in progress.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[1.0; 960 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// let mut old = player.crossfade(Duration::from_millis(100)).unwrap();
+    /// assert_eq!(player.buffered_samples(), 0);
+    /// // the old stream continues while it fades out
+    /// old.queue(&[1.0; 4800 * 2]);
+    /// player.queue(&[0.0; 4800 * 2]);
+    ///
+    /// // half way, both streams are at half volume
+    /// let mut played = Vec::new();
+    /// clock.advance_frames(2400);
+    /// player.recent_samples(&mut played);
+    /// assert!((played.last().unwrap() - 0.5).abs() < 0.01);
+    ///
+    /// clock.advance_frames(2400);
+    /// assert!(old.is_finished());
+    /// ```
+    pub fn crossfade(&mut self, duration: Duration) -> Result<OutgoingStream<T>, AudioPlayerError> {
+        let (outgoing, consumer) = self.take_outgoing_stream()?;
+        let frames = (duration.as_secs_f64() * self.output_config.sample_rate as f64) as usize;
+        self.send_command(SourceCommand::Crossfade(CrossfadeSource::new(
+            consumer, frames,
+        )));
+        Ok(outgoing)
+    }
+
+    /// Start a new source that plays right after the samples queued so far, without a gap or an overlap,
+    /// for example to chain tracks back to back.
+    ///
+    /// The samples queued before are moved to the returned [`OutgoingStream`], where the rest of the old
+    /// source can be queued. The samples queued into the player after this call are held until
+    /// the old source ends, when the [`OutgoingStream`] is dropped and all of its samples are played.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&[0.1; 10 * 2]);
+    /// player.play().unwrap();
+    ///
+    /// let mut track_a = player.swap_source().unwrap();
+    /// player.queue(&[0.2; 10 * 2]);
+    /// // the end of the first track is queued after the second one started
+    /// track_a.queue(&[0.1; 5 * 2]);
+    /// drop(track_a);
+    ///
+    /// clock.advance_frames(25);
+    /// let mut played = Vec::new();
+    /// player.recent_samples(&mut played);
+    /// assert_eq!(played[..15 * 2], [0.1; 15 * 2]);
+    /// assert_eq!(played[15 * 2..], [0.2; 10 * 2]);
+    /// ```
+    pub fn swap_source(&mut self) -> Result<OutgoingStream<T>, AudioPlayerError> {
+        let (outgoing, consumer) = self.take_outgoing_stream()?;
+        self.send_command(SourceCommand::SwapSource(consumer));
+        Ok(outgoing)
+    }
+
+    /// Move the queued samples to a new stream, so the player can start another source
+    fn take_outgoing_stream(
+        &mut self,
+    ) -> Result<(OutgoingStream<T>, HeapCons<T>), AudioPlayerError> {
+        // the old stream keeps the input samples waiting in the resampler
+        let mut resampler = AudioResampler::for_rates(
+            self.input_sample_rate,
+            self.output_config.sample_rate,
+            self.output_config.channels,
+            self.low_latency,
+        )?;
+        if let (Some(old), Some(new)) = (&self.resampler, &mut resampler) {
+            new.restore(old.pending());
+        }
+
+        let capacity = self.buffer_capacity();
+        let buffered = self.flush_buffer(capacity);
+        let (mut producer, consumer) = HeapRb::new(capacity).split();
+        producer.push_slice(&buffered);
+        Ok((OutgoingStream::new(producer, resampler), consumer))
+    }
+
+    /// Drop the queued samples, and the ones waiting in the resampler,
+    /// returns the first `keep` samples that were in the buffer
+    fn flush_buffer(&mut self, keep: usize) -> Vec<T> {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.restore(&[]);
+        }
+
+        let mut removed = vec![T::EQUILIBRIUM; keep];
+        let Ok(mut consumer) = self.buffer_consumer.lock() else {
+            return Vec::new();
+        };
+        let taken = consumer.pop_slice(&mut removed);
+        consumer.clear();
+        let written_frames = self.written_frames_with(0);
+        drop(consumer);
+        self.written_frames = written_frames;
+        self.timestamps.clear();
+
+        removed.truncate(taken);
+        removed
+    }
+
+    /// Play `clip` once, mixed on top of the samples queued with [`queue`](Self::queue).
+    ///
+    /// This is meant for short sounds, like UI clicks, the clip has the same sample rate as the player,
+    /// and is resampled separately, so it doesn't affect the queued samples.
+    ///
+    /// Multiple clips can be played at the same time, the clip is dropped from the output after it is played.
+    /// If the player is paused, the clip is played when it resumes.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    /// player.play().unwrap();
+    ///
+    /// let click = vec![0.5; 441 * 2];
+    /// player.play_oneshot(&click).unwrap();
+    /// ```
+    pub fn play_oneshot(&mut self, clip: &[T]) -> Result<(), AudioPlayerError> {
+        let resampled = match AudioResampler::for_rates(
+            self.input_sample_rate,
+            self.output_config.sample_rate,
+            self.output_config.channels,
+            self.low_latency,
+        )? {
+            Some(mut resampler) => resampler.resample_all(clip),
+            None => clip.to_vec(),
+        };
+
+        let (mut producer, consumer) = HeapRb::new(resampled.len().max(1)).split();
+        producer.push_slice(&resampled);
+        // the producer is dropped here, so the track is removed after it is played
+        self.send_command(SourceCommand::AddTrack(
+            mixer::TrackSource::new(
+                consumer,
+                mixer::TrackControls::new(),
+                self.output_config.sample_rate,
+            )
+            .oneshot(),
+        ));
+        Ok(())
+    }
+
+    /// Decode the WAV file at `path` and queue it, see [`queue_reader`](Self::queue_reader).
+    ///
+    /// Only available with the `decode` feature.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::new(44100, BufferSize::OneSecond).unwrap();
+    /// player.queue_wav_file("jingle.wav").unwrap();
+    /// player.play().unwrap();
+    /// ```
+    #[cfg(feature = "decode")]
+    pub fn queue_wav_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), AudioPlayerError>
+    where
+        T: FromSample<f32>,
+    {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        self.queue_reader(file)
+    }
+
+    /// Decode the WAV audio in `reader` and [`queue`](Self::queue) it.
+    ///
+    /// The audio is resampled to the sample rate of the player if it's different,
+    /// and converted to stereo, the file can have 1, 2 or 6 (5.1) channels.
+    /// The [`input_layout`](AudioPlayerBuilder::input_layout) of the player doesn't apply here.
+    ///
+    /// The whole file is queued at once, so samples that don't fit in the buffer are dropped,
+    /// use a buffer big enough for the audio, this is meant for short sounds, like intro jingles or test fixtures.
+    ///
+    /// Only available with the `decode` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .buffer_size(BufferSize::OneSecond)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// # let mut wav = std::io::Cursor::new(Vec::new());
+    /// # let spec = hound::WavSpec {
+    /// #     channels: 1,
+    /// #     sample_rate: 24000,
+    /// #     bits_per_sample: 16,
+    /// #     sample_format: hound::SampleFormat::Int,
+    /// # };
+    /// # let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+    /// # for _ in 0..2400 {
+    /// #     writer.write_sample(0i16).unwrap();
+    /// # }
+    /// # writer.finalize().unwrap();
+    /// # wav.set_position(0);
+    /// // 100ms of mono audio at 24000Hz
+    /// player.queue_reader(wav).unwrap();
+    /// // converted to stereo at 48000Hz
+    /// assert_eq!(player.buffered_duration().as_millis(), 100);
+    /// ```
+    #[cfg(feature = "decode")]
+    pub fn queue_reader(&mut self, reader: impl std::io::Read) -> Result<(), AudioPlayerError>
+    where
+        T: FromSample<f32>,
+    {
+        let samples = decode::decode_wav(reader, self.input_sample_rate)?;
+        let mut progress = self.begin_queue();
+        self.push_queued_stereo(&mut progress, &samples);
+        self.end_queue(progress);
+        Ok(())
+    }
+
+    /// Start recording the played samples into a WAV file at `path`.
+    ///
+    /// The recorded samples are exactly what is sent to the output (after resampling),
+    /// written with the same sample rate and number of channels as the output, and in the same sample format
+    /// if WAV supports it. Nothing is recorded while the player is paused.
+    ///
+    /// With the `flac` feature, the recording is written as a FLAC file if `path` has the `.flac` extension,
+    /// which is lossless and much smaller, with 24 bits samples for formats bigger than 16 bits.
+    ///
+    /// If a recording is already in progress, it is stopped first.
+    ///
+    /// Only available with the `wav` feature.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, BufferSize};
+    /// let mut player = AudioPlayer::<f32>::new(44100, BufferSize::HalfSecond).unwrap();
+    /// player.play().unwrap();
+    /// player.start_recording("recording.wav").unwrap();
+    /// // queue samples...
+    /// player.stop_recording().unwrap();
+    /// ```
+    #[cfg(feature = "wav")]
+    pub fn start_recording(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), AudioPlayerError> {
+        self.stop_recording()?;
+
+        let (recording, tap) = recording::Recording::start(path.as_ref(), &self.output_config)?;
+        self.send_command(SourceCommand::SetTap(Some(tap)));
+        self.recording = Some(recording);
+        Ok(())
+    }
+
+    /// Stop the recording started with [`start_recording`](Self::start_recording), and finalize the file.
+    ///
+    /// Does nothing if there is no recording in progress.
+    ///
+    /// Only available with the `wav` feature.
+    #[cfg(feature = "wav")]
+    pub fn stop_recording(&mut self) -> Result<(), AudioPlayerError> {
+        match self.recording.take() {
+            Some(recording) => {
+                self.send_command(SourceCommand::SetTap(None));
+                recording.stop()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` if a recording is in progress.
+    ///
+    /// Only available with the `wav` feature.
+    #[cfg(feature = "wav")]
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Add `effect` at the end of the effect chain, see [`effects`] for more details.
+    pub fn add_effect(&mut self, effect: impl AudioEffect<T> + 'static) {
+        self.send_command(SourceCommand::AddEffect(Box::new(effect)));
+    }
+
+    /// Remove all the effects from the effect chain, including the ones added with the builder.
+    pub fn clear_effects(&mut self) {
+        self.send_command(SourceCommand::ClearEffects);
+    }
+
+    /// The decisions of the negotiation with the output, and the notable events since,
+    /// in the order they happened, see [`Diagnostic`].
+    ///
+    /// Only the first 64 are kept.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn add_diagnostics(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        let room = DIAGNOSTICS_CAPACITY.saturating_sub(self.diagnostics.len());
+        self.diagnostics.extend(diagnostics.into_iter().take(room));
+    }
+
+    fn send_command(&mut self, command: SourceCommand<T>) {
+        self.overflow_commands.push_back(command);
+        self.flush_commands();
+    }
+
+    /// Move the commands that didn't fit to the output, in the order they were sent
+    fn flush_commands(&mut self) {
+        while let Some(command) = self.overflow_commands.pop_front() {
+            if let Err(command) = self.commands.try_push(command) {
+                self.overflow_commands.push_front(command);
+                break;
+            }
+        }
+    }
+}
+
+impl<T: FloatSample> Drop for AudioPlayer<T> {
+    /// Fade the output out before stopping the stream, see [`AudioPlayerBuilder::fade_out_on_drop`]
+    fn drop(&mut self) {
+        let Some(stream) = &self.output_stream else {
+            return;
+        };
+        if self.drop_fade_timeout.is_zero() || self.shared.state() == PlayerState::Paused {
+            return;
+        }
+        self.shared.set_fading_out(true);
+        let start = Instant::now();
+        while !self.shared.is_faded_out() && start.elapsed() < self.drop_fade_timeout {
+            thread::sleep(DROP_FADE_POLL);
+        }
+        let _ = stream.pause();
     }
 }
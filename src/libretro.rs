@@ -0,0 +1,119 @@
+//! Adapter from the audio callbacks of a libretro core to an [`AudioPlayer`].
+//!
+//! Libretro cores output interleaved stereo `i16` samples, either one frame at a time with
+//! `retro_audio_sample_t`, or in batches with `retro_audio_sample_batch_t`. A frontend forwards
+//! both callbacks to a [`LibretroAudio`], and calls [`LibretroAudio::end_frame`] after each `retro_run`.
+//!
+//! # Example
+//! ```rust
+//! # use dynwave::{backend::NullBackend, libretro::LibretroAudio, AudioPlayer};
+//! // `sample_rate` from `retro_system_av_info::timing`
+//! let sample_rate = 32040.5;
+//! let player = AudioPlayer::<f32>::builder(LibretroAudio::player_sample_rate(sample_rate))
+//!     .backend(NullBackend::new())
+//!     .build()
+//!     .unwrap();
+//! let mut audio = LibretroAudio::new(player);
+//!
+//! // inside `retro_run`, from the callbacks of the core
+//! audio.audio_sample(i16::MAX, i16::MIN);
+//! assert_eq!(audio.audio_sample_batch(&[0; 534 * 2]), 534);
+//!
+//! // after `retro_run` returns
+//! audio.end_frame();
+//! assert_eq!(audio.player().buffered_samples(), 535 * 2);
+//! ```
+
+use std::time::Duration;
+
+use cpal::FromSample;
+
+use crate::AudioPlayer;
+
+/// Frames of single samples kept before queueing them, cores using `retro_audio_sample_t`
+/// usually call it for every frame
+const SINGLE_SAMPLES_CHUNK: usize = 512;
+
+/// Receives the audio of a libretro core and queues it into an [`AudioPlayer`], see the [module docs](self).
+pub struct LibretroAudio {
+    player: AudioPlayer<f32>,
+    // converted samples, from single samples or the last batch
+    converted: Vec<f32>,
+}
+
+impl LibretroAudio {
+    /// Creates an adapter queueing into `player`, which should have the sample rate of the core,
+    /// see [`player_sample_rate`](Self::player_sample_rate).
+    pub fn new(player: AudioPlayer<f32>) -> Self {
+        Self {
+            player,
+            converted: Vec::with_capacity(SINGLE_SAMPLES_CHUNK * 2),
+        }
+    }
+
+    /// The sample rate to create the player with, from the `f64` sample rate reported by the core.
+    ///
+    /// Cores often report fractional rates, like `32040.5` for the SNES, the fraction is rounded
+    /// and its small difference is absorbed by the buffer.
+    pub fn player_sample_rate(sample_rate: f64) -> u32 {
+        sample_rate.round() as u32
+    }
+
+    /// `retro_audio_sample_t`: queue one stereo frame.
+    ///
+    /// The frames are queued in chunks, or in [`end_frame`](Self::end_frame).
+    pub fn audio_sample(&mut self, left: i16, right: i16) {
+        self.converted.push(f32::from_sample_(left));
+        self.converted.push(f32::from_sample_(right));
+        if self.converted.len() >= SINGLE_SAMPLES_CHUNK * 2 {
+            self.queue_converted();
+        }
+    }
+
+    /// `retro_audio_sample_batch_t`: queue the interleaved stereo frames in `data`,
+    /// returns the number of frames, which is what the callback returns to the core.
+    pub fn audio_sample_batch(&mut self, data: &[i16]) -> usize {
+        // keep the order with the single samples before this
+        self.queue_converted();
+        self.converted
+            .extend(data.iter().map(|&sample| f32::from_sample_(sample)));
+        self.queue_converted();
+        data.len() / 2
+    }
+
+    /// Queue the single samples of the emulated frame, to be called after `retro_run`.
+    pub fn end_frame(&mut self) {
+        self.queue_converted();
+    }
+
+    /// Pacing hint for frontends syncing to audio: how long to wait before running the next frame,
+    /// to keep `target` of audio buffered.
+    ///
+    /// Returns [`Duration::ZERO`] if the buffer is below the target, i.e. the next frame should run now.
+    pub fn wait_hint(&self, target: Duration) -> Duration {
+        self.player.buffered_duration().saturating_sub(target)
+    }
+
+    /// The player the samples are queued into.
+    pub fn player(&self) -> &AudioPlayer<f32> {
+        &self.player
+    }
+
+    /// The player the samples are queued into, to play, pause or configure it.
+    pub fn player_mut(&mut self) -> &mut AudioPlayer<f32> {
+        &mut self.player
+    }
+
+    /// Returns the player, after queueing the remaining single samples.
+    pub fn into_player(mut self) -> AudioPlayer<f32> {
+        self.queue_converted();
+        self.player
+    }
+
+    fn queue_converted(&mut self) {
+        if !self.converted.is_empty() {
+            self.player.queue(&self.converted);
+            self.converted.clear();
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Capturing the audio played by the other applications, see
+//! [`AudioRecorderBuilder::loopback`](crate::AudioRecorderBuilder::loopback)
+
+use crate::{error::AudioPlayerError, recorder::InputDevice};
+
+/// Open the loopback capture of `device`, or of the default output device of `host`
+#[cfg(windows)]
+pub(crate) fn open(
+    host: &cpal::Host,
+    device: Option<cpal::Device>,
+) -> Result<InputDevice, AudioPlayerError> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    // WASAPI captures what an output device plays when an input stream is built on it,
+    // in the formats of the output
+    let device = match device {
+        Some(device) => device,
+        None => host
+            .default_output_device()
+            .ok_or(AudioPlayerError::NoOutputDevice)?,
+    };
+    let configs = device.supported_output_configs()?.collect();
+    Ok(InputDevice {
+        device,
+        configs,
+        output_configs: true,
+    })
+}
+
+/// Open the loopback capture of the default output, through the monitor source of the sound server
+/// on the default input device of `host`, or on `device`
+#[cfg(target_os = "linux")]
+pub(crate) fn open(
+    host: &cpal::Host,
+    device: Option<cpal::Device>,
+) -> Result<InputDevice, AudioPlayerError> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    // the configurations are read by opening the device, so the monitor is used from here
+    let monitor = MonitorEnv::set();
+    let device = match device {
+        Some(device) => device,
+        None => host
+            .default_input_device()
+            .ok_or(AudioPlayerError::NoInputDevice)?,
+    };
+    let configs = device.supported_input_configs()?.collect();
+    Ok(InputDevice {
+        device,
+        configs,
+        output_configs: false,
+        _monitor: Some(monitor),
+    })
+}
+
+/// The other platforms can't capture their output through cpal
+#[cfg(not(any(windows, target_os = "linux")))]
+pub(crate) fn open(
+    _host: &cpal::Host,
+    _device: Option<cpal::Device>,
+) -> Result<InputDevice, AudioPlayerError> {
+    Err(AudioPlayerError::LoopbackNotSupported)
+}
+
+/// The environment making the `pulse` and `pipewire` ALSA plugins record from the monitor of the default output,
+/// restored when dropped, so the other input streams of the process record from the default input again.
+///
+/// PulseAudio and `pipewire-pulse` read `PULSE_SOURCE`, and the `pipewire` plugin the properties of `PIPEWIRE_PROPS`.
+#[cfg(target_os = "linux")]
+pub(crate) struct MonitorEnv {
+    pulse_source: Option<std::ffi::OsString>,
+    pipewire_props: Option<std::ffi::OsString>,
+}
+
+#[cfg(target_os = "linux")]
+impl MonitorEnv {
+    fn set() -> Self {
+        let pulse_source = std::env::var_os("PULSE_SOURCE");
+        let pipewire_props = std::env::var_os("PIPEWIRE_PROPS");
+        std::env::set_var("PULSE_SOURCE", "@DEFAULT_MONITOR@");
+        // keep the properties of the stream metadata
+        let props = pipewire_props
+            .as_ref()
+            .and_then(|props| props.to_str())
+            .map_or("", |props| {
+                props.trim().trim_start_matches('{').trim_end_matches('}')
+            });
+        std::env::set_var(
+            "PIPEWIRE_PROPS",
+            format!("{{ stream.capture.sink=true {} }}", props.trim()),
+        );
+        Self {
+            pulse_source,
+            pipewire_props,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MonitorEnv {
+    fn drop(&mut self) {
+        for (key, value) in [
+            ("PULSE_SOURCE", &self.pulse_source),
+            ("PIPEWIRE_PROPS", &self.pipewire_props),
+        ] {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+/// The role of the audio in a stream, used by the system mixer to route it and apply its policies,
+/// for example to lower the music while a phone call plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaRole {
+    /// Game audio.
+    Game,
+    /// Music playback.
+    Music,
+    /// The audio of a video.
+    Video,
+    /// Short sounds, like notifications.
+    Event,
+    /// Voice calls.
+    Phone,
+}
+
+impl MediaRole {
+    /// The name of the role in PulseAudio and PipeWire properties
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Game => "game",
+            Self::Music => "music",
+            Self::Video => "video",
+            Self::Event => "event",
+            Self::Phone => "phone",
+        }
+    }
+}
+
+/// Names and role of the output stream shown by the system mixer, see
+/// [`AudioPlayerBuilder::stream_metadata`](crate::AudioPlayerBuilder::stream_metadata).
+///
+/// # Platform support
+/// - Linux: the properties are read by PulseAudio and PipeWire when the stream is opened through their
+///   ALSA plugins, which is the case on most desktop systems. Set them before building the first player,
+///   as the plugins only read them for new clients.
+/// - Windows and macOS: cpal doesn't expose the WASAPI audio session or the CoreAudio stream,
+///   so the system mixer shows the name of the executable, as for any application without a name.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{MediaRole, StreamMetadata};
+/// let metadata = StreamMetadata::new()
+///     .application_name("MyEmulator")
+///     .stream_name("Game audio")
+///     .media_role(MediaRole::Game);
+/// assert_eq!(metadata.get_application_name(), Some("MyEmulator"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamMetadata {
+    application_name: Option<String>,
+    stream_name: Option<String>,
+    media_role: Option<MediaRole>,
+}
+
+impl StreamMetadata {
+    /// Creates empty metadata, the system mixer uses its defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The name of the application owning the stream.
+    pub fn application_name(mut self, name: impl Into<String>) -> Self {
+        self.application_name = Some(name.into());
+        self
+    }
+
+    /// The name of the stream itself.
+    pub fn stream_name(mut self, name: impl Into<String>) -> Self {
+        self.stream_name = Some(name.into());
+        self
+    }
+
+    /// The role of the audio in the stream, see [`MediaRole`].
+    pub fn media_role(mut self, role: MediaRole) -> Self {
+        self.media_role = Some(role);
+        self
+    }
+
+    /// The name of the application, if set.
+    pub fn get_application_name(&self) -> Option<&str> {
+        self.application_name.as_deref()
+    }
+
+    /// The name of the stream, if set.
+    pub fn get_stream_name(&self) -> Option<&str> {
+        self.stream_name.as_deref()
+    }
+
+    /// The role of the stream, if set.
+    pub fn get_media_role(&self) -> Option<MediaRole> {
+        self.media_role
+    }
+
+    fn properties(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("application.name", self.application_name.as_deref()),
+            ("media.name", self.stream_name.as_deref()),
+            ("media.role", self.media_role.map(MediaRole::as_str)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+    }
+
+    /// Expose the metadata to the stream that is created next.
+    ///
+    /// cpal doesn't take stream properties, and on Linux it opens the stream with ALSA,
+    /// which goes through the `pulse` or `pipewire` ALSA plugins on desktop systems.
+    /// Those read the client properties from the environment when the stream is opened,
+    /// so the properties are set there, for the whole process.
+    /// Other platforms don't have a way to set them through cpal, so nothing is done.
+    pub(crate) fn apply(&self) {
+        #[cfg(target_os = "linux")]
+        {
+            let mut pipewire_props = Vec::new();
+            for (key, value) in self.properties() {
+                std::env::set_var(format!("PULSE_PROP_{key}"), value);
+                pipewire_props.push(format!("{key}=\"{}\"", value.replace('"', "\\\"")));
+            }
+            if !pipewire_props.is_empty() {
+                std::env::set_var(
+                    "PIPEWIRE_PROPS",
+                    format!("{{ {} }}", pipewire_props.join(" ")),
+                );
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = self.properties();
+    }
+}
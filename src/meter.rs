@@ -0,0 +1,89 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use cpal::{FromSample, SizedSample};
+
+use crate::{backend::OutputConfig, effects::AudioEffect};
+
+/// The level of one channel of the output, from [`AudioPlayer::levels`](crate::AudioPlayer::levels).
+///
+/// The values are linear, `1.0` is full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelLevel {
+    /// The highest absolute sample value since the last call to [`AudioPlayer::levels`](crate::AudioPlayer::levels).
+    pub peak: f32,
+    /// The RMS of the last buffer sent to the output.
+    pub rms: f32,
+}
+
+/// The levels of each channel, written by the [`LevelMeter`] and read by the player
+pub(crate) struct Levels {
+    // `f32` bits of the peak and rms of each channel
+    channels: Box<[(AtomicU32, AtomicU32)]>,
+}
+
+impl Levels {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels: (0..channels)
+                .map(|_| (AtomicU32::new(0), AtomicU32::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Read the levels, and reset the peaks
+    pub fn read(&self) -> Vec<ChannelLevel> {
+        self.channels
+            .iter()
+            .map(|(peak, rms)| ChannelLevel {
+                peak: f32::from_bits(peak.swap(0, Ordering::Relaxed)),
+                rms: f32::from_bits(rms.load(Ordering::Relaxed)),
+            })
+            .collect()
+    }
+}
+
+/// Computes the [`Levels`] of the output, runs after all the effects
+pub(crate) struct LevelMeter {
+    levels: Arc<Levels>,
+    sums: Vec<f32>,
+}
+
+impl LevelMeter {
+    pub fn new(levels: Arc<Levels>) -> Self {
+        Self {
+            sums: vec![0.0; levels.channels.len()],
+            levels,
+        }
+    }
+}
+
+impl<T> AudioEffect<T> for LevelMeter
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    fn process(&mut self, samples: &mut [T], _config: &OutputConfig) {
+        let channels = self.levels.channels.len();
+        if channels == 0 || samples.is_empty() {
+            return;
+        }
+        self.sums.fill(0.0);
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let sample = f32::from_sample_(sample).abs();
+                self.sums[channel] += sample * sample;
+                // positive floats keep their order as bits
+                self.levels.channels[channel]
+                    .0
+                    .fetch_max(sample.to_bits(), Ordering::Relaxed);
+            }
+        }
+        let frames = (samples.len() / channels).max(1) as f32;
+        for (sum, (_, rms)) in self.sums.iter().zip(self.levels.channels.iter()) {
+            rms.store((sum / frames).sqrt().to_bits(), Ordering::Relaxed);
+        }
+    }
+}
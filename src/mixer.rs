@@ -0,0 +1,588 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use cpal::{FromSample, SizedSample};
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+use crate::{
+    backend::source::SourceCommand,
+    error::{AudioPlayerError, PlayError},
+    resampler::AudioResampler,
+    sample::FloatSample,
+    AudioPlayer, BufferSize,
+};
+
+/// The `Mixer` plays multiple independent streams of samples with one [`AudioPlayer`].
+///
+/// Each stream is a [`Track`] with its own buffer and sample rate, created with [`add_track`](Self::add_track).
+/// The tracks are summed in the output callback, together with the samples queued into the player itself.
+///
+/// The tracks are not synchronized with each other, each one is played as soon as its samples are queued,
+/// and if a track runs out of samples, it's silent until more are queued.
+///
+/// Each track has its own volume, mute and pan, see [`TrackControls`].
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, BufferSize, Mixer, PlayerState};
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .build()
+///     .unwrap();
+/// let mut mixer = Mixer::new(player);
+///
+/// let mut apu = mixer.add_track(48000, BufferSize::QuarterSecond).unwrap();
+/// let mut cd = mixer.add_track(44100, BufferSize::HalfSecond).unwrap();
+/// mixer.play().unwrap();
+///
+/// // the tracks can be moved to other threads
+/// std::thread::spawn(move || cd.queue(&[0.0; 1470])).join().unwrap();
+/// apu.queue(&[0.0; 1600]);
+/// apu.controls().set_volume(0.5);
+///
+/// // nothing was queued into the player itself, but the tracks are playing
+/// clock.advance_frames(800);
+/// assert_eq!(mixer.player().state(), PlayerState::Playing);
+/// ```
+pub struct Mixer<T: FloatSample> {
+    player: AudioPlayer<T>,
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> Mixer<T>
+where
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// Creates a mixer playing into `player`.
+    pub fn new(player: AudioPlayer<T>) -> Self {
+        Self { player }
+    }
+
+    /// Add a new track to the mixer, that will play samples of `sample_rate` Hz.
+    ///
+    /// The samples are resampled to the output sample rate when queued if needed,
+    /// `buffer_size` is the size of the track's own buffer, see [`BufferSize`].
+    ///
+    /// The track is removed from the mixer when the [`Track`] is dropped and all its samples are played.
+    pub fn add_track(
+        &mut self,
+        sample_rate: u32,
+        buffer_size: BufferSize,
+    ) -> Result<Track<T>, AudioPlayerError> {
+        let output_config = self.player.output_config;
+        let resampler = AudioResampler::for_rates(
+            sample_rate,
+            output_config.sample_rate,
+            output_config.channels,
+            self.player.low_latency,
+        )?;
+
+        let ring_buffer_len = buffer_size.store_for_samples(
+            output_config.sample_rate as usize,
+            output_config.channels as usize,
+        );
+        let (producer, consumer) = HeapRb::new(ring_buffer_len).split();
+
+        let controls = TrackControls::new();
+        self.player
+            .send_command(SourceCommand::AddTrack(TrackSource::new(
+                consumer,
+                controls.clone(),
+                output_config.sample_rate,
+            )));
+
+        Ok(Track {
+            producer,
+            resampler,
+            controls,
+            sample_rate,
+        })
+    }
+
+    /// Play `clip` on a loop until the returned [`LoopingClip`] is stopped or dropped,
+    /// for example for menu music.
+    ///
+    /// `clip` is interleaved stereo samples of `sample_rate` Hz, it's played from the start,
+    /// then the frames in `loop_frames` are repeated without a gap, so a clip can have an intro before the loop.
+    /// The samples after the end of the loop are never played.
+    ///
+    /// The loop points are sample accurate if the clip is not resampled, otherwise they are rounded
+    /// to the closest output frame.
+    ///
+    /// # Panics
+    /// If `loop_frames` is empty or goes past the end of `clip`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, Mixer};
+    /// # use std::time::Duration;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .visualization(Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    /// let mut mixer = Mixer::new(player);
+    /// mixer.play().unwrap();
+    ///
+    /// // one frame of intro, then two frames repeated
+    /// let clip = [0.1, 0.1, 0.2, 0.2, 0.3, 0.3];
+    /// let music = mixer.add_loop(&clip, 48000, 1..3).unwrap();
+    ///
+    /// clock.advance_frames(5);
+    /// let mut played = Vec::new();
+    /// mixer.player().recent_samples(&mut played);
+    /// assert_eq!(played, [0.1, 0.1, 0.2, 0.2, 0.3, 0.3, 0.2, 0.2, 0.3, 0.3]);
+    ///
+    /// // fades out and stops
+    /// music.stop();
+    /// ```
+    pub fn add_loop(
+        &mut self,
+        clip: &[T],
+        sample_rate: u32,
+        loop_frames: Range<usize>,
+    ) -> Result<LoopingClip, AudioPlayerError> {
+        assert!(
+            !loop_frames.is_empty() && loop_frames.end * 2 <= clip.len(),
+            "invalid loop points"
+        );
+        let output_config = self.player.output_config;
+        let clip = &clip[..loop_frames.end * 2];
+        let resampler = AudioResampler::for_rates(
+            sample_rate,
+            output_config.sample_rate,
+            output_config.channels,
+            self.player.low_latency,
+        )?;
+
+        let (samples, loop_start) = match resampler {
+            Some(mut resampler) => {
+                // resampled with the loop repeated twice more, then the second copy is looped,
+                // so the samples around the loop points are filtered with the ones played next to them
+                let body = &clip[loop_frames.start * 2..];
+                let mut input = clip.to_vec();
+                input.extend_from_slice(body);
+                input.extend_from_slice(body);
+                let mut resampled = resampler.resample_all(&input);
+
+                let channels = output_config.channels as usize;
+                let to_output = |frames: usize| {
+                    (frames as u64 * output_config.sample_rate as u64 / sample_rate as u64) as usize
+                        * channels
+                };
+                let loop_start = to_output(loop_frames.end);
+                let loop_len = to_output(loop_frames.len()).max(channels);
+                resampled.truncate(loop_start + loop_len);
+                (resampled, loop_start)
+            }
+            None => (clip.to_vec(), loop_frames.start * 2),
+        };
+
+        let controls = TrackControls::new();
+        self.player
+            .send_command(SourceCommand::AddTrack(TrackSource::looping(
+                samples,
+                loop_start,
+                controls.clone(),
+                output_config.sample_rate,
+            )));
+        Ok(LoopingClip { controls })
+    }
+
+    /// Start playing all the tracks, see [`AudioPlayer::play`].
+    pub fn play(&self) -> Result<(), PlayError> {
+        self.player.play()
+    }
+
+    /// Pause all the tracks, see [`AudioPlayer::pause`].
+    pub fn pause(&self) -> Result<(), PlayError> {
+        self.player.pause()
+    }
+
+    /// The player the tracks are mixed into.
+    pub fn player(&self) -> &AudioPlayer<T> {
+        &self.player
+    }
+
+    /// The player the tracks are mixed into, samples queued into it are mixed with the tracks.
+    pub fn player_mut(&mut self) -> &mut AudioPlayer<T> {
+        &mut self.player
+    }
+
+    /// Returns the player, the tracks keep playing in it.
+    pub fn into_player(self) -> AudioPlayer<T> {
+        self.player
+    }
+}
+
+/// A stream of samples played by a [`Mixer`], created with [`Mixer::add_track`].
+///
+/// The samples are interleaved stereo, same as [`AudioPlayer::queue`].
+pub struct Track<T: FloatSample> {
+    producer: HeapProd<T>,
+    resampler: Option<AudioResampler<T>>,
+    controls: TrackControls,
+    sample_rate: u32,
+}
+
+impl<T: FloatSample + SizedSample> Track<T> {
+    /// Queue samples to be played in this track.
+    ///
+    /// Samples that don't fit in the track's buffer are dropped.
+    pub fn queue(&mut self, data: &[T]) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.resample_into_producer(data, &mut self.producer);
+        } else {
+            self.producer.push_slice(data);
+        }
+    }
+
+    /// The sample rate of the samples queued into this track in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns `true` if the samples of this track are resampled before being played.
+    pub fn is_resampling(&self) -> bool {
+        self.resampler.is_some()
+    }
+
+    /// The volume, mute and pan controls of this track.
+    ///
+    /// The controls can be cloned and used from other threads than the one queueing the samples,
+    /// a UI thread for example.
+    pub fn controls(&self) -> &TrackControls {
+        &self.controls
+    }
+}
+
+/// A clip played on a loop by a [`Mixer`], created with [`Mixer::add_loop`].
+///
+/// The clip fades out and is removed when this is dropped.
+pub struct LoopingClip {
+    controls: TrackControls,
+}
+
+impl LoopingClip {
+    /// The volume, mute and pan controls of the clip.
+    pub fn controls(&self) -> &TrackControls {
+        &self.controls
+    }
+
+    /// Fade out the clip and remove it from the output, same as dropping it.
+    pub fn stop(self) {}
+}
+
+impl Drop for LoopingClip {
+    fn drop(&mut self) {
+        self.controls.stop();
+    }
+}
+
+/// Time to move from the old to the new volume of a track, to avoid clicks
+const CONTROLS_RAMP_SECONDS: f32 = 0.01;
+
+#[derive(Default)]
+struct TrackControlsInner {
+    // `f32` bits
+    volume: AtomicU32,
+    pan: AtomicU32,
+    // the position of the source around the listener, used instead of the pan when set
+    azimuth: AtomicU32,
+    distance: AtomicU32,
+    positioned: AtomicBool,
+    muted: AtomicBool,
+    // the track fades out and is removed, used to steal voices
+    stopped: AtomicBool,
+}
+
+/// The volume, mute, pan and position of a [`Track`], from [`Track::controls`].
+///
+/// The changes are applied gradually over a few milliseconds, so they can be changed while playing without clicks.
+#[derive(Clone)]
+pub struct TrackControls {
+    inner: Arc<TrackControlsInner>,
+}
+
+impl TrackControls {
+    pub(crate) fn new() -> Self {
+        let controls = Self {
+            inner: Arc::default(),
+        };
+        controls.set_volume(1.0);
+        controls
+    }
+
+    /// Set the volume of the track, `1.0` plays the samples as is, and `0.0` is silent.
+    ///
+    /// Values above `1.0` amplify the samples, negative values are treated as `0.0`.
+    pub fn set_volume(&self, volume: f32) {
+        self.inner
+            .volume
+            .store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// The volume of the track, see [`set_volume`](Self::set_volume).
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.inner.volume.load(Ordering::Relaxed))
+    }
+
+    /// Mute or unmute the track, the samples are still consumed while muted.
+    pub fn set_muted(&self, muted: bool) {
+        self.inner.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the track is muted.
+    pub fn is_muted(&self) -> bool {
+        self.inner.muted.load(Ordering::Relaxed)
+    }
+
+    /// Set the stereo balance of the track, from `-1.0` (left only) to `1.0` (right only), `0.0` is the center.
+    ///
+    /// Moving to one side lowers the volume of the other channel, the samples are not moved between channels.
+    pub fn set_pan(&self, pan: f32) {
+        self.inner
+            .pan
+            .store(pan.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// The stereo balance of the track, see [`set_pan`](Self::set_pan).
+    pub fn pan(&self) -> f32 {
+        f32::from_bits(self.inner.pan.load(Ordering::Relaxed))
+    }
+
+    /// Place the track around the listener, at `azimuth` radians from the front, positive to the right,
+    /// and `distance` from the listener, for basic spatialization of game sounds.
+    ///
+    /// The track is panned with constant power, `-3dB` on both channels in front of the listener,
+    /// and only the left or right channel at `-π/2` or `π/2`. Sources behind the listener are panned
+    /// like the ones in front. The volume is divided by the distance, in units of a reference distance:
+    /// sources closer than `1.0` play at full volume. The position is used instead of the [`pan`](Self::set_pan),
+    /// until [`clear_position`](Self::clear_position) is called, and can be updated every frame.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use dynwave::{backend::NullBackend, AudioPlayer, BufferSize, Mixer};
+    /// # use std::f32::consts::FRAC_PI_2;
+    /// let backend = NullBackend::new();
+    /// let clock = backend.clock();
+    /// let player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(backend)
+    ///     .build()
+    ///     .unwrap();
+    /// let mut mixer = Mixer::new(player);
+    /// let mut footsteps = mixer.add_track(48000, BufferSize::QuarterSecond).unwrap();
+    /// mixer.play().unwrap();
+    ///
+    /// // on the right, twice the reference distance away
+    /// footsteps.controls().set_position(FRAC_PI_2, 2.0);
+    /// footsteps.queue(&[0.5; 960 * 2]);
+    ///
+    /// let output = clock.render_frames(960);
+    /// let last = &output[output.len() - 2..];
+    /// assert!(last[0].abs() < 1e-6);
+    /// assert!((last[1] - 0.25).abs() < 1e-6);
+    /// ```
+    pub fn set_position(&self, azimuth: f32, distance: f32) {
+        self.inner
+            .azimuth
+            .store(azimuth.to_bits(), Ordering::Relaxed);
+        self.inner
+            .distance
+            .store(distance.max(0.0).to_bits(), Ordering::Relaxed);
+        self.inner.positioned.store(true, Ordering::Relaxed);
+    }
+
+    /// The azimuth and distance of the track, if set with [`set_position`](Self::set_position).
+    pub fn position(&self) -> Option<(f32, f32)> {
+        if !self.inner.positioned.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some((
+            f32::from_bits(self.inner.azimuth.load(Ordering::Relaxed)),
+            f32::from_bits(self.inner.distance.load(Ordering::Relaxed)),
+        ))
+    }
+
+    /// Stop positioning the track, it's played with its [`pan`](Self::pan) again.
+    pub fn clear_position(&self) {
+        self.inner.positioned.store(false, Ordering::Relaxed);
+    }
+
+    /// Fade out the track and remove it from the output
+    pub(crate) fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::Relaxed)
+    }
+
+    /// The gain of the left and right channels
+    fn channel_gains(&self) -> [f32; 2] {
+        if self.is_muted() || self.is_stopped() {
+            return [0.0; 2];
+        }
+        let volume = self.volume();
+        if let Some((azimuth, distance)) = self.position() {
+            // constant power panning, from 0 (left) to π/2 (right)
+            let angle = (azimuth.sin() + 1.0) * std::f32::consts::FRAC_PI_4;
+            let volume = volume / distance.max(1.0);
+            return [volume * angle.cos(), volume * angle.sin()];
+        }
+        let pan = self.pan();
+        [volume * (1.0 - pan).min(1.0), volume * (1.0 + pan).min(1.0)]
+    }
+}
+
+/// The samples of a [`LoopingClip`], the loop is the end of the samples starting at `loop_start`
+struct LoopBuffer<T> {
+    samples: Vec<T>,
+    loop_start: usize,
+    position: usize,
+}
+
+impl<T: Copy> LoopBuffer<T> {
+    fn next_sample(&mut self) -> T {
+        let sample = self.samples[self.position];
+        self.position += 1;
+        if self.position == self.samples.len() {
+            self.position = self.loop_start;
+        }
+        sample
+    }
+}
+
+/// Where the samples of a track come from
+enum TrackInput<T> {
+    Stream(HeapCons<T>),
+    Loop(LoopBuffer<T>),
+}
+
+/// The output side of a [`Track`], mixed in the output callback
+pub(crate) struct TrackSource<T> {
+    input: TrackInput<T>,
+    controls: TrackControls,
+    // the gains currently applied, moving towards the controls
+    gains: [f32; 2],
+    ramp_step: f32,
+    // played once with `play_oneshot`, and ducks the queued samples
+    oneshot: bool,
+}
+
+impl<T: FloatSample + SizedSample> TrackSource<T> {
+    pub(crate) fn new(consumer: HeapCons<T>, controls: TrackControls, sample_rate: u32) -> Self {
+        Self::with_input(TrackInput::Stream(consumer), controls, sample_rate)
+    }
+
+    fn with_input(input: TrackInput<T>, controls: TrackControls, sample_rate: u32) -> Self {
+        Self {
+            input,
+            gains: controls.channel_gains(),
+            controls,
+            ramp_step: 1.0 / (sample_rate as f32 * CONTROLS_RAMP_SECONDS),
+            oneshot: false,
+        }
+    }
+
+    /// A track playing `samples`, then repeating the ones from `loop_start` until it's stopped
+    pub(crate) fn looping(
+        samples: Vec<T>,
+        loop_start: usize,
+        controls: TrackControls,
+        sample_rate: u32,
+    ) -> Self {
+        let clip = LoopBuffer {
+            samples,
+            loop_start,
+            position: 0,
+        };
+        Self::with_input(TrackInput::Loop(clip), controls, sample_rate)
+    }
+
+    /// Mark the track as a one-shot clip, see [`AudioPlayer::duck`](crate::AudioPlayer::duck)
+    pub(crate) fn oneshot(mut self) -> Self {
+        self.oneshot = true;
+        self
+    }
+
+    /// The number of samples left to play if it's a one-shot clip, `0` otherwise
+    pub fn oneshot_remaining(&self) -> usize {
+        match &self.input {
+            TrackInput::Stream(consumer) if self.oneshot => consumer.occupied_len(),
+            _ => 0,
+        }
+    }
+
+    /// Add the next samples of the track to `output`, returns the number of samples added
+    pub fn mix_into(&mut self, output: &mut [T]) -> usize {
+        let target = self.controls.channel_gains();
+        match &mut self.input {
+            TrackInput::Stream(consumer) => mix_samples(
+                output,
+                consumer.pop_iter(),
+                &mut self.gains,
+                target,
+                self.ramp_step,
+            ),
+            TrackInput::Loop(clip) => mix_samples(
+                output,
+                std::iter::from_fn(|| Some(clip.next_sample())),
+                &mut self.gains,
+                target,
+                self.ramp_step,
+            ),
+        }
+    }
+
+    /// The [`Track`] was dropped and all of its samples were played, or it was stopped and faded out
+    pub fn is_finished(&self) -> bool {
+        let faded_out = self.controls.is_stopped() && self.gains == [0.0; 2];
+        faded_out
+            || match &self.input {
+                TrackInput::Stream(consumer) => !consumer.write_is_held() && consumer.is_empty(),
+                TrackInput::Loop(_) => false,
+            }
+    }
+}
+
+/// Add `samples` to `output` with the gains of the channels moving towards `target`,
+/// returns the number of samples added
+fn mix_samples<T: FloatSample>(
+    output: &mut [T],
+    samples: impl Iterator<Item = T>,
+    gains: &mut [f32; 2],
+    target: [f32; 2],
+    ramp_step: f32,
+) -> usize {
+    let mut mixed = 0;
+    // `output` comes first, so no sample is popped after it is full
+    for (i, (sample, value)) in output.iter_mut().zip(samples).enumerate() {
+        let channel = i % 2;
+        let gain = &mut gains[channel];
+        *gain += (target[channel] - *gain).clamp(-ramp_step, ramp_step);
+        *sample += value * T::coerce(*gain);
+        mixed += 1;
+    }
+    mixed
+}
@@ -0,0 +1,184 @@
+//! Choosing the stream configuration of a device, shared by the output and input sides
+use cpal::{
+    SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+
+use crate::{backend::OutputConfig, error::AudioPlayerError, ResamplingAlgorithm};
+
+/// The result of the negotiation of the output, from [`AudioPlayerBuilder::negotiate`](crate::AudioPlayerBuilder::negotiate)
+/// or [`AudioPlayer::negotiate`](crate::AudioPlayer::negotiate), without opening a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiation {
+    /// The name of the output device, or `None` if the backend doesn't play on a device.
+    pub device: Option<String>,
+    /// The output configuration that would be used.
+    pub output: OutputConfig,
+    /// The queued samples would be resampled to the output sample rate.
+    pub resampling: bool,
+    /// The queued samples would be converted to the output sample format.
+    pub format_conversion: bool,
+}
+
+/// The `ConfigPreference` enum controls which device configuration is chosen when none of them
+/// matches the sample rate and sample format of the player exactly.
+///
+/// Only configurations with the requested number of channels are considered, and except for
+/// [`ConfigPreference::PreferLowestLatency`], a configuration with the requested sample rate and format
+/// is always chosen if the device has one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigPreference {
+    #[default]
+    /// Prefer a configuration with the same sample rate, and convert the sample format.
+    ///
+    /// This avoids resampling, which is more expensive and less transparent than the format conversion.
+    ///
+    /// This is the default.
+    PreferNativeRate,
+    /// Prefer a configuration with the same sample format, and resample to another sample rate.
+    PreferFormat,
+    /// Prefer the configuration that supports the smallest callback size,
+    /// then the one with the same sample rate.
+    ///
+    /// Useful with [`CallbackSize::Minimum`](crate::CallbackSize::Minimum).
+    PreferLowestLatency,
+}
+
+impl ConfigPreference {
+    /// The score of the config `c`, higher is better
+    fn score(
+        &self,
+        c: &SupportedStreamConfigRange,
+        sample_rate: SampleRate,
+        sample_format: SampleFormat,
+    ) -> u32 {
+        let rate_match =
+            (c.min_sample_rate() <= sample_rate && c.max_sample_rate() >= sample_rate) as u32;
+        let format_match = (c.sample_format() == sample_format) as u32;
+
+        match self {
+            Self::PreferNativeRate => rate_match * 3 + format_match * 2,
+            Self::PreferFormat => format_match * 3 + rate_match * 2,
+            Self::PreferLowestLatency => {
+                // the callback size dominates, the matches only break ties
+                let min_frames = match c.buffer_size() {
+                    SupportedBufferSize::Range { min, .. } => (*min).min(u16::MAX as u32),
+                    SupportedBufferSize::Unknown => u16::MAX as u32,
+                };
+                (u16::MAX as u32 - min_frames) * 8 + rate_match * 3 + format_match * 2
+            }
+        }
+    }
+}
+
+/// Fails if the output needs resampling while the player requires its exact sample rate,
+/// or when `algorithm` isn't available in this build
+pub(crate) fn check_exact_rate(
+    exact: bool,
+    sample_rate: u32,
+    output: &OutputConfig,
+    algorithm: ResamplingAlgorithm,
+) -> Result<(), AudioPlayerError> {
+    if output.sample_rate == sample_rate {
+        return Ok(());
+    }
+    if exact {
+        return Err(AudioPlayerError::SampleRateNotSupported(sample_rate));
+    }
+    if !algorithm.is_available() {
+        return Err(AudioPlayerError::ResamplingUnavailable {
+            from: sample_rate,
+            to: output.sample_rate,
+        });
+    }
+    Ok(())
+}
+
+/// The configurations chosen first when the device doesn't support the requested sample rate
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Preferred {
+    /// Prefer the configurations supporting this rate
+    pub rate: Option<u32>,
+    /// The rate and format of the default configuration of the device, preferred over everything else
+    pub default: Option<(SampleRate, SampleFormat)>,
+}
+
+/// Choose the best configuration from `configs` for samples with `sample_rate`, `sample_format` and `channels`.
+///
+/// If no configuration supports `sample_rate`, the one matching `preferred.default` is chosen first,
+/// then the ones supporting `preferred.rate`, with these rates.
+///
+/// If no configuration has the requested number of channels, the `default_config` is returned,
+/// the caller should check the number of channels of the result.
+pub(crate) fn choose_config(
+    configs: &[SupportedStreamConfigRange],
+    default_config: impl FnOnce() -> Result<SupportedStreamConfig, AudioPlayerError>,
+    sample_rate: u32,
+    sample_format: SampleFormat,
+    channels: u16,
+    preference: ConfigPreference,
+    preferred: Preferred,
+) -> Result<SupportedStreamConfig, AudioPlayerError> {
+    let sample_rate = SampleRate(sample_rate);
+    let supports = |c: &SupportedStreamConfigRange, rate: SampleRate| {
+        c.min_sample_rate() <= rate && c.max_sample_rate() >= rate
+    };
+    let supports_preferred = |c: &SupportedStreamConfigRange| {
+        preferred
+            .rate
+            .is_some_and(|rate| supports(c, SampleRate(rate)))
+    };
+    // the default configuration only matters when resampling is needed anyway,
+    // the other configurations may go through another resampler of the system
+    let resampling = !configs
+        .iter()
+        .any(|c| c.channels() == channels && supports(c, sample_rate));
+    let is_default = |c: &SupportedStreamConfigRange| {
+        resampling
+            && preferred
+                .default
+                .is_some_and(|(rate, format)| c.sample_format() == format && supports(c, rate))
+    };
+
+    if preference != ConfigPreference::PreferLowestLatency {
+        for c in configs {
+            // must have the same channels and format
+            // (almost all? devices will have at least one configuration with these)
+            if c.channels() == channels
+                && c.sample_format() == sample_format
+                && c.min_sample_rate() <= sample_rate
+                && c.max_sample_rate() >= sample_rate
+            {
+                return Ok((*c).with_sample_rate(sample_rate));
+            }
+        }
+    }
+
+    // second time, try to find something that has the same channels, but format and sample range can
+    // be different, match with highest score
+    let mut max_match = None;
+    let mut matched_conf = None;
+    for c in configs.iter().filter(|c| c.channels() == channels) {
+        // the default configuration comes first, and the preferred rate only breaks ties
+        let curr_match = (
+            is_default(c),
+            preference.score(c, sample_rate, sample_format) * 2 + supports_preferred(c) as u32,
+        );
+        if max_match.map_or(true, |max_match| curr_match > max_match) {
+            max_match = Some(curr_match);
+            matched_conf = Some(c);
+        }
+    }
+
+    match matched_conf {
+        Some(conf) => Ok(conf
+            .try_with_sample_rate(sample_rate)
+            .or_else(|| {
+                let (rate, _) = preferred.default.filter(|_| is_default(conf))?;
+                conf.try_with_sample_rate(rate)
+            })
+            .or_else(|| conf.try_with_sample_rate(SampleRate(preferred.rate?)))
+            .unwrap_or_else(|| conf.with_max_sample_rate())),
+        None => default_config(),
+    }
+}
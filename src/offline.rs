@@ -0,0 +1,114 @@
+use cpal::{FromSample, SizedSample};
+
+use crate::{backend::VirtualClock, sample::FloatSample, AudioPlayer};
+
+/// A player rendering its output offline without any device, as fast as it's pulled,
+/// created with [`AudioPlayerBuilder::build_offline`](crate::AudioPlayerBuilder::build_offline).
+///
+/// The output goes through the same path as live playback, resampling, effects, volume, dither and the
+/// conversion to the output sample type, so it can export the audio of a session, or feed benchmarks and
+/// regression tests. The player is [`deterministic`](crate::AudioPlayerBuilder::deterministic),
+/// so the same input always renders the same output.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::AudioPlayer;
+/// let mut renderer = AudioPlayer::<f32>::builder(48000).build_offline(48000).unwrap();
+///
+/// let mut output = Vec::<i16>::new();
+/// renderer.render(&[0.5; 4800 * 2], &mut output);
+/// renderer.finish(&mut output);
+/// assert_eq!(output, [16384; 4800 * 2]);
+/// ```
+pub struct OfflineRenderer<T: FloatSample> {
+    player: AudioPlayer<T>,
+    clock: VirtualClock<T>,
+}
+
+impl<T: FloatSample + SizedSample + Send + 'static> OfflineRenderer<T>
+where
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    pub(crate) fn new(player: AudioPlayer<T>, clock: VirtualClock<T>) -> Self {
+        Self { player, clock }
+    }
+
+    /// The player rendered, to inspect its state.
+    pub fn player(&self) -> &AudioPlayer<T> {
+        &self.player
+    }
+
+    /// The player rendered, to queue samples or change its settings between the renders.
+    ///
+    /// The samples that don't fit in the buffer are dropped, use [`render`](Self::render)
+    /// to queue any amount of samples.
+    pub fn player_mut(&mut self) -> &mut AudioPlayer<T> {
+        &mut self.player
+    }
+
+    /// Queue `data` and append its rendered output to `output`.
+    ///
+    /// The samples are queued in chunks that fit in the buffer, rendering the buffer in between,
+    /// the samples kept by the resampler are rendered by the next calls or by [`finish`](Self::finish).
+    pub fn render<S>(&mut self, data: &[T], output: &mut Vec<S>)
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let input_channels = self.player.input_layout.channels();
+        let channels = self.player.output_channels().max(1) as usize;
+        // half the buffer, so a chunk always fits after the previous one was rendered
+        let chunk_frames = (self.player.buffer_capacity() / channels / 2) as u64
+            * self.player.sample_rate() as u64
+            / self.player.output_sample_rate() as u64;
+        let chunk = chunk_frames.max(1) as usize * input_channels;
+        for samples in data.chunks(chunk) {
+            self.player.queue(samples);
+            self.render_buffered(output);
+        }
+    }
+
+    /// Render the buffered samples into `output`, up to its length, returns the number of samples rendered.
+    ///
+    /// Only whole frames are rendered, the rest of `output` is left as it is.
+    pub fn render_into<S>(&mut self, output: &mut [S]) -> usize
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let len = output.len().min(self.player.buffered_samples());
+        if len == 0 {
+            return 0;
+        }
+        // not real-time, the render takes as long as it needs
+        self.clock.render_offline(&mut output[..len])
+    }
+
+    /// Render the samples kept by the resampler, and the rest of the buffer, appending them to `output`.
+    ///
+    /// The resampler starts again from silence for the samples queued after.
+    pub fn finish<S>(&mut self, output: &mut Vec<S>)
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        self.player.drain_resampler();
+        self.render_buffered(output);
+    }
+
+    fn render_buffered<S>(&mut self, output: &mut Vec<S>)
+    where
+        S: SizedSample + FromSample<T> + 'static,
+    {
+        let start = output.len();
+        output.resize(start + self.player.buffered_samples(), S::EQUILIBRIUM);
+        let rendered = self.render_into(&mut output[start..]);
+        output.truncate(start + rendered);
+    }
+}
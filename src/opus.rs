@@ -0,0 +1,146 @@
+//! Opus encoding of the network streams, see [`UdpBackend::opus`](crate::backend::UdpBackend::opus)
+//! and [`WebSocketBackend::opus`](crate::backend::WebSocketBackend::opus)
+
+#[cfg(feature = "udp-receiver")]
+use audiopus::{coder::Decoder, packet::Packet, MutSignals};
+use audiopus::{coder::Encoder, Application, Bitrate, Channels, SampleRate};
+use cpal::{FromSample, SampleFormat};
+
+use crate::{
+    backend::{OutputConfig, OutputRequest},
+    error::AudioPlayerError,
+    sample::FloatSample,
+};
+
+/// The sample rate of the Opus streams, the only one the RTP payload format allows (RFC 7587)
+pub(crate) const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// The largest packet of one frame, recommended by the Opus documentation
+const MAX_PACKET_LEN: usize = 4000;
+
+/// The longest frame of a packet, 120ms
+#[cfg(feature = "udp-receiver")]
+const MAX_FRAME_SAMPLES: usize = OPUS_SAMPLE_RATE as usize * 120 / 1000;
+
+fn channels(channels: u16) -> Result<Channels, AudioPlayerError> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        _ => Err(AudioPlayerError::StreamConfigNotSupported),
+    }
+}
+
+/// The settings of the Opus encoder of a network stream
+#[derive(Clone, Copy)]
+pub(crate) struct OpusSettings {
+    pub bitrate: Option<u32>,
+}
+
+/// The configuration of the Opus streams, at 48000Hz, in mono or stereo,
+/// fails if another `sample_rate` was requested
+pub(crate) fn opus_config(
+    sample_rate: Option<u32>,
+    request: &OutputRequest,
+) -> Result<OutputConfig, AudioPlayerError> {
+    match sample_rate {
+        Some(sample_rate) if sample_rate != OPUS_SAMPLE_RATE => {
+            Err(AudioPlayerError::SampleRateNotSupported(sample_rate))
+        }
+        _ => Ok(OutputConfig {
+            sample_rate: OPUS_SAMPLE_RATE,
+            sample_format: SampleFormat::F32,
+            channels: request.channels.clamp(1, 2),
+        }),
+    }
+}
+
+fn error(err: audiopus::Error) -> AudioPlayerError {
+    AudioPlayerError::Opus(err.to_string())
+}
+
+/// Encodes the frames of the output into Opus packets
+pub(crate) struct OpusEncoder {
+    encoder: Encoder,
+    samples: Vec<f32>,
+    packet: Vec<u8>,
+}
+
+impl OpusEncoder {
+    /// Creates an encoder of `channels` channels at [`OPUS_SAMPLE_RATE`], with `bitrate` bits per second
+    /// or the default of libopus
+    pub fn new(channels: u16, bitrate: Option<u32>) -> Result<Self, AudioPlayerError> {
+        let mut encoder = Encoder::new(
+            SampleRate::Hz48000,
+            self::channels(channels)?,
+            Application::Audio,
+        )
+        .map_err(error)?;
+        if let Some(bitrate) = bitrate {
+            let bitrate = Bitrate::BitsPerSecond(bitrate.min(i32::MAX as u32) as i32);
+            encoder.set_bitrate(bitrate).map_err(error)?;
+        }
+        Ok(Self {
+            encoder,
+            samples: Vec::new(),
+            packet: vec![0; MAX_PACKET_LEN],
+        })
+    }
+
+    /// Encode one frame of interleaved `samples`, of 2.5, 5, 10, 20, 40 or 60ms, into a packet
+    pub fn encode<T>(&mut self, samples: &[T]) -> Result<&[u8], audiopus::Error>
+    where
+        T: FloatSample,
+        f32: FromSample<T>,
+    {
+        self.samples.clear();
+        self.samples
+            .extend(samples.iter().map(|&sample| f32::from_sample_(sample)));
+        let len = self.encoder.encode_float(&self.samples, &mut self.packet)?;
+        Ok(&self.packet[..len])
+    }
+}
+
+/// Decodes the Opus packets of a stream, and conceals the lost ones
+#[cfg(feature = "udp-receiver")]
+pub(crate) struct OpusDecoder {
+    decoder: Decoder,
+    channels: usize,
+    samples: Vec<f32>,
+    // the frames of the last packet, the length of the concealment of a lost packet
+    last_frames: usize,
+}
+
+#[cfg(feature = "udp-receiver")]
+impl OpusDecoder {
+    pub fn new(channels: u16) -> Result<Self, AudioPlayerError> {
+        Ok(Self {
+            decoder: Decoder::new(SampleRate::Hz48000, self::channels(channels)?).map_err(error)?,
+            channels: channels as usize,
+            samples: vec![0.0; MAX_FRAME_SAMPLES * channels as usize],
+            last_frames: 0,
+        })
+    }
+
+    /// Decode `packet`, or conceal a lost packet if `None`, returns the interleaved samples,
+    /// empty if the packet is invalid
+    pub fn decode(&mut self, packet: Option<&[u8]>) -> &[f32] {
+        let frames = match packet {
+            Some(packet) => Packet::try_from(packet).ok().and_then(|packet| {
+                let output = MutSignals::try_from(&mut self.samples[..]).ok()?;
+                self.decoder.decode_float(Some(packet), output, false).ok()
+            }),
+            None if self.last_frames == 0 => None,
+            None => {
+                let len = self.last_frames * self.channels;
+                MutSignals::try_from(&mut self.samples[..len])
+                    .ok()
+                    .and_then(|output| self.decoder.decode_float(None, output, false).ok())
+            }
+        };
+        let frames = frames.unwrap_or(0);
+        if packet.is_some() && frames > 0 {
+            self.last_frames = frames;
+        }
+        &self.samples[..frames * self.channels]
+    }
+}
@@ -0,0 +1,204 @@
+use std::io;
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use crate::sample::FloatSample;
+use crate::{error::AudioPlayerError, AudioPlayer};
+
+/// Queue raw interleaved PCM bytes into an [`AudioPlayer`] with [`std::io::Write`].
+///
+/// The bytes are little-endian samples of the declared [`SampleFormat`], converted to the sample type
+/// of the player. Writes don't need to be aligned to samples, the bytes of an incomplete sample
+/// are kept until the next write.
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use dynwave::{backend::NullBackend, AudioPlayer, PcmWriter};
+/// # use cpal::SampleFormat;
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .build()
+///     .unwrap();
+///
+/// let mut writer = PcmWriter::new(&mut player, SampleFormat::I16).unwrap();
+/// let bytes = [0x00, 0x40, 0x00, 0xC0]; // 0.5 and -0.5
+/// writer.write_all(&bytes[..3]).unwrap();
+/// writer.write_all(&bytes[3..]).unwrap();
+///
+/// assert_eq!(player.snapshot().buffered, [0.5, -0.5]);
+/// ```
+pub struct PcmWriter<'a, T: FloatSample + SizedSample + Send + 'static> {
+    player: &'a mut AudioPlayer<T>,
+    format: SampleFormat,
+    // the bytes of an incomplete sample from the last write
+    partial: Vec<u8>,
+    converted: Vec<T>,
+}
+
+impl<'a, T> PcmWriter<'a, T>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// Creates a writer queueing bytes of `format` samples into `player`.
+    ///
+    /// Returns [`AudioPlayerError::UnsupportedInputFormat`] if the format can't be converted.
+    pub fn new(
+        player: &'a mut AudioPlayer<T>,
+        format: SampleFormat,
+    ) -> Result<Self, AudioPlayerError> {
+        match format {
+            SampleFormat::I8
+            | SampleFormat::I16
+            | SampleFormat::I32
+            | SampleFormat::I64
+            | SampleFormat::U8
+            | SampleFormat::U16
+            | SampleFormat::U32
+            | SampleFormat::U64
+            | SampleFormat::F32
+            | SampleFormat::F64 => Ok(Self {
+                player,
+                format,
+                partial: Vec::with_capacity(format.sample_size()),
+                converted: Vec::new(),
+            }),
+            format => Err(AudioPlayerError::UnsupportedInputFormat(format)),
+        }
+    }
+
+    /// The sample format of the written bytes.
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    fn convert(&mut self, bytes: &[u8]) {
+        // the format is checked in `new`
+        let _ = decode_bytes(bytes, self.format, &mut self.converted);
+    }
+}
+
+impl<T> io::Write for PcmWriter<'_, T>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let sample_size = self.format.sample_size();
+        self.converted.clear();
+
+        // complete the sample left from the last write
+        let mut bytes = buf;
+        if !self.partial.is_empty() {
+            let needed = (sample_size - self.partial.len()).min(bytes.len());
+            self.partial.extend_from_slice(&bytes[..needed]);
+            bytes = &bytes[needed..];
+            if self.partial.len() < sample_size {
+                return Ok(buf.len());
+            }
+            let partial = std::mem::take(&mut self.partial);
+            self.convert(&partial);
+            self.partial = partial;
+            self.partial.clear();
+        }
+
+        let whole = bytes.len() - bytes.len() % sample_size;
+        self.convert(&bytes[..whole]);
+        self.partial.extend_from_slice(&bytes[whole..]);
+
+        self.player.queue(&self.converted);
+        Ok(buf.len())
+    }
+
+    /// The bytes of an incomplete sample are kept, as they can't be played alone.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert the little-endian samples of `format` in `bytes`, and append them to `output`.
+///
+/// The bytes of an incomplete sample at the end are ignored.
+pub(crate) fn decode_bytes<T>(
+    bytes: &[u8],
+    format: SampleFormat,
+    output: &mut Vec<T>,
+) -> Result<(), AudioPlayerError>
+where
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+{
+    match format {
+        SampleFormat::I8 => decode(bytes, output, i8::from_le_bytes),
+        SampleFormat::I16 => decode(bytes, output, i16::from_le_bytes),
+        SampleFormat::I32 => decode(bytes, output, i32::from_le_bytes),
+        SampleFormat::I64 => decode(bytes, output, i64::from_le_bytes),
+        SampleFormat::U8 => decode(bytes, output, u8::from_le_bytes),
+        SampleFormat::U16 => decode(bytes, output, u16::from_le_bytes),
+        SampleFormat::U32 => decode(bytes, output, u32::from_le_bytes),
+        SampleFormat::U64 => decode(bytes, output, u64::from_le_bytes),
+        SampleFormat::F32 => decode(bytes, output, f32::from_le_bytes),
+        SampleFormat::F64 => decode(bytes, output, f64::from_le_bytes),
+        format => return Err(AudioPlayerError::UnsupportedInputFormat(format)),
+    }
+    Ok(())
+}
+
+/// Convert the little-endian samples of `N` bytes in `bytes`, and append them to `output`
+fn decode<S, T, const N: usize>(bytes: &[u8], output: &mut Vec<T>, from_le_bytes: fn([u8; N]) -> S)
+where
+    T: FromSample<S>,
+{
+    output.extend(
+        bytes
+            .chunks_exact(N)
+            .map(|sample| T::from_sample_(from_le_bytes(sample.try_into().unwrap()))),
+    );
+}
@@ -0,0 +1,67 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::state::PlayerShared;
+
+/// A handle to a chunk queued with [`AudioPlayer::queue_tracked`](crate::AudioPlayer::queue_tracked),
+/// completed once the device played the last frame of the chunk.
+///
+/// The position of the chunk is computed when it's queued, from the frames written before it,
+/// and compared with the frames consumed by the output minus the
+/// [`device_latency`](crate::AudioPlayer::device_latency), so it completes when the chunk is heard,
+/// not when it leaves the buffer.
+///
+/// The token doesn't keep the player alive, it can be polled every game frame with
+/// [`is_played`](Self::is_played), or waited on from another thread with [`wait`](Self::wait).
+/// If the chunk is removed before it's played, by [`clear`](crate::AudioPlayer::clear) for example,
+/// the token completes once the output played as many frames as were before the end of the chunk.
+#[derive(Clone)]
+pub struct PlaybackToken {
+    shared: Arc<PlayerShared>,
+    end_frame: u64,
+    sample_rate: u32,
+}
+
+impl PlaybackToken {
+    pub(crate) fn new(shared: Arc<PlayerShared>, end_frame: u64, sample_rate: u32) -> Self {
+        Self {
+            shared,
+            end_frame,
+            sample_rate,
+        }
+    }
+
+    /// The output frames played by the device
+    fn played_frames(&self) -> u64 {
+        self.shared
+            .frames_consumed()
+            .saturating_sub(self.shared.device_latency())
+    }
+
+    /// Whether the last frame of the chunk was played.
+    pub fn is_played(&self) -> bool {
+        self.played_frames() >= self.end_frame
+    }
+
+    /// The time left until the chunk is played, at the current playback speed,
+    /// [`Duration::ZERO`] once it's played.
+    pub fn remaining(&self) -> Duration {
+        let frames = self.end_frame.saturating_sub(self.played_frames());
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Block until the chunk is played or `timeout` passes, returns whether the chunk was played.
+    ///
+    /// The thread is woken by the output when it consumes samples, so it doesn't need to poll.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        self.shared.wait_for_space(|| self.is_played(), timeout)
+    }
+}
+
+impl std::fmt::Debug for PlaybackToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlaybackToken")
+            .field("end_frame", &self.end_frame)
+            .field("played", &self.is_played())
+            .finish()
+    }
+}
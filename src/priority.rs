@@ -0,0 +1,83 @@
+use crate::error::AudioPlayerError;
+
+/// Raise the priority of the current thread, to be scheduled before the other threads of the system.
+///
+/// Use it on the thread calling [`AudioPlayer::queue`](crate::AudioPlayer::queue), usually the thread
+/// running the emulation, so it's not delayed by other processes and doesn't cause underruns,
+/// this happens mostly on Linux without real-time scheduling.
+///
+/// - On Unix, the thread uses the `SCHED_FIFO` real-time scheduling. On Linux, if it's not allowed
+///   (it needs `CAP_SYS_NICE` or an `rtprio` limit), the nice value of the thread is lowered instead.
+/// - On Windows, the thread gets the time critical priority.
+///
+/// Returns [`AudioPlayerError::Io`] if the system refused both, or on other platforms.
+/// The thread keeps running with its priority in that case, so the error can be ignored.
+///
+/// # Example
+/// ```rust
+/// if let Err(err) = dynwave::promote_current_thread() {
+///     eprintln!("running without real-time priority: {err}");
+/// }
+/// ```
+pub fn promote_current_thread() -> Result<(), AudioPlayerError> {
+    imp::promote_current_thread().map_err(AudioPlayerError::Io)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+
+    /// The nice value used if the real-time scheduling is not allowed
+    #[cfg(target_os = "linux")]
+    const NICE: libc::c_int = -10;
+
+    pub fn promote_current_thread() -> io::Result<()> {
+        // SAFETY: only reads and sets the scheduling of the current thread
+        unsafe {
+            let min = libc::sched_get_priority_min(libc::SCHED_FIFO);
+            let max = libc::sched_get_priority_max(libc::SCHED_FIFO);
+            // above the normal threads, below the audio servers and the kernel
+            let mut param: libc::sched_param = std::mem::zeroed();
+            param.sched_priority = min + (max - min) / 4;
+            let error = libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param);
+            if error == 0 {
+                return Ok(());
+            }
+            // on Linux, this sets the nice value of the current thread only
+            #[cfg(target_os = "linux")]
+            if libc::setpriority(libc::PRIO_PROCESS, 0, NICE) == 0 {
+                return Ok(());
+            }
+            Err(io::Error::from_raw_os_error(error))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::io;
+
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    pub fn promote_current_thread() -> io::Result<()> {
+        // SAFETY: the pseudo handle of the current thread is always valid
+        if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::io;
+
+    pub fn promote_current_thread() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "thread priority is not supported on this platform",
+        ))
+    }
+}
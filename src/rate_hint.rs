@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+/// Time measured for each estimate, the timing jitter of the queues is small compared to it
+const WINDOW: Duration = Duration::from_secs(10);
+/// The measurement restarts if nothing was queued for this long, i.e. the application was paused
+const MAX_GAP: Duration = Duration::from_millis(500);
+/// The weight of a new window in the estimate, so it follows slow changes of the application speed
+const SMOOTHING: f64 = 0.25;
+
+/// Estimates how many output frames the application produces per second,
+/// from the frames it queues and the times of the queues
+pub(crate) struct RateHint {
+    // the first queue of the window
+    start: Option<Instant>,
+    // the time of the last queue
+    last: Option<Instant>,
+    // output frames queued since the start of the window, before the last queue
+    frames: u64,
+    // the frames of the last queue, they were produced after its time
+    last_frames: u64,
+    estimate: Option<f64>,
+}
+
+impl RateHint {
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            last: None,
+            frames: 0,
+            last_frames: 0,
+            estimate: None,
+        }
+    }
+
+    /// Count `frames` output frames queued at `time`
+    pub fn queued(&mut self, frames: usize, time: Instant) {
+        let gap = self.last.and_then(|last| time.checked_duration_since(last));
+        if gap.map_or(true, |gap| gap > MAX_GAP) {
+            self.restart();
+        }
+
+        // the frames of a queue are counted at the next one, so the window spans whole periods of the producer
+        let start = *self.start.get_or_insert(time);
+        self.frames += self.last_frames;
+        self.last_frames = frames as u64;
+        self.last = Some(time);
+
+        let elapsed = time.saturating_duration_since(start);
+        if elapsed >= WINDOW {
+            let rate = self.frames as f64 / elapsed.as_secs_f64();
+            self.estimate = Some(match self.estimate {
+                Some(estimate) => estimate + (rate - estimate) * SMOOTHING,
+                None => rate,
+            });
+            self.start = Some(time);
+            self.frames = 0;
+        }
+    }
+
+    /// Start a new window, the frames queued until now don't count, for example after the input rate changed
+    pub fn restart(&mut self) {
+        self.start = None;
+        self.last = None;
+        self.frames = 0;
+        self.last_frames = 0;
+    }
+
+    /// Forget the estimate, when the frames queued from now on are produced at another rate
+    pub fn reset(&mut self) {
+        self.restart();
+        self.estimate = None;
+    }
+
+    /// The output frames produced per second, `None` until a window was measured
+    pub fn estimate(&self) -> Option<f64> {
+        self.estimate
+    }
+}
@@ -0,0 +1,339 @@
+//! Receives the audio streamed by a `UdpBackend`, to play it on another machine
+
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use cpal::{FromSample, SizedSample};
+
+#[cfg(feature = "opus")]
+use crate::opus::OpusDecoder;
+use crate::sample::FloatSample;
+use crate::{error::AudioPlayerError, AudioPlayer};
+
+/// The size of the RTP header, without CSRC or extensions
+const RTP_HEADER_LEN: usize = 12;
+
+/// The largest UDP payload
+const MAX_PACKET_LEN: usize = 65536;
+
+/// The default number of packets waiting for a missing one before it's considered lost
+const DEFAULT_REORDER_WINDOW: usize = 4;
+
+/// The most lost packets concealed at once, the decoder fades to silence after a few of them anyway
+#[cfg(feature = "opus")]
+const MAX_CONCEALED_PACKETS: u64 = 5;
+
+/// Receives 16-bit big-endian PCM (`L16`) over UDP, like the packets sent by a
+/// [`UdpBackend`](crate::backend::UdpBackend), and queues it into a player.
+///
+/// By default, the packets are RTP packets (RFC 3550): they are put back in order by their sequence number,
+/// waiting for at most [`reorder_window`](Self::reorder_window) packets for a missing one,
+/// after that it's counted as [`lost`](Self::lost) and skipped. With [`raw`](Self::raw),
+/// the packets only have the samples, and are queued in the order they arrive.
+/// With the `opus` feature, the packets can be Opus packets instead, see [`opus`](Self::opus).
+///
+/// The stream has the sample rate of the player, mono streams are played on both channels,
+/// and only the first two channels of streams with more channels are played.
+/// The packets arrive with the jitter of the network, so the player should use a
+/// [`JitterBuffer`](crate::JitterBuffer), which also keeps the latency bounded when the sender plays
+/// a bit faster than the device of the receiver.
+///
+/// Only available with the `udp-receiver` feature.
+///
+/// # Example
+/// ```rust
+/// # use std::{net::UdpSocket, thread, time::Duration};
+/// # use dynwave::{backend::NullBackend, AudioPlayer, JitterBuffer, UdpReceiver};
+/// let mut receiver = UdpReceiver::<f32>::bind("127.0.0.1:0", 2).unwrap();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .jitter_buffer(JitterBuffer::new(Duration::from_millis(20), Duration::from_millis(200)))
+///     .build()
+///     .unwrap();
+///
+/// // RTP packets of one stereo frame, the third one arrives before the second
+/// let packet = |sequence: u16| {
+///     let mut packet = vec![0x80, 96];
+///     packet.extend_from_slice(&sequence.to_be_bytes());
+///     packet.extend_from_slice(&[0; 8]);
+///     packet.extend_from_slice(&[0x40, 0, 0x40, 0]);
+///     packet
+/// };
+/// let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// sender.connect(receiver.local_addr().unwrap()).unwrap();
+///
+/// sender.send(&packet(0)).unwrap();
+/// sender.send(&packet(2)).unwrap();
+/// thread::sleep(Duration::from_millis(50));
+/// // waiting for the second packet
+/// assert_eq!(receiver.queue_into(&mut player).unwrap(), 2);
+///
+/// sender.send(&packet(1)).unwrap();
+/// thread::sleep(Duration::from_millis(50));
+/// assert_eq!(receiver.queue_into(&mut player).unwrap(), 4);
+/// assert_eq!(player.snapshot().buffered, [0.5; 6]);
+/// ```
+pub struct UdpReceiver<T> {
+    socket: UdpSocket,
+    channels: u16,
+    rtp: bool,
+    reorder_window: usize,
+    // the extended sequence number of the next packet to queue, unknown until the first packet
+    next_sequence: Option<u64>,
+    // the payloads of the packets that arrived before the ones preceding them
+    pending: BTreeMap<u64, Vec<u8>>,
+    lost: u64,
+    late: u64,
+    packet: Vec<u8>,
+    converted: Vec<T>,
+    #[cfg(feature = "opus")]
+    decoder: Option<OpusDecoder>,
+}
+
+impl<T> UdpReceiver<T>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i16>,
+    i8: FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    i64: FromSample<T>,
+    u8: FromSample<T>,
+    u16: FromSample<T>,
+    u32: FromSample<T>,
+    u64: FromSample<T>,
+    f32: FromSample<T>,
+    f64: FromSample<T>,
+{
+    /// Listen on `address` for a stream of `channels` interleaved channels.
+    ///
+    /// Returns [`AudioPlayerError::Io`] if the socket couldn't be bound.
+    pub fn bind(address: impl ToSocketAddrs, channels: u16) -> Result<Self, AudioPlayerError> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            channels: channels.max(1),
+            rtp: true,
+            reorder_window: DEFAULT_REORDER_WINDOW,
+            next_sequence: None,
+            pending: BTreeMap::new(),
+            lost: 0,
+            late: 0,
+            packet: vec![0; MAX_PACKET_LEN],
+            converted: Vec::new(),
+            #[cfg(feature = "opus")]
+            decoder: None,
+        })
+    }
+
+    /// Receive packets with only the samples, without the RTP header,
+    /// like the ones sent by [`UdpBackend::raw`](crate::backend::UdpBackend::raw).
+    pub fn raw(mut self) -> Self {
+        self.rtp = false;
+        self
+    }
+
+    /// Receive Opus packets, like the ones sent by [`UdpBackend::opus`](crate::backend::UdpBackend::opus),
+    /// instead of `L16`. The lost packets are concealed by the decoder, which extrapolates the previous ones.
+    ///
+    /// Opus streams are decoded at 48000Hz, so the player should have this sample rate.
+    /// Returns [`AudioPlayerError::StreamConfigNotSupported`] if the stream isn't mono or stereo,
+    /// or [`AudioPlayerError::Opus`] if the decoder couldn't be created.
+    ///
+    /// Only available with the `opus` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::{thread, time::Duration};
+    /// # use dynwave::{AudioPlayer, backend::{NullBackend, Pacing, UdpBackend}, UdpReceiver};
+    /// let mut receiver = UdpReceiver::<f32>::bind("127.0.0.1:0", 2)
+    ///     .unwrap()
+    ///     .opus()
+    ///     .unwrap();
+    /// let mut sender = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(UdpBackend::new(receiver.local_addr().unwrap()).opus(None).pacing(Pacing::AsFastAsPossible))
+    ///     .build()
+    ///     .unwrap();
+    /// let mut player = AudioPlayer::<f32>::builder(48000)
+    ///     .backend(NullBackend::new())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// sender.play().unwrap();
+    /// sender.queue(&[0.5; 240 * 2]);
+    /// thread::sleep(Duration::from_millis(50));
+    /// assert_eq!(receiver.queue_into(&mut player).unwrap(), 240 * 2);
+    /// ```
+    #[cfg(feature = "opus")]
+    pub fn opus(mut self) -> Result<Self, AudioPlayerError> {
+        self.decoder = Some(OpusDecoder::new(self.channels)?);
+        Ok(self)
+    }
+
+    /// Wait for a missing packet while at most `packets` later packets arrived, the default is 4.
+    ///
+    /// A larger window handles networks reordering the packets more, but delays the audio more
+    /// when a packet is lost.
+    pub fn reorder_window(mut self, packets: usize) -> Self {
+        self.reorder_window = packets;
+        self
+    }
+
+    /// The address the receiver listens on, to send the stream to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The number of packets skipped because they didn't arrive within the [`reorder_window`](Self::reorder_window).
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// The number of packets that arrived after they were counted as lost, or twice, they are dropped.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    /// Queue the samples of the packets received since the last call into `player`,
+    /// returns the number of samples queued.
+    ///
+    /// Doesn't block, call it regularly, for example once per frame of the application.
+    /// Returns [`AudioPlayerError::Io`] if receiving failed.
+    pub fn queue_into(&mut self, player: &mut AudioPlayer<T>) -> Result<usize, AudioPlayerError> {
+        self.converted.clear();
+        loop {
+            let len = match self.socket.recv(&mut self.packet) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                // the ICMP errors of packets sent from this socket, on some platforms
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if self.rtp {
+                self.receive_rtp(len);
+            } else {
+                let packet = std::mem::take(&mut self.packet);
+                self.convert(&packet[..len]);
+                self.packet = packet;
+            }
+        }
+        player.queue(&self.converted);
+        Ok(self.converted.len())
+    }
+
+    /// Put the RTP packet of `len` bytes in order, and convert the packets that are ready
+    fn receive_rtp(&mut self, len: usize) {
+        let Some((sequence, payload)) = parse_rtp(&self.packet[..len]) else {
+            return;
+        };
+        let payload = payload.to_vec();
+        let next = *self.next_sequence.get_or_insert(sequence as u64);
+        // the sequence numbers wrap around, so only the distance from the next one matters
+        let distance = sequence.wrapping_sub(next as u16) as i16;
+        if distance < 0 || self.pending.contains_key(&(next + distance as u64)) {
+            self.late += 1;
+            return;
+        }
+        self.pending.insert(next + distance as u64, payload);
+
+        let mut next = next;
+        loop {
+            if let Some(payload) = self.pending.remove(&next) {
+                self.convert(&payload);
+                next += 1;
+            } else if self.pending.len() > self.reorder_window {
+                // give up on the missing packets before the first one that arrived
+                let (&first, _) = self
+                    .pending
+                    .first_key_value()
+                    .expect("pending is not empty");
+                self.lost += first - next;
+                #[cfg(feature = "opus")]
+                for _ in 0..(first - next).min(MAX_CONCEALED_PACKETS) {
+                    self.conceal();
+                }
+                next = first;
+            } else {
+                break;
+            }
+        }
+        self.next_sequence = Some(next);
+    }
+
+    /// Convert the samples of `payload` into stereo samples
+    fn convert(&mut self, payload: &[u8]) {
+        let channels = self.channels as usize;
+        #[cfg(feature = "opus")]
+        if let Some(decoder) = &mut self.decoder {
+            let samples = decoder.decode(Some(payload));
+            extend_stereo(&mut self.converted, samples, channels);
+            return;
+        }
+        for frame in payload.chunks_exact(channels * 2) {
+            let sample = |channel: usize| {
+                T::from_sample_(i16::from_be_bytes([
+                    frame[channel * 2],
+                    frame[channel * 2 + 1],
+                ]))
+            };
+            let left = sample(0);
+            let right = if channels == 1 { left } else { sample(1) };
+            self.converted.extend_from_slice(&[left, right]);
+        }
+    }
+
+    /// Queue the concealment of a lost Opus packet
+    #[cfg(feature = "opus")]
+    fn conceal(&mut self) {
+        if let Some(decoder) = &mut self.decoder {
+            let samples = decoder.decode(None);
+            extend_stereo(&mut self.converted, samples, self.channels as usize);
+        }
+    }
+}
+
+/// Append the stereo samples of the interleaved `samples` of `channels` channels to `output`
+#[cfg(feature = "opus")]
+fn extend_stereo<T: FloatSample>(output: &mut Vec<T>, samples: &[f32], channels: usize) {
+    for frame in samples.chunks_exact(channels) {
+        let left = T::coerce(frame[0]);
+        let right = if channels == 1 {
+            left
+        } else {
+            T::coerce(frame[1])
+        };
+        output.extend_from_slice(&[left, right]);
+    }
+}
+
+/// The sequence number and the payload of an RTP packet, or `None` if it's not a valid one
+fn parse_rtp(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if packet.len() < RTP_HEADER_LEN || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+    let csrc_count = (packet[0] & 0x0F) as usize;
+    let mut start = RTP_HEADER_LEN + csrc_count * 4;
+    if packet[0] & 0x10 != 0 {
+        // the extension header has its length in 32-bit words
+        let header = packet.get(start..start + 4)?;
+        start += 4 + u16::from_be_bytes([header[2], header[3]]) as usize * 4;
+    }
+    let mut end = packet.len();
+    if packet[0] & 0x20 != 0 {
+        // the last byte is the length of the padding
+        end = end.checked_sub(*packet.last()? as usize)?;
+    }
+    Some((sequence, packet.get(start..end)?))
+}
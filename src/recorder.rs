@@ -0,0 +1,548 @@
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Data, FromSample, InputCallbackInfo, SampleFormat, SizedSample, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+use crate::{
+    error::{AudioPlayerError, CallbackError, PlayError},
+    negotiation::{choose_config, ConfigPreference, Preferred},
+    resampler::AudioResampler,
+    sample::FloatSample,
+    state::STREAM_ERRORS_CAPACITY,
+    BufferSize,
+};
+
+type DataCallback<T> = Box<dyn FnMut(&[T]) + Send + 'static>;
+
+/// The device recorded from, with its configurations
+pub(crate) struct InputDevice {
+    pub device: cpal::Device,
+    pub configs: Vec<SupportedStreamConfigRange>,
+    /// The device is an output captured by loopback, with the configurations of the output
+    pub output_configs: bool,
+    /// Records from the monitor of the output until the stream is built
+    #[cfg(all(feature = "loopback", target_os = "linux"))]
+    pub _monitor: Option<crate::loopback::MonitorEnv>,
+}
+
+impl InputDevice {
+    fn default_config(&self) -> Result<SupportedStreamConfig, AudioPlayerError> {
+        if self.output_configs {
+            Ok(self.device.default_output_config()?)
+        } else {
+            Ok(self.device.default_input_config()?)
+        }
+    }
+}
+
+/// Where the recorded samples go after conversion and resampling
+enum InputSink<T> {
+    Buffer(HeapProd<T>),
+    Callback(DataCallback<T>),
+}
+
+struct InputProcessor<T: FloatSample> {
+    channels: usize,
+    /// Allocated at build time, `data` is converted in chunks fitting its capacity
+    converted: Vec<T>,
+    resampler: Option<AudioResampler<T>>,
+    sink: InputSink<T>,
+}
+
+impl<T: FloatSample + SizedSample> InputProcessor<T> {
+    fn process<S>(&mut self, data: &[S])
+    where
+        S: SizedSample,
+        T: FromSample<S>,
+    {
+        let chunk_frames = (self.converted.capacity() / 2).max(1);
+        for chunk in data.chunks(chunk_frames * self.channels) {
+            // convert into stereo samples of `T`
+            self.converted.clear();
+            if self.channels == 1 {
+                for sample in chunk {
+                    let value = T::from_sample_(*sample);
+                    self.converted.extend_from_slice(&[value, value]);
+                }
+            } else {
+                self.converted
+                    .extend(chunk.iter().map(|sample| T::from_sample_(*sample)));
+            }
+
+            let sink = &mut self.sink;
+            let mut deliver = |samples: &[T]| match sink {
+                InputSink::Buffer(producer) => {
+                    producer.push_slice(samples);
+                }
+                InputSink::Callback(callback) => callback(samples),
+            };
+
+            match &mut self.resampler {
+                Some(resampler) => resampler.resample(&self.converted, deliver),
+                None => deliver(&self.converted),
+            }
+        }
+    }
+}
+
+// Type alias for the processing function - matches the required callback signature
+type InputProcessingFn = Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>;
+
+fn create_input_processor<T>(
+    format: SampleFormat,
+    mut processor: InputProcessor<T>,
+) -> Result<InputProcessingFn, AudioPlayerError>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+{
+    Ok(match format {
+        SampleFormat::I8 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<i8>().expect("Valid format"));
+        }),
+        SampleFormat::I16 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<i16>().expect("Valid format"));
+        }),
+        SampleFormat::I32 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<i32>().expect("Valid format"));
+        }),
+        SampleFormat::I64 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<i64>().expect("Valid format"));
+        }),
+        SampleFormat::U8 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<u8>().expect("Valid format"));
+        }),
+        SampleFormat::U16 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<u16>().expect("Valid format"));
+        }),
+        SampleFormat::U32 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<u32>().expect("Valid format"));
+        }),
+        SampleFormat::U64 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<u64>().expect("Valid format"));
+        }),
+        SampleFormat::F32 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<f32>().expect("Valid format"));
+        }),
+        SampleFormat::F64 => Box::new(move |data, _| {
+            processor.process(data.as_slice::<f64>().expect("Valid format"));
+        }),
+        format => return Err(AudioPlayerError::UnsupportedInputFormat(format)),
+    })
+}
+
+/// Builder for [`AudioRecorder`].
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::AudioRecorder;
+/// let recorder = AudioRecorder::<f32>::builder(32768)
+///     .on_data(|samples| {
+///         // samples are interleaved stereo at 32768Hz
+///     })
+///     .build()
+///     .unwrap();
+/// recorder.record().unwrap();
+/// ```
+pub struct AudioRecorderBuilder<T> {
+    sample_rate: u32,
+    buffer_size: BufferSize,
+    host: Option<cpal::HostId>,
+    device: Option<cpal::Device>,
+    #[cfg(feature = "loopback")]
+    loopback: bool,
+    on_data: Option<DataCallback<T>>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> AudioRecorderBuilder<T> {
+    /// Creates a new builder for a recorder that will provide samples of `sample_rate` Hz.
+    ///
+    /// Prefer using [`AudioRecorder::builder`].
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            buffer_size: BufferSize::default(),
+            host: None,
+            device: None,
+            #[cfg(feature = "loopback")]
+            loopback: false,
+            on_data: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the size of the buffer that will store the recorded samples until they are read.
+    /// See [`BufferSize`] for options.
+    pub fn buffer_size(mut self, buffer_size: BufferSize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Use the default input device of the host `host` instead of the default host.
+    pub fn host(mut self, host: cpal::HostId) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// Use `device` as the input device, instead of the default input device of the host.
+    ///
+    /// This overrides [`host`](Self::host).
+    pub fn device(mut self, device: cpal::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Record the audio played by the other applications on the output, instead of an input device,
+    /// to process or route the audio of the system, with the same conversion and resampling.
+    ///
+    /// - On Windows, the output [`device`](Self::device) or the default output device of the host is captured
+    ///   with the loopback mode of WASAPI.
+    /// - On Linux, the monitor of the default output of PulseAudio or PipeWire is recorded, through the default
+    ///   input device of their ALSA plugins, or the [`device`](Self::device) of one of them.
+    ///
+    /// Returns [`AudioPlayerError::LoopbackNotSupported`] on the other platforms when built.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioPlayer, AudioRecorder, BufferSize};
+    /// # use std::sync::mpsc;
+    /// let (sender, receiver) = mpsc::channel::<Vec<f32>>();
+    /// let recorder = AudioRecorder::<f32>::builder(48000)
+    ///     .loopback(true)
+    ///     .on_data(move |samples| {
+    ///         let _ = sender.send(samples.to_vec());
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// recorder.record().unwrap();
+    ///
+    /// // play the system audio again, on another device for example
+    /// let mut player = AudioPlayer::<f32>::new(48000, BufferSize::QuarterSecond).unwrap();
+    /// player.play().unwrap();
+    /// for samples in receiver {
+    ///     player.queue(&samples);
+    /// }
+    /// ```
+    #[cfg(feature = "loopback")]
+    pub fn loopback(mut self, loopback: bool) -> Self {
+        self.loopback = loopback;
+        self
+    }
+
+    /// Deliver the recorded samples to `callback` as soon as they are available, instead of
+    /// storing them to be read with [`AudioRecorder::read`].
+    ///
+    /// The callback is called from the audio thread, so it should not block.
+    pub fn on_data(mut self, callback: impl FnMut(&[T]) + Send + 'static) -> Self {
+        self.on_data = Some(Box::new(callback));
+        self
+    }
+}
+
+impl<T> AudioRecorderBuilder<T>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+{
+    /// Creates the [`AudioRecorder`] with the configured options.
+    ///
+    /// Check [`AudioRecorder::new`] for the possible errors.
+    pub fn build(mut self) -> Result<AudioRecorder<T>, AudioPlayerError> {
+        let input = self.open_input()?;
+
+        // stereo is preferred, but most microphones are mono, which we duplicate into both channels
+        let mut used_conf = None;
+        for channels in [2, 1] {
+            let chosen = choose_config(
+                &input.configs,
+                || input.default_config(),
+                self.sample_rate,
+                T::FORMAT,
+                channels,
+                ConfigPreference::default(),
+                Preferred::default(),
+            )?;
+            if chosen.channels() == channels {
+                used_conf = Some(chosen);
+                break;
+            }
+        }
+        let used_conf = used_conf.ok_or(AudioPlayerError::DualChannelNotSupported)?;
+
+        let input_sample_rate = used_conf.sample_rate().0;
+        let resampler = if input_sample_rate == self.sample_rate {
+            None
+        } else {
+            Some(AudioResampler::new(
+                input_sample_rate as usize,
+                self.sample_rate as usize,
+                // the number of samples for one video frame in 60 FPS
+                input_sample_rate as usize / 60,
+                2,
+            )?)
+        };
+
+        let (sink, buffer_consumer) = match self.on_data {
+            Some(callback) => (InputSink::Callback(callback), None),
+            None => {
+                let ring_buffer_len = self
+                    .buffer_size
+                    .store_for_samples(self.sample_rate as usize, 2);
+                let (producer, consumer) = HeapRb::new(ring_buffer_len).split();
+                (InputSink::Buffer(producer), Some(consumer))
+            }
+        };
+
+        let processor = InputProcessor {
+            channels: used_conf.channels() as usize,
+            // the number of samples for one video frame in 60 FPS
+            converted: Vec::with_capacity(input_sample_rate as usize / 60 * 2),
+            resampler,
+            sink,
+        };
+
+        let config = cpal::StreamConfig {
+            channels: used_conf.channels(),
+            sample_rate: used_conf.sample_rate(),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let input_stream = input.device.build_input_stream_raw(
+            &config,
+            used_conf.sample_format(),
+            create_input_processor(used_conf.sample_format(), processor)?,
+            AudioRecorder::<T>::err_fn(errors.clone()),
+            None,
+        )?;
+        drop(input);
+
+        Ok(AudioRecorder {
+            buffer_consumer,
+            input_stream,
+            sample_rate: self.sample_rate,
+            input_sample_rate,
+            input_sample_format: used_conf.sample_format(),
+            input_channels: used_conf.channels(),
+            errors,
+        })
+    }
+
+    /// Open the device to record from, see [`device`](Self::device) and [`loopback`](Self::loopback)
+    fn open_input(&mut self) -> Result<InputDevice, AudioPlayerError> {
+        let host = || match self.host {
+            Some(host_id) => cpal::host_from_id(host_id),
+            None => Ok(cpal::default_host()),
+        };
+        #[cfg(feature = "loopback")]
+        if self.loopback {
+            return crate::loopback::open(&host()?, self.device.take());
+        }
+        let device = match self.device.take() {
+            Some(device) => device,
+            None => host()?
+                .default_input_device()
+                .ok_or(AudioPlayerError::NoInputDevice)?,
+        };
+        let configs = device.supported_input_configs()?.collect();
+        Ok(InputDevice {
+            device,
+            configs,
+            output_configs: false,
+            #[cfg(all(feature = "loopback", target_os = "linux"))]
+            _monitor: None,
+        })
+    }
+}
+
+/// The `AudioRecorder` struct records audio from an input device, the mirror image of [`AudioPlayer`](crate::AudioPlayer).
+///
+/// The recorded samples are converted to `T` and resampled to the requested sample rate if the device doesn't
+/// support it. The samples are always interleaved stereo, mono devices are duplicated into both channels.
+///
+/// The samples can be read with [`read`](Self::read), or delivered to a callback as soon as they are recorded,
+/// see [`AudioRecorderBuilder::on_data`].
+///
+/// # Example
+/// ```rust,no_run
+/// # use dynwave::{AudioRecorder, BufferSize};
+/// let mut recorder = AudioRecorder::<f32>::new(44100, BufferSize::QuarterSecond).unwrap();
+/// recorder.record().unwrap();
+///
+/// let mut samples = vec![0.0; 735 * 2];
+/// // in the emulation loop
+/// let read = recorder.read(&mut samples);
+/// // use `samples[..read]`
+/// ```
+pub struct AudioRecorder<T> {
+    buffer_consumer: Option<HeapCons<T>>,
+    input_stream: cpal::Stream,
+    sample_rate: u32,
+    input_sample_rate: u32,
+    input_sample_format: SampleFormat,
+    input_channels: u16,
+    // the errors reported by the input stream, from the error callback of cpal which isn't real-time
+    errors: Arc<Mutex<Vec<CallbackError>>>,
+}
+
+impl<T> AudioRecorder<T>
+where
+    T: FloatSample + SizedSample + Send + 'static,
+    T: FromSample<i8>
+        + FromSample<i16>
+        + FromSample<i32>
+        + FromSample<i64>
+        + FromSample<u8>
+        + FromSample<u16>
+        + FromSample<u32>
+        + FromSample<u64>
+        + FromSample<f32>
+        + FromSample<f64>,
+{
+    /// Creates a new `AudioRecorder` recording from the default input device.
+    ///
+    /// # Parameters
+    /// * `sample_rate`: The sample rate of the samples provided by the recorder in Hz.
+    /// * `buffer_size`: The size of the buffer that will store the recorded samples until they are read. See [`BufferSize`] for options.
+    ///
+    /// # Returns
+    /// Might return an `Error` if:
+    /// - No input device is found
+    /// - The input device does not support mono or dual channel
+    /// - The input device only supports a sample format that can't be converted
+    /// - Some error happened with the device backend
+    /// - Could not create the audio stream
+    ///
+    /// Check [`AudioPlayerError`] for more information about the possible errors.
+    pub fn new(sample_rate: u32, buffer_size: BufferSize) -> Result<Self, AudioPlayerError> {
+        Self::builder(sample_rate).buffer_size(buffer_size).build()
+    }
+
+    /// Creates a builder for an `AudioRecorder` that will provide samples of `sample_rate` Hz.
+    ///
+    /// See [`AudioRecorderBuilder`] for the available options.
+    pub fn builder(sample_rate: u32) -> AudioRecorderBuilder<T> {
+        AudioRecorderBuilder::new(sample_rate)
+    }
+
+    /// Start recording
+    ///
+    /// Check [`PlayError`] for more information about the possible errors.
+    pub fn record(&self) -> Result<(), PlayError> {
+        self.input_stream.play().map_err(|e| e.into())
+    }
+
+    /// Pause recording
+    ///
+    /// Check [`PlayError`] for more information about the possible errors.
+    pub fn pause(&self) -> Result<(), PlayError> {
+        self.input_stream.pause().map_err(|e| e.into())
+    }
+
+    /// Read the recorded samples into `data`, returns the number of samples read.
+    ///
+    /// Doesn't block, so it might read less than `data.len()` samples.
+    /// If the samples are delivered with [`AudioRecorderBuilder::on_data`], this always returns `0`.
+    pub fn read(&mut self, data: &mut [T]) -> usize {
+        match &mut self.buffer_consumer {
+            Some(consumer) => consumer.pop_slice(data),
+            None => 0,
+        }
+    }
+
+    /// The number of recorded samples available to be read.
+    pub fn available(&self) -> usize {
+        self.buffer_consumer
+            .as_ref()
+            .map_or(0, |consumer| consumer.occupied_len())
+    }
+
+    /// The sample rate of the samples provided by the recorder in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The sample rate in Hz used by the input device.
+    pub fn input_sample_rate(&self) -> u32 {
+        self.input_sample_rate
+    }
+
+    /// The sample format used by the input device.
+    pub fn input_sample_format(&self) -> SampleFormat {
+        self.input_sample_format
+    }
+
+    /// The number of channels of the input device stream.
+    pub fn input_channels(&self) -> u16 {
+        self.input_channels
+    }
+
+    /// Returns `true` if the recorded samples are resampled, i.e. the device didn't support the requested sample rate.
+    pub fn is_resampling(&self) -> bool {
+        self.input_sample_rate != self.sample_rate
+    }
+
+    /// Take the errors reported by the input stream since the last call, like [`AudioPlayer::take_errors`](crate::AudioPlayer::take_errors).
+    ///
+    /// They are [`CallbackError::Stream`] errors, with the description of the error of the device,
+    /// up to 64 errors, the newer ones are dropped until they are taken.
+    /// The recorder doesn't reopen the device, so after a disconnection the recording stops.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use dynwave::{AudioRecorder, BufferSize};
+    /// let mut recorder = AudioRecorder::<f32>::new(48000, BufferSize::QuarterSecond).unwrap();
+    /// recorder.record().unwrap();
+    ///
+    /// for error in recorder.take_errors() {
+    ///     println!("recording: {error}");
+    /// }
+    /// ```
+    pub fn take_errors(&mut self) -> Vec<CallbackError> {
+        self.errors
+            .lock()
+            .map(|mut errors| std::mem::take(&mut *errors))
+            .unwrap_or_default()
+    }
+
+    fn err_fn(
+        errors: Arc<Mutex<Vec<CallbackError>>>,
+    ) -> impl FnMut(cpal::StreamError) + Send + 'static {
+        move |err| {
+            if let Ok(mut errors) = errors.lock() {
+                if errors.len() < STREAM_ERRORS_CAPACITY {
+                    errors.push(CallbackError::Stream(err.to_string()));
+                }
+            }
+        }
+    }
+}
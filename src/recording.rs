@@ -0,0 +1,162 @@
+//! Recording of the played samples into a WAV or FLAC file
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use cpal::{FromSample, SizedSample};
+use ringbuf::{
+    traits::{Consumer, Observer, Split},
+    HeapProd, HeapRb,
+};
+
+#[cfg(feature = "flac")]
+use crate::flac::{flac_bits_for, FlacFileWriter};
+use crate::{
+    backend::OutputConfig,
+    error::AudioPlayerError,
+    sample::FloatSample,
+    wav::{wav_format_for, WavFileWriter},
+};
+
+/// The file a recording is written into
+enum RecordingWriter {
+    Wav(WavFileWriter),
+    #[cfg(feature = "flac")]
+    Flac(FlacFileWriter),
+}
+
+impl RecordingWriter {
+    /// Create a FLAC file if `path` has the `flac` extension, and a WAV file otherwise
+    fn create(path: &Path, config: &OutputConfig) -> Result<Self, AudioPlayerError> {
+        #[cfg(feature = "flac")]
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"))
+        {
+            return Ok(Self::Flac(FlacFileWriter::create(
+                path,
+                config.sample_rate,
+                config.channels,
+                flac_bits_for(config.sample_format),
+            )?));
+        }
+
+        Ok(Self::Wav(WavFileWriter::create(
+            path,
+            config.sample_rate,
+            config.channels,
+            wav_format_for(config.sample_format),
+        )?))
+    }
+
+    fn write<T>(&mut self, samples: &[T]) -> Result<(), AudioPlayerError>
+    where
+        T: FloatSample,
+        i8: FromSample<T>,
+        i16: FromSample<T>,
+        i32: FromSample<T>,
+        f32: FromSample<T>,
+    {
+        match self {
+            Self::Wav(writer) => Ok(writer.write(samples)?),
+            #[cfg(feature = "flac")]
+            Self::Flac(writer) => Ok(writer.write(samples)?),
+        }
+    }
+
+    fn finalize(self) -> Result<(), AudioPlayerError> {
+        match self {
+            Self::Wav(writer) => Ok(writer.finalize()?),
+            #[cfg(feature = "flac")]
+            Self::Flac(writer) => Ok(writer.finalize()?),
+        }
+    }
+}
+
+/// A recording in progress, the samples are written from a separate thread
+pub(crate) struct Recording {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), AudioPlayerError>>>,
+}
+
+impl Recording {
+    /// Start a recording into `path`, returns the producer the output should copy the played samples into
+    pub fn start<T>(
+        path: &Path,
+        config: &OutputConfig,
+    ) -> Result<(Self, HeapProd<T>), AudioPlayerError>
+    where
+        T: FloatSample + SizedSample + Send + 'static,
+        i8: FromSample<T>,
+        i16: FromSample<T>,
+        i32: FromSample<T>,
+        f32: FromSample<T>,
+    {
+        let mut writer = RecordingWriter::create(path, config)?;
+
+        // 1 second of samples, way more than what is played between two wakeups of the thread
+        let buffer = HeapRb::new(config.sample_rate as usize * config.channels as usize);
+        let (producer, mut consumer) = buffer.split();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut chunk = vec![T::EQUILIBRIUM; 4096];
+                loop {
+                    // read the flag before draining, so we don't miss samples pushed before stopping
+                    let stopping = stop.load(Ordering::Acquire) || !consumer.write_is_held();
+                    loop {
+                        let len = consumer.pop_slice(&mut chunk);
+                        if len == 0 {
+                            break;
+                        }
+                        writer.write(&chunk[..len])?;
+                    }
+                    if stopping {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                writer.finalize()
+            })
+        };
+
+        Ok((
+            Self {
+                stop,
+                thread: Some(thread),
+            },
+            producer,
+        ))
+    }
+
+    /// Stop the recording and finalize the file
+    pub fn stop(mut self) -> Result<(), AudioPlayerError> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), AudioPlayerError> {
+        self.stop.store(true, Ordering::Release);
+        match self.thread.take().map(|thread| thread.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(AudioPlayerError::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "recording thread panicked",
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+/// What the player does when its output stream stops at runtime, for example when the
+/// device is disconnected, set with [`AudioPlayerBuilder::recovery_policy`](crate::AudioPlayerBuilder::recovery_policy).
+///
+/// The loss is handled on the next [`queue`](crate::AudioPlayer::queue), so the recovery happens
+/// on the thread of the player, the queued samples are kept, and the player plays again
+/// if it was playing.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, RecoveryPolicy};
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(backend)
+///     .recovery_policy(RecoveryPolicy::ReopenSameDevice)
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+/// player.queue(&[0.5; 480 * 2]);
+///
+/// clock.disconnect();
+/// // the stream is built again when queueing
+/// player.queue(&[0.5; 480 * 2]);
+/// assert!(player.is_open());
+/// assert!(player.is_playing());
+///
+/// clock.advance_frames(960);
+/// assert_eq!(player.buffered_samples(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    #[default]
+    /// Don't recover, the player is [closed](crate::AudioPlayer::close), which can be checked with
+    /// [`is_open`](crate::AudioPlayer::is_open), and [`reopen`](crate::AudioPlayer::reopen) can be used to try again.
+    Never,
+    /// Build the stream again on the same device once, if it fails, the player is closed like [`Never`](Self::Never).
+    ReopenSameDevice,
+    /// Build the stream again on the same device up to `retries` times, waiting `backoff` between the attempts,
+    /// then move to the default output device, see [`AudioPlayer::set_device`](crate::AudioPlayer::set_device).
+    ///
+    /// If the default device fails too, the player is closed like [`Never`](Self::Never).
+    FallbackToDefaultDevice {
+        /// The number of attempts on the same device.
+        retries: u32,
+        /// The time between the attempts.
+        backoff: Duration,
+    },
+}
+
+/// What to try next to recover the stream
+pub(crate) enum RecoveryAction {
+    Reopen,
+    DefaultDevice,
+}
+
+/// The state of the recovery of a lost stream
+pub(crate) struct Recovery {
+    policy: RecoveryPolicy,
+    // the attempts done, and when to do the next one
+    pending: Option<(u32, Instant)>,
+    // the player was playing when the stream was lost
+    resume: bool,
+}
+
+impl Recovery {
+    pub fn new(policy: RecoveryPolicy) -> Self {
+        Self {
+            policy,
+            pending: None,
+            resume: false,
+        }
+    }
+
+    /// The stream was lost, start recovering it if the policy allows
+    pub fn start(&mut self, resume: bool) {
+        if self.policy != RecoveryPolicy::Never {
+            self.pending = Some((0, Instant::now()));
+            self.resume = resume;
+        }
+    }
+
+    /// Stop recovering, when the player is closed or opened by the user
+    pub fn cancel(&mut self) {
+        self.pending = None;
+        self.resume = false;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The next attempt to recover the stream, if it's time for it
+    pub fn next_action(&mut self, now: Instant) -> Option<RecoveryAction> {
+        let (attempts, next_attempt) = self.pending?;
+        if now < next_attempt {
+            return None;
+        }
+        match self.policy {
+            RecoveryPolicy::Never => None,
+            RecoveryPolicy::ReopenSameDevice => {
+                self.pending = None;
+                Some(RecoveryAction::Reopen)
+            }
+            RecoveryPolicy::FallbackToDefaultDevice { retries, backoff } => {
+                if attempts < retries {
+                    self.pending = Some((attempts + 1, now + backoff));
+                    Some(RecoveryAction::Reopen)
+                } else {
+                    self.pending = None;
+                    Some(RecoveryAction::DefaultDevice)
+                }
+            }
+        }
+    }
+
+    /// The stream was built again, returns `true` if the player should play again
+    pub fn finish(&mut self) -> bool {
+        self.pending = None;
+        std::mem::take(&mut self.resume)
+    }
+}
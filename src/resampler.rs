@@ -0,0 +1,621 @@
+use std::time::Duration;
+
+use cpal::SizedSample;
+#[cfg(feature = "resample")]
+use rubato::{FastFixedIn, FftFixedInOut, PolynomialDegree, Resampler};
+
+use crate::{
+    error::AudioPlayerError, interpolator::Interpolator, ring::RingProducer, sample::FloatSample,
+};
+
+/// The algorithm used to resample the input samples, see [`ResamplingInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResamplingAlgorithm {
+    /// Synchronous FFT based resampling, from [rubato]'s `FftFixedInOut`.
+    ///
+    /// High quality, but adds a delay of about one chunk.
+    Fft,
+    /// Asynchronous cubic interpolation, from [rubato]'s `FastFixedIn`.
+    ///
+    /// Used with [`AudioPlayerBuilder::drift_compensation`](crate::AudioPlayerBuilder::drift_compensation),
+    /// as the ratio can be adjusted while playing.
+    Polynomial,
+    /// Asynchronous linear interpolation, from [rubato]'s `FastFixedIn`.
+    ///
+    /// The cheapest and lowest quality, used when resampling can't keep up,
+    /// see [`AudioPlayerBuilder::resampler_load_limit`](crate::AudioPlayerBuilder::resampler_load_limit).
+    Linear,
+    /// Asynchronous cubic interpolation, built into dynwave.
+    ///
+    /// Lighter than the resamplers of rubato, without their anti-aliasing filter, for platforms where
+    /// they are too heavy and sources without much high frequency content, like chip-tune era consoles.
+    /// Available without the `resample` feature.
+    BuiltinCubic,
+    /// Asynchronous linear interpolation, built into dynwave.
+    ///
+    /// The cheapest algorithm, available without the `resample` feature.
+    BuiltinLinear,
+}
+
+impl ResamplingAlgorithm {
+    /// The next algorithm that takes less time, or `None` if this is the cheapest
+    pub(crate) fn cheaper(self) -> Option<Self> {
+        match self {
+            Self::Fft => Some(Self::Polynomial),
+            Self::Polynomial => Some(Self::Linear),
+            Self::Linear => None,
+            Self::BuiltinCubic => Some(Self::BuiltinLinear),
+            Self::BuiltinLinear => None,
+        }
+    }
+
+    /// Whether the ratio of the resampler can be adjusted while playing, to compensate the drift of the device
+    pub(crate) fn is_adjustable(self) -> bool {
+        self != Self::Fft
+    }
+
+    /// Whether `self`, or the built-in algorithm replacing it, can be used in this build
+    pub(crate) fn is_available(self) -> bool {
+        cfg!(feature = "resample")
+            || matches!(self.available(), Self::BuiltinCubic | Self::BuiltinLinear)
+    }
+
+    /// The algorithm used in place of `self`, the built-in ones replace the ones of rubato
+    /// when it's not available and the `lite-resample` feature is enabled
+    fn available(self) -> Self {
+        if cfg!(feature = "resample") || !cfg!(feature = "lite-resample") {
+            return self;
+        }
+        match self {
+            Self::Fft | Self::Polynomial | Self::BuiltinCubic => Self::BuiltinCubic,
+            Self::Linear | Self::BuiltinLinear => Self::BuiltinLinear,
+        }
+    }
+}
+
+/// The maximum drift of the device clock that can be compensated, relative to the nominal rate
+pub(crate) const MAX_DRIFT: f64 = 1.02;
+
+/// The duration of resampled audio the load of the resampler is measured over
+const LOAD_WINDOW_SECONDS: f64 = 1.0;
+
+/// The methods of the resamplers we need, in an object safe trait
+pub(crate) trait ChunkResampler<T>: Send {
+    /// Resample one chunk, returns the number of output frames
+    fn process(&mut self, input: &[Vec<T>], output: &mut [Vec<T>]) -> usize;
+    fn input_frames_max(&self) -> usize;
+    fn input_frames_next(&self) -> usize;
+    fn output_frames_max(&self) -> usize;
+    fn output_frames_next(&self) -> usize;
+    fn output_delay(&self) -> usize;
+    fn reset(&mut self);
+    fn set_ratio_relative(&mut self, ratio: f64);
+}
+
+/// A resampler of rubato
+#[cfg(feature = "resample")]
+struct Rubato<R>(R);
+
+#[cfg(feature = "resample")]
+impl<T: FloatSample, R: Resampler<T>> ChunkResampler<T> for Rubato<R> {
+    fn process(&mut self, input: &[Vec<T>], output: &mut [Vec<T>]) -> usize {
+        let (_, output_frames) = self.0.process_into_buffer(input, output, None).unwrap();
+        output_frames
+    }
+
+    fn input_frames_max(&self) -> usize {
+        self.0.input_frames_max()
+    }
+
+    fn input_frames_next(&self) -> usize {
+        self.0.input_frames_next()
+    }
+
+    fn output_frames_max(&self) -> usize {
+        self.0.output_frames_max()
+    }
+
+    fn output_frames_next(&self) -> usize {
+        self.0.output_frames_next()
+    }
+
+    fn output_delay(&self) -> usize {
+        self.0.output_delay()
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+
+    fn set_ratio_relative(&mut self, ratio: f64) {
+        // synchronous resamplers can't be adjusted, they are never given a drift
+        let _ = self.0.set_resample_ratio_relative(ratio, true);
+    }
+}
+
+/// Creates the resampler of `algorithm`
+fn build<T: FloatSample>(
+    input_rate: usize,
+    output_rate: usize,
+    chunk_size: usize,
+    channels: usize,
+    algorithm: ResamplingAlgorithm,
+) -> Result<Box<dyn ChunkResampler<T>>, AudioPlayerError> {
+    let ratio = output_rate as f64 / input_rate as f64;
+    match algorithm {
+        ResamplingAlgorithm::BuiltinCubic => Ok(Box::new(Interpolator::new(
+            ratio, chunk_size, channels, true,
+        ))),
+        ResamplingAlgorithm::BuiltinLinear => Ok(Box::new(Interpolator::new(
+            ratio, chunk_size, channels, false,
+        ))),
+        _ => build_rubato(input_rate, output_rate, chunk_size, channels, algorithm),
+    }
+}
+
+/// Creates the rubato resampler of `algorithm`
+#[cfg(feature = "resample")]
+fn build_rubato<T: FloatSample>(
+    input_rate: usize,
+    output_rate: usize,
+    chunk_size: usize,
+    channels: usize,
+    algorithm: ResamplingAlgorithm,
+) -> Result<Box<dyn ChunkResampler<T>>, AudioPlayerError> {
+    let degree = match algorithm {
+        ResamplingAlgorithm::Fft => {
+            let resampler = FftFixedInOut::<T>::new(input_rate, output_rate, chunk_size, channels)?;
+            return Ok(Box::new(Rubato(resampler)));
+        }
+        ResamplingAlgorithm::Linear | ResamplingAlgorithm::BuiltinLinear => {
+            PolynomialDegree::Linear
+        }
+        _ => PolynomialDegree::Cubic,
+    };
+    let resampler = FastFixedIn::<T>::new(
+        output_rate as f64 / input_rate as f64,
+        MAX_DRIFT,
+        degree,
+        chunk_size,
+        channels,
+    )?;
+    Ok(Box::new(Rubato(resampler)))
+}
+
+/// Without the `resample` feature, only the built-in resamplers are available
+#[cfg(not(feature = "resample"))]
+fn build_rubato<T: FloatSample>(
+    input_rate: usize,
+    output_rate: usize,
+    _chunk_size: usize,
+    _channels: usize,
+    _algorithm: ResamplingAlgorithm,
+) -> Result<Box<dyn ChunkResampler<T>>, AudioPlayerError> {
+    Err(AudioPlayerError::ResamplingUnavailable {
+        from: input_rate as u32,
+        to: output_rate as u32,
+    })
+}
+
+/// The parameters of the resampling done by an [`AudioPlayer`](crate::AudioPlayer),
+/// see [`resampling_info`](crate::AudioPlayer::resampling_info).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResamplingInfo {
+    /// The sample rate of the queued samples in Hz.
+    pub input_rate: u32,
+    /// The sample rate of the output in Hz.
+    pub output_rate: u32,
+    /// The number of input frames resampled at once,
+    /// queued samples are kept until they fill a chunk.
+    pub chunk_size: usize,
+    /// The resampling algorithm.
+    pub algorithm: ResamplingAlgorithm,
+}
+
+/// Resample interleaved samples of `channels` channels from `input_rate` to `output_rate`,
+/// with the same resampler used by [`AudioPlayer`](crate::AudioPlayer).
+///
+/// Meant for offline conversions, like exporting recordings, so all of `data` is resampled at once.
+/// The output has no delay at the start, and is as long as `data` in time.
+///
+/// With the `parallel-resample` feature, more than 2 channels, like 5.1 audio, are resampled
+/// on several threads. The player itself only resamples stereo, surround input is downmixed first.
+///
+/// # Example
+/// ```rust
+/// let input = vec![0.5f32; 44100 * 2];
+/// let output = dynwave::resample(&input, 2, 44100, 48000).unwrap();
+/// assert_eq!(output.len(), 48000 * 2);
+/// ```
+pub fn resample<T: FloatSample + SizedSample>(
+    data: &[T],
+    channels: u16,
+    input_rate: u32,
+    output_rate: u32,
+) -> Result<Vec<T>, AudioPlayerError> {
+    if channels == 0 {
+        return Err(AudioPlayerError::UnsupportedInputChannels(channels));
+    }
+    #[cfg(feature = "parallel-resample")]
+    if channels > 2 && input_rate != output_rate {
+        return resample_parallel(data, channels as usize, input_rate, output_rate);
+    }
+    match AudioResampler::for_rates(input_rate, output_rate, channels, false)? {
+        Some(mut resampler) => Ok(resampler.resample_all(data)),
+        None => Ok(data.to_vec()),
+    }
+}
+
+/// Resample groups of channels on their own threads, one for each core at most.
+///
+/// The channels are resampled independently, so the output is the same as resampling them together.
+#[cfg(feature = "parallel-resample")]
+fn resample_parallel<T: FloatSample + SizedSample>(
+    data: &[T],
+    channels: usize,
+    input_rate: u32,
+    output_rate: u32,
+) -> Result<Vec<T>, AudioPlayerError> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(channels);
+    let group = (channels + threads - 1) / threads;
+
+    let groups = std::thread::scope(|scope| {
+        let handles = (0..channels)
+            .step_by(group)
+            .map(|first| {
+                let width = group.min(channels - first);
+                scope.spawn(move || {
+                    let split = data
+                        .chunks_exact(channels)
+                        .flat_map(|frame| &frame[first..first + width])
+                        .copied()
+                        .collect::<Vec<_>>();
+                    let mut resampler = AudioResampler::new(
+                        input_rate as usize,
+                        output_rate as usize,
+                        chunk_size(input_rate, false),
+                        width,
+                    )?;
+                    Ok((first, width, resampler.resample_all(&split)))
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("resampling thread panicked"))
+            .collect::<Result<Vec<_>, AudioPlayerError>>()
+    })?;
+
+    let frames = groups
+        .first()
+        .map_or(0, |(_, width, resampled)| resampled.len() / width);
+    let mut output = vec![T::EQUILIBRIUM; frames * channels];
+    for (first, width, resampled) in groups {
+        for (frame, samples) in output
+            .chunks_exact_mut(channels)
+            .zip(resampled.chunks_exact(width))
+        {
+            frame[first..first + width].copy_from_slice(samples);
+        }
+    }
+    Ok(output)
+}
+
+pub(crate) struct AudioResampler<T: FloatSample> {
+    resampler: Box<dyn ChunkResampler<T>>,
+    info: ResamplingInfo,
+    drift: f64,
+    channels: usize,
+    pre_resampled_buffer: Vec<T>,
+    // one buffer for each channel
+    pre_resampled_split_buffers: Vec<Vec<T>>,
+    resample_process_buffers: Vec<Vec<T>>,
+    resampled_buffer: Vec<T>,
+}
+
+impl<T: FloatSample + SizedSample> AudioResampler<T> {
+    /// Creates a resampler for interleaved samples of `channels` channels
+    pub fn new(
+        input_rate: usize,
+        output_rate: usize,
+        chunk_size: usize,
+        channels: usize,
+    ) -> Result<Self, AudioPlayerError> {
+        Self::with_chunk_size(
+            ResamplingAlgorithm::Fft,
+            input_rate,
+            output_rate,
+            chunk_size,
+            channels,
+        )
+    }
+
+    /// Creates a resampler using `algorithm`, or the built-in one replacing it
+    fn with_chunk_size(
+        algorithm: ResamplingAlgorithm,
+        input_rate: usize,
+        output_rate: usize,
+        chunk_size: usize,
+        channels: usize,
+    ) -> Result<Self, AudioPlayerError> {
+        let algorithm = algorithm.available();
+        let resampler = build(input_rate, output_rate, chunk_size, channels, algorithm)?;
+        Ok(Self::with_resampler(
+            resampler,
+            input_rate,
+            output_rate,
+            channels,
+            algorithm,
+        ))
+    }
+
+    fn with_resampler(
+        resampler: Box<dyn ChunkResampler<T>>,
+        input_rate: usize,
+        output_rate: usize,
+        channels: usize,
+        algorithm: ResamplingAlgorithm,
+    ) -> Self {
+        // the chunk sizes have a maximum, so the buffers never need to grow after this
+        let input_frames = resampler.input_frames_max();
+        let output_frames = resampler.output_frames_max();
+
+        Self {
+            info: ResamplingInfo {
+                input_rate: input_rate as u32,
+                output_rate: output_rate as u32,
+                chunk_size: input_frames,
+                algorithm,
+            },
+            drift: 1.0,
+            resampler,
+            channels,
+            pre_resampled_buffer: Vec::with_capacity(input_frames * channels),
+            pre_resampled_split_buffers: (0..channels)
+                .map(|_| Vec::with_capacity(input_frames))
+                .collect(),
+            resample_process_buffers: (0..channels)
+                .map(|_| Vec::with_capacity(output_frames))
+                .collect(),
+            resampled_buffer: Vec::with_capacity(output_frames * channels),
+        }
+    }
+
+    /// Creates a resampler from `input_rate` to `output_rate` for `channels` channels,
+    /// or `None` if the rates are the same
+    pub fn for_rates(
+        input_rate: u32,
+        output_rate: u32,
+        channels: u16,
+        low_latency: bool,
+    ) -> Result<Option<Self>, AudioPlayerError> {
+        if input_rate == output_rate {
+            return Ok(None);
+        }
+        Self::new(
+            input_rate as usize,
+            output_rate as usize,
+            chunk_size(input_rate, low_latency),
+            channels as usize,
+        )
+        .map(Some)
+    }
+
+    /// Creates a resampler from `input_rate` to `output_rate` using `algorithm`, even if the rates are the same
+    pub fn with_algorithm(
+        algorithm: ResamplingAlgorithm,
+        input_rate: u32,
+        output_rate: u32,
+        channels: u16,
+        low_latency: bool,
+    ) -> Result<Self, AudioPlayerError> {
+        Self::with_chunk_size(
+            algorithm,
+            input_rate as usize,
+            output_rate as usize,
+            chunk_size(input_rate, low_latency),
+            channels as usize,
+        )
+    }
+
+    /// Adjust the ratio to follow the device clock, which plays `drift` times faster than its nominal rate.
+    ///
+    /// Does nothing for [`ResamplingAlgorithm::Fft`] resamplers.
+    pub fn set_drift(&mut self, drift: f64) {
+        let drift = drift.clamp(1.0 / MAX_DRIFT, MAX_DRIFT);
+        // small changes are not worth the work of the resampler
+        if (drift - self.drift).abs() > 1e-6 {
+            self.drift = drift;
+            self.resampler.set_ratio_relative(drift);
+        }
+    }
+
+    /// Pass the samples kept in the resampler to `output`, followed by silence to fill the last chunk.
+    pub fn flush(&mut self, mut output: impl FnMut(&[T])) {
+        // one chunk of silence pushes out the partial chunk and the delay of the resampler
+        // fed in small pieces, to avoid allocating a whole chunk of silence
+        let silence = [T::EQUILIBRIUM; 64];
+        let mut remaining = self.resampler.input_frames_next() * self.channels;
+        while remaining > 0 {
+            let len = remaining.min(silence.len());
+            self.resample(&silence[..len], &mut output);
+            remaining -= len;
+        }
+    }
+
+    /// Pass the samples kept in the resampler to `output`, without the silence used to flush them,
+    /// and reset it to start again from silence
+    pub fn drain(&mut self, mut output: impl FnMut(&[T])) {
+        // the samples in the resampler are the pending input, and the output delay
+        let pending_frames = (self.pre_resampled_buffer.len() / self.channels) as u64;
+        let frames = self.resampler.output_delay() as u64
+            + pending_frames * self.info.output_rate as u64 / self.info.input_rate as u64;
+        let mut remaining = frames as usize * self.channels;
+        while remaining > 0 {
+            self.flush(|samples| {
+                let len = samples.len().min(remaining);
+                if len > 0 {
+                    output(&samples[..len]);
+                    remaining -= len;
+                }
+            });
+        }
+        self.restore(&[]);
+    }
+
+    /// Resample all of `data` at once, without the delay of the resampler at the start,
+    /// and the silence used to flush it at the end
+    pub fn resample_all(&mut self, data: &[T]) -> Vec<T> {
+        let frames = (data.len() / self.channels) as u64 * self.info.output_rate as u64
+            / self.info.input_rate as u64;
+        let len = frames as usize * self.channels;
+        let delay = self.resampler.output_delay() * self.channels;
+
+        let mut resampled = Vec::with_capacity(delay + len);
+        self.resample(data, |samples| resampled.extend_from_slice(samples));
+        while resampled.len() < delay + len {
+            self.flush(|samples| resampled.extend_from_slice(samples));
+        }
+        resampled.drain(..delay);
+        resampled.truncate(len);
+        resampled
+    }
+
+    /// The delay added by the resampler, in input frames
+    pub fn delay(&self) -> usize {
+        let output_delay = self.resampler.output_delay() as u64;
+        (output_delay * self.info.input_rate as u64 / self.info.output_rate as u64) as usize
+    }
+
+    pub fn info(&self) -> ResamplingInfo {
+        self.info
+    }
+
+    /// The input samples waiting for a full chunk
+    pub fn pending(&self) -> &[T] {
+        &self.pre_resampled_buffer
+    }
+
+    /// Reset the resampler, and replace the input samples waiting for a full chunk with `pending`
+    pub fn restore(&mut self, pending: &[T]) {
+        self.resampler.reset();
+        self.pre_resampled_buffer.clear();
+        self.pre_resampled_buffer.extend_from_slice(pending);
+    }
+
+    /// Resample `data` into `producer`, returns the number of samples pushed
+    pub fn resample_into_producer(
+        &mut self,
+        data: &[T],
+        producer: &mut (impl RingProducer<T> + ?Sized),
+    ) -> usize {
+        let mut pushed = 0;
+        self.resample(data, |resampled| {
+            pushed += producer.push_slice(resampled);
+        });
+        pushed
+    }
+
+    /// Resample `data`, and pass the resampled samples to `output`.
+    ///
+    /// The resampler works in chunks, so the samples that don't fill a chunk are kept until
+    /// the next call, and `output` might be called multiple times or not at all.
+    pub fn resample(&mut self, mut data: &[T], mut output: impl FnMut(&[T])) {
+        // all the buffers are allocated on creation with the fixed chunk sizes of the resampler,
+        // so nothing here allocates
+        let input_len = self.resampler.input_frames_next() * self.channels;
+
+        // finish all the frames, as sometimes after appending many data
+        // we might get 2 loops worth of unprocessed audio
+        while !data.is_empty() {
+            let needed = input_len - self.pre_resampled_buffer.len();
+            let (chunk, rest) = data.split_at(needed.min(data.len()));
+            self.pre_resampled_buffer.extend_from_slice(chunk);
+            data = rest;
+
+            if self.pre_resampled_buffer.len() < input_len {
+                return;
+            }
+
+            // split channels into separate buffers
+            for (channel, split) in self.pre_resampled_split_buffers.iter_mut().enumerate() {
+                split.clear();
+                split.extend(
+                    self.pre_resampled_buffer
+                        .iter()
+                        .skip(channel)
+                        .step_by(self.channels),
+                );
+            }
+            self.pre_resampled_buffer.clear();
+
+            let output_frames = self.resampler.output_frames_next();
+            for buffer in self.resample_process_buffers.iter_mut() {
+                buffer.resize(output_frames, T::EQUILIBRIUM);
+            }
+
+            let output_frames = self.resampler.process(
+                &self.pre_resampled_split_buffers,
+                &mut self.resample_process_buffers,
+            );
+
+            // merge channels back into interleaved samples
+            self.resampled_buffer.clear();
+            for frame in 0..output_frames {
+                self.resampled_buffer.extend(
+                    self.resample_process_buffers
+                        .iter()
+                        .map(|buffer| buffer[frame]),
+                );
+            }
+
+            output(&self.resampled_buffer);
+        }
+    }
+}
+
+/// Measures the time spent resampling against the duration of the resampled audio
+pub(crate) struct LoadMonitor {
+    // the maximum time spent per second of audio
+    limit: f64,
+    time: Duration,
+    audio_seconds: f64,
+}
+
+impl LoadMonitor {
+    pub fn new(limit: f32) -> Self {
+        Self {
+            limit: limit as f64,
+            time: Duration::ZERO,
+            audio_seconds: 0.0,
+        }
+    }
+
+    /// Record resampling `frames` input frames of `sample_rate` in `elapsed`,
+    /// returns `true` if the resampling of the last window took longer than the limit
+    pub fn record(&mut self, elapsed: Duration, frames: usize, sample_rate: u32) -> bool {
+        self.time += elapsed;
+        self.audio_seconds += frames as f64 / sample_rate as f64;
+        if self.audio_seconds < LOAD_WINDOW_SECONDS {
+            return false;
+        }
+        let load = self.time.as_secs_f64() / self.audio_seconds;
+        self.time = Duration::ZERO;
+        self.audio_seconds = 0.0;
+        load > self.limit
+    }
+}
+
+/// The number of input frames resampled at once
+fn chunk_size(input_rate: u32, low_latency: bool) -> usize {
+    if low_latency {
+        // ~5ms of samples
+        input_rate as usize / 200
+    } else {
+        // the number of samples for one video frame in 60 FPS
+        input_rate as usize / 60
+    }
+}
@@ -0,0 +1,53 @@
+/// The last played frames, played backwards while rewinding
+pub(crate) struct RewindHistory<T> {
+    samples: Vec<T>,
+    channels: usize,
+    // where the next frame is written, in samples
+    position: usize,
+    // number of valid samples before `position`
+    len: usize,
+}
+
+impl<T: Copy> RewindHistory<T> {
+    pub fn new(frames: usize, channels: usize, silence: T) -> Self {
+        Self {
+            samples: vec![silence; frames * channels],
+            channels,
+            position: 0,
+            len: 0,
+        }
+    }
+
+    /// Record the played interleaved `data`
+    pub fn record(&mut self, data: &[T]) {
+        let capacity = self.samples.len();
+        if capacity == 0 {
+            return;
+        }
+        for &sample in data {
+            self.samples[self.position] = sample;
+            self.position = (self.position + 1) % capacity;
+        }
+        self.len = (self.len + data.len()).min(capacity);
+    }
+
+    /// Fill `output` with the recorded frames from the newest to the oldest, the played frames are removed,
+    /// so the next call continues further back.
+    ///
+    /// Returns the number of samples filled, the rest is left as is.
+    pub fn rewind_into(&mut self, output: &mut [T]) -> usize {
+        let capacity = self.samples.len();
+        let mut filled = 0;
+        for frame in output.chunks_exact_mut(self.channels) {
+            if self.len < self.channels {
+                break;
+            }
+            // the frame before `position`, keeping the order of the channels
+            self.position = (self.position + capacity - self.channels) % capacity;
+            self.len -= self.channels;
+            frame.copy_from_slice(&self.samples[self.position..self.position + self.channels]);
+            filled += self.channels;
+        }
+        filled
+    }
+}
@@ -0,0 +1,178 @@
+//! The queue between [`AudioPlayer::queue`](crate::AudioPlayer::queue) and the output callback.
+//!
+//! The samples are passed through a single-producer single-consumer ring buffer, the player writes
+//! into the [`RingProducer`], and the output callback reads from the [`RingConsumer`].
+//! By default, it's a [`ringbuf::HeapRb`], see [`HeapRing`].
+//!
+//! Other implementations, like a cache-padded ring or a ring in shared memory, can be used with
+//! [`AudioPlayerBuilder::ring_buffer`](crate::AudioPlayerBuilder::ring_buffer) by implementing [`SampleRing`].
+
+use std::mem::MaybeUninit;
+
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+/// The writing side of a ring buffer, used by the player.
+pub trait RingProducer<T>: Send {
+    /// Push as many samples of `samples` as there is space for, returns the number of samples pushed.
+    fn push_slice(&mut self, samples: &[T]) -> usize;
+
+    /// The number of samples in the buffer.
+    fn occupied_len(&self) -> usize;
+
+    /// The maximum number of samples the buffer can hold.
+    fn capacity(&self) -> usize;
+
+    /// The number of samples that can be pushed before the buffer is full.
+    fn vacant_len(&self) -> usize {
+        self.capacity().saturating_sub(self.occupied_len())
+    }
+
+    /// Write samples in place into the vacant space of the buffer: `write` is given the vacant space,
+    /// in two slices when it wraps around, filled with `fill`, and returns the number of samples it wrote
+    /// from the start of the first slice, which are then made visible to the consumer.
+    ///
+    /// Returns `None` by default, without calling `write`, the player then writes the samples
+    /// in a temporary buffer and pushes them with [`push_slice`](Self::push_slice).
+    fn write_in_place(
+        &mut self,
+        fill: T,
+        write: &mut dyn FnMut(&mut [T], &mut [T]) -> usize,
+    ) -> Option<usize> {
+        let _ = (fill, write);
+        None
+    }
+}
+
+/// The reading side of a ring buffer, used by the output callback.
+///
+/// The player also reads from it when it changes the buffer, for example in
+/// [`AudioPlayer::snapshot`](crate::AudioPlayer::snapshot), so all the methods should be
+/// usable from any thread.
+pub trait RingConsumer<T>: Send {
+    /// Pop samples into `output`, returns the number of samples popped.
+    ///
+    /// This is called from the output callback, so it should not block.
+    fn pop_slice(&mut self, output: &mut [T]) -> usize;
+
+    /// The samples in the buffer, in order, without removing them.
+    fn as_slices(&self) -> (&[T], &[T]);
+
+    /// The number of samples in the buffer.
+    fn occupied_len(&self) -> usize;
+
+    /// Remove up to `count` samples, returns the number of samples removed.
+    fn skip(&mut self, count: usize) -> usize;
+
+    /// Remove all the samples, returns the number of samples removed.
+    fn clear(&mut self) -> usize {
+        self.skip(self.occupied_len())
+    }
+}
+
+/// Creates the ring buffers of the player, see the [module docs](self).
+///
+/// A new ring is created when the buffer is resized, for example with
+/// [`AudioPlayer::set_buffer_size`](crate::AudioPlayer::set_buffer_size),
+/// the samples of the old one are moved into it.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, ring::{HeapRing, RingConsumer, RingProducer, SampleRing}, AudioPlayer, BufferSize};
+/// /// Round the capacity up to a power of two
+/// struct PowerOfTwoRing;
+///
+/// impl SampleRing<f32> for PowerOfTwoRing {
+///     fn split(&self, capacity: usize) -> (Box<dyn RingProducer<f32>>, Box<dyn RingConsumer<f32>>) {
+///         HeapRing.split(capacity.next_power_of_two())
+///     }
+/// }
+///
+/// let player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .buffer_size(BufferSize::Samples(1000))
+///     .ring_buffer(PowerOfTwoRing)
+///     .build()
+///     .unwrap();
+/// assert_eq!(player.buffer_capacity(), 1024);
+/// ```
+pub trait SampleRing<T>: Send {
+    /// Create a ring buffer holding at least `capacity` samples, and split it into its two sides.
+    fn split(&self, capacity: usize) -> (Box<dyn RingProducer<T>>, Box<dyn RingConsumer<T>>);
+}
+
+/// The default ring buffer, a [`ringbuf::HeapRb`] allocated on the heap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapRing;
+
+impl<T: Copy + Send + 'static> SampleRing<T> for HeapRing {
+    fn split(&self, capacity: usize) -> (Box<dyn RingProducer<T>>, Box<dyn RingConsumer<T>>) {
+        let (producer, consumer) = HeapRb::new(capacity.max(1)).split();
+        (Box::new(producer), Box::new(consumer))
+    }
+}
+
+impl<T: Copy + Send> RingProducer<T> for HeapProd<T> {
+    fn push_slice(&mut self, samples: &[T]) -> usize {
+        Producer::push_slice(self, samples)
+    }
+
+    fn occupied_len(&self) -> usize {
+        Observer::occupied_len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        Observer::capacity(self).get()
+    }
+
+    fn vacant_len(&self) -> usize {
+        Observer::vacant_len(self)
+    }
+
+    fn write_in_place(
+        &mut self,
+        fill: T,
+        write: &mut dyn FnMut(&mut [T], &mut [T]) -> usize,
+    ) -> Option<usize> {
+        let (first, second) = Producer::vacant_slices_mut(self);
+        for sample in first.iter_mut().chain(second.iter_mut()) {
+            sample.write(fill);
+        }
+        let vacant = first.len() + second.len();
+        // SAFETY: all the samples were initialized above
+        let written = unsafe {
+            write(
+                &mut *(first as *mut [MaybeUninit<T>] as *mut [T]),
+                &mut *(second as *mut [MaybeUninit<T>] as *mut [T]),
+            )
+        }
+        .min(vacant);
+        // SAFETY: the samples written are within the vacant ones, which were initialized
+        unsafe { Producer::advance_write_index(self, written) };
+        Some(written)
+    }
+}
+
+impl<T: Copy + Send> RingConsumer<T> for HeapCons<T> {
+    fn pop_slice(&mut self, output: &mut [T]) -> usize {
+        Consumer::pop_slice(self, output)
+    }
+
+    fn as_slices(&self) -> (&[T], &[T]) {
+        Consumer::as_slices(self)
+    }
+
+    fn occupied_len(&self) -> usize {
+        Observer::occupied_len(self)
+    }
+
+    fn skip(&mut self, count: usize) -> usize {
+        Consumer::skip(self, count)
+    }
+
+    fn clear(&mut self) -> usize {
+        Consumer::clear(self)
+    }
+}
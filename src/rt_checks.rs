@@ -0,0 +1,221 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The default time budget of the output callback, in percent of the duration of the filled samples
+const DEFAULT_TIME_BUDGET: u32 = 100;
+
+static TIME_BUDGET: AtomicU32 = AtomicU32::new(DEFAULT_TIME_BUDGET);
+
+thread_local! {
+    // the number of output callbacks running on this thread, they can be nested with `AudioEngine`
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+    // the number of `AllocPermit`s alive on this thread
+    static PERMITS: Cell<u32> = const { Cell::new(0) };
+    // the first violation of the outermost callback running on this thread
+    static VIOLATION: Cell<Option<Violation>> = const { Cell::new(None) };
+    // the output callback running on this thread panicked, its violations are ignored
+    static PANICKED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Something the output callback must not do
+#[derive(Debug, Clone, Copy)]
+enum Violation {
+    Allocation(usize),
+    Blocking(&'static str),
+    Overrun { elapsed: Duration, budget: Duration },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Allocation(size) => write!(f, "allocated {size} bytes"),
+            Violation::Blocking(operation) => write!(f, "blocked on {operation}"),
+            Violation::Overrun { elapsed, budget } => {
+                write!(f, "took {elapsed:?}, longer than its budget of {budget:?}")
+            }
+        }
+    }
+}
+
+/// Record `violation` if it's the first one of the output callback running on this thread
+fn report(violation: Violation) {
+    // the thread locals may be destroyed already when the thread exits
+    let _ = DEPTH.try_with(|depth| {
+        if depth.get() > 0 && PERMITS.with(Cell::get) == 0 {
+            VIOLATION.with(|current| {
+                if current.get().is_none() {
+                    current.set(Some(violation));
+                }
+            });
+        }
+    });
+}
+
+/// Ignore the violations of the output callback running on this thread, after it panicked
+pub(crate) fn ignore_panicked() {
+    PANICKED.with(|panicked| panicked.set(true));
+}
+
+/// A global allocator that records the allocations done in the output callback, behind the `debug-rt-checks` feature.
+///
+/// It allocates with [`System`], the allocations of the output callback make it panic after it fills the output,
+/// see [`assert_not_realtime`] for the other checks.
+/// Deallocations are not checked, since finished tracks and fades are dropped in the output callback.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, RtCheckAllocator};
+/// # use std::time::Duration;
+/// #[global_allocator]
+/// static ALLOCATOR: RtCheckAllocator = RtCheckAllocator;
+///
+/// # fn main() {
+/// let backend = NullBackend::new();
+/// let clock = backend.clock();
+/// let mut player = AudioPlayer::<f32>::builder(44100)
+///     .backend(backend)
+///     .build()
+///     .unwrap();
+/// player.queue(&[0.5; 441 * 2]);
+/// player.play().unwrap();
+///
+/// // panics if the output callback allocated
+/// clock.advance(Duration::from_millis(10));
+/// # }
+/// ```
+pub struct RtCheckAllocator;
+
+// SAFETY: all the allocations are done by `System`, recording the violation doesn't allocate
+unsafe impl GlobalAlloc for RtCheckAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        report(Violation::Allocation(layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        report(Violation::Allocation(layout.size()));
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        report(Violation::Allocation(new_size));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Allows the allocations on the current thread while alive, for allocations that are expected to be rare,
+/// like buffers growing when the callback size changes
+pub(crate) struct AllocPermit;
+
+impl AllocPermit {
+    pub fn new() -> Self {
+        PERMITS.with(|permits| permits.set(permits.get() + 1));
+        Self
+    }
+}
+
+impl Drop for AllocPermit {
+    fn drop(&mut self) {
+        let _ = PERMITS.try_with(|permits| permits.set(permits.get() - 1));
+    }
+}
+
+/// Run `f` without checking its allocations, behind the `debug-rt-checks` feature.
+///
+/// Use it for allocations of effects that happen once, like growing a buffer to the callback size,
+/// the other allocations of the output callback make [`RtCheckAllocator`] panic.
+pub fn permit_alloc<R>(f: impl FnOnce() -> R) -> R {
+    let _permit = AllocPermit::new();
+    f()
+}
+
+/// Fail if this is called in the output callback, behind the `debug-rt-checks` feature.
+///
+/// Call it before blocking operations, like locking a mutex or doing I/O,
+/// in code that can run in the output callback, for example in an [`AudioEffect`](crate::effects::AudioEffect).
+/// The output callback panics after filling the output if it was called, naming the `operation`.
+///
+/// # Example
+/// ```rust
+/// # use std::sync::{Arc, Mutex};
+/// # use dynwave::{assert_not_realtime, effects::AudioEffect, backend::OutputConfig};
+/// struct SharedGain(Arc<Mutex<f32>>);
+///
+/// impl AudioEffect<f32> for SharedGain {
+///     fn process(&mut self, samples: &mut [f32], _config: &OutputConfig) {
+///         assert_not_realtime("locking the gain");
+///         let gain = *self.0.lock().unwrap();
+///         samples.iter_mut().for_each(|sample| *sample *= gain);
+///     }
+/// }
+/// ```
+pub fn assert_not_realtime(operation: &'static str) {
+    report(Violation::Blocking(operation));
+}
+
+/// Whether the current thread is running the output callback, behind the `debug-rt-checks` feature.
+pub fn is_realtime() -> bool {
+    DEPTH.try_with(Cell::get).unwrap_or(0) > 0
+}
+
+/// Set the time the output callback can take, as a fraction of the duration of the samples it fills,
+/// behind the `debug-rt-checks` feature. The default is `1.0`, the callback panics if it takes longer.
+///
+/// Debug builds are slower, so tests may need a larger budget.
+pub fn set_rt_time_budget(fraction: f32) {
+    let percent = (fraction.max(0.0) * 100.0).round() as u32;
+    TIME_BUDGET.store(percent, Ordering::Relaxed);
+}
+
+/// An output callback running on the current thread, its violations make it panic on [`finish`](Self::finish)
+pub(crate) struct RealtimeSection {
+    start: Instant,
+    budget: Duration,
+}
+
+impl RealtimeSection {
+    /// Start checking the callback filling `duration` of samples
+    pub fn enter(duration: Duration) -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let budget = duration * TIME_BUDGET.load(Ordering::Relaxed) / 100;
+        Self {
+            start: Instant::now(),
+            budget,
+        }
+    }
+
+    /// Stop checking the callback, panics if it violated one of the checks
+    pub fn finish(self) {
+        let elapsed = self.start.elapsed();
+        // an empty callback has no deadline
+        if !self.budget.is_zero() && elapsed > self.budget && !PANICKED.with(Cell::get) {
+            report(Violation::Overrun {
+                elapsed,
+                budget: self.budget,
+            });
+        }
+        let depth = DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get()
+        });
+        // nested callbacks are reported by the outermost one
+        if depth == 0 {
+            let violation = VIOLATION.with(Cell::take);
+            if PANICKED.with(|panicked| panicked.replace(false)) {
+                return;
+            }
+            if let Some(violation) = violation {
+                panic!("real-time safety violation in the output callback: it {violation}");
+            }
+        }
+    }
+}
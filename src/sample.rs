@@ -0,0 +1,147 @@
+//! The traits of the samples played by the players
+
+use cpal::{FromSample, SizedSample};
+
+/// A sample that can be queued into a player, implemented for [`f32`], [`f64`], [`i16`], [`i32`] and [`u8`].
+///
+/// The players process the samples as floats, the samples are promoted to their [`Promoted`](Self::Promoted) type,
+/// [`f64`] for [`f64`] and [`i32`] which have more precision than [`f32`], and [`f32`] for the others.
+/// See [`AudioPlayer::queue_samples`](crate::AudioPlayer::queue_samples).
+pub trait Sample: SizedSample + Send + Sync + 'static {
+    /// The float type the sample is processed as.
+    type Promoted: FloatSample;
+
+    /// Promote the sample to its float type, in the range `-1.0..1.0`.
+    fn to_float(self) -> Self::Promoted;
+
+    /// Convert a float sample back to this type.
+    fn from_float(float: Self::Promoted) -> Self;
+}
+
+macro_rules! impl_sample {
+    ($($ty:ident => $float:ident),*) => {
+        $(
+            impl Sample for $ty {
+                type Promoted = $float;
+
+                fn to_float(self) -> $float {
+                    $float::from_sample_(self)
+                }
+
+                fn from_float(float: $float) -> Self {
+                    $ty::from_sample_(float)
+                }
+            }
+        )*
+    };
+}
+
+impl_sample!(f32 => f32, f64 => f64, i16 => f32, i32 => f64, u8 => f32);
+
+/// The float samples the players process, implemented for [`f32`] and [`f64`].
+///
+/// [`AudioPlayer`](crate::AudioPlayer) and the other types processing samples are generic over it,
+/// samples of other [`Sample`] types are promoted to it when queued.
+///
+/// This trait is sealed, it can't be implemented outside of this crate.
+pub trait FloatSample
+where
+    Self: Sample<Promoted = Self>
+        + sealed::Sealed
+        + PartialOrd
+        + std::fmt::Debug
+        + std::ops::Add<Output = Self>
+        + std::ops::Sub<Output = Self>
+        + std::ops::Mul<Output = Self>
+        + std::ops::Div<Output = Self>
+        + std::ops::Neg<Output = Self>
+        + std::ops::AddAssign
+        + std::ops::SubAssign
+        + std::ops::MulAssign
+        + std::ops::DivAssign,
+{
+}
+
+impl FloatSample for f32 {}
+
+impl FloatSample for f64 {}
+
+/// The math used internally on [`FloatSample`], from rubato with the `resample` feature
+mod sealed {
+    #[cfg(feature = "resample")]
+    pub trait Sealed: rubato::Sample {}
+
+    #[cfg(not(feature = "resample"))]
+    pub trait Sealed: super::imp::Float {}
+
+    impl Sealed for f32 {}
+
+    impl Sealed for f64 {}
+}
+
+/// Replacement of [`rubato::Sample`] without the `resample` feature
+#[cfg(not(feature = "resample"))]
+mod imp {
+    /// The trait used to coerce a value infallibly from one type to another, like `value as T`.
+    pub trait CoerceFrom<T> {
+        /// Perform a coercion from `value` into the current type.
+        fn coerce_from(value: T) -> Self;
+    }
+
+    pub trait Float: Copy + CoerceFrom<usize> + CoerceFrom<f64> + CoerceFrom<f32> {
+        /// The constant π.
+        const PI: Self;
+
+        /// Calculate the sine of `self`.
+        fn sin(self) -> Self;
+
+        /// Calculate the cosine of `self`.
+        fn cos(self) -> Self;
+
+        /// Coerce `value` into the current type.
+        fn coerce<T>(value: T) -> Self
+        where
+            Self: CoerceFrom<T>,
+        {
+            Self::coerce_from(value)
+        }
+    }
+
+    macro_rules! impl_float {
+        ($($ty:ident),*) => {
+            $(
+                impl Float for $ty {
+                    const PI: Self = std::$ty::consts::PI;
+
+                    fn sin(self) -> Self {
+                        $ty::sin(self)
+                    }
+
+                    fn cos(self) -> Self {
+                        $ty::cos(self)
+                    }
+                }
+
+                impl CoerceFrom<usize> for $ty {
+                    fn coerce_from(value: usize) -> Self {
+                        value as $ty
+                    }
+                }
+
+                impl CoerceFrom<f32> for $ty {
+                    fn coerce_from(value: f32) -> Self {
+                        value as $ty
+                    }
+                }
+
+                impl CoerceFrom<f64> for $ty {
+                    fn coerce_from(value: f64) -> Self {
+                        value as $ty
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_float!(f32, f64);
+}
@@ -0,0 +1,14 @@
+use std::time::Instant;
+
+/// When the queued samples start playing, see [`AudioPlayer::play_at`](crate::AudioPlayer::play_at).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartTime {
+    /// Start after this number of output frames, counted from the next output callback.
+    AfterFrames(u64),
+    /// Start when the device plays at this instant.
+    ///
+    /// The time is converted to frames using the playback timestamps of the device, if the backend
+    /// reports them with [`OutputSource::report_playback_time`](crate::backend::OutputSource::report_playback_time),
+    /// otherwise from the time of the output callback.
+    At(Instant),
+}
@@ -0,0 +1,599 @@
+//! A ring buffer in shared memory, to play the samples produced by another process
+
+use std::{
+    marker::PhantomData,
+    mem,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use crate::sample::FloatSample;
+use crate::{error::AudioPlayerError, AudioPlayer};
+
+/// Written at the start of the memory, to check that it was created by [`SharedMemoryConsumer`]
+const MAGIC: u32 = u32::from_le_bytes(*b"DWSM");
+
+/// The version of the layout of the memory, both sides must use the same one
+const VERSION: u32 = 1;
+
+/// The samples start after the header, aligned for all the sample types
+const DATA_OFFSET: usize = 64;
+
+/// The channels of the samples, they are interleaved stereo like [`AudioPlayer::queue`]
+const CHANNELS: u64 = 2;
+
+/// The start of the shared memory, the positions only grow, the samples are at `position % capacity`
+#[repr(C)]
+struct Header {
+    magic: u32,
+    version: u32,
+    format: u32,
+    // the sample rate of the producer, `0` while no producer is connected
+    sample_rate: AtomicU32,
+    capacity: u64,
+    write: AtomicU64,
+    read: AtomicU64,
+    // samples the producer dropped because the ring was full
+    dropped: AtomicU64,
+}
+
+const _: () = assert!(mem::size_of::<Header>() <= DATA_OFFSET);
+
+/// A number for each sample format, stored in the header to check that both sides use the same one
+fn format_code(format: SampleFormat) -> u32 {
+    match format {
+        SampleFormat::I8 => 1,
+        SampleFormat::I16 => 2,
+        SampleFormat::I32 => 3,
+        SampleFormat::I64 => 4,
+        SampleFormat::U8 => 5,
+        SampleFormat::U16 => 6,
+        SampleFormat::U32 => 7,
+        SampleFormat::U64 => 8,
+        SampleFormat::F32 => 9,
+        SampleFormat::F64 => 10,
+        _ => 0,
+    }
+}
+
+fn invalid(message: &str) -> AudioPlayerError {
+    AudioPlayerError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        message,
+    ))
+}
+
+/// The shared memory and the typed views into it
+struct Ring<T> {
+    mapping: imp::Mapping,
+    capacity: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: SizedSample> Ring<T> {
+    fn header(&self) -> &Header {
+        // SAFETY: the mapping is at least `DATA_OFFSET` long, page aligned, and the header
+        // only has atomics besides the fields written before the other side opens it
+        unsafe { &*(self.mapping.ptr as *const Header) }
+    }
+
+    fn data(&self) -> *mut T {
+        // SAFETY: the mapping holds `capacity` samples after `DATA_OFFSET`
+        unsafe { self.mapping.ptr.add(DATA_OFFSET) as *mut T }
+    }
+
+    /// The samples written and not read yet from `read`, or `None` if the write position
+    /// doesn't fit in the ring, the other process can write anything into the header
+    fn occupied(&self, read: u64) -> Option<usize> {
+        let occupied = self
+            .header()
+            .write
+            .load(Ordering::Acquire)
+            .wrapping_sub(read);
+        (occupied <= self.capacity as u64).then_some(occupied as usize)
+    }
+}
+
+/// The side of the process playing the samples, it creates the shared memory
+/// that a [`SharedMemoryProducer`] in another process writes into.
+///
+/// This is for emulator cores running sandboxed in another process, the core writes the samples
+/// into the shared memory, and the process with the [`AudioPlayer`] moves them into the player with
+/// [`queue_into`](Self::queue_into), which resamples them like [`AudioPlayer::queue`].
+///
+/// The samples are interleaved stereo. The sample format is the type of both sides, and the sample rate
+/// is set by the producer when it connects, see [`sample_rate`](Self::sample_rate).
+/// When the ring is full, the producer drops the samples, see [`dropped`](Self::dropped).
+///
+/// The producer is not trusted, the consumer keeps its own read position and checks the write position
+/// of the producer before reading, the invalid ones are skipped and counted, see [`protocol_errors`](Self::protocol_errors).
+///
+/// Only available with the `shared-memory` feature, on Unix (except Android) with `shm_open`,
+/// and on Windows with named file mappings.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, SharedMemoryConsumer, SharedMemoryProducer};
+/// let name = format!("dynwave-example-{}", std::process::id());
+/// // in the process of the player
+/// let mut consumer = SharedMemoryConsumer::<f32>::create(&name, 4800).unwrap();
+/// assert_eq!(consumer.sample_rate(), None);
+///
+/// // in the process of the core
+/// let mut producer = SharedMemoryProducer::<f32>::open(&name, 48000).unwrap();
+/// producer.push(&[0.5; 480 * 2]);
+///
+/// // the player is created once the producer connected
+/// let sample_rate = consumer.sample_rate().unwrap();
+/// let mut player = AudioPlayer::<f32>::builder(sample_rate)
+///     .backend(NullBackend::new())
+///     .build()
+///     .unwrap();
+/// assert_eq!(consumer.queue_into(&mut player), 480 * 2);
+/// assert_eq!(player.buffered_samples(), 480 * 2);
+/// ```
+pub struct SharedMemoryConsumer<T> {
+    ring: Ring<T>,
+    // the read position, the one in the header is only published for the producer
+    read: u64,
+    protocol_errors: u64,
+    scratch: Vec<T>,
+}
+
+impl<T: SizedSample> SharedMemoryConsumer<T> {
+    /// Create the shared memory named `name`, holding `capacity` samples.
+    ///
+    /// Returns [`AudioPlayerError::Io`] if the memory couldn't be created, or if it already exists.
+    /// The memory is removed when the consumer is dropped.
+    pub fn create(name: &str, capacity: usize) -> Result<Self, AudioPlayerError> {
+        let capacity = (capacity - capacity % CHANNELS as usize).max(CHANNELS as usize);
+        let mapping = imp::Mapping::create(name, DATA_OFFSET + capacity * mem::size_of::<T>())?;
+        // SAFETY: the memory was just created, so nobody else accesses it yet
+        unsafe {
+            (mapping.ptr as *mut Header).write(Header {
+                magic: MAGIC,
+                version: VERSION,
+                format: format_code(T::FORMAT),
+                sample_rate: AtomicU32::new(0),
+                capacity: capacity as u64,
+                write: AtomicU64::new(0),
+                read: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+            });
+        }
+        Ok(Self {
+            ring: Ring {
+                mapping,
+                capacity,
+                _phantom: PhantomData,
+            },
+            read: 0,
+            protocol_errors: 0,
+            scratch: Vec::with_capacity(capacity),
+        })
+    }
+
+    /// The sample rate of the connected producer, or `None` if no producer is connected.
+    pub fn sample_rate(&self) -> Option<u32> {
+        match self.ring.header().sample_rate.load(Ordering::Acquire) {
+            0 => None,
+            sample_rate => Some(sample_rate),
+        }
+    }
+
+    /// The number of samples waiting to be moved into the player.
+    pub fn available(&self) -> usize {
+        self.ring.occupied(self.read).unwrap_or(0)
+    }
+
+    /// The number of samples the producer dropped because the shared memory was full,
+    /// the player doesn't take them fast enough.
+    pub fn dropped(&self) -> u64 {
+        self.ring.header().dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of times the producer moved its write position outside of the shared memory,
+    /// the samples it wrote were skipped.
+    ///
+    /// This only happens with a broken or malicious producer.
+    pub fn protocol_errors(&self) -> u64 {
+        self.protocol_errors
+    }
+
+    /// Queue the available samples into `player`, returns the number of samples queued.
+    ///
+    /// Only the samples that fit in the buffer of the player are taken, see [`AudioPlayer::try_queue`],
+    /// the rest stay in the shared memory for the next call.
+    pub fn queue_into(&mut self, player: &mut AudioPlayer<T>) -> usize
+    where
+        T: FloatSample + Send + 'static,
+        i8: FromSample<T>,
+        i16: FromSample<T>,
+        i32: FromSample<T>,
+        i64: FromSample<T>,
+        u8: FromSample<T>,
+        u16: FromSample<T>,
+        u32: FromSample<T>,
+        u64: FromSample<T>,
+        f32: FromSample<T>,
+        f64: FromSample<T>,
+    {
+        let header = self.ring.header();
+        let read = self.read;
+        let Some(available) = self.ring.occupied(read) else {
+            // continue from the position of the producer, the samples before it can't be trusted
+            self.protocol_errors += 1;
+            self.read = header.write.load(Ordering::Acquire);
+            header.read.store(self.read, Ordering::Release);
+            return 0;
+        };
+        let start = (read % self.ring.capacity as u64) as usize;
+        let first = available.min(self.ring.capacity - start);
+        self.scratch.clear();
+        // SAFETY: `available` is at most the capacity, so both parts are in the mapping,
+        // and the producer doesn't write the samples between `read` and `write` until `read` moves
+        unsafe {
+            let data = self.ring.data();
+            self.scratch
+                .extend_from_slice(std::slice::from_raw_parts(data.add(start), first));
+            self.scratch
+                .extend_from_slice(std::slice::from_raw_parts(data, available - first));
+        }
+        let queued = match player.try_queue(&self.scratch) {
+            Ok(()) => self.scratch.len(),
+            Err(err) => err.accepted,
+        };
+        self.read = read.wrapping_add(queued as u64);
+        header.read.store(self.read, Ordering::Release);
+        queued
+    }
+}
+
+/// The side of the process producing the samples, it writes into the shared memory
+/// of a [`SharedMemoryConsumer`] in another process, see its docs for more details.
+///
+/// Only one producer can be connected at a time, the next one can connect after it's dropped.
+pub struct SharedMemoryProducer<T> {
+    ring: Ring<T>,
+}
+
+impl<T: SizedSample> SharedMemoryProducer<T> {
+    /// Open the shared memory named `name`, and connect to its consumer with samples of `sample_rate`.
+    ///
+    /// Returns [`AudioPlayerError::Io`] if the memory doesn't exist, was created for another sample format,
+    /// or if another producer is connected.
+    pub fn open(name: &str, sample_rate: u32) -> Result<Self, AudioPlayerError> {
+        if sample_rate == 0 {
+            return Err(invalid("the sample rate must not be zero"));
+        }
+        let mapping = imp::Mapping::open(name)?;
+        if mapping.len < DATA_OFFSET {
+            return Err(invalid("the shared memory is too small"));
+        }
+        // SAFETY: the mapping is at least as long as the header
+        let header = unsafe { &*(mapping.ptr as *const Header) };
+        if header.magic != MAGIC || header.version != VERSION {
+            return Err(invalid(
+                "the shared memory was not created by this version of dynwave",
+            ));
+        }
+        if header.format != format_code(T::FORMAT) {
+            return Err(invalid(
+                "the shared memory was created for another sample format",
+            ));
+        }
+        let capacity = header.capacity as usize;
+        if capacity == 0 || DATA_OFFSET + capacity * mem::size_of::<T>() > mapping.len {
+            return Err(invalid("the shared memory is too small"));
+        }
+        if header
+            .sample_rate
+            .compare_exchange(0, sample_rate, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(invalid(
+                "another producer is connected to the shared memory",
+            ));
+        }
+        Ok(Self {
+            ring: Ring {
+                mapping,
+                capacity,
+                _phantom: PhantomData,
+            },
+        })
+    }
+
+    /// Write interleaved stereo samples, returns the number of samples written.
+    ///
+    /// The samples that don't fit are dropped and counted, see [`SharedMemoryConsumer::dropped`].
+    pub fn push(&mut self, data: &[T]) -> usize {
+        let header = self.ring.header();
+        let write = header.write.load(Ordering::Relaxed);
+        let vacant = self.vacant();
+        // only whole frames, so the channels stay in order
+        let len = data.len().min(vacant);
+        let len = len - len % CHANNELS as usize;
+        let start = (write % self.ring.capacity as u64) as usize;
+        let first = len.min(self.ring.capacity - start);
+        // SAFETY: the consumer doesn't read the samples after `write` until `write` moves
+        unsafe {
+            let out = self.ring.data();
+            std::ptr::copy_nonoverlapping(data.as_ptr(), out.add(start), first);
+            std::ptr::copy_nonoverlapping(data.as_ptr().add(first), out, len - first);
+        }
+        header
+            .write
+            .store(write.wrapping_add(len as u64), Ordering::Release);
+        if len < data.len() {
+            header
+                .dropped
+                .fetch_add((data.len() - len) as u64, Ordering::Relaxed);
+        }
+        len
+    }
+
+    /// The number of samples that can be written before the shared memory is full.
+    pub fn vacant(&self) -> usize {
+        let read = self.ring.header().read.load(Ordering::Acquire);
+        // the consumer only publishes valid positions, unless the header was overwritten
+        self.ring
+            .occupied(read)
+            .map_or(0, |occupied| self.ring.capacity - occupied)
+    }
+
+    /// The number of samples dropped because the shared memory was full.
+    pub fn dropped(&self) -> u64 {
+        self.ring.header().dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for SharedMemoryProducer<T> {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is at least as long as the header, checked on open
+        let header = unsafe { &*(self.ring.mapping.ptr as *const Header) };
+        header.sample_rate.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(all(unix, not(target_os = "android")))]
+mod imp {
+    use std::{ffi::CString, io};
+
+    /// The shared memory mapped into this process
+    pub struct Mapping {
+        pub ptr: *mut u8,
+        pub len: usize,
+        // the name to remove the memory with, only for the side that created it
+        owned_name: Option<CString>,
+    }
+
+    // SAFETY: the memory is only accessed through atomics and the ring positions
+    unsafe impl Send for Mapping {}
+
+    fn name(name: &str) -> io::Result<CString> {
+        Ok(CString::new(format!("/{name}"))?)
+    }
+
+    impl Mapping {
+        pub fn create(name: &str, len: usize) -> io::Result<Self> {
+            let name = self::name(name)?;
+            #[cfg(target_vendor = "apple")]
+            let mode = 0o600 as libc::c_uint;
+            #[cfg(not(target_vendor = "apple"))]
+            let mode: libc::mode_t = 0o600;
+            // SAFETY: the name is a valid C string, and the descriptor is closed after mapping
+            unsafe {
+                let fd = libc::shm_open(
+                    name.as_ptr(),
+                    libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                    mode,
+                );
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let result = if libc::ftruncate(fd, len as libc::off_t) == 0 {
+                    map(fd, len)
+                } else {
+                    Err(io::Error::last_os_error())
+                };
+                libc::close(fd);
+                match result {
+                    Ok(ptr) => Ok(Self {
+                        ptr,
+                        len,
+                        owned_name: Some(name),
+                    }),
+                    Err(err) => {
+                        libc::shm_unlink(name.as_ptr());
+                        Err(err)
+                    }
+                }
+            }
+        }
+
+        pub fn open(name: &str) -> io::Result<Self> {
+            let name = self::name(name)?;
+            // SAFETY: the name is a valid C string, and the descriptor is closed after mapping
+            unsafe {
+                let fd = libc::shm_open(name.as_ptr(), libc::O_RDWR, 0);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let mut stat: libc::stat = std::mem::zeroed();
+                let result = if libc::fstat(fd, &mut stat) == 0 {
+                    let len = stat.st_size as usize;
+                    map(fd, len).map(|ptr| Self {
+                        ptr,
+                        len,
+                        owned_name: None,
+                    })
+                } else {
+                    Err(io::Error::last_os_error())
+                };
+                libc::close(fd);
+                result
+            }
+        }
+    }
+
+    /// Map the whole shared memory of `fd`
+    unsafe fn map(fd: libc::c_int, len: usize) -> io::Result<*mut u8> {
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ptr as *mut u8)
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: the pointer and length are the ones mapped
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+                if let Some(name) = &self.owned_name {
+                    libc::shm_unlink(name.as_ptr());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{ffi::OsStr, io, iter, mem, os::windows::ffi::OsStrExt};
+
+    use windows_sys::Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, INVALID_HANDLE_VALUE,
+        },
+        System::Memory::{
+            CreateFileMappingW, MapViewOfFile, OpenFileMappingW, UnmapViewOfFile, VirtualQuery,
+            FILE_MAP_ALL_ACCESS, MEMORY_BASIC_INFORMATION, MEMORY_MAPPED_VIEW_ADDRESS,
+            PAGE_READWRITE,
+        },
+    };
+
+    /// The shared memory mapped into this process
+    pub struct Mapping {
+        pub ptr: *mut u8,
+        pub len: usize,
+        handle: HANDLE,
+    }
+
+    // SAFETY: the memory is only accessed through atomics and the ring positions
+    unsafe impl Send for Mapping {}
+
+    fn name(name: &str) -> Vec<u16> {
+        OsStr::new(&format!("Local\\{name}"))
+            .encode_wide()
+            .chain(iter::once(0))
+            .collect()
+    }
+
+    impl Mapping {
+        pub fn create(name: &str, len: usize) -> io::Result<Self> {
+            let name = self::name(name);
+            // SAFETY: the name is null terminated, and the handle is closed on errors
+            unsafe {
+                let handle = CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    std::ptr::null(),
+                    PAGE_READWRITE,
+                    (len as u64 >> 32) as u32,
+                    len as u32,
+                    name.as_ptr(),
+                );
+                if handle == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if GetLastError() == ERROR_ALREADY_EXISTS {
+                    CloseHandle(handle);
+                    return Err(io::ErrorKind::AlreadyExists.into());
+                }
+                Self::map(handle)
+            }
+        }
+
+        pub fn open(name: &str) -> io::Result<Self> {
+            let name = self::name(name);
+            // SAFETY: the name is null terminated, and the handle is closed on errors
+            unsafe {
+                let handle = OpenFileMappingW(FILE_MAP_ALL_ACCESS, 0, name.as_ptr());
+                if handle == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Self::map(handle)
+            }
+        }
+
+        /// Map the whole memory of `handle`, it's closed on errors
+        unsafe fn map(handle: HANDLE) -> io::Result<Self> {
+            let view = MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, 0);
+            if view.Value.is_null() {
+                let err = io::Error::last_os_error();
+                CloseHandle(handle);
+                return Err(err);
+            }
+            let mut info: MEMORY_BASIC_INFORMATION = mem::zeroed();
+            VirtualQuery(
+                view.Value,
+                &mut info,
+                mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            );
+            Ok(Self {
+                ptr: view.Value as *mut u8,
+                len: info.RegionSize,
+                handle,
+            })
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: the view and handle are the ones opened, the memory is removed
+            // by the system when all the handles are closed
+            unsafe {
+                UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
+                    Value: self.ptr as *mut _,
+                });
+                CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(all(unix, not(target_os = "android")), windows)))]
+mod imp {
+    use std::io;
+
+    pub struct Mapping {
+        pub ptr: *mut u8,
+        pub len: usize,
+    }
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "shared memory is not supported on this platform",
+        )
+    }
+
+    impl Mapping {
+        pub fn create(_name: &str, _len: usize) -> io::Result<Self> {
+            Err(unsupported())
+        }
+
+        pub fn open(_name: &str) -> io::Result<Self> {
+            Err(unsupported())
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use cpal::FromSample;
+
+/// Callback called when the queued samples become silent or stop being silent
+pub(crate) type SilenceCallback = Box<dyn FnMut(bool) + Send + 'static>;
+
+/// Configuration of the silence detection of the queued samples,
+/// set with [`AudioPlayerBuilder::silence_detection`](crate::AudioPlayerBuilder::silence_detection).
+///
+/// The queued samples are silent when all of them are below the threshold for the whole duration.
+/// This only looks at the queued samples, so not queueing anything (underrun) is not detected as silence.
+///
+/// # Example
+/// ```rust
+/// # use dynwave::{backend::NullBackend, AudioPlayer, SilenceDetection};
+/// # use std::time::Duration;
+/// let mut player = AudioPlayer::<f32>::builder(48000)
+///     .backend(NullBackend::new())
+///     .silence_detection(SilenceDetection::new(Duration::from_millis(100)).suspend(true))
+///     .on_silence(|silent| println!("silent: {silent}"))
+///     .build()
+///     .unwrap();
+/// player.play().unwrap();
+///
+/// player.queue(&[0.0; 9600]);
+/// assert!(player.is_silent());
+/// assert!(player.is_suspended());
+///
+/// // resumes as soon as sound is queued
+/// player.queue(&[0.5; 2]);
+/// assert!(!player.is_suspended());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceDetection {
+    pub(crate) duration: Duration,
+    pub(crate) threshold: f32,
+    pub(crate) suspend: bool,
+}
+
+impl SilenceDetection {
+    /// Detect silence after `duration` of silent samples, with a threshold of `-60dBFS`.
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            threshold: 0.001,
+            suspend: false,
+        }
+    }
+
+    /// Set the level below which a sample is silent, linear, `1.0` is full scale.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.abs();
+        self
+    }
+
+    /// Pause the output stream while silent, and resume it when non silent samples are queued.
+    ///
+    /// This lets the audio device go idle, saving power, for example while the emulator is in a menu.
+    pub fn suspend(mut self, suspend: bool) -> Self {
+        self.suspend = suspend;
+        self
+    }
+}
+
+/// Tracks the silence of the queued samples
+pub(crate) struct SilenceDetector {
+    pub config: SilenceDetection,
+    silent_frames: u64,
+    silent: bool,
+}
+
+impl SilenceDetector {
+    pub fn new(config: SilenceDetection) -> Self {
+        Self {
+            config,
+            silent_frames: 0,
+            silent: false,
+        }
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
+    /// Check the queued stereo `data`, returns the new silence state if it changed
+    pub fn process<T: Copy>(&mut self, data: &[T], sample_rate: u32) -> Option<bool>
+    where
+        f32: FromSample<T>,
+    {
+        // frames since the last non silent sample in `data`
+        let trailing_silent = data
+            .iter()
+            .rev()
+            .take_while(|&&sample| f32::from_sample_(sample).abs() <= self.config.threshold)
+            .count()
+            / 2;
+
+        if trailing_silent == data.len() / 2 {
+            self.silent_frames += trailing_silent as u64;
+        } else {
+            self.silent_frames = trailing_silent as u64;
+        }
+
+        let silent_duration =
+            Duration::from_secs_f64(self.silent_frames as f64 / sample_rate as f64);
+        let silent = silent_duration >= self.config.duration;
+        if silent != self.silent {
+            self.silent = silent;
+            Some(silent)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// Notices when the machine slept between two checks, set with
+/// [`AudioPlayerBuilder::sleep_recovery`](crate::AudioPlayerBuilder::sleep_recovery)
+///
+/// The monotonic clock behind [`Instant`] doesn't count the time asleep on Linux and macOS,
+/// while the wall clock does, so the sleep shows as the wall clock moving ahead of it.
+pub(crate) struct SleepDetector {
+    threshold: Duration,
+    last: Option<(Instant, SystemTime)>,
+}
+
+impl SleepDetector {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            last: None,
+        }
+    }
+
+    /// Returns `true` if the wall clock moved ahead of the monotonic clock by more than the threshold
+    /// since the last check
+    pub fn check(&mut self, now: Instant, wall_now: SystemTime) -> bool {
+        let Some((last, wall_last)) = self.last.replace((now, wall_now)) else {
+            return false;
+        };
+        // a wall clock moved backwards is not a sleep
+        let Ok(wall_elapsed) = wall_now.duration_since(wall_last) else {
+            return false;
+        };
+        wall_elapsed.saturating_sub(now.saturating_duration_since(last)) > self.threshold
+    }
+}
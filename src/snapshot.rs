@@ -0,0 +1,22 @@
+/// The samples waiting to be played by an [`AudioPlayer`](crate::AudioPlayer),
+/// from [`AudioPlayer::snapshot`](crate::AudioPlayer::snapshot).
+///
+/// This is meant to be stored with the save states of an emulator, and restored with
+/// [`AudioPlayer::restore`](crate::AudioPlayer::restore) when the state is loaded, so the audio continues
+/// from where it was when the state was saved.
+///
+/// With the `serde` feature, this implements `Serialize` and `Deserialize`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioSnapshot<T> {
+    /// The sample rate of the queued samples in Hz.
+    pub sample_rate: u32,
+    /// The sample rate of the output in Hz, the sample rate of [`buffered`](Self::buffered).
+    pub output_sample_rate: u32,
+    /// The number of interleaved channels of the output.
+    pub channels: u16,
+    /// Queued samples that are waiting in the resampler, at [`sample_rate`](Self::sample_rate).
+    pub pending: Vec<T>,
+    /// Samples in the buffer waiting to be played, at [`output_sample_rate`](Self::output_sample_rate).
+    pub buffered: Vec<T>,
+}
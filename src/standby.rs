@@ -0,0 +1,29 @@
+use crate::{
+    backend::{OutputBackend, OutputConfig},
+    resampler::AudioResampler,
+    sample::FloatSample,
+};
+
+/// The resamplers to move the output from one config to another, created before the switch
+pub(crate) struct OutputSwitch<T: FloatSample> {
+    /// The config of the output the resamplers were created for
+    pub from: OutputConfig,
+    pub config: OutputConfig,
+    /// `None` if the sample rate doesn't change
+    pub resamplers: Option<RateChange<T>>,
+}
+
+/// The resamplers used when the output moves to another sample rate
+pub(crate) struct RateChange<T: FloatSample> {
+    /// Converts the buffered samples to the new rate
+    pub converter: Option<AudioResampler<T>>,
+    /// Resamples the queued samples to the new rate
+    pub resampler: Option<AudioResampler<T>>,
+}
+
+/// A backend negotiated ahead of time, used when the output stream is lost,
+/// see [`AudioPlayer::set_standby_device`](crate::AudioPlayer::set_standby_device)
+pub(crate) struct Standby<T: FloatSample> {
+    pub backend: Box<dyn OutputBackend<T>>,
+    pub switch: OutputSwitch<T>,
+}
@@ -0,0 +1,436 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::CallbackError, stats::StatsRecorder, timestamps::CallbackTimestamp, volume::VolumeFade,
+};
+
+/// The maximum number of errors reported by the backends kept until they are taken
+pub(crate) const STREAM_ERRORS_CAPACITY: usize = 64;
+
+/// The longest time a thread waiting for space parks before checking again,
+/// the output notifies without locking, so a notification can be missed
+const MAX_SPACE_PARK: Duration = Duration::from_millis(5);
+
+/// The playback state of an [`AudioPlayer`](crate::AudioPlayer).
+///
+/// cpal doesn't expose the state of a stream, so this is tracked internally from the calls to
+/// [`play`](crate::AudioPlayer::play) and [`pause`](crate::AudioPlayer::pause),
+/// and from the output callback for underruns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    /// The player is paused, this is the state right after creation.
+    Paused,
+    /// The player is playing the queued samples.
+    Playing,
+    /// The player is playing, but the buffer ran out of samples, so silence is being played.
+    ///
+    /// The state goes back to [`PlayerState::Playing`] as soon as new samples are queued and played.
+    Underrun,
+}
+
+impl PlayerState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Paused,
+            1 => Self::Playing,
+            _ => Self::Underrun,
+        }
+    }
+}
+
+/// What happens to the queued samples when pausing with [`AudioPlayer::pause_with`](crate::AudioPlayer::pause_with).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PauseMode {
+    #[default]
+    /// Stop the stream and keep the queued samples, they are played when resumed.
+    ///
+    /// This is what [`pause`](crate::AudioPlayer::pause) does, the audio is cut,
+    /// and continues from the same place when resumed.
+    RetainBuffer,
+    /// Stop the stream and drop the queued samples, so nothing stale is played when resumed,
+    /// for example when switching to other content.
+    FlushBuffer,
+    /// Fade out the audio over a few milliseconds and keep the rest of the queued samples,
+    /// when resumed they are faded in, which avoids the clicks of cutting the audio,
+    /// for example when pausing a game.
+    ///
+    /// The stream keeps running and plays silence while paused, so the fade can finish.
+    FadeAndRetain,
+    /// Keep the stream running and play silence instead of the queued samples, which are kept
+    /// and played when resumed, like [`RetainBuffer`](Self::RetainBuffer) without stopping the stream.
+    ///
+    /// Some drivers power-cycle the device when the stream stops and starts, with loud pops
+    /// and a long delay before the audio resumes, this avoids it at the cost of keeping the device busy.
+    KeepAlive,
+}
+
+/// An output channel, for [`AudioPlayer::mute_channel`](crate::AudioPlayer::mute_channel).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The first output channel.
+    Left,
+    /// The second output channel.
+    Right,
+}
+
+impl Channel {
+    /// The index of the channel in an output frame
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Self::Left => 0,
+            Self::Right => 1,
+        }
+    }
+}
+
+/// State shared between the player and the output callback
+pub(crate) struct PlayerShared {
+    state: AtomicU8,
+    underruns: AtomicU64,
+    // after an underrun, the number of samples to buffer before playing again
+    resume_threshold: AtomicUsize,
+    // the measured device rate relative to its nominal rate, as `f64` bits
+    drift: AtomicU64,
+    // the sample rate the output consumes at, measured from the callbacks, as `f64` bits, `0.0` if unknown
+    measured_rate: AtomicU64,
+    // frames taken from the buffer by the output
+    frames_consumed: AtomicU64,
+    // frames filled by the output, including silence, only written by the output
+    frames_delivered: AtomicU64,
+    // the frames delivered at the last reset, only written by the player, so a reset can't lose a fill
+    frames_delivered_base: AtomicU64,
+    // the number of fills of the output, the players of a sync group start after the same one
+    fill_count: AtomicU64,
+    // the number of times samples were queued, the output pads with silence when it stops changing
+    queue_count: AtomicU64,
+    // frames filled by the output that the device didn't play yet
+    device_latency: AtomicU64,
+    // paused with `PauseMode::FadeAndRetain`, the output fades out and holds the buffer
+    fading_out: AtomicBool,
+    // the output finished the fade of `fading_out`, and only plays silence
+    faded_out: AtomicBool,
+    // paused with `PauseMode::KeepAlive`, the output plays silence and holds the buffer
+    holding: AtomicBool,
+    // stepping frame by frame, the output plays the queued samples without waiting or concealing the gaps
+    frame_stepping: AtomicBool,
+    // one bit for each muted output channel
+    muted_channels: AtomicU8,
+    // `f32` bits of the stereo width, `1.0` plays the channels as they are
+    stereo_width: AtomicU32,
+    // `f32` bits of the rolling time spent in the output callbacks, relative to the duration they fill
+    callback_load: AtomicU32,
+    // the last volume fade requested by the player, as the bits of a `VolumeFade`,
+    // the output starts it when it changes
+    volume_fade: AtomicU64,
+    // the backend reported that the stream stopped, handled by the player with its recovery policy
+    stream_lost: AtomicBool,
+    // the output panicked, and only plays silence
+    panicked: AtomicBool,
+    // only locked for a copy, and the output skips the update if it can't lock
+    callback_timestamp: Mutex<Option<CallbackTimestamp>>,
+    // the errors reported by the backends, not from the output callback, so they can lock
+    stream_errors: Mutex<Vec<CallbackError>>,
+    // threads waiting for space in the buffer, woken when the output consumes samples
+    space_waiters: AtomicUsize,
+    space_lock: Mutex<()>,
+    space_changed: Condvar,
+    stats: StatsRecorder,
+}
+
+impl PlayerShared {
+    pub fn new() -> Self {
+        Self {
+            state: AtomicU8::new(PlayerState::Paused as u8),
+            underruns: AtomicU64::new(0),
+            resume_threshold: AtomicUsize::new(0),
+            drift: AtomicU64::new(1f64.to_bits()),
+            measured_rate: AtomicU64::new(0f64.to_bits()),
+            frames_consumed: AtomicU64::new(0),
+            frames_delivered: AtomicU64::new(0),
+            frames_delivered_base: AtomicU64::new(0),
+            fill_count: AtomicU64::new(0),
+            queue_count: AtomicU64::new(0),
+            device_latency: AtomicU64::new(0),
+            fading_out: AtomicBool::new(false),
+            faded_out: AtomicBool::new(false),
+            holding: AtomicBool::new(false),
+            frame_stepping: AtomicBool::new(false),
+            muted_channels: AtomicU8::new(0),
+            stereo_width: AtomicU32::new(1f32.to_bits()),
+            callback_load: AtomicU32::new(0f32.to_bits()),
+            volume_fade: AtomicU64::new(VolumeFade::set(1.0).to_bits()),
+            stream_lost: AtomicBool::new(false),
+            panicked: AtomicBool::new(false),
+            callback_timestamp: Mutex::new(None),
+            stream_errors: Mutex::new(Vec::new()),
+            space_waiters: AtomicUsize::new(0),
+            space_lock: Mutex::new(()),
+            space_changed: Condvar::new(),
+            stats: StatsRecorder::new(),
+        }
+    }
+
+    pub fn state(&self) -> PlayerState {
+        PlayerState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    pub fn set_state(&self, state: PlayerState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Called from the output callback after filling the output buffer,
+    /// `underrun` is `true` if the buffer didn't have enough samples to fill it.
+    ///
+    /// Only moves between `Playing` and `Underrun`, so it never overrides a `Paused` state.
+    pub fn report_fill(&self, underrun: bool) {
+        let (from, to) = if underrun {
+            (PlayerState::Playing, PlayerState::Underrun)
+        } else {
+            (PlayerState::Underrun, PlayerState::Playing)
+        };
+        let changed = self
+            .state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok();
+        if changed && underrun {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The number of times the player went from `Playing` to `Underrun`
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    pub fn resume_threshold(&self) -> usize {
+        self.resume_threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_resume_threshold(&self, samples: usize) {
+        self.resume_threshold.store(samples, Ordering::Relaxed);
+    }
+
+    pub fn drift(&self) -> f64 {
+        f64::from_bits(self.drift.load(Ordering::Relaxed))
+    }
+
+    pub fn set_drift(&self, drift: f64) {
+        self.drift.store(drift.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn measured_rate(&self) -> Option<f64> {
+        let rate = f64::from_bits(self.measured_rate.load(Ordering::Relaxed));
+        (rate > 0.0).then_some(rate)
+    }
+
+    pub fn set_measured_rate(&self, rate: Option<f64>) {
+        self.measured_rate
+            .store(rate.unwrap_or(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn frames_consumed(&self) -> u64 {
+        self.frames_consumed.load(Ordering::Acquire)
+    }
+
+    pub fn add_frames_consumed(&self, frames: u64) {
+        self.frames_consumed.fetch_add(frames, Ordering::Release);
+    }
+
+    /// The frames filled by the output since the last reset
+    pub fn frames_delivered(&self) -> u64 {
+        let base = self.frames_delivered_base.load(Ordering::Acquire);
+        self.frames_delivered
+            .load(Ordering::Acquire)
+            .wrapping_sub(base)
+    }
+
+    /// Count `frames` more frames filled by the output, wraps around on overflow
+    pub fn add_frames_delivered(&self, frames: u64) {
+        self.frames_delivered.fetch_add(frames, Ordering::Release);
+    }
+
+    pub fn reset_frames_delivered(&self) {
+        let delivered = self.frames_delivered.load(Ordering::Acquire);
+        self.frames_delivered_base
+            .store(delivered, Ordering::Release);
+    }
+
+    pub fn fill_count(&self) -> u64 {
+        self.fill_count.load(Ordering::Acquire)
+    }
+
+    pub fn add_fill(&self) {
+        self.fill_count.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn queue_count(&self) -> u64 {
+        self.queue_count.load(Ordering::Relaxed)
+    }
+
+    pub fn add_queue(&self) {
+        self.queue_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn device_latency(&self) -> u64 {
+        self.device_latency.load(Ordering::Relaxed)
+    }
+
+    pub fn set_device_latency(&self, frames: u64) {
+        self.device_latency.store(frames, Ordering::Relaxed);
+    }
+
+    pub fn is_fading_out(&self) -> bool {
+        self.fading_out.load(Ordering::Acquire)
+    }
+
+    pub fn set_fading_out(&self, fading_out: bool) {
+        self.fading_out.store(fading_out, Ordering::Release);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.faded_out.load(Ordering::Acquire)
+    }
+
+    pub fn set_faded_out(&self, faded_out: bool) {
+        self.faded_out.store(faded_out, Ordering::Release);
+    }
+
+    pub fn is_holding(&self) -> bool {
+        self.holding.load(Ordering::Acquire)
+    }
+
+    pub fn set_holding(&self, holding: bool) {
+        self.holding.store(holding, Ordering::Release);
+    }
+
+    pub fn is_frame_stepping(&self) -> bool {
+        self.frame_stepping.load(Ordering::Relaxed)
+    }
+
+    pub fn set_frame_stepping(&self, stepping: bool) {
+        self.frame_stepping.store(stepping, Ordering::Relaxed);
+    }
+
+    pub fn stereo_width(&self) -> f32 {
+        f32::from_bits(self.stereo_width.load(Ordering::Relaxed))
+    }
+
+    pub fn set_stereo_width(&self, width: f32) {
+        self.stereo_width.store(width.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn callback_load(&self) -> f32 {
+        f32::from_bits(self.callback_load.load(Ordering::Relaxed))
+    }
+
+    pub fn set_callback_load(&self, load: f32) {
+        self.callback_load.store(load.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn volume_fade(&self) -> u64 {
+        self.volume_fade.load(Ordering::Relaxed)
+    }
+
+    pub fn set_volume_fade(&self, fade: VolumeFade) {
+        self.volume_fade.store(fade.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_channel_muted(&self, channel: usize) -> bool {
+        self.muted_channels.load(Ordering::Relaxed) & (1 << channel) != 0
+    }
+
+    pub fn set_channel_muted(&self, channel: usize, muted: bool) {
+        if muted {
+            self.muted_channels
+                .fetch_or(1 << channel, Ordering::Relaxed);
+        } else {
+            self.muted_channels
+                .fetch_and(!(1 << channel), Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_stream_lost(&self) {
+        self.stream_lost.store(true, Ordering::Release);
+    }
+
+    pub fn callback_timestamp(&self) -> Option<CallbackTimestamp> {
+        *self.callback_timestamp.lock().ok()?
+    }
+
+    pub fn set_callback_timestamp(&self, timestamp: CallbackTimestamp) {
+        if let Ok(mut current) = self.callback_timestamp.try_lock() {
+            *current = Some(timestamp);
+        }
+    }
+
+    pub fn stats(&self) -> &StatsRecorder {
+        &self.stats
+    }
+
+    /// Returns `true` once after the stream was reported lost
+    pub fn take_stream_lost(&self) -> bool {
+        self.stream_lost.swap(false, Ordering::AcqRel)
+    }
+
+    pub fn has_panicked(&self) -> bool {
+        self.panicked.load(Ordering::Acquire)
+    }
+
+    pub fn set_panicked(&self) {
+        self.panicked.store(true, Ordering::Release);
+    }
+
+    /// Wake the threads waiting for space, called by the output after consuming samples
+    pub fn notify_space(&self) {
+        // no system call when nobody waits
+        if self.space_waiters.load(Ordering::Acquire) > 0 {
+            self.space_changed.notify_all();
+        }
+    }
+
+    /// Park until `ready` returns `true` or `timeout` passes, returns the last result of `ready`
+    pub fn wait_for_space(&self, ready: impl Fn() -> bool, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.space_waiters.fetch_add(1, Ordering::AcqRel);
+        let mut guard = self.space_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let ready = loop {
+            if ready() {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            let park = (deadline - now).min(MAX_SPACE_PARK);
+            guard = match self.space_changed.wait_timeout(guard, park) {
+                Ok((guard, _)) => guard,
+                Err(e) => e.into_inner().0,
+            };
+        };
+        self.space_waiters.fetch_sub(1, Ordering::AcqRel);
+        ready
+    }
+
+    pub fn report_error(&self, error: CallbackError) {
+        #[cfg(feature = "debug-rt-checks")]
+        crate::rt_checks::assert_not_realtime("locking the stream errors");
+        if let Ok(mut errors) = self.stream_errors.lock() {
+            if errors.len() < STREAM_ERRORS_CAPACITY {
+                errors.push(error);
+            }
+        }
+    }
+
+    pub fn take_errors(&self) -> Vec<CallbackError> {
+        self.stream_errors
+            .lock()
+            .map(|mut errors| std::mem::take(&mut *errors))
+            .unwrap_or_default()
+    }
+}
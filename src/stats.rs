@@ -0,0 +1,218 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The upper limits of the buckets of [`CallbackStats::interval_histogram`], in milliseconds
+const INTERVAL_BUCKETS_MS: [u64; 7] = [1, 2, 5, 10, 20, 50, 100];
+
+/// Statistics of the output callbacks, from [`AudioPlayer::callback_stats`](crate::AudioPlayer::callback_stats).
+///
+/// Useful to find why the audio crackles on a specific machine, for example if the device
+/// requests very different numbers of frames, or if the callbacks are late.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallbackStats {
+    /// The number of callbacks.
+    pub callbacks: u64,
+    /// The smallest number of frames requested by a callback.
+    pub min_frames: u64,
+    /// The largest number of frames requested by a callback.
+    pub max_frames: u64,
+    /// The number of frames requested by the last callback.
+    pub last_frames: u64,
+    /// The longest time between two callbacks.
+    pub max_interval: Duration,
+    /// The number of times between two callbacks in each bucket of [`INTERVAL_BUCKETS`](Self::INTERVAL_BUCKETS),
+    /// the last one counts the times longer than all the buckets.
+    pub interval_histogram: [u64; INTERVAL_BUCKETS_MS.len() + 1],
+    /// The lowest number of samples in the buffer at the start of a callback.
+    pub min_fill: u64,
+    /// The number of samples in the buffer at the start of the last callback.
+    pub last_fill: u64,
+}
+
+impl CallbackStats {
+    /// The upper limits of the buckets of [`interval_histogram`](Self::interval_histogram).
+    pub const INTERVAL_BUCKETS: [Duration; INTERVAL_BUCKETS_MS.len()] = [
+        Duration::from_millis(INTERVAL_BUCKETS_MS[0]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[1]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[2]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[3]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[4]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[5]),
+        Duration::from_millis(INTERVAL_BUCKETS_MS[6]),
+    ];
+}
+
+/// The callback statistics, written by the output without locking and read by the player
+pub(crate) struct StatsRecorder {
+    callbacks: AtomicU64,
+    min_frames: AtomicU64,
+    max_frames: AtomicU64,
+    last_frames: AtomicU64,
+    // in nanoseconds
+    max_interval: AtomicU64,
+    interval_histogram: [AtomicU64; INTERVAL_BUCKETS_MS.len() + 1],
+    min_fill: AtomicU64,
+    last_fill: AtomicU64,
+}
+
+impl StatsRecorder {
+    pub fn new() -> Self {
+        Self {
+            callbacks: AtomicU64::new(0),
+            min_frames: AtomicU64::new(u64::MAX),
+            max_frames: AtomicU64::new(0),
+            last_frames: AtomicU64::new(0),
+            max_interval: AtomicU64::new(0),
+            interval_histogram: Default::default(),
+            min_fill: AtomicU64::new(u64::MAX),
+            last_fill: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a callback of `frames` frames, with `fill` samples in the buffer if it could be read,
+    /// `interval` is the time since the previous callback
+    pub fn record(&self, frames: u64, fill: Option<u64>, interval: Option<Duration>) {
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.min_frames.fetch_min(frames, Ordering::Relaxed);
+        self.max_frames.fetch_max(frames, Ordering::Relaxed);
+        self.last_frames.store(frames, Ordering::Relaxed);
+        if let Some(fill) = fill {
+            self.min_fill.fetch_min(fill, Ordering::Relaxed);
+            self.last_fill.store(fill, Ordering::Relaxed);
+        }
+        if let Some(interval) = interval {
+            self.max_interval
+                .fetch_max(interval.as_nanos() as u64, Ordering::Relaxed);
+            let millis = interval.as_millis() as u64;
+            let bucket = INTERVAL_BUCKETS_MS
+                .iter()
+                .position(|&limit| millis < limit)
+                .unwrap_or(INTERVAL_BUCKETS_MS.len());
+            self.interval_histogram[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> CallbackStats {
+        let callbacks = self.callbacks.load(Ordering::Relaxed);
+        // the minimums are `u64::MAX` before the first callback
+        let min = |value: &AtomicU64| match value.load(Ordering::Relaxed) {
+            u64::MAX => 0,
+            value => value,
+        };
+        CallbackStats {
+            callbacks,
+            min_frames: min(&self.min_frames),
+            max_frames: self.max_frames.load(Ordering::Relaxed),
+            last_frames: self.last_frames.load(Ordering::Relaxed),
+            max_interval: Duration::from_nanos(self.max_interval.load(Ordering::Relaxed)),
+            interval_histogram: std::array::from_fn(|i| {
+                self.interval_histogram[i].load(Ordering::Relaxed)
+            }),
+            min_fill: min(&self.min_fill),
+            last_fill: self.last_fill.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.callbacks.store(0, Ordering::Relaxed);
+        self.min_frames.store(u64::MAX, Ordering::Relaxed);
+        self.max_frames.store(0, Ordering::Relaxed);
+        self.last_frames.store(0, Ordering::Relaxed);
+        self.max_interval.store(0, Ordering::Relaxed);
+        for bucket in &self.interval_histogram {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.min_fill.store(u64::MAX, Ordering::Relaxed);
+        self.last_fill.store(0, Ordering::Relaxed);
+    }
+}
+
+/// A snapshot of the player counters, from [`AudioPlayer::metrics`](crate::AudioPlayer::metrics).
+///
+/// Plain values, suitable for logging or showing in a debug overlay.
+/// The sample counts are in the units of the buffer: interleaved output channels,
+/// at the output sample rate, after resampling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The samples queued into the buffer, including the dropped ones.
+    pub samples_queued: u64,
+    /// The samples played from the buffer by the output.
+    pub samples_played: u64,
+    /// The samples dropped because the buffer was full, or above the
+    /// [`latency_clamp`](crate::AudioPlayerBuilder::latency_clamp) or the [`JitterBuffer`](crate::JitterBuffer) maximum,
+    /// or didn't fit after [`set_buffer_size`](crate::AudioPlayer::set_buffer_size).
+    pub samples_dropped: u64,
+    /// The number of times the output ran out of samples.
+    pub underruns: u64,
+    /// The number of samples in the buffer waiting to be played.
+    pub buffered: usize,
+    /// The capacity of the buffer, in samples.
+    pub buffer_capacity: usize,
+    /// The total time spent resampling the queued samples.
+    pub resampler_time: Duration,
+    /// The playback time of the input samples passed to the resampler.
+    pub resampled_duration: Duration,
+}
+
+impl Metrics {
+    /// The time spent resampling per second of resampled audio, as a percentage of real time.
+    ///
+    /// For example `5.0` means that resampling one second of audio takes 50ms of the thread
+    /// queuing the samples. Returns `0.0` if nothing was resampled.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use dynwave::{backend::NullBackend, AudioPlayer};
+    /// let mut player = AudioPlayer::<f32>::builder(44100)
+    ///     .backend(NullBackend::new().sample_rate(48000))
+    ///     .build()
+    ///     .unwrap();
+    /// player.queue(&vec![0.0; 4410 * 2]);
+    ///
+    /// let metrics = player.metrics();
+    /// assert_eq!(metrics.resampled_duration, Duration::from_millis(100));
+    /// assert!(metrics.resampler_load() > 0.0);
+    /// ```
+    pub fn resampler_load(&self) -> f32 {
+        if self.resampled_duration.is_zero() {
+            return 0.0;
+        }
+        (self.resampler_time.as_secs_f64() / self.resampled_duration.as_secs_f64() * 100.0) as f32
+    }
+}
+
+/// Callback called when the callback load crosses the threshold
+pub(crate) type OverloadCallback = Box<dyn FnMut(bool) + Send + 'static>;
+
+/// Tracks the callback load against the threshold when samples are queued,
+/// see [`AudioPlayerBuilder::on_callback_overload`](crate::AudioPlayerBuilder::on_callback_overload)
+pub(crate) struct LoadWarning {
+    // percentage of the time of the callbacks
+    threshold: f32,
+    overloaded: bool,
+    callback: Option<OverloadCallback>,
+}
+
+impl LoadWarning {
+    pub fn new(threshold: f32, callback: Option<OverloadCallback>) -> Self {
+        Self {
+            threshold,
+            overloaded: false,
+            callback,
+        }
+    }
+
+    /// Update with the current `load`, in percent
+    pub fn update(&mut self, load: f32) {
+        let overloaded = load >= self.threshold;
+        if overloaded != self.overloaded {
+            self.overloaded = overloaded;
+            if let Some(callback) = &mut self.callback {
+                callback(overloaded);
+            }
+        }
+    }
+}
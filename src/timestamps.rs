@@ -0,0 +1,87 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The timing of the last output callback, from [`AudioPlayer::callback_timestamp`](crate::AudioPlayer::callback_timestamp).
+///
+/// The times are reported by the device, and converted to the clock of [`Instant`],
+/// so they can be compared with the other clocks of the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackTimestamp {
+    /// When the callback was called.
+    pub callback: Instant,
+    /// When the first frame filled by the callback will be played by the device.
+    pub playback: Instant,
+    /// The number of output frames filled before this callback, so the position of its first frame.
+    pub frame: u64,
+}
+
+/// Maximum number of timestamps kept, older ones are dropped first
+const MAX_TIMESTAMPS: usize = 1024;
+
+/// The presentation timestamps of the queued chunks, by the output frame they start at
+pub(crate) struct Timestamps {
+    markers: VecDeque<(u64, Duration)>,
+    sample_rate: f64,
+}
+
+impl Timestamps {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            markers: VecDeque::new(),
+            sample_rate: sample_rate as f64,
+        }
+    }
+
+    /// The chunk starting at the output frame `position` has `timestamp`
+    pub fn push(&mut self, position: u64, timestamp: Duration) {
+        if self.markers.len() == MAX_TIMESTAMPS {
+            self.markers.pop_front();
+        }
+        self.markers.push_back((position, timestamp));
+    }
+
+    /// Drop the timestamps of the chunks that were played before the output frame `position`
+    pub fn prune(&mut self, position: u64) {
+        // the last chunk that started is kept, it might still be playing
+        while self.markers.len() > 1 && self.markers[1].0 <= position {
+            self.markers.pop_front();
+        }
+    }
+
+    /// The `frames` output frames starting at `position` were dropped, the later frames move back to `position`
+    pub fn skip(&mut self, position: u64, frames: u64) {
+        let end = position + frames;
+        // the frame that plays at `position` now
+        let resumed = self.at(end);
+        self.markers
+            .retain(|(start, _)| *start < position || *start >= end);
+        for (start, _) in &mut self.markers {
+            if *start >= end {
+                *start -= frames;
+            }
+        }
+        if let Some(resumed) = resumed {
+            let index = self.markers.partition_point(|(start, _)| *start < position);
+            if self.markers.get(index).map(|(start, _)| *start) != Some(position) {
+                self.markers.insert(index, (position, resumed));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.markers.clear();
+    }
+
+    /// The timestamp of the output frame `position`, from the chunk it's in
+    pub fn at(&self, position: u64) -> Option<Duration> {
+        let (start, timestamp) = self
+            .markers
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= position)?;
+        let offset = (position - start) as f64 / self.sample_rate;
+        Some(*timestamp + Duration::from_secs_f64(offset))
+    }
+}
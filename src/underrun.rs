@@ -0,0 +1,91 @@
+use cpal::SampleFormat;
+
+use crate::sample::FloatSample;
+
+/// What is played in place of the missing samples when the buffer runs out,
+/// see [`AudioPlayerBuilder::underrun_fill`](crate::AudioPlayerBuilder::underrun_fill).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnderrunFill {
+    /// Silence at the exact midpoint of the device format, for example `128` for `U8`
+    /// and `32768` for `U16`, without dither.
+    #[default]
+    Midpoint,
+    /// Repeat the last played sample of each channel, so the level doesn't step to the midpoint,
+    /// for devices that click on the jump.
+    HoldLast,
+}
+
+/// The [`UnderrunFill`] of each device format, [`UnderrunFill::Midpoint`] if not set
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UnderrunFills {
+    formats: Vec<(SampleFormat, UnderrunFill)>,
+}
+
+impl UnderrunFills {
+    pub fn set(&mut self, format: SampleFormat, fill: UnderrunFill) {
+        self.formats.retain(|&(f, _)| f != format);
+        self.formats.push((format, fill));
+    }
+
+    pub fn get(&self, format: SampleFormat) -> UnderrunFill {
+        self.formats
+            .iter()
+            .find(|&&(f, _)| f == format)
+            .map_or(UnderrunFill::default(), |&(_, fill)| fill)
+    }
+}
+
+/// Conceal the missing samples after the first `played` samples, if they are at most `max_frames`.
+///
+/// The last two played frames are extrapolated linearly, and faded out over the gap,
+/// so a few missing samples don't make a click.
+pub(crate) fn conceal<T: FloatSample>(
+    samples: &mut [T],
+    played: usize,
+    channels: usize,
+    max_frames: usize,
+) {
+    let missing_frames = (samples.len() - played) / channels;
+    if missing_frames == 0 || missing_frames > max_frames || played < channels * 2 {
+        return;
+    }
+    let start = played / channels * channels;
+    let (history, missing) = samples.split_at_mut(start);
+    let last = &history[history.len() - channels..];
+    let before_last = &history[history.len() - channels * 2..history.len() - channels];
+    for (k, frame) in missing.chunks_exact_mut(channels).enumerate() {
+        let step = (k + 1) as f32;
+        let fade = T::coerce(1.0 - step / (missing_frames + 1) as f32);
+        for (c, sample) in frame.iter_mut().enumerate() {
+            let slope = last[c] - before_last[c];
+            *sample = (last[c] + slope * T::coerce(step)) * fade;
+        }
+    }
+}
+
+/// Fade the samples of a stepped frame in from the silence before them if `fade_in`,
+/// and out to the silence after them if `fade_out`, over at most `ramp_frames` frames each.
+pub(crate) fn ramp_step<T: FloatSample>(
+    samples: &mut [T],
+    channels: usize,
+    ramp_frames: usize,
+    fade_in: bool,
+    fade_out: bool,
+) {
+    let frames = samples.len() / channels;
+    let ramp = ramp_frames.min(frames / 2);
+    for k in 0..ramp {
+        let gain = T::coerce((k + 1) as f32 / (ramp + 1) as f32);
+        if fade_in {
+            for sample in &mut samples[k * channels..(k + 1) * channels] {
+                *sample *= gain;
+            }
+        }
+        if fade_out {
+            let frame = frames - 1 - k;
+            for sample in &mut samples[frame * channels..(frame + 1) * channels] {
+                *sample *= gain;
+            }
+        }
+    }
+}
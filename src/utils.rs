@@ -1,16 +1,75 @@
-use cpal::{Data, FromSample, Sample, SampleFormat, SizedSample};
-use ringbuf::{traits::Consumer, HeapCons};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use cpal::{Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample};
+
+use crate::{
+    backend::OutputSource,
+    error::{AudioPlayerError, CallbackError},
+    sample::FloatSample,
+};
+
+/// Call `f` until it succeeds, up to `retries` more times with `backoff` between them,
+/// only the transient errors are retried
+pub fn with_retries<R>(
+    retries: u32,
+    backoff: Duration,
+    mut f: impl FnMut() -> Result<R, AudioPlayerError>,
+) -> Result<R, AudioPlayerError> {
+    let mut attempts = 0;
+    loop {
+        match f() {
+            Err(err) if err.is_transient() && attempts < retries => {
+                attempts += 1;
+                thread::sleep(backoff);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// View interleaved frames as a flat slice of samples
+pub fn flatten_frames<T, const CH: usize>(frames: &[[T; CH]]) -> &[T] {
+    // SAFETY: `[T; CH]` has the layout of `CH` consecutive `T`s, without padding between the arrays
+    unsafe { std::slice::from_raw_parts(frames.as_ptr().cast::<T>(), frames.len() * CH) }
+}
+
+/// Whether the output processor can write samples of `format`.
+///
+/// cpal 0.15 has no 24-bit formats, so its hosts don't offer the 24-bit configurations of devices.
+/// Formats added by newer versions, like the packed 24-bit ones, are skipped by the negotiation
+/// until the processor handles them, instead of failing when the device prefers them.
+pub fn is_playable(format: SampleFormat) -> bool {
+    matches!(
+        format,
+        SampleFormat::I8
+            | SampleFormat::I16
+            | SampleFormat::I32
+            | SampleFormat::I64
+            | SampleFormat::U8
+            | SampleFormat::U16
+            | SampleFormat::U32
+            | SampleFormat::U64
+            | SampleFormat::F32
+            | SampleFormat::F64
+    )
+}
 
 // Type alias for the processing function - matches the required callback signature
-type ProcessingFn = Box<dyn FnMut(&mut Data, &cpal::OutputCallbackInfo) + Send + 'static>;
+type ProcessingFn = Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>;
 
 // Function to create the appropriate processing function based on format
+//
+// `SampleFormat` is non-exhaustive, so formats added by newer versions of cpal are returned as
+// `AudioPlayerError::UnsupportedOutputFormat`
 pub fn create_output_processor<T>(
     format: SampleFormat,
-    mut buffer_consumer: HeapCons<T>,
-) -> ProcessingFn
+    mut source: OutputSource<T>,
+) -> Result<ProcessingFn, AudioPlayerError>
 where
-    T: Sample + SizedSample + Send + 'static,
+    T: FloatSample + SizedSample + Send + 'static,
 
     // sadly, cpal uses macro to generate those, and there is no auto way
     // to use the type system to, even though it seems that it makes sense
@@ -26,57 +85,58 @@ where
     f32: FromSample<T>,
     f64: FromSample<T>,
 {
-    match format {
-        SampleFormat::I8 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<i8>().expect("Valid format") {
-                *sample = i8::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::I16 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<i16>().expect("Valid format") {
-                *sample = i16::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::I32 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<i32>().expect("Valid format") {
-                *sample = i32::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::I64 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<i64>().expect("Valid format") {
-                *sample = i64::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::U8 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<u8>().expect("Valid format") {
-                *sample = u8::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::U16 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<u16>().expect("Valid format") {
-                *sample = u16::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::U32 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<u32>().expect("Valid format") {
-                *sample = u32::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::U64 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<u64>().expect("Valid format") {
-                *sample = u64::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::F32 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<f32>().expect("Valid format") {
-                *sample = f32::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        SampleFormat::F64 => Box::new(move |data, _| {
-            for sample in data.as_slice_mut::<f64>().expect("Valid format") {
-                *sample = f64::from_sample(buffer_consumer.try_pop().unwrap_or(T::EQUILIBRIUM));
-            }
-        }),
-        e => panic!("Format {e:?} isn't supported"),
+    let processor: ProcessingFn = match format {
+        SampleFormat::I8 => Box::new(move |data, info| fill_data::<T, i8>(&mut source, data, info)),
+        SampleFormat::I16 => {
+            Box::new(move |data, info| fill_data::<T, i16>(&mut source, data, info))
+        }
+        SampleFormat::I32 => {
+            Box::new(move |data, info| fill_data::<T, i32>(&mut source, data, info))
+        }
+        SampleFormat::I64 => {
+            Box::new(move |data, info| fill_data::<T, i64>(&mut source, data, info))
+        }
+        SampleFormat::U8 => Box::new(move |data, info| fill_data::<T, u8>(&mut source, data, info)),
+        SampleFormat::U16 => {
+            Box::new(move |data, info| fill_data::<T, u16>(&mut source, data, info))
+        }
+        SampleFormat::U32 => {
+            Box::new(move |data, info| fill_data::<T, u32>(&mut source, data, info))
+        }
+        SampleFormat::U64 => {
+            Box::new(move |data, info| fill_data::<T, u64>(&mut source, data, info))
+        }
+        SampleFormat::F32 => {
+            Box::new(move |data, info| fill_data::<T, f32>(&mut source, data, info))
+        }
+        SampleFormat::F64 => {
+            Box::new(move |data, info| fill_data::<T, f64>(&mut source, data, info))
+        }
+        format => return Err(AudioPlayerError::UnsupportedOutputFormat(format)),
+    };
+    Ok(processor)
+}
+
+// Fill `data` with samples of format `S`, reporting a mismatch instead of panicking in the callback
+fn fill_data<T, S>(source: &mut OutputSource<T>, data: &mut Data, info: &OutputCallbackInfo)
+where
+    T: FloatSample + SizedSample,
+    S: SizedSample + FromSample<T> + 'static,
+{
+    source.report_playback_time(playback_instant(info));
+    let format = data.sample_format();
+    match data.as_slice_mut::<S>() {
+        Some(output) => source.fill_converted(output),
+        None => source.report_error(CallbackError::FormatMismatch(format)),
     }
 }
+
+// The time the samples of the callback will be played, on the clock of `Instant`
+fn playback_instant(info: &OutputCallbackInfo) -> Instant {
+    let timestamp = info.timestamp();
+    let latency = timestamp
+        .playback
+        .duration_since(&timestamp.callback)
+        .unwrap_or_default();
+    Instant::now() + latency
+}
@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "spectrum")]
+use realfft::{num_complex::Complex, RealFftPlanner};
+
+use crate::{backend::OutputConfig, effects::AudioEffect};
+
+/// The last played samples, kept in a circular buffer
+pub(crate) struct History<T> {
+    samples: Vec<T>,
+    // where the next sample is written
+    position: usize,
+    filled: bool,
+}
+
+impl<T: Copy> History<T> {
+    pub fn new(len: usize, silence: T) -> Self {
+        Self {
+            samples: vec![silence; len],
+            position: 0,
+            filled: false,
+        }
+    }
+
+    fn push(&mut self, data: &[T]) {
+        let len = self.samples.len();
+        if len == 0 {
+            return;
+        }
+        // only the last `len` samples are kept anyway
+        let data = &data[data.len().saturating_sub(len)..];
+        for &sample in data {
+            self.samples[self.position] = sample;
+            self.position += 1;
+            if self.position == len {
+                self.position = 0;
+                self.filled = true;
+            }
+        }
+    }
+
+    /// The samples from the oldest to the newest
+    pub fn read_into(&self, output: &mut Vec<T>) {
+        output.clear();
+        if self.filled {
+            output.extend_from_slice(&self.samples[self.position..]);
+        }
+        output.extend_from_slice(&self.samples[..self.position]);
+    }
+}
+
+/// Copies the output into a [`History`]
+pub(crate) struct VisualizationTap<T> {
+    history: Arc<Mutex<History<T>>>,
+}
+
+impl<T> VisualizationTap<T> {
+    pub fn new(history: Arc<Mutex<History<T>>>) -> Self {
+        Self { history }
+    }
+}
+
+impl<T: Copy + Send> AudioEffect<T> for VisualizationTap<T> {
+    fn process(&mut self, samples: &mut [T], _config: &OutputConfig) {
+        // never block the output, the reader only holds the lock to copy the samples,
+        // so missing an update is not noticeable
+        if let Ok(mut history) = self.history.try_lock() {
+            history.push(samples);
+        }
+    }
+}
+
+/// Computes the magnitude spectrum of the recent samples
+#[cfg(feature = "spectrum")]
+#[derive(Default)]
+pub(crate) struct SpectrumAnalyzer {
+    planner: RealFftPlanner<f32>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+#[cfg(feature = "spectrum")]
+impl SpectrumAnalyzer {
+    /// Compute the spectrum of the mono `samples` into `output`
+    ///
+    /// Uses the largest power of two number of samples from the end of `samples`
+    pub fn compute(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        if samples.len() < 2 {
+            return;
+        }
+        let size = 1 << samples.len().ilog2();
+        let samples = &samples[samples.len() - size..];
+
+        if self.window.len() != size {
+            // hann window
+            self.window = (0..size)
+                .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / size as f32).cos())
+                .collect();
+        }
+        let fft = self.planner.plan_fft_forward(size);
+        self.input.clear();
+        self.input
+            .extend(samples.iter().zip(&self.window).map(|(s, w)| s * w));
+        self.spectrum.resize(fft.complex_len(), Complex::default());
+        fft.process(&mut self.input, &mut self.spectrum)
+            .expect("Valid buffer sizes");
+
+        // a full scale sine wave has a magnitude of `1.0`
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        output.extend(
+            self.spectrum[..size / 2]
+                .iter()
+                .map(|bin| bin.norm() * scale),
+        );
+    }
+}
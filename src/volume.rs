@@ -0,0 +1,201 @@
+use crate::sample::FloatSample;
+
+/// The volume below which an exponential fade jumps to silence, `-60dB`
+const EXPONENTIAL_FLOOR: f32 = 0.001;
+
+/// The volume in decibels at or below which the output is muted, about the noise floor of 16-bit audio
+const MUTE_DB: f32 = -96.0;
+
+/// Convert a volume in decibels to a linear gain, [`MUTE_DB`] and below are `0.0`
+pub(crate) fn db_to_gain(db: f32) -> f32 {
+    if db.is_nan() || db <= MUTE_DB {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    }
+}
+
+/// Convert a linear gain to decibels, `0.0` is negative infinity
+pub(crate) fn gain_to_db(gain: f32) -> f32 {
+    if gain <= 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        20.0 * gain.log10()
+    }
+}
+
+/// How the volume moves during a fade, see [`AudioPlayer::fade_volume_to_with`](crate::AudioPlayer::fade_volume_to_with).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FadeCurve {
+    #[default]
+    /// The volume changes by the same amount every frame.
+    Linear,
+    /// The volume changes by the same ratio every frame, so the same number of decibels.
+    ///
+    /// This sounds more even to the ear, especially when fading out, as a linear fade
+    /// seems to stay loud and then drop at the end.
+    Exponential,
+}
+
+/// A fade of the volume requested by the player, packed into a `u64` so the output reads it atomically
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct VolumeFade {
+    pub target: f32,
+    pub frames: u64,
+    pub curve: FadeCurve,
+}
+
+impl VolumeFade {
+    /// The bits of the frames, the fades longer than this, about 12 hours at 48kHz, are shortened
+    const FRAMES_MASK: u64 = (1 << 31) - 1;
+
+    /// The volume at `target` without a fade
+    pub fn set(target: f32) -> Self {
+        Self {
+            target,
+            frames: 0,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    pub fn to_bits(self) -> u64 {
+        let curve = match self.curve {
+            FadeCurve::Linear => 0,
+            FadeCurve::Exponential => 1,
+        };
+        (self.target.to_bits() as u64) << 32 | curve << 31 | self.frames.min(Self::FRAMES_MASK)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            target: f32::from_bits((bits >> 32) as u32),
+            frames: bits & Self::FRAMES_MASK,
+            curve: match (bits >> 31) & 1 {
+                0 => FadeCurve::Linear,
+                _ => FadeCurve::Exponential,
+            },
+        }
+    }
+}
+
+/// The volume of the output, moving towards a target over a number of frames
+pub(crate) struct VolumeRamp {
+    gain: f32,
+    target: f32,
+    // the change of the gain every frame, added for linear and multiplied for exponential
+    step: f32,
+    remaining: u64,
+    curve: FadeCurve,
+}
+
+impl VolumeRamp {
+    pub fn new() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            step: 0.0,
+            remaining: 0,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    /// Start moving from the current gain to `target` over `frames`
+    pub fn fade_to(&mut self, target: f32, frames: u64, curve: FadeCurve) {
+        self.target = target;
+        self.remaining = frames;
+        self.curve = curve;
+        if frames == 0 {
+            self.gain = target;
+            return;
+        }
+        match curve {
+            FadeCurve::Linear => self.step = (target - self.gain) / frames as f32,
+            FadeCurve::Exponential => {
+                self.gain = self.gain.max(EXPONENTIAL_FLOOR);
+                let ratio = target.max(EXPONENTIAL_FLOOR) / self.gain;
+                self.step = ratio.powf(1.0 / frames as f32);
+            }
+        }
+    }
+
+    /// Apply the volume to `output`, moving it towards the target
+    pub fn apply<T: FloatSample>(&mut self, output: &mut [T], channels: usize) {
+        if self.remaining == 0 && self.gain == 1.0 {
+            return;
+        }
+        for frame in output.chunks_mut(channels) {
+            if self.remaining > 0 {
+                match self.curve {
+                    FadeCurve::Linear => self.gain += self.step,
+                    FadeCurve::Exponential => self.gain *= self.step,
+                }
+                self.remaining -= 1;
+                if self.remaining == 0 {
+                    self.gain = self.target;
+                }
+            }
+            for sample in frame {
+                *sample *= T::coerce(self.gain);
+            }
+        }
+    }
+}
+
+/// Lowers the gain of the queued samples while one-shot clips play, see
+/// [`AudioPlayer::duck`](crate::AudioPlayer::duck)
+pub(crate) struct Ducker {
+    gain: f32,
+    target: f32,
+    // the change of the gain every frame, when going down and back up
+    attack_step: f32,
+    release_step: f32,
+    // stays at the target until it's reached and no clip is playing
+    held: bool,
+}
+
+impl Ducker {
+    pub fn new() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            attack_step: 0.0,
+            release_step: 0.0,
+            held: false,
+        }
+    }
+
+    /// Move the gain to `target` over `attack_frames`, then back to `1.0` over `release_frames`
+    pub fn duck(&mut self, target: f32, attack_frames: u64, release_frames: u64) {
+        self.attack_step = (1.0 - target) / attack_frames.max(1) as f32;
+        // from the lowest gain, in case it's still down from a deeper duck
+        self.release_step = (1.0 - target.min(self.gain)) / release_frames.max(1) as f32;
+        self.target = target;
+        self.held = true;
+    }
+
+    /// Apply the gain to `output`, the gain is kept down for at least `hold_frames`,
+    /// the frames of one-shot clips left to play
+    pub fn apply<T: FloatSample>(&mut self, output: &mut [T], channels: usize, hold_frames: usize) {
+        if !self.held && self.gain == 1.0 {
+            return;
+        }
+        for (i, frame) in output.chunks_mut(channels).enumerate() {
+            if self.held {
+                if self.gain > self.target {
+                    self.gain = (self.gain - self.attack_step).max(self.target);
+                } else {
+                    // a shallower duck while ducked, move up to it
+                    self.gain = (self.gain + self.release_step).min(self.target);
+                }
+                if self.gain == self.target && i >= hold_frames {
+                    self.held = false;
+                }
+            } else {
+                self.gain = (self.gain + self.release_step).min(1.0);
+            }
+            for sample in frame {
+                *sample *= T::coerce(self.gain);
+            }
+        }
+    }
+}
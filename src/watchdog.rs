@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+pub(crate) type StallCallback = Box<dyn FnMut() + Send + 'static>;
+
+/// Notices when the output callbacks stop while the player is playing,
+/// set with [`AudioPlayerBuilder::watchdog`](crate::AudioPlayerBuilder::watchdog)
+pub(crate) struct Watchdog {
+    timeout: Duration,
+    // the callbacks seen on the last check, and since when
+    callbacks: u64,
+    since: Instant,
+    active: bool,
+    callback: Option<StallCallback>,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration, callback: Option<StallCallback>) -> Self {
+        Self {
+            timeout,
+            callbacks: 0,
+            since: Instant::now(),
+            active: false,
+            callback,
+        }
+    }
+
+    /// Check the number of `callbacks` so far, while the player is `active` (playing with an open stream),
+    /// returns `true` if no callback was done for longer than the timeout
+    pub fn check(&mut self, callbacks: u64, active: bool, now: Instant) -> bool {
+        let was_active = std::mem::replace(&mut self.active, active);
+        if !active || !was_active || callbacks != self.callbacks {
+            self.callbacks = callbacks;
+            self.since = now;
+            return false;
+        }
+        if now.saturating_duration_since(self.since) <= self.timeout {
+            return false;
+        }
+        self.since = now;
+        if let Some(callback) = &mut self.callback {
+            callback();
+        }
+        true
+    }
+}
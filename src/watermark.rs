@@ -0,0 +1,65 @@
+/// Callback called when the [`BufferLevel`] changes
+pub(crate) type WatermarkCallback = Box<dyn FnMut(BufferLevel) + Send + 'static>;
+
+/// The fill level of the player's buffer relative to the watermarks,
+/// set with [`AudioPlayerBuilder::watermarks`](crate::AudioPlayerBuilder::watermarks).
+///
+/// Useful to slightly speed up or slow down the emulation to keep the buffer level stable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BufferLevel {
+    /// The buffer is below the low watermark, samples should be generated faster.
+    Low,
+    #[default]
+    /// The buffer is between the watermarks.
+    Normal,
+    /// The buffer is above the high watermark, samples should be generated slower.
+    High,
+}
+
+/// Tracks the [`BufferLevel`] when samples are queued
+pub(crate) struct Watermarks {
+    low: f32,
+    high: f32,
+    level: BufferLevel,
+    callback: Option<WatermarkCallback>,
+}
+
+impl Watermarks {
+    pub fn new(low: f32, high: f32) -> Self {
+        let low = low.clamp(0.0, 1.0);
+        Self {
+            low,
+            high: high.clamp(low, 1.0),
+            level: BufferLevel::Normal,
+            callback: None,
+        }
+    }
+
+    pub fn set_callback(&mut self, callback: Option<WatermarkCallback>) {
+        self.callback = callback;
+    }
+
+    pub fn level(&self) -> BufferLevel {
+        self.level
+    }
+
+    /// Update the level after queueing, `before` is the number of samples before queueing,
+    /// which is the lowest level since the last queue, and `after` is the number of samples after.
+    pub fn update(&mut self, before: usize, after: usize, capacity: usize) {
+        let capacity = capacity.max(1) as f32;
+        let level = if (after as f32 / capacity) > self.high {
+            BufferLevel::High
+        } else if (before as f32 / capacity) < self.low {
+            BufferLevel::Low
+        } else {
+            BufferLevel::Normal
+        };
+
+        if level != self.level {
+            self.level = level;
+            if let Some(callback) = &mut self.callback {
+                callback(level);
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Helper to write samples into WAV files, used by the WAV backend and recording
+use std::{fs::File, io::BufWriter, path::Path};
+
+use cpal::{FromSample, SampleFormat};
+
+use crate::error::AudioPlayerError;
+use crate::sample::FloatSample;
+
+/// The sample formats that can be written into a WAV file
+pub(crate) const WAV_FORMATS: [SampleFormat; 4] = [
+    SampleFormat::I8,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::F32,
+];
+
+/// Pick the WAV format to use for samples in `format`, this is the same format if supported
+pub(crate) fn wav_format_for(format: SampleFormat) -> SampleFormat {
+    match format {
+        SampleFormat::F64 => SampleFormat::F32,
+        format if WAV_FORMATS.contains(&format) => format,
+        _ => SampleFormat::I16,
+    }
+}
+
+pub(crate) struct WavFileWriter {
+    writer: hound::WavWriter<BufWriter<File>>,
+    format: SampleFormat,
+}
+
+impl WavFileWriter {
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        format: SampleFormat,
+    ) -> Result<Self, AudioPlayerError> {
+        let (bits_per_sample, sample_format) = match format {
+            SampleFormat::I8 => (8, hound::SampleFormat::Int),
+            SampleFormat::I16 => (16, hound::SampleFormat::Int),
+            SampleFormat::I32 => (32, hound::SampleFormat::Int),
+            SampleFormat::F32 => (32, hound::SampleFormat::Float),
+            _ => return Err(AudioPlayerError::StreamConfigNotSupported),
+        };
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        };
+        let writer = hound::WavWriter::create(path, spec)?;
+
+        Ok(Self { writer, format })
+    }
+
+    /// Write interleaved samples, converting them to the format of the file
+    pub fn write<T>(&mut self, samples: &[T]) -> Result<(), hound::Error>
+    where
+        T: FloatSample,
+        i8: FromSample<T>,
+        i16: FromSample<T>,
+        i32: FromSample<T>,
+        f32: FromSample<T>,
+    {
+        match self.format {
+            SampleFormat::I8 => samples
+                .iter()
+                .try_for_each(|s| self.writer.write_sample(i8::from_sample_(*s))),
+            SampleFormat::I16 => samples
+                .iter()
+                .try_for_each(|s| self.writer.write_sample(i16::from_sample_(*s))),
+            SampleFormat::I32 => samples
+                .iter()
+                .try_for_each(|s| self.writer.write_sample(i32::from_sample_(*s))),
+            SampleFormat::F32 => samples
+                .iter()
+                .try_for_each(|s| self.writer.write_sample(f32::from_sample_(*s))),
+            _ => unreachable!("Checked on creation"),
+        }
+    }
+
+    pub fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
+    }
+}